@@ -0,0 +1,137 @@
+//! How long it takes to render a large chapter, so regressions in the
+//! markdown pipeline are easy to spot. Run it with `cargo bench`.
+//!
+//! This uses a plain `main()` instead of `#[bench]`, which needs a nightly
+//! compiler.
+
+extern crate mdbook;
+
+use std::path::Path;
+use std::time::{Duration, Instant};
+use mdbook::config::RawHtml;
+use mdbook::utils::{render_markdown, render_markdown_with_path, rendered_capacity,
+                    MarkdownOptions};
+
+const ITERATIONS: u32 = 50;
+
+/// One section of a chapter, with a bit of everything a real book has.
+const SECTION: &str = r#"
+## Section {n}
+
+Some prose with "quotes", an apostrophe's worth of text, **bold** and
+_emphasised_ words, `inline code`, and a [link to another
+chapter](../other/chapter_{n}.md#Some-Heading). There's also a link to
+[the Rust website](https://www.rust-lang.org/) and an image:
+![The logo](../images/logo.svg "mdBook").
+
+- A list item which goes on for a little while, like they usually do
+- Another item with `code` in it
+  - A nested item :rocket:
+
+```rust,ignore,title="Example {n}"
+fn main() {
+    let greeting = "Hello, world!";
+    println!("{}", greeting);
+}
+```
+
+| Option | Default | Description |
+|--------|---------|-------------|
+| `curly-quotes` | `false` | Converts "straight" quotes |
+| `emoji` | `false` | Replaces :shortcodes: |
+
+> A block quote, with a footnote[^{n}].
+
+[^{n}]: The footnote's text.
+
+<div class="warning">Some raw HTML.</div>
+"#;
+
+fn large_chapter() -> String {
+    let mut chapter = String::from("# A Large Chapter\n");
+    for n in 0..200 {
+        chapter.push_str(&SECTION.replace("{n}", &n.to_string()));
+    }
+    chapter
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs() as f64 * 1000.0 + f64::from(duration.subsec_nanos()) / 1_000_000.0
+}
+
+fn bench<F: FnMut() -> String>(name: &str, mut render: F) {
+    // the first run warms up the caches (and the lazy regexes)
+    let size = render().len();
+
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        render();
+    }
+    let per_iteration = start.elapsed() / ITERATIONS;
+
+    println!("{:<20} {:>9.3} ms/iter ({} bytes of HTML)", name, millis(per_iteration), size);
+}
+
+/// How many times a `String` starting out with `capacity` bytes has to grow
+/// (doubling each time) to hold `len` bytes.
+fn reallocations(capacity: usize, len: usize) -> u32 {
+    let mut capacity = capacity;
+    let mut grown = 0;
+
+    while capacity < len {
+        capacity = if capacity == 0 { 8 } else { capacity * 2 };
+        grown += 1;
+    }
+    grown
+}
+
+/// How well the initial capacity fits, for both a chapter and lots of tiny
+/// strings like the ones `SUMMARY.md` entries are rendered from.
+fn capacity_report(chapter: &str) {
+    let rendered = render_markdown(chapter, false);
+    println!("{:<20} {:>9} bytes reserved for {} bytes of HTML ({} reallocations)",
+             "chapter capacity",
+             rendered_capacity(chapter),
+             rendered.len(),
+             reallocations(rendered_capacity(chapter), rendered.len()));
+
+    let entries: Vec<String> = (0..1000).map(|n| format!("Chapter {} with `code`", n)).collect();
+    let reserved: usize = entries.iter().map(|e| rendered_capacity(e)).sum();
+    let used: usize = entries.iter().map(|e| render_markdown(e, false).len()).sum();
+    let grown: u32 = entries.iter()
+                            .map(|e| reallocations(rendered_capacity(e), render_markdown(e, false).len()))
+                            .sum();
+    println!("{:<20} {:>9} bytes reserved for {} bytes of HTML ({} reallocations)",
+             "1000 tiny strings",
+             reserved,
+             used,
+             grown);
+}
+
+fn main() {
+    let chapter = large_chapter();
+    println!("Rendering a {} byte chapter, {} times each", chapter.len(), ITERATIONS);
+    capacity_report(&chapter);
+
+    let plain = MarkdownOptions::default();
+    bench("default options", || {
+        render_markdown_with_path(&chapter, &plain, None, |_| false)
+    });
+
+    let path = Some(Path::new("first/chapter.md"));
+    bench("translated links", || {
+        render_markdown_with_path(&chapter, &plain, path, |_| true)
+    });
+
+    let everything = MarkdownOptions {
+        curly_quotes: true,
+        sanitize_html: true,
+        raw_html: RawHtml::Escape,
+        emoji: true,
+        external_links_new_tab: true,
+        ..Default::default()
+    };
+    bench("every option", || {
+        render_markdown_with_path(&chapter, &everything, path, |_| true)
+    });
+}
@@ -2,7 +2,7 @@ use std::path::PathBuf;
 use clap::{App, ArgMatches, SubCommand};
 use mdbook::MDBook;
 use mdbook::errors::Result;
-use {get_book_dir, open};
+use {configure_hooks, get_book_dir, open};
 
 // Create clap subcommand arguments
 pub fn make_subcommand<'a, 'b>() -> App<'a, 'b> {
@@ -16,22 +16,53 @@ pub fn make_subcommand<'a, 'b>() -> App<'a, 'b> {
         .arg_from_usage(
             "[dir] 'A directory for your book{n}(Defaults to Current Directory when omitted)'",
         )
+        .arg_from_usage("--locked 'Skip running the commands in [build.hooks]'")
+        .arg_from_usage(
+            "-f, --force 'Render every chapter again, instead of only the ones{n}\
+             which changed since the last build'",
+        )
+        .arg_from_usage(
+            "-l, --language=[code] 'Only build this language of a book with more than one{n}\
+             (Defaults to every language when omitted)'",
+        )
 }
 
 // Build command implementation
 pub fn execute(args: &ArgMatches) -> Result<()> {
     let book_dir = get_book_dir(args);
-    let mut book = MDBook::load(&book_dir)?;
+    let books = match args.value_of("language") {
+        Some(code) => vec![MDBook::load_language(&book_dir, code)?],
+        None => MDBook::load_all_languages(&book_dir)?,
+    };
 
-    if let Some(dest_dir) = args.value_of("dest-dir") {
-        book.config.build.build_dir = PathBuf::from(dest_dir);
-    }
+    let mut index = None;
+    for mut book in books {
+        if let Some(dest_dir) = args.value_of("dest-dir") {
+            let mut build_dir = PathBuf::from(dest_dir);
+            if let Some(code) = book.language() {
+                build_dir.push(code);
+            }
+            book.config.build.build_dir = build_dir;
+        }
 
-    book.build()?;
+        if args.is_present("force") {
+            book.config.build.incremental = false;
+        }
+
+        configure_hooks(&mut book, args.is_present("locked"), false);
+
+        book.build()?;
+
+        if index.is_none() {
+            // FIXME: What's the right behaviour if we don't use the HTML renderer?
+            index = Some(book.build_dir_for("html").join("index.html"));
+        }
+    }
 
     if args.is_present("open") {
-        // FIXME: What's the right behaviour if we don't use the HTML renderer?
-        open(book.build_dir_for("html").join("index.html"));
+        if let Some(index) = index {
+            open(index);
+        }
     }
 
     Ok(())
@@ -1,5 +1,3 @@
-use std::io;
-use std::io::Write;
 use clap::{App, ArgMatches, SubCommand};
 use mdbook::MDBook;
 use mdbook::errors::Result;
@@ -12,8 +10,10 @@ pub fn make_subcommand<'a, 'b>() -> App<'a, 'b> {
         // the {n} denotes a newline which will properly aligned in all help messages
         .arg_from_usage("[dir] 'A directory for your book{n}(Defaults to Current Directory \
                          when omitted)'")
-        .arg_from_usage("--theme 'Copies the default theme into your source folder'")
-        .arg_from_usage("--force 'skip confirmation prompts'")
+        .arg_from_usage("--theme 'Copies the default theme into a theme directory, so you \
+                         can customize it'")
+        .arg_from_usage("--force 'Overwrites files which already exist instead of \
+                         skipping them'")
 }
 
 // Init command implementation
@@ -21,45 +21,13 @@ pub fn execute(args: &ArgMatches) -> Result<()> {
     let book_dir = get_book_dir(args);
     let mut builder = MDBook::init(&book_dir);
 
-    // If flag `--theme` is present, copy theme to src
-    if args.is_present("theme") {
-        // Skip this if `--force` is present
-        if !args.is_present("force") {
-            // Print warning
-            println!();
-            println!(
-                "Copying the default theme to {}",
-                builder.config().book.src.display()
-            );
-            println!("This could potentially overwrite files already present in that directory.");
-            print!("\nAre you sure you want to continue? (y/n) ");
-
-            // Read answer from user and exit if it's not 'yes'
-            if confirm() {
-                builder.copy_theme(true);
-            }
-        }
-    }
-
-    println!("\nDo you want a .gitignore to be created? (y/n)");
-
-    if confirm() {
-        builder.create_gitignore(true);
-    }
+    builder
+        .create_gitignore(true)
+        .copy_theme(args.is_present("theme"))
+        .force(args.is_present("force"));
 
     builder.build()?;
-    println!("\nAll done, no errors...");
+    info!("All done, no errors...");
 
     Ok(())
 }
-
-// Simple function that user comfirmation
-fn confirm() -> bool {
-    io::stdout().flush().unwrap();
-    let mut s = String::new();
-    io::stdin().read_line(&mut s).ok();
-    match &*s.trim() {
-        "Y" | "y" | "yes" | "Yes" => true,
-        _ => false,
-    }
-}
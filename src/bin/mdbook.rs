@@ -7,15 +7,19 @@ extern crate error_chain;
 extern crate log;
 extern crate mdbook;
 extern crate open;
+#[cfg(test)]
+extern crate tempdir;
 
 use std::env;
 use std::ffi::OsStr;
 use std::path::{Path, PathBuf};
 use std::io::Write;
-use clap::{App, AppSettings, ArgMatches};
+use clap::{App, AppSettings, Arg, ArgMatches};
 use chrono::Local;
 use log::LevelFilter;
 use env_logger::Builder;
+use mdbook::MDBook;
+use mdbook::config::HooksConfig;
 use mdbook::utils;
 
 pub mod build;
@@ -42,6 +46,9 @@ fn main() {
                              try `mdbook <command> --help`\n\
                              Source code for mdbook available \
                              at: https://github.com/rust-lang-nursery/mdBook")
+                .arg(Arg::from_usage("--error-format [FORMAT] 'How errors are reported, human (the default) or json'")
+                         .possible_values(&["human", "json"])
+                         .global(true))
                 .subcommand(init::make_subcommand())
                 .subcommand(build::make_subcommand())
                 .subcommand(test::make_subcommand());
@@ -51,8 +58,10 @@ fn main() {
     #[cfg(feature = "serve")]
     let app = app.subcommand(serve::make_subcommand());
 
+    let matches = app.get_matches();
+
     // Check which subcomamnd the user ran...
-    let res = match app.get_matches().subcommand() {
+    let res = match matches.subcommand() {
         ("init", Some(sub_matches)) => init::execute(sub_matches),
         ("build", Some(sub_matches)) => build::execute(sub_matches),
         #[cfg(feature = "watch")]
@@ -64,12 +73,24 @@ fn main() {
     };
 
     if let Err(e) = res {
-        utils::log_backtrace(&e);
+        if error_format(&matches) == "json" {
+            utils::print_error_json(&e);
+        } else {
+            utils::log_backtrace(&e);
+        }
 
-        ::std::process::exit(101);
+        ::std::process::exit(e.report().category.exit_code());
     }
 }
 
+/// The `--error-format`, which can be given before or after the subcommand.
+fn error_format<'a>(matches: &'a ArgMatches) -> &'a str {
+    matches
+        .value_of("error-format")
+        .or_else(|| matches.subcommand().1.and_then(|sub| sub.value_of("error-format")))
+        .unwrap_or("human")
+}
+
 fn init_logger() {
     let mut builder = Builder::new();
 
@@ -105,6 +126,19 @@ fn get_book_dir(args: &ArgMatches) -> PathBuf {
     }
 }
 
+/// Remove any `[build.hooks]` which shouldn't be run, either because the user
+/// passed `--locked` or because this is a rebuild and the `pre` hooks should
+/// only run once.
+fn configure_hooks(book: &mut MDBook, locked: bool, is_rebuild: bool) {
+    let hooks = &mut book.config.build.hooks;
+
+    if locked {
+        *hooks = HooksConfig::default();
+    } else if is_rebuild && hooks.pre_once {
+        hooks.pre.clear();
+    }
+}
+
 fn open<P: AsRef<OsStr>>(path: P) {
     if let Err(e) = open::that(path) {
         error!("Error opening web browser: {}", e);
@@ -3,17 +3,33 @@ extern crate staticfile;
 extern crate ws;
 
 use std;
+#[cfg(feature = "watch")]
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::Read;
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
 use self::iron::{status, AfterMiddleware, Chain, Iron, IronError, IronResult, Request, Response,
                  Set};
-use clap::{App, ArgMatches, SubCommand};
+use self::iron::headers::ContentType;
+use self::iron::mime::Mime;
+use self::iron::modifiers::Header;
+use clap::{App, Arg, ArgMatches, SubCommand};
 use mdbook::MDBook;
 use mdbook::utils;
 use mdbook::errors::*;
-use {get_book_dir, open};
+use {configure_hooks, get_book_dir, open};
 #[cfg(feature = "watch")]
 use watch;
 
-struct ErrorRecover;
+/// Serves the build directory's `404.html` (if there is one) for anything
+/// which couldn't be found.
+struct ErrorRecover {
+    build_dir: PathBuf,
+}
+
+/// Makes sure the common file types are sent with the right `Content-Type`.
+struct ContentTypes;
 
 // Create clap subcommand arguments
 pub fn make_subcommand<'a, 'b>() -> App<'a, 'b> {
@@ -27,14 +43,22 @@ pub fn make_subcommand<'a, 'b>() -> App<'a, 'b> {
             "-w, --websocket-port=[ws-port] 'Use another port for the websocket connection \
              (livereload){n}(Defaults to 3001)'",
         )
-        .arg_from_usage(
-            "-i, --interface=[interface] 'Interface to listen on{n}(Defaults to localhost)'",
+        .arg(
+            Arg::from_usage(
+                "-i, --hostname=[hostname] 'Hostname to listen on for HTTP connections{n}\
+                 (Defaults to localhost)'",
+            ).alias("interface"),
         )
         .arg_from_usage(
             "-a, --address=[address] 'Address that the browser can reach the websocket server \
-             from{n}(Defaults to the interface address)'",
+             from{n}(Defaults to the hostname)'",
         )
         .arg_from_usage("-o, --open 'Open the book server in a web browser'")
+        .arg_from_usage("--locked 'Skip running the commands in [build.hooks]'")
+        .arg_from_usage(
+            "--delay=[ms] 'How long to wait for more changes before rebuilding{n}\
+             (Defaults to 300)'",
+        )
 }
 
 // Watch command implementation
@@ -44,24 +68,34 @@ pub fn execute(args: &ArgMatches) -> Result<()> {
 
     let port = args.value_of("port").unwrap_or("3000");
     let ws_port = args.value_of("websocket-port").unwrap_or("3001");
-    let interface = args.value_of("interface").unwrap_or("localhost");
-    let public_address = args.value_of("address").unwrap_or(interface);
+    let hostname = args.value_of("hostname").unwrap_or("localhost");
+    let public_address = args.value_of("address").unwrap_or(hostname);
     let open_browser = args.is_present("open");
+    let locked = args.is_present("locked");
+
+    let address = format!("{}:{}", hostname, port);
+    let ws_address = format!("{}:{}", hostname, ws_port);
 
-    let address = format!("{}:{}", interface, port);
-    let ws_address = format!("{}:{}", interface, ws_port);
+    #[cfg(feature = "watch")]
+    let delay = watch::rebuild_delay(args)?;
 
     let livereload_url = format!("ws://{}:{}", public_address, ws_port);
     book.config
         .set("output.html.livereload-url", &livereload_url)?;
+    configure_hooks(&mut book, locked, false);
 
     book.build()?;
 
-    let mut chain = Chain::new(staticfile::Static::new(book.build_dir_for("html")));
-    chain.link_after(ErrorRecover);
+    // check up front, the websocket server is started on another thread
+    ensure_available(&ws_address)?;
+
+    let build_dir = book.build_dir_for("html");
+    let mut chain = Chain::new(staticfile::Static::new(&build_dir));
+    chain.link_after(ContentTypes);
+    chain.link_after(ErrorRecover { build_dir: build_dir });
     let _iron = Iron::new(chain)
         .http(&*address)
-        .chain_err(|| "Unable to launch the server")?;
+        .chain_err(|| format!("Unable to launch the server on {}, is the port already in use?", address))?;
 
     let ws_server =
         ws::WebSocket::new(|_| |_| Ok(())).chain_err(|| "Unable to start the websocket")?;
@@ -69,7 +103,9 @@ pub fn execute(args: &ArgMatches) -> Result<()> {
     let broadcaster = ws_server.broadcaster();
 
     std::thread::spawn(move || {
-        ws_server.listen(&*ws_address).unwrap();
+        if let Err(e) = ws_server.listen(&*ws_address) {
+            error!("The websocket server stopped, live reloading won't work: {}", e);
+        }
     });
 
     let serving_url = format!("http://{}", address);
@@ -80,38 +116,121 @@ pub fn execute(args: &ArgMatches) -> Result<()> {
     }
 
     #[cfg(feature = "watch")]
-    watch::trigger_on_change(&mut book, move |path, book_dir| {
-        info!("File changed: {:?}", path);
-        info!("Building book...");
-
-        // FIXME: This area is really ugly because we need to re-set livereload :(
+    {
+        let config = RefCell::new(book.config.clone());
 
-        let livereload_url = livereload_url.clone();
+        watch::trigger_on_change(&book, delay, move |paths, book_dir| {
+            info!("Files changed: {:?}", paths);
+            info!("Building book...");
 
-        let result = MDBook::load(&book_dir)
-            .and_then(move |mut b| {
+            // the livereload url is lost if book.toml changed and the config is
+            // read again
+            let result = watch::reload(book_dir, &config.borrow(), paths).and_then(|mut b| {
                 b.config.set("output.html.livereload-url", &livereload_url)?;
+                configure_hooks(&mut b, locked, true);
+                b.build()?;
                 Ok(b)
-            })
-            .and_then(|b| b.build());
-
-        if let Err(e) = result {
-            error!("Unable to load the book");
-            utils::log_backtrace(&e);
-        } else {
-            let _ = broadcaster.send("reload");
-        }
-    });
+            });
+
+            match result {
+                Ok(b) => {
+                    *config.borrow_mut() = b.config;
+                    let _ = broadcaster.send("reload");
+                }
+                Err(e) => {
+                    error!("Unable to load the book");
+                    utils::log_backtrace(&e);
+                }
+            }
+        });
+    }
 
     Ok(())
 }
 
+/// Make sure nothing is listening on `address` yet, so we can fail with a
+/// nice error instead of a panic.
+fn ensure_available(address: &str) -> Result<()> {
+    TcpListener::bind(address)
+        .map(|_| ())
+        .chain_err(|| format!("Unable to listen on {}, is the port already in use?", address))
+}
+
+/// Guess a file's content type from the extension on the end of a URL's path.
+fn content_type(path: &[&str]) -> Option<&'static str> {
+    let filename = path.last().cloned().unwrap_or("");
+
+    // directories are served their index.html
+    if filename.is_empty() {
+        return Some("text/html; charset=utf-8");
+    }
+
+    let ext = match Path::new(filename).extension().and_then(|ext| ext.to_str()) {
+        Some(ext) => ext,
+        None => return None,
+    };
+
+    match ext {
+        "html" => Some("text/html; charset=utf-8"),
+        "css" => Some("text/css; charset=utf-8"),
+        "js" => Some("application/javascript; charset=utf-8"),
+        "svg" => Some("image/svg+xml"),
+        "woff2" => Some("font/woff2"),
+        _ => None,
+    }
+}
+
+impl AfterMiddleware for ContentTypes {
+    fn after(&self, req: &mut Request, mut res: Response) -> IronResult<Response> {
+        if let Some(mime) = content_type(&req.url.path()).and_then(|m| m.parse::<Mime>().ok()) {
+            res.headers.set(ContentType(mime));
+        }
+
+        Ok(res)
+    }
+}
+
+impl ErrorRecover {
+    fn not_found_page(&self) -> Option<Vec<u8>> {
+        let mut content = Vec::new();
+
+        File::open(self.build_dir.join("404.html"))
+            .and_then(|mut f| f.read_to_end(&mut content))
+            .ok()
+            .map(|_| content)
+    }
+}
+
 impl AfterMiddleware for ErrorRecover {
     fn catch(&self, _: &mut Request, err: IronError) -> IronResult<Response> {
         match err.response.status {
             // each error will result in 404 response
-            Some(_) => Ok(err.response.set(status::NotFound)),
+            Some(_) => match self.not_found_page() {
+                Some(page) => {
+                    Ok(Response::with((status::NotFound, Header(ContentType::html()), page)))
+                },
+                None => Ok(err.response.set(status::NotFound)),
+            },
             _ => Err(err),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_hostname_has_a_short_flag_and_the_old_name() {
+        let inputs = vec![
+            vec!["serve", "-i", "127.0.0.1"],
+            vec!["serve", "--hostname", "127.0.0.1"],
+            vec!["serve", "--interface", "127.0.0.1"],
+        ];
+
+        for args in inputs {
+            let matches = make_subcommand().get_matches_from_safe(args.clone()).unwrap();
+            assert_eq!(matches.value_of("hostname"), Some("127.0.0.1"), "{:?}", args);
+        }
+    }
+}
@@ -1,14 +1,23 @@
+extern crate gitignore;
 extern crate notify;
 
-use std::path::Path;
+use std::cell::{Cell, RefCell};
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
 use self::notify::Watcher;
-use std::time::Duration;
-use std::sync::mpsc::channel;
+use std::time::{Duration, Instant};
+use std::sync::mpsc::{channel, Receiver};
 use clap::{App, ArgMatches, SubCommand};
 use mdbook::MDBook;
+use mdbook::config::Config;
 use mdbook::utils;
-use mdbook::errors::Result;
-use {get_book_dir, open};
+use mdbook::errors::*;
+use {configure_hooks, get_book_dir, open};
+
+/// How long to keep collecting changes after the first one before
+/// rebuilding, so a burst of changes (e.g. a `git checkout`) only causes a
+/// single rebuild.
+const DEFAULT_DELAY_MS: u64 = 300;
 
 // Create clap subcommand arguments
 pub fn make_subcommand<'a, 'b>() -> App<'a, 'b> {
@@ -18,43 +27,96 @@ pub fn make_subcommand<'a, 'b>() -> App<'a, 'b> {
         .arg_from_usage(
             "[dir] 'A directory for your book{n}(Defaults to Current Directory when omitted)'",
         )
+        .arg_from_usage("--locked 'Skip running the commands in [build.hooks]'")
+        .arg_from_usage(
+            "--delay=[ms] 'How long to wait for more changes before rebuilding{n}\
+             (Defaults to 300)'",
+        )
 }
 
 // Watch command implementation
 pub fn execute(args: &ArgMatches) -> Result<()> {
     let book_dir = get_book_dir(args);
-    let book = MDBook::load(&book_dir)?;
+    let locked = args.is_present("locked");
+    let delay = rebuild_delay(args)?;
+    let mut book = MDBook::load(&book_dir)?;
+    configure_hooks(&mut book, locked, false);
+
+    let built_once = Cell::new(false);
 
     if args.is_present("open") {
         book.build()?;
+        built_once.set(true);
         open(book.build_dir_for("html").join("index.html"));
     }
 
-    trigger_on_change(&book, |path, book_dir| {
-        info!("File changed: {:?}\nBuilding book...\n", path);
-        let result = MDBook::load(&book_dir).and_then(|b| b.build());
+    let config = RefCell::new(book.config.clone());
+
+    trigger_on_change(&book, delay, |paths, book_dir| {
+        info!("Files changed: {:?}\nBuilding book...\n", paths);
+        let result = reload(book_dir, &config.borrow(), paths).and_then(|mut b| {
+            configure_hooks(&mut b, locked, built_once.get());
+            b.build()?;
+            Ok(b)
+        });
+        built_once.set(true);
 
-        if let Err(e) = result {
-            error!("Unable to build the book");
-            utils::log_backtrace(&e);
+        match result {
+            Ok(b) => *config.borrow_mut() = b.config,
+            Err(e) => {
+                error!("Unable to build the book");
+                utils::log_backtrace(&e);
+            }
         }
     });
 
     Ok(())
 }
 
-/// Calls the closure when a book source file is changed, blocking indefinitely.
-pub fn trigger_on_change<F>(book: &MDBook, closure: F)
+/// Get the `--delay` to wait for more changes before rebuilding.
+pub fn rebuild_delay(args: &ArgMatches) -> Result<Duration> {
+    match args.value_of("delay") {
+        Some(ms) => ms.parse()
+            .map(Duration::from_millis)
+            .chain_err(|| format!("Invalid delay \"{}\", expected a number of milliseconds", ms)),
+        None => Ok(Duration::from_millis(DEFAULT_DELAY_MS)),
+    }
+}
+
+/// Load the book again after some of its files changed.
+///
+/// `book.toml` is only read again when it was one of the changed files,
+/// otherwise the previous `config` is reused. The summary and chapters are
+/// always read again.
+pub fn reload(book_dir: &Path, config: &Config, changed: &[PathBuf]) -> Result<MDBook> {
+    if changed
+        .iter()
+        .any(|p| p.file_name() == Some(OsStr::new("book.toml")))
+    {
+        debug!("book.toml changed, reloading the configuration");
+        MDBook::load(book_dir)
+    } else {
+        MDBook::load_with_config(book_dir, config.clone())
+    }
+}
+
+/// Calls the closure with everything which changed in a burst of changes to
+/// the book's source files, blocking indefinitely.
+///
+/// Changes to the build directory and anything matching the book's
+/// `.gitignore` are ignored.
+pub fn trigger_on_change<F>(book: &MDBook, delay: Duration, closure: F)
 where
-    F: Fn(&Path, &Path),
+    F: Fn(&[PathBuf], &Path),
 {
     use self::notify::RecursiveMode::*;
-    use self::notify::DebouncedEvent::*;
 
-    // Create a channel to receive the events.
+    // Create a channel to receive the events. The raw events are used because
+    // `next_changes()` does the debouncing, and notify's debouncer would add
+    // its own delay on top.
     let (tx, rx) = channel();
 
-    let mut watcher = match notify::watcher(tx, Duration::from_secs(1)) {
+    let mut watcher = match notify::raw_watcher(tx) {
         Ok(w) => w,
         Err(e) => {
             error!("Error while trying to watch the files:\n\n\t{:?}", e);
@@ -73,15 +135,290 @@ where
     // Add the book.toml file to the watcher if it exists
     let _ = watcher.watch(book.root.join("book.toml"), NonRecursive);
 
+    let build_dir = book.root.join(&book.config.build.build_dir);
+    let gitignore_path = book.root.join(".gitignore");
+
     info!("Listening for changes...");
 
-    for event in rx.iter() {
+    while let Some(paths) = next_changes(&rx, delay) {
+        let gitignore = if gitignore_path.exists() {
+            match GitIgnore::load(&gitignore_path, &book.root) {
+                Ok(gitignore) => Some(gitignore),
+                Err(e) => {
+                    warn!("Unable to read {}: {}", gitignore_path.display(), e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        let paths = filter_ignored(paths, &build_dir, gitignore.as_ref());
+
+        if !paths.is_empty() {
+            closure(&paths, &book.root);
+        }
+    }
+}
+
+/// Wait for something to change, then keep collecting changes until `delay`
+/// has passed. Returns `None` once the watcher goes away.
+fn next_changes(rx: &Receiver<notify::RawEvent>, delay: Duration) -> Option<Vec<PathBuf>> {
+    let mut events = match rx.recv() {
+        Ok(event) => vec![event],
+        Err(_) => return None,
+    };
+
+    let deadline = Instant::now() + delay;
+    loop {
+        let now = Instant::now();
+        if now >= deadline {
+            break;
+        }
+
+        match rx.recv_timeout(deadline - now) {
+            Ok(event) => events.push(event),
+            Err(_) => break,
+        }
+    }
+
+    Some(changed_paths(events))
+}
+
+/// The paths which were created, written to, removed or renamed, without
+/// duplicates.
+fn changed_paths(events: Vec<notify::RawEvent>) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+
+    for event in events {
         debug!("Received filesystem event: {:?}", event);
-        match event {
-            Create(path) | Write(path) | Remove(path) | Rename(_, path) => {
-                closure(&path, &book.root);
+
+        let changed = match event.op {
+            // permission changes don't change what gets rendered
+            Ok(op) => !(op - notify::op::CHMOD).is_empty(),
+            Err(e) => {
+                warn!("Error while watching the files: {:?}", e);
+                false
+            }
+        };
+
+        if let Some(path) = event.path {
+            if changed && !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+    }
+
+    paths
+}
+
+/// Remove the paths which shouldn't trigger a rebuild, i.e. anything inside
+/// the build directory (otherwise every build would trigger another one) or
+/// ignored by the book's `.gitignore` (editor swap files and the like).
+fn filter_ignored(
+    paths: Vec<PathBuf>,
+    build_dir: &Path,
+    gitignore: Option<&GitIgnore>,
+) -> Vec<PathBuf> {
+    paths
+        .into_iter()
+        .filter(|path| !path.starts_with(build_dir))
+        .filter(|path| gitignore.map_or(true, |gitignore| !gitignore.is_ignored(path)))
+        .collect()
+}
+
+/// The patterns in the book's `.gitignore`.
+///
+/// `gitignore::File::is_excluded()` only knows about files which currently
+/// exist (it walks the whole directory to find them), so it can't be used for
+/// files which were just removed or renamed, and is slow for big books.
+/// Instead the paths are matched against the patterns directly.
+struct GitIgnore<'a> {
+    root: &'a Path,
+    patterns: Vec<gitignore::Pattern<'a>>,
+}
+
+impl<'a> GitIgnore<'a> {
+    /// Read a `.gitignore` whose patterns are relative to `root`.
+    fn load(path: &Path, root: &'a Path) -> Result<GitIgnore<'a>> {
+        let content = utils::fs::file_to_string(path)?;
+        let patterns = content
+            .lines()
+            .map(|line| line.trim_right())
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(|line| match gitignore::Pattern::new(line, root) {
+                Ok(pattern) => Some(pattern),
+                Err(e) => {
+                    warn!("Ignoring the invalid .gitignore pattern \"{}\": {:?}", line, e);
+                    None
+                }
+            })
+            .collect();
+
+        Ok(GitIgnore {
+            root: root,
+            patterns: patterns,
+        })
+    }
+
+    /// Does the `.gitignore` exclude this path, or a directory it's in?
+    fn is_ignored(&self, path: &Path) -> bool {
+        if self.excludes(path, path.is_dir()) {
+            return true;
+        }
+
+        let mut dir = path.parent();
+        while let Some(d) = dir {
+            if !d.starts_with(self.root) || d == self.root {
+                break;
             }
-            _ => {}
+            if self.excludes(d, true) {
+                return true;
+            }
+            dir = d.parent();
         }
+
+        false
+    }
+
+    /// Check a single path against the patterns, where later patterns (like
+    /// `!important.swp`) override earlier ones.
+    fn excludes(&self, path: &Path, directory: bool) -> bool {
+        self.patterns.iter().fold(false, |excluded, pattern| {
+            // `Pattern::is_excluded()` inverts the result for negated
+            // patterns, but we want to know whether it matched at all
+            let matches = pattern.is_excluded(path, directory) != pattern.negation;
+
+            if matches {
+                !pattern.negation
+            } else {
+                excluded
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::io::Write;
+    use tempdir::TempDir;
+
+    fn with_gitignore<F>(contents: &str, test: F)
+    where
+        F: FnOnce(&Path, &GitIgnore),
+    {
+        let temp = TempDir::new("mdbook-watch").unwrap();
+        let gitignore_path = temp.path().join(".gitignore");
+        File::create(&gitignore_path)
+            .unwrap()
+            .write_all(contents.as_bytes())
+            .unwrap();
+
+        let gitignore = GitIgnore::load(&gitignore_path, temp.path()).unwrap();
+        test(temp.path(), &gitignore);
+    }
+
+    #[test]
+    fn chapter_changes_trigger_a_rebuild() {
+        let root = Path::new("/book");
+        let paths = vec![root.join("src/chapter_1.md"), root.join("book.toml")];
+
+        let got = filter_ignored(paths.clone(), &root.join("book"), None);
+
+        assert_eq!(got, paths);
+    }
+
+    #[test]
+    fn permission_changes_and_duplicates_are_dropped() {
+        let event = |path: &str, op| notify::RawEvent {
+            path: Some(PathBuf::from(path)),
+            op: Ok(op),
+            cookie: None,
+        };
+        let events = vec![
+            event("/book/src/a.md", notify::op::WRITE),
+            event("/book/src/b.md", notify::op::CHMOD),
+            event("/book/src/a.md", notify::op::WRITE | notify::op::CHMOD),
+            event("/book/src/c.md", notify::op::CREATE),
+        ];
+
+        let got = changed_paths(events);
+
+        assert_eq!(got, vec![PathBuf::from("/book/src/a.md"), PathBuf::from("/book/src/c.md")]);
+    }
+
+    #[test]
+    fn changes_in_the_build_directory_are_ignored() {
+        let root = Path::new("/book");
+        let paths = vec![
+            root.join("book/index.html"),
+            root.join("book/nested/chapter.html"),
+            root.join("src/chapter_1.md"),
+        ];
+
+        let got = filter_ignored(paths, &root.join("book"), None);
+
+        assert_eq!(got, vec![root.join("src/chapter_1.md")]);
+    }
+
+    #[test]
+    fn gitignored_files_are_ignored() {
+        with_gitignore("*.swp\n*~\n", |root, gitignore| {
+            let paths = vec![
+                root.join("src/.chapter_1.md.swp"),
+                root.join("src/chapter_1.md~"),
+                root.join("src/chapter_1.md"),
+            ];
+
+            let got = filter_ignored(paths, &root.join("book"), Some(gitignore));
+
+            assert_eq!(got, vec![root.join("src/chapter_1.md")]);
+        });
+    }
+
+    #[test]
+    fn ignored_directories_and_negated_patterns() {
+        with_gitignore("# drafts\ndrafts/\n*.bak\n!keep.bak\n", |root, gitignore| {
+            ::std::fs::create_dir_all(root.join("src/drafts")).unwrap();
+            let paths = vec![
+                root.join("src/drafts/chapter.md"),
+                root.join("src/old.bak"),
+                root.join("src/keep.bak"),
+                root.join("src/drafts.md"),
+            ];
+
+            let got = filter_ignored(paths, &root.join("book"), Some(gitignore));
+
+            assert_eq!(got, vec![root.join("src/keep.bak"), root.join("src/drafts.md")]);
+        });
+    }
+
+    #[test]
+    fn only_book_toml_changes_reload_the_config() {
+        let temp = TempDir::new("mdbook-watch").unwrap();
+        let root = temp.path();
+        ::std::fs::create_dir(root.join("src")).unwrap();
+        File::create(root.join("src/SUMMARY.md"))
+            .unwrap()
+            .write_all(b"# Summary\n\n- [Chapter 1](./chapter_1.md)\n")
+            .unwrap();
+        File::create(root.join("src/chapter_1.md")).unwrap();
+        File::create(root.join("book.toml"))
+            .unwrap()
+            .write_all(b"[book]\ntitle = \"On Disk\"\n")
+            .unwrap();
+
+        let mut previous = Config::default();
+        previous.book.title = Some(String::from("Previous"));
+
+        let chapter_changed = vec![root.join("src/chapter_1.md")];
+        let got = reload(root, &previous, &chapter_changed).unwrap();
+        assert_eq!(got.config.book.title, Some(String::from("Previous")));
+
+        let config_changed = vec![root.join("book.toml")];
+        let got = reload(root, &previous, &config_changed).unwrap();
+        assert_eq!(got.config.book.title, Some(String::from("On Disk")));
     }
 }
@@ -4,9 +4,12 @@ use std::collections::VecDeque;
 use std::fs::{self, File};
 use std::io::{Read, Write};
 
-use super::summary::{parse_summary, Link, SectionNumber, Summary, SummaryItem};
+use super::summary::{parse_summary_with_max_depth, Link, SectionNumber, Summary, SummaryItem};
 use config::BuildConfig;
+use utils::string::LineMap;
 use errors::*;
+use toml;
+use toml::value::Table;
 
 /// Load a book into memory from its `src/` directory.
 pub fn load_book<P: AsRef<Path>>(src_dir: P, cfg: &BuildConfig) -> Result<Book> {
@@ -18,7 +21,8 @@ pub fn load_book<P: AsRef<Path>>(src_dir: P, cfg: &BuildConfig) -> Result<Book>
         .chain_err(|| "Couldn't open SUMMARY.md")?
         .read_to_string(&mut summary_content)?;
 
-    let summary = parse_summary(&summary_content).chain_err(|| "Summary parsing failed")?;
+    let summary = parse_summary_with_max_depth(&summary_content, cfg.max_depth)
+        .chain_err(|| "Summary parsing failed")?;
 
     if cfg.create_missing {
         create_missing(&src_dir, &summary).chain_err(|| "Unable to create missing chapters")?;
@@ -39,17 +43,19 @@ fn create_missing(src_dir: &Path, summary: &Summary) -> Result<()> {
         let next = items.pop().expect("already checked");
 
         if let SummaryItem::Link(ref link) = *next {
-            let filename = src_dir.join(&link.location);
-            if !filename.exists() {
-                if let Some(parent) = filename.parent() {
-                    if !parent.exists() {
-                        fs::create_dir_all(parent)?;
+            if let Some(ref location) = link.location {
+                let filename = src_dir.join(location);
+                if !filename.exists() {
+                    if let Some(parent) = filename.parent() {
+                        if !parent.exists() {
+                            fs::create_dir_all(parent)?;
+                        }
                     }
-                }
-                debug!("Creating missing file {}", filename.display());
+                    debug!("Creating missing file {}", filename.display());
 
-                let mut f = File::create(&filename)?;
-                writeln!(f, "# {}", link.name)?;
+                    let mut f = File::create(&filename)?;
+                    writeln!(f, "# {}", link.name)?;
+                }
             }
 
             items.extend(&link.nested_items);
@@ -87,6 +93,25 @@ impl Book {
         }
     }
 
+    /// The items at the top level of the book (the ones which aren't nested
+    /// inside a chapter), in order.
+    pub fn sections(&self) -> &[BookItem] {
+        &self.sections
+    }
+
+    /// Get every chapter which has a page of its own, in the order a reader
+    /// goes through them (a depth-first walk of `SUMMARY.md`, including any
+    /// prefix and suffix chapters). Drafts, separators and part titles are
+    /// skipped.
+    pub fn reading_order(&self) -> Vec<&Chapter> {
+        self.iter()
+            .filter_map(|item| match *item {
+                BookItem::Chapter(ref ch) if !ch.is_draft_chapter() => Some(ch),
+                _ => None,
+            })
+            .collect()
+    }
+
     /// Recursively apply a closure to each item in the book, allowing you to
     /// mutate them.
     ///
@@ -130,6 +155,8 @@ pub enum BookItem {
     Chapter(Chapter),
     /// A section separator.
     Separator,
+    /// A title for the following part of the book.
+    PartTitle(String),
 }
 
 impl From<Chapter> for BookItem {
@@ -150,8 +177,18 @@ pub struct Chapter {
     pub number: Option<SectionNumber>,
     /// Nested items.
     pub sub_items: Vec<BookItem>,
-    /// The chapter's location, relative to the `SUMMARY.md` file.
-    pub path: PathBuf,
+    /// The chapter's location, relative to the `SUMMARY.md` file. Draft
+    /// chapters don't have one.
+    pub path: Option<PathBuf>,
+    /// Anything set in the TOML front matter (between `+++` lines) at the
+    /// top of the chapter's file.
+    #[serde(default)]
+    pub metadata: Table,
+    /// Which line of the chapter's file each line of `content` came from,
+    /// when they aren't the same (because of front matter or an
+    /// `{{#include}}`, say).
+    #[serde(default, skip_serializing_if = "LineMap::is_empty")]
+    pub line_map: LineMap,
 }
 
 impl Chapter {
@@ -160,10 +197,24 @@ impl Chapter {
         Chapter {
             name: name.to_string(),
             content: content,
-            path: path.into(),
+            path: Some(path.into()),
             ..Default::default()
         }
     }
+
+    /// Create a new draft chapter, which hasn't been written yet and so has
+    /// no contents or location on disk.
+    pub fn new_draft(name: &str) -> Chapter {
+        Chapter {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Is this a draft chapter (i.e. one without a source file)?
+    pub fn is_draft_chapter(&self) -> bool {
+        self.path.is_none()
+    }
 }
 
 /// Use the provided `Summary` to load a `Book` from disk.
@@ -181,56 +232,134 @@ fn load_book_from_disk<P: AsRef<Path>>(summary: &Summary, src_dir: P) -> Result<
     let summary_items = prefix.chain(numbered).chain(suffix);
 
     let mut chapters = Vec::new();
+    let mut ancestors = Vec::new();
 
     for summary_item in summary_items {
-        let chapter = load_summary_item(summary_item, src_dir)?;
+        let chapter = load_summary_item(summary_item, src_dir, &mut ancestors)?;
         chapters.push(chapter);
     }
 
     Ok(Book { sections: chapters })
 }
 
-fn load_summary_item<P: AsRef<Path>>(item: &SummaryItem, src_dir: P) -> Result<BookItem> {
+/// `ancestors` are the canonical paths of the chapters the item is nested
+/// in, so a chapter which (e.g. through a symlinked directory) ends up
+/// nested inside itself is caught.
+fn load_summary_item<P: AsRef<Path>>(
+    item: &SummaryItem,
+    src_dir: P,
+    ancestors: &mut Vec<PathBuf>,
+) -> Result<BookItem> {
     match *item {
         SummaryItem::Separator => Ok(BookItem::Separator),
-        SummaryItem::Link(ref link) => load_chapter(link, src_dir).map(|c| BookItem::Chapter(c)),
+        SummaryItem::PartTitle(ref title) => Ok(BookItem::PartTitle(title.clone())),
+        SummaryItem::Link(ref link) => {
+            load_chapter(link, src_dir, ancestors).map(|c| BookItem::Chapter(c))
+        }
     }
 }
 
-fn load_chapter<P: AsRef<Path>>(link: &Link, src_dir: P) -> Result<Chapter> {
-    debug!("Loading {} ({})", link.name, link.location.display());
+fn load_chapter<P: AsRef<Path>>(
+    link: &Link,
+    src_dir: P,
+    ancestors: &mut Vec<PathBuf>,
+) -> Result<Chapter> {
+    debug!("Loading {} ({:?})", link.name, link.location);
     let src_dir = src_dir.as_ref();
 
-    let location = if link.location.is_absolute() {
-        link.location.clone()
-    } else {
-        src_dir.join(&link.location)
-    };
-
-    let mut f = File::open(&location)
-        .chain_err(|| format!("Chapter file not found, {}", link.location.display()))?;
-
-    let mut content = String::new();
-    f.read_to_string(&mut content)
-        .chain_err(|| format!("Unable to read \"{}\" ({})", link.name, location.display()))?;
+    let mut canonical = None;
+
+    let mut ch = match link.location {
+        Some(ref link_location) => {
+            let location = if link_location.is_absolute() {
+                link_location.clone()
+            } else {
+                src_dir.join(link_location)
+            };
+
+            let mut f = File::open(&location)
+                .chain_err(|| format!("Chapter file not found, {}", link_location.display()))?;
+
+            let mut content = String::new();
+            f.read_to_string(&mut content).chain_err(|| {
+                format!("Unable to read \"{}\" ({})", link.name, location.display())
+            })?;
+
+            let (metadata, rest) = split_front_matter(&location, &content)?;
+            let front_matter_lines = content[..content.len() - rest.len()].matches('\n').count();
+            let content = rest;
+
+            let resolved = location.canonicalize().chain_err(|| {
+                format!("Unable to resolve \"{}\" ({})", link.name, link_location.display())
+            })?;
+            if ancestors.contains(&resolved) {
+                bail!(
+                    "\"{}\" ({}) is nested inside itself",
+                    link.name,
+                    link_location.display()
+                );
+            }
+            canonical = Some(resolved);
 
-    let stripped = location
-        .strip_prefix(&src_dir)
-        .expect("Chapters are always inside a book");
+            let stripped = location
+                .strip_prefix(&src_dir)
+                .expect("Chapters are always inside a book");
 
-    let mut ch = Chapter::new(&link.name, content, stripped);
+            let mut ch = Chapter::new(&link.name, content.to_string(), stripped);
+            ch.metadata = metadata;
+            ch.line_map = LineMap::skipping(front_matter_lines, content.lines().count());
+            ch
+        }
+        None => Chapter::new_draft(&link.name),
+    };
     ch.number = link.number.clone();
 
+    let is_ancestor = canonical.is_some();
+    ancestors.extend(canonical);
+
     let sub_items = link.nested_items
         .iter()
-        .map(|i| load_summary_item(i, src_dir))
+        .map(|i| load_summary_item(i, src_dir, ancestors))
         .collect::<Result<Vec<_>>>()?;
 
+    if is_ancestor {
+        ancestors.pop();
+    }
     ch.sub_items = sub_items;
 
     Ok(ch)
 }
 
+/// Separate the TOML front matter (between a `+++` line at the very top of
+/// the file and the next `+++` line) from the rest of a chapter.
+fn split_front_matter<'a>(location: &Path, content: &'a str) -> Result<(Table, &'a str)> {
+    let mut lines = content.split('\n');
+    if lines.next().map(|line| line.trim_right()) != Some("+++") {
+        return Ok((Table::new(), content));
+    }
+
+    let start = content.find('\n').map_or(content.len(), |ix| ix + 1);
+    let mut end = start;
+    for line in lines {
+        if line.trim_right() == "+++" {
+            let front_matter = &content[start..end];
+            let rest = &content[(end + line.len() + 1).min(content.len())..];
+
+            let metadata = toml::from_str(front_matter).map_err(|e| {
+                ErrorKind::InvalidFrontMatter(location.to_path_buf(), e.to_string())
+            })?;
+            return Ok((metadata, rest));
+        }
+
+        end += line.len() + 1;
+    }
+
+    bail!(ErrorKind::InvalidFrontMatter(
+        location.to_path_buf(),
+        String::from("the \"+++\" on the first line is never closed"),
+    ))
+}
+
 /// A depth-first iterator over the items in a book.
 ///
 /// # Note
@@ -326,7 +455,7 @@ And here is some \
         let (link, temp_dir) = dummy_link();
         let should_be = Chapter::new("Chapter 1", DUMMY_SRC.to_string(), "chapter_1.md");
 
-        let got = load_chapter(&link, temp_dir.path()).unwrap();
+        let got = load_chapter(&link, temp_dir.path(), &mut Vec::new()).unwrap();
         assert_eq!(got, should_be);
     }
 
@@ -334,10 +463,71 @@ And here is some \
     fn cant_load_a_nonexistent_chapter() {
         let link = Link::new("Chapter 1", "/foo/bar/baz.md");
 
-        let got = load_chapter(&link, "");
+        let got = load_chapter(&link, "", &mut Vec::new());
         assert!(got.is_err());
     }
 
+    #[test]
+    fn front_matter_is_split_off_into_the_metadata() {
+        let temp = TempDir::new("book").unwrap();
+        let src = "+++\ntitle = \"A Better Title\"\nauthors = [\"Jane\"]\n+++\n# Chapter 1\n";
+        File::create(temp.path().join("chapter_1.md"))
+            .unwrap()
+            .write_all(src.as_bytes())
+            .unwrap();
+        let link = Link::new("Chapter 1", "chapter_1.md");
+
+        let got = load_chapter(&link, temp.path(), &mut Vec::new()).unwrap();
+
+        assert_eq!(got.content, "# Chapter 1\n");
+        assert_eq!(got.metadata["title"].as_str(), Some("A Better Title"));
+        assert_eq!(got.metadata["authors"].as_array().unwrap().len(), 1);
+        // the heading is on the 5th line of the file
+        assert_eq!(got.line_map.original_line(1), 5);
+    }
+
+    #[test]
+    fn front_matter_has_to_be_on_the_first_line() {
+        let src = "# Chapter 1\n\n+++\ntitle = \"Not metadata\"\n+++\n";
+
+        let (metadata, content) = split_front_matter(Path::new("chapter_1.md"), src).unwrap();
+
+        assert!(metadata.is_empty());
+        assert_eq!(content, src);
+    }
+
+    #[test]
+    fn front_matter_can_end_the_file() {
+        let (metadata, content) = split_front_matter(Path::new("chapter_1.md"),
+                                                     "+++\r\ndraft = true\r\n+++")
+            .unwrap();
+
+        assert_eq!(metadata["draft"].as_bool(), Some(true));
+        assert_eq!(content, "");
+    }
+
+    #[test]
+    fn malformed_front_matter_says_which_chapter_it_is_in() {
+        let err = split_front_matter(Path::new("src/chapter_1.md"), "+++\ntitle = \n+++\n")
+            .unwrap_err();
+
+        match *err.kind() {
+            ErrorKind::InvalidFrontMatter(ref path, _) => {
+                assert_eq!(path, Path::new("src/chapter_1.md"))
+            }
+            ref other => panic!("Unexpected error: {:?}", other),
+        }
+        assert!(err.to_string().starts_with("src/chapter_1.md: Invalid front matter"));
+    }
+
+    #[test]
+    fn unclosed_front_matter_is_an_error() {
+        let got = split_front_matter(Path::new("chapter_1.md"), "+++\ntitle = \"Oops\"\n# Chapter 1\n");
+
+        let err = got.unwrap_err();
+        assert!(err.to_string().contains("never closed"), "{}", err);
+    }
+
     #[test]
     fn load_recursive_link_with_separators() {
         let (root, temp) = nested_links();
@@ -346,22 +536,27 @@ And here is some \
             name: String::from("Nested Chapter 1"),
             content: String::from("Hello World!"),
             number: Some(SectionNumber(vec![1, 2])),
-            path: PathBuf::from("second.md"),
+            path: Some(PathBuf::from("second.md")),
             sub_items: Vec::new(),
+            metadata: Table::new(),
+            line_map: LineMap::default(),
         };
         let should_be = BookItem::Chapter(Chapter {
             name: String::from("Chapter 1"),
             content: String::from(DUMMY_SRC),
             number: None,
-            path: PathBuf::from("chapter_1.md"),
+            path: Some(PathBuf::from("chapter_1.md")),
             sub_items: vec![
                 BookItem::Chapter(nested.clone()),
                 BookItem::Separator,
                 BookItem::Chapter(nested.clone()),
             ],
+            metadata: Table::new(),
+            line_map: LineMap::default(),
         });
 
-        let got = load_summary_item(&SummaryItem::Link(root), temp.path()).unwrap();
+        let got = load_summary_item(&SummaryItem::Link(root), temp.path(), &mut Vec::new())
+            .unwrap();
         assert_eq!(got, should_be);
     }
 
@@ -377,7 +572,7 @@ And here is some \
                 BookItem::Chapter(Chapter {
                     name: String::from("Chapter 1"),
                     content: String::from(DUMMY_SRC),
-                    path: PathBuf::from("chapter_1.md"),
+                    path: Some(PathBuf::from("chapter_1.md")),
                     ..Default::default()
                 }),
             ],
@@ -388,6 +583,71 @@ And here is some \
         assert_eq!(got, should_be);
     }
 
+    #[test]
+    fn part_titles_are_carried_through_to_the_book() {
+        let (link, temp) = dummy_link();
+        let summary = Summary {
+            numbered_chapters: vec![
+                SummaryItem::PartTitle(String::from("Part One")),
+                SummaryItem::Link(link),
+                SummaryItem::PartTitle(String::from("Part Two")),
+            ],
+            ..Default::default()
+        };
+
+        let got = load_book_from_disk(&summary, temp.path()).unwrap();
+
+        assert_eq!(got.sections.len(), 3);
+        assert_eq!(got.sections[0], BookItem::PartTitle(String::from("Part One")));
+        assert_eq!(got.sections[2], BookItem::PartTitle(String::from("Part Two")));
+    }
+
+    #[test]
+    fn load_a_draft_chapter_with_nested_chapters() {
+        let (nested, temp) = dummy_link();
+        let draft = Link {
+            name: String::from("Draft"),
+            location: None,
+            number: Some(SectionNumber(vec![1])),
+            nested_items: vec![SummaryItem::Link(nested)],
+        };
+
+        let got = load_chapter(&draft, temp.path(), &mut Vec::new()).unwrap();
+
+        assert!(got.is_draft_chapter());
+        assert_eq!(got.content, "");
+        assert_eq!(got.sub_items.len(), 1);
+        match got.sub_items[0] {
+            BookItem::Chapter(ref ch) => {
+                assert_eq!(ch.path, Some(PathBuf::from("chapter_1.md")));
+                assert_eq!(ch.content, DUMMY_SRC);
+            }
+            ref other => panic!("Expected a chapter, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn drafts_are_not_created_on_disk() {
+        let temp = TempDir::new("book").unwrap();
+        let summary = Summary {
+            numbered_chapters: vec![
+                SummaryItem::Link(Link {
+                    name: String::from("Draft"),
+                    location: None,
+                    number: None,
+                    nested_items: vec![SummaryItem::Link(Link::new("Nested", "nested.md"))],
+                }),
+            ],
+            ..Default::default()
+        };
+
+        create_missing(temp.path(), &summary).unwrap();
+
+        let entries: Vec<_> = fs::read_dir(temp.path()).unwrap().collect();
+        assert_eq!(entries.len(), 1);
+        assert!(temp.path().join("nested.md").exists());
+    }
+
     #[test]
     fn book_iter_iterates_over_sequential_items() {
         let book = Book {
@@ -416,7 +676,7 @@ And here is some \
                     name: String::from("Chapter 1"),
                     content: String::from(DUMMY_SRC),
                     number: None,
-                    path: PathBuf::from("Chapter_1/index.md"),
+                    path: Some(PathBuf::from("Chapter_1/index.md")),
                     sub_items: vec![
                         BookItem::Chapter(Chapter::new(
                             "Hello World",
@@ -430,6 +690,8 @@ And here is some \
                             "Chapter_1/goodbye.md",
                         )),
                     ],
+                    metadata: Table::new(),
+                    line_map: LineMap::default(),
                 }),
                 BookItem::Separator,
             ],
@@ -455,6 +717,54 @@ And here is some \
         assert_eq!(chapter_names, should_be);
     }
 
+    #[test]
+    fn reading_order_is_depth_first() {
+        let mut first = Chapter::new("First", String::new(), "first.md");
+        first.sub_items = vec![
+            BookItem::Chapter(Chapter::new("Nested", String::new(), "first/nested.md")),
+        ];
+
+        let book = Book {
+            sections: vec![
+                BookItem::Chapter(Chapter::new("Prefix", String::new(), "prefix.md")),
+                BookItem::Chapter(first),
+                BookItem::Separator,
+                BookItem::Chapter(Chapter::new("Second", String::new(), "second.md")),
+                BookItem::Chapter(Chapter::new("Suffix", String::new(), "suffix.md")),
+            ],
+        };
+
+        let got: Vec<_> = book.reading_order().iter().map(|ch| ch.name.as_str()).collect();
+        assert_eq!(got, vec!["Prefix", "First", "Nested", "Second", "Suffix"]);
+    }
+
+    #[test]
+    fn reading_order_skips_drafts_and_part_titles() {
+        let mut draft = Chapter::new_draft("Draft");
+        draft.sub_items = vec![
+            BookItem::Chapter(Chapter::new("Nested", String::new(), "draft/nested.md")),
+        ];
+
+        let book = Book {
+            sections: vec![
+                BookItem::PartTitle(String::from("Part One")),
+                BookItem::Chapter(Chapter::new("First", String::new(), "first.md")),
+                BookItem::Chapter(draft),
+                BookItem::PartTitle(String::from("Part Two")),
+                BookItem::Chapter(Chapter::new_draft("Another Draft")),
+                BookItem::Chapter(Chapter::new("Last", String::new(), "last.md")),
+            ],
+        };
+
+        let got: Vec<_> = book.reading_order().iter().map(|ch| ch.name.as_str()).collect();
+        assert_eq!(got, vec!["First", "Nested", "Last"]);
+    }
+
+    #[test]
+    fn an_empty_book_has_nothing_to_read() {
+        assert!(Book::new().reading_order().is_empty());
+    }
+
     #[test]
     fn for_each_mut_visits_all_items() {
         let mut book = Book {
@@ -463,7 +773,7 @@ And here is some \
                     name: String::from("Chapter 1"),
                     content: String::from(DUMMY_SRC),
                     number: None,
-                    path: PathBuf::from("Chapter_1/index.md"),
+                    path: Some(PathBuf::from("Chapter_1/index.md")),
                     sub_items: vec![
                         BookItem::Chapter(Chapter::new(
                             "Hello World",
@@ -477,6 +787,8 @@ And here is some \
                             "Chapter_1/goodbye.md",
                         )),
                     ],
+                    metadata: Table::new(),
+                    line_map: LineMap::default(),
                 }),
                 BookItem::Separator,
             ],
@@ -497,7 +809,7 @@ And here is some \
             numbered_chapters: vec![
                 SummaryItem::Link(Link {
                     name: String::from("Empty"),
-                    location: PathBuf::from(""),
+                    location: Some(PathBuf::from("")),
                     ..Default::default()
                 }),
             ],
@@ -518,7 +830,7 @@ And here is some \
             numbered_chapters: vec![
                 SummaryItem::Link(Link {
                     name: String::from("nested"),
-                    location: dir,
+                    location: Some(dir),
                     ..Default::default()
                 }),
             ],
@@ -528,4 +840,94 @@ And here is some \
         let got = load_book_from_disk(&summary, temp.path());
         assert!(got.is_err());
     }
+
+    /// A `SUMMARY.md` with one chapter at every level, `depth` levels deep.
+    fn deeply_nested_summary(depth: usize) -> String {
+        let mut summary = String::from("# Summary\n\n");
+        for level in 0..depth {
+            summary.push_str(&"  ".repeat(level));
+            summary.push_str(&format!("- [Level {}](./level_{}.md)\n", level + 1, level + 1));
+        }
+        summary
+    }
+
+    #[test]
+    fn the_nesting_depth_is_limited_by_the_config() {
+        let temp = TempDir::new("book").unwrap();
+        let cfg = BuildConfig {
+            max_depth: 3,
+            ..Default::default()
+        };
+
+        File::create(temp.path().join("SUMMARY.md"))
+            .unwrap()
+            .write_all(deeply_nested_summary(3).as_bytes())
+            .unwrap();
+        let book = load_book(temp.path(), &cfg).unwrap();
+
+        let deepest = book.iter()
+                          .filter_map(|item| match *item {
+                              BookItem::Chapter(ref ch) => ch.number.clone(),
+                              _ => None,
+                          })
+                          .max_by_key(|number| number.len());
+        assert_eq!(deepest, Some(SectionNumber(vec![1, 1, 1])));
+
+        File::create(temp.path().join("SUMMARY.md"))
+            .unwrap()
+            .write_all(deeply_nested_summary(4).as_bytes())
+            .unwrap();
+        let err = load_book(temp.path(), &cfg).unwrap_err();
+
+        assert!(err.iter().any(|e| e.to_string().contains("more than 3 levels deep")),
+                "{:?}",
+                err);
+    }
+
+    #[test]
+    fn a_chapter_cant_be_nested_inside_itself() {
+        let (mut root, temp) = dummy_link();
+        root.nested_items.push(Link::new("Again", "chapter_1.md").into());
+        let summary = Summary {
+            numbered_chapters: vec![SummaryItem::Link(root)],
+            ..Default::default()
+        };
+
+        let err = load_book_from_disk(&summary, temp.path()).unwrap_err();
+
+        assert!(err.to_string().contains("is nested inside itself"), "{}", err);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinked_directory_cycles_are_detected() {
+        use std::os::unix::fs::symlink;
+
+        let (mut root, temp) = dummy_link();
+        symlink(temp.path(), temp.path().join("loop")).unwrap();
+
+        root.nested_items.push(Link::new("Through the loop", "loop/chapter_1.md").into());
+        let summary = Summary {
+            numbered_chapters: vec![SummaryItem::Link(root)],
+            ..Default::default()
+        };
+
+        let err = load_book_from_disk(&summary, temp.path()).unwrap_err();
+
+        let should_be = "\"Through the loop\" (loop/chapter_1.md) is nested inside itself";
+        assert!(err.to_string().contains(should_be), "{}", err);
+    }
+
+    #[test]
+    fn the_same_chapter_can_appear_in_different_places() {
+        let (root, temp) = dummy_link();
+        let summary = Summary {
+            numbered_chapters: vec![SummaryItem::Link(root.clone()), SummaryItem::Link(root)],
+            ..Default::default()
+        };
+
+        let got = load_book_from_disk(&summary, temp.path()).unwrap();
+
+        assert_eq!(got.sections.len(), 2);
+    }
 }
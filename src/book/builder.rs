@@ -0,0 +1,188 @@
+use std::env;
+use std::path::PathBuf;
+
+use config::Config;
+use preprocess::Preprocessor;
+use renderer::Renderer;
+use super::{determine_preprocessors, determine_renderers, load_config, Book, MDBook};
+use errors::*;
+
+/// A helper for putting together an `MDBook` from code, either from a book's
+/// root directory on disk or from a `Book` which only exists in memory (e.g.
+/// one generated from some other documentation).
+///
+/// ```rust,no_run
+/// use mdbook::MDBookBuilder;
+/// use mdbook::book::{Book, Chapter};
+///
+/// let mut book = Book::new();
+/// book.push_item(Chapter::new("Intro", String::from("# Intro"), "intro.md"));
+///
+/// MDBookBuilder::new()
+///     .book(book)
+///     .title("Generated")
+///     .build_dir("/tmp/generated-book")
+///     .render()
+///     .expect("Rendering failed");
+/// ```
+///
+/// When the book is loaded from disk, anything in its `book.toml` is used
+/// unless the builder says otherwise. An in-memory book never looks at a
+/// `book.toml` (or a `SUMMARY.md`), and starts from `Config::default()` or
+/// the config given to `config()`.
+#[derive(Default)]
+pub struct MDBookBuilder {
+    root: Option<PathBuf>,
+    book: Option<Book>,
+    config: Option<Config>,
+    build_dir: Option<PathBuf>,
+    title: Option<String>,
+    authors: Option<Vec<String>>,
+    curly_quotes: Option<bool>,
+    preprocessors: Option<Vec<Box<Preprocessor>>>,
+    renderers: Option<Vec<Box<Renderer>>>,
+}
+
+impl MDBookBuilder {
+    /// Create a new `MDBookBuilder`.
+    pub fn new() -> MDBookBuilder {
+        MDBookBuilder::default()
+    }
+
+    /// The book's root directory. Unless an in-memory book is given, the
+    /// book (and its `book.toml`) is loaded from here. Relative paths, like
+    /// the build directory, are always relative to it. Defaults to the
+    /// current directory.
+    pub fn root<P: Into<PathBuf>>(&mut self, root: P) -> &mut MDBookBuilder {
+        self.root = Some(root.into());
+        self
+    }
+
+    /// Use a book which only exists in memory, instead of loading one from
+    /// disk.
+    pub fn book(&mut self, book: Book) -> &mut MDBookBuilder {
+        self.book = Some(book);
+        self
+    }
+
+    /// Start from this config instead of the one in `book.toml` (or the
+    /// default one for an in-memory book).
+    pub fn config(&mut self, config: Config) -> &mut MDBookBuilder {
+        self.config = Some(config);
+        self
+    }
+
+    /// Where the rendered book goes.
+    pub fn build_dir<P: Into<PathBuf>>(&mut self, build_dir: P) -> &mut MDBookBuilder {
+        self.build_dir = Some(build_dir.into());
+        self
+    }
+
+    /// The book's title.
+    pub fn title<S: Into<String>>(&mut self, title: S) -> &mut MDBookBuilder {
+        self.title = Some(title.into());
+        self
+    }
+
+    /// Add one of the book's authors. The authors given here replace any in
+    /// the config.
+    pub fn author<S: Into<String>>(&mut self, author: S) -> &mut MDBookBuilder {
+        self.authors.get_or_insert_with(Vec::new).push(author.into());
+        self
+    }
+
+    /// Should the HTML renderer use "smart quotes"? This sets
+    /// `output.html.curly-quotes`, so the HTML renderer is used even if the
+    /// config only mentions other renderers.
+    pub fn curly_quotes(&mut self, curly_quotes: bool) -> &mut MDBookBuilder {
+        self.curly_quotes = Some(curly_quotes);
+        self
+    }
+
+    /// Add a preprocessor. Once one has been added, the preprocessors given
+    /// here are the only ones run, in the order they were added.
+    pub fn with_preprocessor<P: Preprocessor + 'static>(&mut self, preprocessor: P) -> &mut MDBookBuilder {
+        self.preprocessors.get_or_insert_with(Vec::new).push(Box::new(preprocessor));
+        self
+    }
+
+    /// Don't run any preprocessors (not even the default `links` one),
+    /// except those added afterwards with `with_preprocessor()`.
+    pub fn without_preprocessors(&mut self) -> &mut MDBookBuilder {
+        self.preprocessors = Some(Vec::new());
+        self
+    }
+
+    /// Add a renderer. Once one has been added, the renderers given here are
+    /// the only ones used.
+    pub fn with_renderer<R: Renderer + 'static>(&mut self, renderer: R) -> &mut MDBookBuilder {
+        self.renderers.get_or_insert_with(Vec::new).push(Box::new(renderer));
+        self
+    }
+
+    /// Load the book.
+    ///
+    /// This takes everything out of the builder (renderers and preprocessors
+    /// can't be copied), so it should only be called once.
+    pub fn build(&mut self) -> Result<MDBook> {
+        let root = match self.root.take() {
+            Some(root) => root,
+            None => env::current_dir().chain_err(|| "Unable to find the current directory")?,
+        };
+        let in_memory = self.book.take();
+
+        let mut config = match self.config.take() {
+            Some(config) => config,
+            None if in_memory.is_some() => Config::default(),
+            None => load_config(&root)?,
+        };
+        self.apply_overrides(&mut config)?;
+
+        let mut md = match in_memory {
+            Some(book) => MDBook {
+                renderers: determine_renderers(&config),
+                preprocessors: determine_preprocessors(&config)?,
+                root: root,
+                config: config,
+                book: book,
+                language: None,
+                in_memory: true,
+            },
+            None => MDBook::load_with_config(root, config)?,
+        };
+
+        if let Some(preprocessors) = self.preprocessors.take() {
+            md.preprocessors = preprocessors;
+        }
+        if let Some(renderers) = self.renderers.take() {
+            md.renderers = renderers;
+        }
+
+        Ok(md)
+    }
+
+    /// Load the book and render it.
+    pub fn render(&mut self) -> Result<MDBook> {
+        let md = self.build()?;
+        md.build()?;
+
+        Ok(md)
+    }
+
+    fn apply_overrides(&self, config: &mut Config) -> Result<()> {
+        if let Some(ref build_dir) = self.build_dir {
+            config.build.build_dir = build_dir.clone();
+        }
+        if let Some(ref title) = self.title {
+            config.book.title = Some(title.clone());
+        }
+        if let Some(ref authors) = self.authors {
+            config.book.authors = authors.clone();
+        }
+        if let Some(curly_quotes) = self.curly_quotes {
+            config.set("output.html.curly-quotes", curly_quotes)?;
+        }
+
+        Ok(())
+    }
+}
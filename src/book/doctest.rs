@@ -0,0 +1,205 @@
+//! Pulling the Rust code samples out of a chapter so `mdbook test` can check
+//! each of them with `rustdoc --test`.
+
+use std::path::Path;
+use std::process::{Command, Output};
+
+use utils::CodeBlockInfo;
+use errors::*;
+
+/// A fenced Rust code block from a chapter.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodeSample {
+    /// The (1-based) line the code block starts on.
+    pub line: usize,
+    /// The code block's info string.
+    pub info: CodeBlockInfo,
+    /// The code itself, with the fence's indentation removed.
+    pub code: String,
+}
+
+impl CodeSample {
+    /// Should this sample be given to `rustdoc` at all?
+    pub fn is_ignored(&self) -> bool {
+        self.info.has_flag("ignore")
+    }
+
+    /// A markdown file containing just this sample, with its flags
+    /// (`no_run`, `should_panic`, `compile_fail`, ...) passed on so rustdoc
+    /// knows how to test it.
+    pub fn to_markdown(&self) -> String {
+        let mut info = vec!["rust"];
+        info.extend(self.info.flags.iter().map(|flag| flag.as_str()));
+
+        // the fence has to be longer than any run of backticks in the code
+        let fence = "`".repeat(longest_backtick_run(&self.code).max(2) + 1);
+
+        format!("{}{}\n{}{}\n", fence, info.join(","), self.code, fence)
+    }
+}
+
+/// Find every fenced code block whose language is `rust`. Blocks in another
+/// language, or without one, are skipped.
+pub fn rust_code_samples(content: &str) -> Vec<CodeSample> {
+    let mut samples = Vec::new();
+    let mut lines = content.lines().enumerate();
+
+    while let Some((ix, line)) = lines.next() {
+        let (fence, info) = match Fence::opening(line) {
+            Some(opening) => opening,
+            None => continue,
+        };
+
+        // an unterminated code block runs to the end of the chapter
+        let mut code = String::new();
+        for (_, line) in lines.by_ref() {
+            if fence.is_closed_by(line) {
+                break;
+            }
+            code.push_str(fence.strip_indent(line));
+            code.push('\n');
+        }
+
+        let info = CodeBlockInfo::parse(info);
+        if info.language.as_ref().map(|lang| lang.as_str()) == Some("rust") {
+            samples.push(CodeSample {
+                line: ix + 1,
+                info: info,
+                code: code,
+            });
+        }
+    }
+
+    samples
+}
+
+/// Run `rustdoc --test` on a markdown file.
+pub fn run_rustdoc(path: &Path, library_args: &[&str]) -> Result<Output> {
+    Command::new("rustdoc")
+        .arg(path)
+        .arg("--test")
+        .args(library_args)
+        .output()
+        .chain_err(|| "Unable to run rustdoc")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Fence {
+    indent: usize,
+    marker: char,
+    len: usize,
+}
+
+impl Fence {
+    /// If this line opens a code block, get its fence and info string.
+    fn opening(line: &str) -> Option<(Fence, &str)> {
+        let trimmed = line.trim_left();
+        let marker = match trimmed.chars().next() {
+            Some(c @ '`') | Some(c @ '~') => c,
+            _ => return None,
+        };
+
+        let len = trimmed.chars().take_while(|&c| c == marker).count();
+        let info = trimmed[len..].trim();
+        if len < 3 || (marker == '`' && info.contains('`')) {
+            return None;
+        }
+
+        let fence = Fence {
+            indent: line.len() - trimmed.len(),
+            marker: marker,
+            len: len,
+        };
+        Some((fence, info))
+    }
+
+    fn is_closed_by(&self, line: &str) -> bool {
+        let trimmed = line.trim();
+        let len = trimmed.chars().take_while(|&c| c == self.marker).count();
+
+        len >= self.len && len == trimmed.len()
+    }
+
+    /// Remove (up to) the fence's indentation from a line of code.
+    fn strip_indent<'a>(&self, line: &'a str) -> &'a str {
+        let indent = line.chars()
+            .take(self.indent)
+            .take_while(|c| c.is_whitespace())
+            .count();
+
+        &line[indent..]
+    }
+}
+
+fn longest_backtick_run(text: &str) -> usize {
+    text.split(|c| c != '`')
+        .map(|run| run.len())
+        .max()
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_rust_code_blocks_are_samples() {
+        let src = "# Chapter\n\n\
+                   ```rust\nfn main() {}\n```\n\n\
+                   ```python\nprint('hello')\n```\n\n\
+                   ```\nno language\n```\n\n\
+                   ~~~rust,no_run\nloop {}\n~~~\n";
+
+        let got = rust_code_samples(src);
+
+        assert_eq!(got.len(), 2);
+        assert_eq!(got[0].line, 3);
+        assert_eq!(got[0].code, "fn main() {}\n");
+        assert_eq!(got[1].line, 15);
+        assert_eq!(got[1].code, "loop {}\n");
+        assert!(got[1].info.has_flag("no_run"));
+    }
+
+    #[test]
+    fn indented_code_blocks_lose_their_indentation() {
+        let src = "- A list item\n\n  ```rust\n  let x = 1;\n      let y = 2;\n  ```\n";
+
+        let got = rust_code_samples(src);
+
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].line, 3);
+        assert_eq!(got[0].code, "let x = 1;\n    let y = 2;\n");
+    }
+
+    #[test]
+    fn shorter_fences_dont_close_the_block() {
+        let src = "````rust\n/// ```\n/// foo();\n/// ```\nfn foo() {}\n````\n";
+
+        let got = rust_code_samples(src);
+
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].code, "/// ```\n/// foo();\n/// ```\nfn foo() {}\n");
+    }
+
+    #[test]
+    fn samples_keep_their_flags_for_rustdoc() {
+        let sample = &rust_code_samples("```rust,should_panic,title=\"Boom\"\npanic!();\n```\n")[0];
+
+        assert!(!sample.is_ignored());
+        assert_eq!(sample.to_markdown(), "```rust,should_panic\npanic!();\n```\n");
+    }
+
+    #[test]
+    fn the_fence_is_longer_than_any_backticks_in_the_code() {
+        let sample = &rust_code_samples("`````rust\n/// ````\n`````\n")[0];
+
+        assert_eq!(sample.to_markdown(), "`````rust\n/// ````\n`````\n");
+    }
+
+    #[test]
+    fn ignored_samples_are_marked() {
+        let sample = &rust_code_samples("```rust,ignore\nthis isn't rust\n```\n")[0];
+
+        assert!(sample.is_ignored());
+    }
+}
@@ -0,0 +1,137 @@
+//! Running the user's `[build.hooks]` shell commands around a build.
+
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use errors::*;
+
+/// Which stage of the build a set of hooks belongs to.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum HookStage {
+    /// Run before the book is loaded and rendered.
+    Pre,
+    /// Run after every renderer has finished.
+    Post,
+}
+
+impl HookStage {
+    fn name(&self) -> &'static str {
+        match *self {
+            HookStage::Pre => "pre",
+            HookStage::Post => "post",
+        }
+    }
+}
+
+/// Everything a hook gets told about the build it is running as part of.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HookEnvironment<'a> {
+    /// The book's root directory, also used as the working directory.
+    pub root: &'a Path,
+    /// The top-level build directory.
+    pub build_dir: &'a Path,
+    /// The names of the renderers which are being run.
+    pub renderers: Vec<&'a str>,
+}
+
+/// Run each of the `commands` in order, bailing on the first one which exits
+/// unsuccessfully.
+pub fn run_hooks(stage: HookStage, commands: &[String], env: &HookEnvironment) -> Result<()> {
+    for command in commands {
+        info!("Running the {} hook \"{}\"", stage.name(), command);
+
+        let output = shell_command(command)
+            .current_dir(env.root)
+            .env("BOOK_ROOT", env.root)
+            .env("BUILD_DIR", env.build_dir)
+            .env("RENDERERS", env.renderers.join(","))
+            .stdin(Stdio::null())
+            .stdout(Stdio::inherit())
+            .stderr(Stdio::piped())
+            .output()
+            .chain_err(|| format!("Unable to start the {} hook \"{}\"", stage.name(), command))?;
+
+        let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+
+        if !output.status.success() {
+            bail!(ErrorKind::HookFailed(command.clone(), stderr));
+        }
+
+        if !stderr.is_empty() {
+            debug!("The \"{}\" hook wrote to stderr: {}", command, stderr);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(not(windows))]
+fn shell_command(command: &str) -> Command {
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c").arg(command);
+    cmd
+}
+
+#[cfg(windows)]
+fn shell_command(command: &str) -> Command {
+    let mut cmd = Command::new("cmd");
+    cmd.arg("/C").arg(command);
+    cmd
+}
+
+#[cfg(all(test, not(windows)))]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+    use utils::fs::file_to_string;
+
+    fn dummy_env<'a>(root: &'a Path) -> HookEnvironment<'a> {
+        HookEnvironment {
+            root: root,
+            build_dir: root,
+            renderers: vec!["html", "random"],
+        }
+    }
+
+    #[test]
+    fn hooks_run_in_order_from_the_book_root() {
+        let temp = TempDir::new("hooks").unwrap();
+        let commands = vec![
+            String::from("echo first >> order.txt"),
+            String::from("echo second >> order.txt"),
+        ];
+
+        run_hooks(HookStage::Pre, &commands, &dummy_env(temp.path())).unwrap();
+
+        let got = file_to_string(temp.path().join("order.txt")).unwrap();
+        assert_eq!(got, "first\nsecond\n");
+    }
+
+    #[test]
+    fn hooks_get_the_renderer_names() {
+        let temp = TempDir::new("hooks").unwrap();
+        let commands = vec![String::from("echo $RENDERERS > renderers.txt")];
+
+        run_hooks(HookStage::Post, &commands, &dummy_env(temp.path())).unwrap();
+
+        let got = file_to_string(temp.path().join("renderers.txt")).unwrap();
+        assert_eq!(got.trim(), "html,random");
+    }
+
+    #[test]
+    fn a_failing_hook_stops_the_others_and_reports_stderr() {
+        let temp = TempDir::new("hooks").unwrap();
+        let commands = vec![
+            String::from("echo 'something broke' >&2; exit 1"),
+            String::from("touch never-created.txt"),
+        ];
+
+        let err = run_hooks(HookStage::Pre, &commands, &dummy_env(temp.path())).unwrap_err();
+
+        match *err.kind() {
+            ErrorKind::HookFailed(_, ref stderr) => assert_eq!(stderr.trim(), "something broke"),
+            ref other => panic!("Unexpected error: {:?}", other),
+        }
+        assert!(!temp.path().join("never-created.txt").exists());
+    }
+}
@@ -1,20 +1,26 @@
-use std::fs::{self, File};
-use std::path::PathBuf;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::io::Write;
-use toml;
+use toml::{self, Value};
 
 use config::Config;
 use super::MDBook;
 use theme;
+use utils;
 use errors::*;
 
 /// A helper for setting up a new book and its directory structure.
+///
+/// Anything which already exists (e.g. when some of the book has been
+/// written already) is left alone and only the missing pieces are created,
+/// unless `force()` is used.
 #[derive(Debug, Clone, PartialEq)]
 pub struct BookBuilder {
     root: PathBuf,
     create_gitignore: bool,
     config: Config,
     copy_theme: bool,
+    force: bool,
 }
 
 impl BookBuilder {
@@ -26,6 +32,7 @@ impl BookBuilder {
             create_gitignore: false,
             config: Config::default(),
             copy_theme: false,
+            force: false,
         }
     }
 
@@ -53,6 +60,12 @@ impl BookBuilder {
         self
     }
 
+    /// Should files which already exist be overwritten?
+    pub fn force(&mut self, force: bool) -> &mut BookBuilder {
+        self.force = force;
+        self
+    }
+
     /// Generate the actual book. This will:
     ///
     /// - Create the directory structure.
@@ -61,127 +74,216 @@ impl BookBuilder {
     /// - Create a themes directory and populate it (if applicable)
     /// - Generate a `book.toml` file,
     /// - Then load the book so we can build it or run tests.
+    ///
+    /// Every file which is skipped because it already exists is logged. When
+    /// there's a `book.toml` already, it decides where everything goes.
     pub fn build(&self) -> Result<MDBook> {
         info!("Creating a new book with stub content");
 
-        self.create_directory_structure()
+        let book_toml = self.root.join("book.toml");
+        let config = if book_toml.is_file() && !self.force {
+            Config::from_disk(&book_toml)?
+        } else {
+            self.config.clone()
+        };
+
+        self.create_directory_structure(&config)
             .chain_err(|| "Unable to create directory structure")?;
 
-        self.create_stub_files()
+        self.create_stub_files(&config)
             .chain_err(|| "Unable to create stub files")?;
 
         if self.create_gitignore {
-            self.build_gitignore()
+            self.build_gitignore(&config)
                 .chain_err(|| "Unable to create .gitignore")?;
         }
 
         if self.copy_theme {
-            self.copy_across_theme()
+            self.copy_across_theme(&config)
                 .chain_err(|| "Unable to copy across the theme")?;
         }
 
-        self.write_book_toml()?;
+        self.write_book_toml(&config)?;
+
+        MDBook::load(&self.root).chain_err(|| "Unable to load the new book")
+    }
 
-        match MDBook::load(&self.root) {
-            Ok(book) => Ok(book),
-            Err(e) => {
-                error!("{}", e);
+    /// Write a file, unless it already exists and we aren't forcing things.
+    /// Returns whether the file was written.
+    fn write_file(&self, path: &Path, content: &[u8]) -> Result<bool> {
+        let relative = path.strip_prefix(&self.root).unwrap_or(path);
 
-                panic!(
-                    "The BookBuilder should always create a valid book. If you are seeing this it \
-                     is a bug and should be reported."
-                );
-            }
+        if path.exists() && !self.force {
+            info!("Skipping {} because it already exists", relative.display());
+            return Ok(false);
         }
+
+        debug!("Creating {}", relative.display());
+        utils::fs::create_file(path)?
+            .write_all(content)
+            .chain_err(|| format!("Unable to write {}", relative.display()))?;
+
+        Ok(true)
     }
 
-    fn write_book_toml(&self) -> Result<()> {
+    fn write_book_toml(&self, config: &Config) -> Result<()> {
         debug!("Writing book.toml");
-        let book_toml = self.root.join("book.toml");
-        let cfg = toml::to_vec(&self.config).chain_err(|| "Unable to serialize the config")?;
+        let book_toml = commented_book_toml(config)?;
 
-        File::create(book_toml)
-            .chain_err(|| "Couldn't create book.toml")?
-            .write_all(&cfg)
+        self.write_file(&self.root.join("book.toml"), book_toml.as_bytes())
             .chain_err(|| "Unable to write config to book.toml")?;
         Ok(())
     }
 
-    fn copy_across_theme(&self) -> Result<()> {
+    fn copy_across_theme(&self, config: &Config) -> Result<()> {
         debug!("Copying theme");
 
-        let themedir = self.config
+        // the same place the HTML renderer looks for it
+        let themedir = config
             .html_config()
             .and_then(|html| html.theme)
-            .unwrap_or_else(|| self.config.book.src.join("theme"));
+            .unwrap_or_else(|| PathBuf::from("theme"));
         let themedir = self.root.join(themedir);
 
-        if !themedir.exists() {
-            debug!(
-                "{} does not exist, creating the directory",
-                themedir.display()
-            );
-            fs::create_dir(&themedir)?;
+        let files: &[(&str, &[u8])] = &[
+            ("index.hbs", theme::INDEX),
+            ("header.hbs", theme::HEADER),
+            ("book.css", theme::CSS),
+            ("favicon.png", theme::FAVICON),
+            ("book.js", theme::JS),
+            ("highlight.css", theme::HIGHLIGHT_CSS),
+            ("tomorrow-night.css", theme::TOMORROW_NIGHT_CSS),
+            ("ayu-highlight.css", theme::AYU_HIGHLIGHT_CSS),
+            ("highlight.js", theme::HIGHLIGHT_JS),
+            ("clipboard.min.js", theme::CLIPBOARD_JS),
+        ];
+
+        for &(name, content) in files {
+            self.write_file(&themedir.join(name), content)?;
         }
 
-        let mut index = File::create(themedir.join("index.hbs"))?;
-        index.write_all(theme::INDEX)?;
-
-        let mut css = File::create(themedir.join("book.css"))?;
-        css.write_all(theme::CSS)?;
-
-        let mut favicon = File::create(themedir.join("favicon.png"))?;
-        favicon.write_all(theme::FAVICON)?;
-
-        let mut js = File::create(themedir.join("book.js"))?;
-        js.write_all(theme::JS)?;
-
-        let mut highlight_css = File::create(themedir.join("highlight.css"))?;
-        highlight_css.write_all(theme::HIGHLIGHT_CSS)?;
-
-        let mut highlight_js = File::create(themedir.join("highlight.js"))?;
-        highlight_js.write_all(theme::HIGHLIGHT_JS)?;
-
         Ok(())
     }
 
-    fn build_gitignore(&self) -> Result<()> {
+    fn build_gitignore(&self, config: &Config) -> Result<()> {
         debug!("Creating .gitignore");
 
-        let mut f = File::create(self.root.join(".gitignore"))?;
-
-        writeln!(f, "{}", self.config.build.build_dir.display())?;
+        let ignored = format!("{}\n", config.build.build_dir.display());
+        self.write_file(&self.root.join(".gitignore"), ignored.as_bytes())?;
 
         Ok(())
     }
 
-    fn create_stub_files(&self) -> Result<()> {
+    fn create_stub_files(&self, config: &Config) -> Result<()> {
         debug!("Creating example book contents");
-        let src_dir = self.root.join(&self.config.book.src);
-
-        let summary = src_dir.join("SUMMARY.md");
-        let mut f = File::create(&summary).chain_err(|| "Unable to create SUMMARY.md")?;
-        writeln!(f, "# Summary")?;
-        writeln!(f, "")?;
-        writeln!(f, "- [Chapter 1](./chapter_1.md)")?;
+        let src_dir = self.root.join(&config.book.src);
 
-        let chapter_1 = src_dir.join("chapter_1.md");
-        let mut f = File::create(&chapter_1).chain_err(|| "Unable to create chapter_1.md")?;
-        writeln!(f, "# Chapter 1")?;
+        let summary = "# Summary\n\n- [Chapter 1](./chapter_1.md)\n";
+        // chapters listed in an existing SUMMARY.md are created when the book
+        // is loaded instead
+        if self.write_file(&src_dir.join("SUMMARY.md"), summary.as_bytes())? {
+            self.write_file(&src_dir.join("chapter_1.md"), b"# Chapter 1\n")?;
+        }
 
         Ok(())
     }
 
-    fn create_directory_structure(&self) -> Result<()> {
+    fn create_directory_structure(&self, config: &Config) -> Result<()> {
         debug!("Creating directory tree");
         fs::create_dir_all(&self.root)?;
 
-        let src = self.root.join(&self.config.book.src);
+        let src = self.root.join(&config.book.src);
         fs::create_dir_all(&src)?;
 
-        let build = self.root.join(&self.config.build.build_dir);
+        let build = self.root.join(&config.build.build_dir);
         fs::create_dir_all(&build)?;
 
         Ok(())
     }
 }
+
+/// Comments for the most important settings in a new `book.toml`, keyed by
+/// their dotted path.
+const BOOK_TOML_COMMENTS: &[(&str, &str)] = &[
+    ("book", "Information about the book itself."),
+    ("book.title", "The book's title, shown on every page."),
+    ("book.src", "Where SUMMARY.md and the chapters are, relative to this file."),
+    ("build", "How the book is built."),
+    ("build.build-dir", "Where the rendered book goes, relative to this file."),
+    ("build.create-missing",
+     "Create any chapters listed in SUMMARY.md which don't exist yet."),
+    ("build.incremental", "Only render the chapters which changed since the last build."),
+];
+
+/// The config as it's written to a new `book.toml`, with some comments to
+/// get people started.
+fn commented_book_toml(config: &Config) -> Result<String> {
+    let mut table = Value::try_from(config).chain_err(|| "Unable to serialize the config")?;
+    let mut build = Value::try_from(&config.build).chain_err(|| "Unable to serialize the config")?;
+    if config.build.hooks == Default::default() {
+        if let Some(build) = build.as_table_mut() {
+            build.remove("hooks");
+        }
+    }
+    if let Some(table) = table.as_table_mut() {
+        table.insert(String::from("build"), build);
+    }
+
+    let serialized = toml::to_string(&table).chain_err(|| "Unable to serialize the config")?;
+
+    let mut commented = String::from(
+        "# The configuration for your book. See the user guide for everything\n\
+         # you can put here: https://rust-lang-nursery.github.io/mdBook/format/config.html\n\n",
+    );
+    let mut current_table = String::new();
+
+    for line in serialized.lines() {
+        let trimmed = line.trim();
+        let key = if trimmed.starts_with('[') {
+            current_table = trimmed.trim_matches(|c| c == '[' || c == ']').to_string();
+            current_table.clone()
+        } else {
+            match trimmed.find('=') {
+                Some(ix) => format!("{}.{}", current_table, trimmed[..ix].trim()),
+                None => String::new(),
+            }
+        };
+
+        if let Some(&(_, comment)) = BOOK_TOML_COMMENTS.iter().find(|&&(k, _)| k == key) {
+            commented.push_str("# ");
+            commented.push_str(comment);
+            commented.push('\n');
+        }
+        commented.push_str(line);
+        commented.push('\n');
+    }
+
+    Ok(commented)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_generated_book_toml_has_comments_and_round_trips() {
+        let mut config = Config::default();
+        config.book.title = Some(String::from("My Book"));
+        config.build.build_dir = PathBuf::from("out");
+
+        let got = commented_book_toml(&config).unwrap();
+
+        assert!(got.contains("# The book's title, shown on every page.\ntitle = \"My Book\"\n"),
+                "{}",
+                got);
+        assert!(got.contains("# Where the rendered book goes, relative to this file.\n\
+                              build-dir = \"out\"\n"),
+                "{}",
+                got);
+        assert!(!got.contains("hooks"), "{}", got);
+
+        let parsed = Config::from_str(&got).unwrap();
+        assert_eq!(parsed.book, config.book);
+        assert_eq!(parsed.build, config.build);
+    }
+}
@@ -7,21 +7,27 @@
 
 mod summary;
 mod book;
+mod builder;
+mod hooks;
 mod init;
+mod doctest;
 
 pub use self::book::{load_book, Book, BookItem, BookItems, Chapter};
-pub use self::summary::{parse_summary, Link, SectionNumber, Summary, SummaryItem};
+pub use self::summary::{parse_summary, parse_summary_with_max_depth, Link, SectionNumber, Summary,
+                        SummaryItem, DEFAULT_MAX_DEPTH};
 pub use self::init::BookBuilder;
+pub use self::builder::MDBookBuilder;
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::io::Write;
-use std::process::Command;
 use tempdir::TempDir;
 use toml::Value;
 
+use self::hooks::{HookEnvironment, HookStage};
 use utils;
-use renderer::{CmdRenderer, HtmlHandlebars, RenderContext, Renderer};
-use preprocess::{LinkPreprocessor, Preprocessor, PreprocessorContext};
+use renderer::{CmdRenderer, EpubRenderer, HtmlHandlebars, RenderContext, Renderer};
+use preprocess::{LinkPreprocessor, Preprocessor, PreprocessorContext, VariablesPreprocessor,
+                 WikiLinkPreprocessor};
 use errors::*;
 
 use config::Config;
@@ -38,46 +44,75 @@ pub struct MDBook {
 
     /// List of pre-processors to be run on the book
     preprocessors: Vec<Box<Preprocessor>>,
+    /// Which language this is, for a book with more than one.
+    language: Option<String>,
+    /// The book was given to an `MDBookBuilder` instead of being loaded from
+    /// disk, so it can't be reloaded.
+    in_memory: bool,
 }
 
 impl MDBook {
     /// Load a book from its root directory on disk.
+    ///
+    /// For a book with more than one language, this loads the default
+    /// language (see `Config::default_language()`).
     pub fn load<P: Into<PathBuf>>(book_root: P) -> Result<MDBook> {
         let book_root = book_root.into();
-        let config_location = book_root.join("book.toml");
-
-        // the book.json file is no longer used, so we should emit a warning to
-        // let people know to migrate to book.toml
-        if book_root.join("book.json").exists() {
-            warn!("It appears you are still using book.json for configuration.");
-            warn!("This format is no longer used, so you should migrate to the");
-            warn!("book.toml format.");
-            warn!("Check the user guide for migration information:");
-            warn!("\thttps://rust-lang-nursery.github.io/mdBook/format/config.html");
-        }
-
-        let mut config = if config_location.exists() {
-            debug!("Loading config from {}", config_location.display());
-            Config::from_disk(&config_location)?
-        } else {
-            Config::default()
-        };
-
-        config.update_from_env();
-
-        if log_enabled!(::log::Level::Trace) {
-            for line in format!("Config: {:#?}", config).lines() {
-                trace!("{}", line);
-            }
-        }
+        let config = load_config(&book_root)?;
 
         MDBook::load_with_config(book_root, config)
     }
 
     /// Load a book from its root directory using a custom config.
+    ///
+    /// For a book with more than one language, this loads the default
+    /// language.
     pub fn load_with_config<P: Into<PathBuf>>(book_root: P, config: Config) -> Result<MDBook> {
-        let root = book_root.into();
+        match config.default_language()? {
+            Some(code) => MDBook::load_language_with_config(book_root, config, &code),
+            None => MDBook::load_translation(book_root.into(), config, None),
+        }
+    }
+
+    /// Load one language of a book with more than one, from the book's root
+    /// directory on disk.
+    pub fn load_language<P: Into<PathBuf>>(book_root: P, language: &str) -> Result<MDBook> {
+        let book_root = book_root.into();
+        let config = load_config(&book_root)?;
+
+        MDBook::load_language_with_config(book_root, config, language)
+    }
 
+    /// Load one language of a book with more than one, using a custom config.
+    pub fn load_language_with_config<P: Into<PathBuf>>(book_root: P,
+                                                       config: Config,
+                                                       language: &str)
+                                                       -> Result<MDBook> {
+        let config = config.for_language(language)?;
+        MDBook::load_translation(book_root.into(), config, Some(language.to_string()))
+    }
+
+    /// Load every language of a book, starting with the default one. A book
+    /// with only one language is loaded as normal.
+    pub fn load_all_languages<P: Into<PathBuf>>(book_root: P) -> Result<Vec<MDBook>> {
+        let book_root = book_root.into();
+        let config = load_config(&book_root)?;
+
+        let default = match config.default_language()? {
+            Some(code) => code,
+            None => return Ok(vec![MDBook::load_with_config(book_root, config)?]),
+        };
+
+        let mut codes: Vec<String> = config.languages()?.keys().cloned().collect();
+        codes.retain(|code| *code != default);
+        codes.insert(0, default);
+
+        codes.iter()
+             .map(|code| MDBook::load_language_with_config(book_root.clone(), config.clone(), code))
+             .collect()
+    }
+
+    fn load_translation(root: PathBuf, config: Config, language: Option<String>) -> Result<MDBook> {
         let src_dir = root.join(&config.book.src);
         let book = book::load_book(&src_dir, &config.build)?;
 
@@ -90,9 +125,17 @@ impl MDBook {
             book,
             renderers,
             preprocessors,
+            language,
+            in_memory: false,
         })
     }
 
+    /// The language this is, when it is one language of a book with more
+    /// than one.
+    pub fn language(&self) -> Option<&str> {
+        self.language.as_ref().map(|code| code.as_str())
+    }
+
     /// Returns a flat depth-first iterator over the elements of the book,
     /// it returns an [BookItem enum](bookitem.html):
     /// `(section: String, bookitem: &BookItem)`
@@ -108,6 +151,7 @@ impl MDBook {
     ///     match *item {
     ///         BookItem::Chapter(ref chapter) => {},
     ///         BookItem::Separator => {},
+    ///         BookItem::PartTitle(ref title) => {},
     ///     }
     /// }
     ///
@@ -146,10 +190,32 @@ impl MDBook {
     }
 
     /// Tells the renderer to build our book and put it in the build directory.
+    ///
+    /// Any `[build.hooks]` are run before and after the renderers.
     pub fn build(&self) -> Result<()> {
         info!("Book building has started");
 
-        let mut preprocessed_book = self.book.clone();
+        let build_dir = self.root.join(&self.config.build.build_dir);
+        let hook_env = HookEnvironment {
+            root: &self.root,
+            build_dir: &build_dir,
+            renderers: self.renderers.iter().map(|r| r.name()).collect(),
+        };
+        let hooks = &self.config.build.hooks;
+
+        let mut preprocessed_book = if hooks.pre.is_empty() {
+            self.book.clone()
+        } else {
+            hooks::run_hooks(HookStage::Pre, &hooks.pre, &hook_env)?;
+
+            // the hooks may have generated or updated chapters, so we need
+            // to reload the book's contents
+            if self.in_memory {
+                self.book.clone()
+            } else {
+                book::load_book(&self.source_dir(), &self.config.build)?
+            }
+        };
         let preprocess_ctx = PreprocessorContext::new(self.root.clone(), self.config.clone());
 
         for preprocessor in &self.preprocessors {
@@ -162,13 +228,16 @@ impl MDBook {
             self.run_renderer(&preprocessed_book, renderer.as_ref())?;
         }
 
+        hooks::run_hooks(HookStage::Post, &hooks.post, &hook_env)?;
+
         Ok(())
     }
 
     fn run_renderer(&self, preprocessed_book: &Book, renderer: &Renderer) -> Result<()> {
         let name = renderer.name();
         let build_dir = self.build_dir_for(name);
-        if build_dir.exists() {
+        let incremental = self.config.build.incremental && renderer.is_incremental();
+        if build_dir.exists() && !incremental {
             debug!(
                 "Cleaning build dir for the \"{}\" renderer ({})",
                 name,
@@ -206,6 +275,12 @@ impl MDBook {
     }
 
     /// Run `rustdoc` tests on the book, linking against the provided libraries.
+    ///
+    /// Every ` ```rust ` code block is tested on its own, after the book's
+    /// links and includes have been expanded. Blocks marked `ignore` are
+    /// skipped, and the `no_run`, `should_panic` and `compile_fail` flags are
+    /// passed on to `rustdoc`. Any failures are reported with the chapter and
+    /// line the code block starts on.
     pub fn test(&mut self, library_paths: Vec<&str>) -> Result<()> {
         let library_args: Vec<&str> = (0..library_paths.len())
             .map(|_| "-L")
@@ -217,35 +292,60 @@ impl MDBook {
 
         let preprocess_context = PreprocessorContext::new(self.root.clone(), self.config.clone());
 
-        LinkPreprocessor::new().run(&preprocess_context, &mut self.book)?;
-
-        for item in self.iter() {
-            if let BookItem::Chapter(ref ch) = *item {
-                if !ch.path.as_os_str().is_empty() {
-                    let path = self.source_dir().join(&ch.path);
-                    let content = utils::fs::file_to_string(&path)?;
-                    info!("Testing file: {:?}", path);
-
-                    // write preprocessed file to tempdir
-                    let path = temp_dir.path().join(&ch.path);
-                    let mut tmpf = utils::fs::create_file(&path)?;
-                    tmpf.write_all(content.as_bytes())?;
-
-                    let output = Command::new("rustdoc")
-                        .arg(&path)
-                        .arg("--test")
-                        .args(&library_args)
-                        .output()?;
-
-                    if !output.status.success() {
-                        bail!(ErrorKind::Subprocess(
-                            "Rustdoc returned an error".to_string(),
-                            output
-                        ));
+        let mut book = self.book.clone();
+        LinkPreprocessor::new().run(&preprocess_context, &mut book)?;
+
+        let mut failures = Vec::new();
+
+        for item in book.iter() {
+            let (chapter, chapter_path) = match *item {
+                BookItem::Chapter(ref ch) => match ch.path {
+                    Some(ref path) if !path.as_os_str().is_empty() => (ch, path),
+                    _ => continue,
+                },
+                _ => continue,
+            };
+            let location = self.config.book.src.join(chapter_path);
+            info!("Testing file: {:?}", location);
+
+            for sample in doctest::rust_code_samples(&chapter.content) {
+                // where the sample is in the chapter's file, rather than in
+                // its (preprocessed) contents
+                let line = chapter.line_map.original_line(sample.line);
+                if sample.is_ignored() {
+                    debug!("Skipping the ignored code sample at {}:{}", location.display(), line);
+                    continue;
+                }
+
+                // write each sample to its own file so a failure can be
+                // traced back to the code block it came from
+                let path = temp_dir
+                    .path()
+                    .join(chapter_path)
+                    .with_extension(format!("{}.md", line));
+                let mut tmpf = utils::fs::create_file(&path)?;
+                tmpf.write_all(sample.to_markdown().as_bytes())?;
+
+                let output = doctest::run_rustdoc(&path, &library_args)?;
+
+                if !output.status.success() {
+                    let failure = format!("{}:{}", location.display(), line);
+                    error!("The code sample at {} failed", failure);
+                    for line in String::from_utf8_lossy(&output.stdout).lines() {
+                        error!("\t{}", line);
+                    }
+                    for line in String::from_utf8_lossy(&output.stderr).lines() {
+                        error!("\t{}", line);
                     }
+                    failures.push(failure);
                 }
             }
         }
+
+        if !failures.is_empty() {
+            bail!("{} code sample(s) failed: {}", failures.len(), failures.join(", "));
+        }
+
         Ok(())
     }
 
@@ -298,15 +398,49 @@ impl MDBook {
     }
 }
 
+/// Read the `book.toml` in a book's root directory, if there is one.
+fn load_config(book_root: &Path) -> Result<Config> {
+    let config_location = book_root.join("book.toml");
+
+    // the book.json file is no longer used, so we should emit a warning to
+    // let people know to migrate to book.toml
+    if book_root.join("book.json").exists() {
+        warn!("It appears you are still using book.json for configuration.");
+        warn!("This format is no longer used, so you should migrate to the");
+        warn!("book.toml format.");
+        warn!("Check the user guide for migration information:");
+        warn!("\thttps://rust-lang-nursery.github.io/mdBook/format/config.html");
+    }
+
+    let mut config = if config_location.exists() {
+        debug!("Loading config from {}", config_location.display());
+        Config::from_disk(&config_location)?
+    } else {
+        Config::default()
+    };
+
+    config.update_from_env();
+
+    if log_enabled!(::log::Level::Trace) {
+        for line in format!("Config: {:#?}", config).lines() {
+            trace!("{}", line);
+        }
+    }
+
+    Ok(config)
+}
+
 /// Look at the `Config` and try to figure out what renderers to use.
 fn determine_renderers(config: &Config) -> Vec<Box<Renderer>> {
     let mut renderers: Vec<Box<Renderer>> = Vec::new();
 
     if let Some(output_table) = config.get("output").and_then(|o| o.as_table()) {
         for (key, table) in output_table.iter() {
-            // the "html" backend has its own Renderer
+            // the "html" and "epub" backends have their own Renderers
             if key == "html" {
                 renderers.push(Box::new(HtmlHandlebars::new()));
+            } else if key == "epub" {
+                renderers.push(Box::new(EpubRenderer::new()));
             } else {
                 let renderer = interpret_custom_renderer(key, table);
                 renderers.push(renderer);
@@ -340,7 +474,12 @@ fn determine_preprocessors(config: &Config) -> Result<Vec<Box<Preprocessor>>> {
     for key in preprocess_list {
         match key.as_ref() {
             "links" => preprocessors.push(Box::new(LinkPreprocessor::new())),
-            _ => bail!("{:?} is not a recognised preprocessor", key),
+            "wiki-links" => preprocessors.push(Box::new(WikiLinkPreprocessor::new())),
+            "variables" => preprocessors.push(Box::new(VariablesPreprocessor::new())),
+            _ => bail!(ErrorKind::InvalidConfig(format!(
+                "{:?} is not a recognised preprocessor",
+                key
+            ))),
         }
     }
 
@@ -389,6 +528,17 @@ mod tests {
         assert_eq!(got[0].name(), "random");
     }
 
+    #[test]
+    fn the_epub_renderer_is_built_in() {
+        let mut cfg = Config::default();
+        cfg.set("output.epub", Table::new()).unwrap();
+
+        let got = determine_renderers(&cfg);
+
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].name(), "epub");
+    }
+
     #[test]
     fn add_a_random_renderer_with_custom_command_to_the_config() {
         let mut cfg = Config::default();
@@ -440,6 +590,36 @@ mod tests {
         assert!(got.unwrap().is_empty());
     }
 
+    #[test]
+    fn config_can_enable_wiki_links() {
+        let cfg_str: &'static str = r#"
+        [build]
+        preprocess = ["links", "wiki-links"]
+        "#;
+
+        let cfg = Config::from_str(cfg_str).unwrap();
+
+        let got = determine_preprocessors(&cfg).unwrap();
+
+        let names: Vec<_> = got.iter().map(|p| p.name()).collect();
+        assert_eq!(names, vec!["links", "wiki-links"]);
+    }
+
+    #[test]
+    fn config_can_enable_variables() {
+        let cfg_str: &'static str = r#"
+        [build]
+        preprocess = ["links", "variables"]
+        "#;
+
+        let cfg = Config::from_str(cfg_str).unwrap();
+
+        let got = determine_preprocessors(&cfg).unwrap();
+
+        let names: Vec<_> = got.iter().map(|p| p.name()).collect();
+        assert_eq!(names, vec!["links", "variables"]);
+    }
+
     #[test]
     fn config_complains_if_unimplemented_preprocessor() {
         let cfg_str: &'static str = r#"
@@ -37,17 +37,45 @@ use errors::*;
 /// You can either use - or * to indicate a numbered chapter, the parser doesn't
 /// care but you'll probably want to stay consistent.
 ///
+/// **Part Title:** Top-level headings between the numbered chapters (or just
+/// before the first of them) split the book into parts. They are shown as an
+/// unlinked label and don't affect the chapter numbering.
+///
+/// ```markdown
+/// # Reference Guide
+/// ```
+///
 /// **Suffix Chapter:** After the numbered chapters you can add a couple of
 /// non-numbered chapters. They are the same as prefix chapters but come after
 /// the numbered chapters instead of before.
 ///
 /// All other elements are unsupported and will be ignored at best or result in
 /// an error.
+///
+/// Chapters can be nested up to [`DEFAULT_MAX_DEPTH`] levels deep (use
+/// [`parse_summary_with_max_depth()`] for a different limit).
+///
+/// [`DEFAULT_MAX_DEPTH`]: constant.DEFAULT_MAX_DEPTH.html
+/// [`parse_summary_with_max_depth()`]: fn.parse_summary_with_max_depth.html
 pub fn parse_summary(summary: &str) -> Result<Summary> {
-    let parser = SummaryParser::new(summary);
+    parse_summary_with_max_depth(summary, DEFAULT_MAX_DEPTH)
+}
+
+/// Like [`parse_summary()`], but numbered chapters can be nested `max_depth`
+/// levels deep (a top-level chapter is at depth 1). Going any deeper is a
+/// parse error which points at the offending line.
+///
+/// [`parse_summary()`]: fn.parse_summary.html
+pub fn parse_summary_with_max_depth(summary: &str, max_depth: usize) -> Result<Summary> {
+    let mut parser = SummaryParser::new(summary);
+    parser.max_depth = max_depth;
     parser.parse()
 }
 
+/// How deeply numbered chapters can be nested, unless `build.max-depth` says
+/// otherwise.
+pub const DEFAULT_MAX_DEPTH: usize = 16;
+
 /// The parsed `SUMMARY.md`, specifying how the book should be laid out.
 #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct Summary {
@@ -70,8 +98,9 @@ pub struct Link {
     /// The name of the chapter.
     pub name: String,
     /// The location of the chapter's source file, taking the book's `src`
-    /// directory as the root.
-    pub location: PathBuf,
+    /// directory as the root. Draft chapters (`[Some Chapter]()`) don't have
+    /// one.
+    pub location: Option<PathBuf>,
     /// The section number, if this chapter is in the numbered section.
     pub number: Option<SectionNumber>,
     /// Any nested items this chapter may contain.
@@ -83,7 +112,7 @@ impl Link {
     pub fn new<S: Into<String>, P: AsRef<Path>>(name: S, location: P) -> Link {
         Link {
             name: name.into(),
-            location: location.as_ref().to_path_buf(),
+            location: Some(location.as_ref().to_path_buf()),
             number: None,
             nested_items: Vec::new(),
         }
@@ -94,7 +123,7 @@ impl Default for Link {
     fn default() -> Self {
         Link {
             name: String::new(),
-            location: PathBuf::new(),
+            location: None,
             number: None,
             nested_items: Vec::new(),
         }
@@ -108,6 +137,8 @@ pub enum SummaryItem {
     Link(Link),
     /// A separator (`---`).
     Separator,
+    /// A title for the following part of the book (`# Some Part`).
+    PartTitle(String),
 }
 
 impl SummaryItem {
@@ -139,7 +170,8 @@ impl From<Link> for SummaryItem {
 ///                     | EPSILON
 /// prefix_chapters   ::= item*
 /// suffix_chapters   ::= item*
-/// numbered_chapters ::= dotted_item+
+/// numbered_chapters ::= (dotted_item | part_title)+
+/// part_title        ::= "# " TEXT
 /// dotted_item       ::= INDENT* DOT_POINT item
 /// item              ::= link
 ///                     | separator
@@ -154,6 +186,10 @@ impl From<Link> for SummaryItem {
 struct SummaryParser<'a> {
     src: &'a str,
     stream: pulldown_cmark::Parser<'a>,
+    /// An event which was read but needs to be handled by the next parser.
+    back: Option<Event<'a>>,
+    /// How many levels deep numbered chapters may be nested.
+    max_depth: usize,
 }
 
 /// Reads `Events` from the provided stream until the corresponding
@@ -200,6 +236,8 @@ impl<'a> SummaryParser<'a> {
         SummaryParser {
             src: text,
             stream: pulldown_parser,
+            back: None,
+            max_depth: DEFAULT_MAX_DEPTH,
         }
     }
 
@@ -255,6 +293,18 @@ impl<'a> SummaryParser<'a> {
                         bail!(self.parse_error("Suffix chapters cannot be followed by a list"));
                     }
                 }
+                Some(event @ Event::Start(Tag::Header(1))) => {
+                    if is_prefix {
+                        // the numbered section starts with a part title, let
+                        // the numbered parser deal with it
+                        self.back = Some(event);
+                        break;
+                    } else {
+                        bail!(self.parse_error(
+                            "Suffix chapters cannot be followed by a part title"
+                        ));
+                    }
+                }
                 Some(Event::Start(Tag::Link(href, _))) => {
                     let link = self.parse_link(href.to_string())?;
                     items.push(SummaryItem::Link(link));
@@ -272,42 +322,66 @@ impl<'a> SummaryParser<'a> {
         let link_content = collect_events!(self.stream, end Tag::Link(..));
         let name = stringify_events(link_content);
 
-        if href.is_empty() {
-            Err(self.parse_error("You can't have an empty link."))
+        // an empty link is a draft chapter which hasn't been written yet
+        let location = if href.is_empty() {
+            None
         } else {
-            Ok(Link {
-                name: name,
-                location: PathBuf::from(href.to_string()),
-                number: None,
-                nested_items: Vec::new(),
-            })
-        }
+            Some(PathBuf::from(href))
+        };
+
+        Ok(Link {
+            name: name,
+            location: location,
+            number: None,
+            nested_items: Vec::new(),
+        })
     }
 
     /// Parse the numbered chapters. This assumes the opening list tag has
-    /// already been consumed by a previous parser.
+    /// already been consumed by a previous parser, unless the numbered
+    /// chapters start with a part title.
     fn parse_numbered(&mut self) -> Result<Vec<SummaryItem>> {
         let mut items = Vec::new();
         let root_number = SectionNumber::default();
+        let mut in_list = self.back.is_none();
 
-        // we need to do this funny loop-match-if-let dance because a rule will
-        // close off any currently running list. Therefore we try to read the
-        // list items before the rule, then if we encounter a rule we'll add a
-        // separator and try to resume parsing numbered chapters if we start a
-        // list immediately afterwards.
+        // we need to do this funny loop-match dance because a rule or part
+        // title will close off any currently running list. Therefore we try
+        // to read the list items before it, then add a separator or part
+        // title and try to resume parsing numbered chapters if we start a
+        // list afterwards.
         //
         // If you can think of a better way to do this then please make a PR :)
 
         loop {
-            let mut bunch_of_items = self.parse_nested_numbered(&root_number)?;
-
-            // if we've resumed after something like a rule the root sections
-            // will be numbered from 1. We need to manually go back and update
-            // them
-            update_section_numbers(&mut bunch_of_items, 0, items.len() as u32);
-            items.extend(bunch_of_items);
+            if in_list {
+                let mut bunch_of_items = self.parse_nested_numbered(&root_number)?;
+
+                // if we've resumed after something like a rule the root
+                // sections will be numbered from 1. We need to manually go
+                // back and update them. Part titles don't count towards the
+                // numbering.
+                let existing = items
+                    .iter()
+                    .filter(|item| match **item {
+                        SummaryItem::PartTitle(_) => false,
+                        _ => true,
+                    })
+                    .count();
+                update_section_numbers(&mut bunch_of_items, 0, existing as u32);
+                items.extend(bunch_of_items);
+                in_list = false;
+            }
 
             match self.next_event() {
+                Some(Event::Start(Tag::List(..))) => {
+                    in_list = true;
+                }
+                Some(Event::Start(Tag::Header(1))) => {
+                    let title = self.parse_part_title();
+                    trace!("Found part title: {}", title);
+                    items.push(SummaryItem::PartTitle(title));
+                }
                 Some(Event::Start(Tag::Paragraph)) => {
                     // we're starting the suffix chapters
                     break;
@@ -333,12 +407,6 @@ impl<'a> SummaryParser<'a> {
                             }
                         }
                     }
-
-                    if let Some(Event::Start(Tag::List(..))) = self.next_event() {
-                        continue;
-                    } else {
-                        break;
-                    }
                 }
                 Some(_) => {
                     // something else... ignore
@@ -355,7 +423,7 @@ impl<'a> SummaryParser<'a> {
     }
 
     fn next_event(&mut self) -> Option<Event<'a>> {
-        let next = self.stream.next();
+        let next = self.back.take().or_else(|| self.stream.next());
         trace!("Next event: {:?}", next);
 
         next
@@ -379,6 +447,13 @@ impl<'a> SummaryParser<'a> {
                         .as_ref()
                         .expect("All numbered chapters have numbers");
 
+                    if last_item_number.len() >= self.max_depth {
+                        bail!(self.parse_error(format!(
+                            "Chapters can't be nested more than {} levels deep (see build.max-depth)",
+                            self.max_depth
+                        )));
+                    }
+
                     let sub_items = self.parse_nested_numbered(last_item_number)?;
 
                     last_item.nested_items = sub_items;
@@ -406,10 +481,10 @@ impl<'a> SummaryParser<'a> {
                     let mut number = parent.clone();
                     number.0.push(num_existing_items as u32 + 1);
                     trace!(
-                        "Found chapter: {} {} ({})",
+                        "Found chapter: {} {} ({:?})",
                         number,
                         link.name,
-                        link.location.display()
+                        link.location
                     );
 
                     link.number = Some(number);
@@ -432,6 +507,13 @@ impl<'a> SummaryParser<'a> {
         ErrorKind::ParseError(line, col, msg.to_string()).into()
     }
 
+    /// Parse a part title, assuming its opening header tag has already been
+    /// consumed.
+    fn parse_part_title(&mut self) -> String {
+        let tags = collect_events!(self.stream, end Tag::Header(1));
+        stringify_events(tags)
+    }
+
     /// Try to parse the title line.
     fn parse_title(&mut self) -> Option<String> {
         if let Some(Event::Start(Tag::Header(1))) = self.next_event() {
@@ -617,12 +699,12 @@ mod tests {
         let should_be = vec![
             SummaryItem::Link(Link {
                 name: String::from("First"),
-                location: PathBuf::from("./first.md"),
+                location: Some(PathBuf::from("./first.md")),
                 ..Default::default()
             }),
             SummaryItem::Link(Link {
                 name: String::from("Second"),
-                location: PathBuf::from("./second.md"),
+                location: Some(PathBuf::from("./second.md")),
                 ..Default::default()
             }),
         ];
@@ -661,7 +743,7 @@ mod tests {
         let src = "[First](./first.md)";
         let should_be = Link {
             name: String::from("First"),
-            location: PathBuf::from("./first.md"),
+            location: Some(PathBuf::from("./first.md")),
             ..Default::default()
         };
 
@@ -682,7 +764,7 @@ mod tests {
         let src = "- [First](./first.md)\n";
         let link = Link {
             name: String::from("First"),
-            location: PathBuf::from("./first.md"),
+            location: Some(PathBuf::from("./first.md")),
             number: Some(SectionNumber(vec![1])),
             ..Default::default()
         };
@@ -703,12 +785,12 @@ mod tests {
         let should_be = vec![
             SummaryItem::Link(Link {
                 name: String::from("First"),
-                location: PathBuf::from("./first.md"),
+                location: Some(PathBuf::from("./first.md")),
                 number: Some(SectionNumber(vec![1])),
                 nested_items: vec![
                     SummaryItem::Link(Link {
                         name: String::from("Nested"),
-                        location: PathBuf::from("./nested.md"),
+                        location: Some(PathBuf::from("./nested.md")),
                         number: Some(SectionNumber(vec![1, 1])),
                         nested_items: Vec::new(),
                     }),
@@ -716,7 +798,7 @@ mod tests {
             }),
             SummaryItem::Link(Link {
                 name: String::from("Second"),
-                location: PathBuf::from("./second.md"),
+                location: Some(PathBuf::from("./second.md")),
                 number: Some(SectionNumber(vec![2])),
                 nested_items: Vec::new(),
             }),
@@ -740,13 +822,13 @@ mod tests {
         let should_be = vec![
             SummaryItem::Link(Link {
                 name: String::from("First"),
-                location: PathBuf::from("./first.md"),
+                location: Some(PathBuf::from("./first.md")),
                 number: Some(SectionNumber(vec![1])),
                 nested_items: Vec::new(),
             }),
             SummaryItem::Link(Link {
                 name: String::from("Second"),
-                location: PathBuf::from("./second.md"),
+                location: Some(PathBuf::from("./second.md")),
                 number: Some(SectionNumber(vec![2])),
                 nested_items: Vec::new(),
             }),
@@ -761,12 +843,139 @@ mod tests {
     }
 
     #[test]
-    fn an_empty_link_location_is_an_error() {
+    fn part_title_before_the_first_chapter() {
+        let src = "# Summary\n\n[Intro](./intro.md)\n\n# Part One\n\n- [First](./first.md)\n";
+
+        let got = parse_summary(src).unwrap();
+
+        assert_eq!(got.title, Some(String::from("Summary")));
+        assert_eq!(got.prefix_chapters.len(), 1);
+        assert_eq!(
+            got.numbered_chapters,
+            vec![
+                SummaryItem::PartTitle(String::from("Part One")),
+                SummaryItem::Link(Link {
+                    name: String::from("First"),
+                    location: Some(PathBuf::from("./first.md")),
+                    number: Some(SectionNumber(vec![1])),
+                    nested_items: Vec::new(),
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn part_titles_between_chapters_dont_reset_numbering() {
+        let src = "- [First](./first.md)\n  - [Nested](./nested.md)\n\n# Part Two\n\n- [Second](./second.md)\n";
+        let mut parser = SummaryParser::new(src);
+        let _ = parser.stream.next();
+
+        let got = parser.parse_numbered().unwrap();
+
+        assert_eq!(got.len(), 3);
+        assert_eq!(got[1], SummaryItem::PartTitle(String::from("Part Two")));
+        match got[2] {
+            SummaryItem::Link(ref link) => assert_eq!(link.number, Some(SectionNumber(vec![2]))),
+            ref other => panic!("Expected a link, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn part_title_after_the_last_chapter() {
+        let src = "# Summary\n\n- [First](./first.md)\n\n# Appendices\n\n[Conclusion](./conclusion.md)\n";
+
+        let got = parse_summary(src).unwrap();
+
+        assert_eq!(got.numbered_chapters.len(), 2);
+        assert_eq!(
+            got.numbered_chapters[1],
+            SummaryItem::PartTitle(String::from("Appendices"))
+        );
+        assert_eq!(got.suffix_chapters.len(), 1);
+    }
+
+    #[test]
+    fn suffix_items_cannot_be_followed_by_a_part_title() {
+        let src = "# Summary\n\n- [First](./first.md)\n\n[Conclusion](./conclusion.md)\n\n# Too Late\n";
+
+        assert!(parse_summary(src).is_err());
+    }
+
+    #[test]
+    fn an_empty_link_location_is_a_draft_chapter() {
         let src = "- [Empty]()\n";
         let mut parser = SummaryParser::new(src);
         parser.stream.next();
 
-        let got = parser.parse_numbered();
-        assert!(got.is_err());
+        let got = parser.parse_numbered().unwrap();
+        let should_be = vec![
+            SummaryItem::Link(Link {
+                name: String::from("Empty"),
+                location: None,
+                number: Some(SectionNumber(vec![1])),
+                nested_items: Vec::new(),
+            }),
+        ];
+
+        assert_eq!(got, should_be);
+    }
+
+    #[test]
+    fn drafts_can_have_nested_chapters() {
+        let src = "- [Draft]()\n  - [Nested](./nested.md)\n- [Second](./second.md)\n";
+        let mut parser = SummaryParser::new(src);
+        parser.stream.next();
+
+        let got = parser.parse_numbered().unwrap();
+
+        match got[0] {
+            SummaryItem::Link(ref link) => {
+                assert_eq!(link.location, None);
+                assert_eq!(link.nested_items.len(), 1);
+            }
+            ref other => panic!("Expected a link, found {:?}", other),
+        }
+        match got[1] {
+            SummaryItem::Link(ref link) => assert_eq!(link.number, Some(SectionNumber(vec![2]))),
+            ref other => panic!("Expected a link, found {:?}", other),
+        }
+    }
+
+    /// `depth` levels of numbered chapters, one inside the other.
+    fn nested_chapters(depth: usize) -> String {
+        let mut summary = String::from("# Summary\n\n");
+        for level in 0..depth {
+            summary.push_str(&"  ".repeat(level));
+            summary.push_str(&format!("- [Level {0}](./level_{0}.md)\n", level + 1));
+        }
+        summary
+    }
+
+    #[test]
+    fn chapters_can_be_nested_right_up_to_the_limit() {
+        let got = parse_summary(&nested_chapters(DEFAULT_MAX_DEPTH)).unwrap();
+
+        let mut link = match got.numbered_chapters[0] {
+            SummaryItem::Link(ref link) => link,
+            ref other => panic!("Expected a link, found {:?}", other),
+        };
+        while let Some(&SummaryItem::Link(ref nested)) = link.nested_items.first() {
+            link = nested;
+        }
+
+        let number = link.number.clone().unwrap();
+        assert_eq!(number.len(), DEFAULT_MAX_DEPTH);
+        assert_eq!(number.to_string(), format!("{}.", vec!["1"; DEFAULT_MAX_DEPTH].join(".")));
+    }
+
+    #[test]
+    fn nesting_chapters_past_the_limit_is_an_error() {
+        let err = parse_summary(&nested_chapters(DEFAULT_MAX_DEPTH + 1)).unwrap_err();
+
+        let found = err.iter().any(|e| e.to_string().contains("more than 16 levels deep"));
+        assert!(found, "{:?}", err);
+
+        let got = parse_summary_with_max_depth(&nested_chapters(DEFAULT_MAX_DEPTH + 1), 32);
+        assert!(got.is_ok());
     }
 }
@@ -54,6 +54,7 @@ use std::path::{Path, PathBuf};
 use std::fs::File;
 use std::io::Read;
 use std::env;
+use std::collections::{BTreeMap, HashMap};
 use toml::{self, Value};
 use toml::value::Table;
 use toml_query::read::TomlValueReadExt;
@@ -61,6 +62,7 @@ use toml_query::insert::TomlValueInsertExt;
 use toml_query::delete::TomlValueDeleteExt;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use serde_json;
+use book::DEFAULT_MAX_DEPTH;
 
 use errors::*;
 
@@ -73,12 +75,16 @@ pub struct Config {
     /// Information about the build environment.
     pub build: BuildConfig,
     rest: Value,
+    /// The config this one was made from by `for_language()`, so it isn't
+    /// turned into the config for a language more than once.
+    untranslated: Option<Box<Config>>,
 }
 
 impl Config {
     /// Load a `Config` from some string.
     pub fn from_str(src: &str) -> Result<Config> {
-        toml::from_str(src).chain_err(|| Error::from("Invalid configuration file"))
+        toml::from_str(src)
+            .chain_err(|| ErrorKind::InvalidConfig(String::from("Invalid configuration file")))
     }
 
     /// Load the configuration file from disk.
@@ -209,6 +215,75 @@ impl Config {
         Ok(())
     }
 
+    /// The languages the book is translated into, from the
+    /// `[language.<code>]` tables, keyed by their code. This is empty for a
+    /// book with only one language.
+    pub fn languages(&self) -> Result<BTreeMap<String, LanguageConfig>> {
+        match self.get("language") {
+            Some(_) => self.get_deserialized("language")
+                .chain_err(|| ErrorKind::InvalidConfig(String::from("Invalid [language] table"))),
+            None => Ok(BTreeMap::new()),
+        }
+    }
+
+    /// The code of the language a book with more than one is built in by
+    /// default. This is `book.language` if it is set, otherwise the first
+    /// language code in alphabetical order.
+    pub fn default_language(&self) -> Result<Option<String>> {
+        let languages = self.languages()?;
+
+        match self.book.language {
+            Some(ref code) if languages.contains_key(code) => Ok(Some(code.clone())),
+            Some(ref code) if !languages.is_empty() => bail!(ErrorKind::InvalidConfig(format!(
+                "The default language \"{}\" doesn't have a [language.{}] table",
+                code, code
+            ))),
+            _ => Ok(languages.keys().next().cloned()),
+        }
+    }
+
+    /// The configuration for building one language of a book with more than
+    /// one. Its chapters come from the language's `src` directory and it is
+    /// built into a directory named after the language code inside the
+    /// `build-dir`. Everything else is shared with the other languages.
+    pub fn for_language(&self, code: &str) -> Result<Config> {
+        if let Some(ref untranslated) = self.untranslated {
+            return untranslated.for_language(code);
+        }
+
+        let languages = self.languages()?;
+        let language = match languages.get(code) {
+            Some(language) => language,
+            None => bail!(ErrorKind::InvalidConfig(format!(
+                "There is no [language.{}] table in book.toml",
+                code
+            ))),
+        };
+
+        let mut config = self.clone();
+        config.book.src = language
+            .src
+            .clone()
+            .unwrap_or_else(|| self.book.src.join(code));
+        if language.title.is_some() {
+            config.book.title = language.title.clone();
+        }
+        if language.description.is_some() {
+            config.book.description = language.description.clone();
+        }
+        config.book.language = Some(code.to_string());
+        config.build.build_dir = self.build.build_dir.join(code);
+        config.untranslated = Some(Box::new(self.clone()));
+
+        Ok(config)
+    }
+
+    /// Is this the config for one language of a book with more than one
+    /// (i.e. it came from `for_language()`)?
+    pub fn is_translation(&self) -> bool {
+        self.untranslated.is_some()
+    }
+
     fn from_legacy(mut table: Value) -> Config {
         let mut cfg = Config::default();
 
@@ -248,6 +323,7 @@ impl Default for Config {
             book: BookConfig::default(),
             build: BuildConfig::default(),
             rest: Value::Table(Table::default()),
+            untranslated: None,
         }
     }
 }
@@ -291,6 +367,7 @@ impl<'de> Deserialize<'de> for Config {
             book: book,
             build: build,
             rest: Value::Table(table),
+            untranslated: None,
         })
     }
 }
@@ -358,6 +435,14 @@ pub struct BookConfig {
     pub src: PathBuf,
     /// Does this book support more than one language?
     pub multilingual: bool,
+    /// The language the book is written in (e.g. `en`). For a book with
+    /// `[language.<code>]` tables this is the default language, and it gets
+    /// set to the language being built.
+    pub language: Option<String>,
+    /// Values chapters can use as `{{ var.<name> }}`, from the
+    /// `[book.variables]` table.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub variables: Table,
 }
 
 impl Default for BookConfig {
@@ -368,6 +453,8 @@ impl Default for BookConfig {
             description: None,
             src: PathBuf::from("src"),
             multilingual: false,
+            language: None,
+            variables: Table::new(),
         }
     }
 }
@@ -383,7 +470,13 @@ pub struct BuildConfig {
     pub create_missing: bool,
     /// Which preprocessors should be applied
     pub preprocess: Option<Vec<String>>,
-
+    /// Shell commands to run before and after the book is built.
+    pub hooks: HooksConfig,
+    /// Should renderers which support it only re-render the chapters which
+    /// changed since the last build?
+    pub incremental: bool,
+    /// How many levels deep chapters can be nested in `SUMMARY.md`.
+    pub max_depth: usize,
 }
 
 impl Default for BuildConfig {
@@ -392,10 +485,48 @@ impl Default for BuildConfig {
             build_dir: PathBuf::from("book"),
             create_missing: true,
             preprocess: None,
+            hooks: HooksConfig::default(),
+            incremental: true,
+            max_depth: DEFAULT_MAX_DEPTH,
         }
     }
 }
 
+/// One of the languages a book is translated into, from a `[language.<code>]`
+/// table.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct LanguageConfig {
+    /// The language's name as shown to readers (e.g. `日本語`). Defaults to
+    /// the language code.
+    pub name: Option<String>,
+    /// The book's title in this language, instead of `book.title`.
+    pub title: Option<String>,
+    /// The book's description in this language, instead of
+    /// `book.description`.
+    pub description: Option<String>,
+    /// Where this language's chapters (and `SUMMARY.md`) are, relative to
+    /// the book's root directory. Defaults to `<book.src>/<code>`.
+    pub src: Option<PathBuf>,
+}
+
+/// Shell commands which are run (in order, from the book's root directory)
+/// around each build.
+///
+/// Each command gets the `BOOK_ROOT`, `BUILD_DIR` and `RENDERERS` environment
+/// variables set, and the build fails if any of them exit unsuccessfully.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct HooksConfig {
+    /// Commands run before the book is loaded and rendered.
+    pub pre: Vec<String>,
+    /// Commands run after all the renderers have finished.
+    pub post: Vec<String>,
+    /// When rebuilding under `mdbook serve` or `mdbook watch`, only run the
+    /// `pre` hooks before the initial build.
+    pub pre_once: bool,
+}
+
 /// Configuration for the HTML renderer.
 #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 #[serde(default, rename_all = "kebab-case")]
@@ -404,6 +535,28 @@ pub struct HtmlConfig {
     pub theme: Option<PathBuf>,
     /// Use "smart quotes" instead of the usual `"` character.
     pub curly_quotes: bool,
+    /// Strip raw HTML in chapters down to an allowlist of safe tags and
+    /// attributes.
+    pub sanitize_html: bool,
+    /// What to do with raw HTML in chapters.
+    pub raw_html: RawHtml,
+    /// Keep the reader's last choice of tab selected in every tabbed group of
+    /// code blocks.
+    pub remember_code_tabs: bool,
+    /// Open links to other websites in a new tab.
+    pub external_links_new_tab: bool,
+    /// Translate relative links without an extension (`./chapter/`) into
+    /// links to the rendered chapter.
+    pub extensionless_links: bool,
+    /// Replace emoji shortcodes like `:rocket:` with the emoji.
+    pub emoji: bool,
+    /// Turn bare `http://` and `https://` URLs into links.
+    pub autolink: bool,
+    /// Number the lines of every code block, not just the ones with a
+    /// `linenos` attribute.
+    pub line_numbers: bool,
+    /// How many levels of headings a chapter's `<!-- toc -->` lists.
+    pub toc_depth: Option<u32>,
     /// Should mathjax be enabled?
     pub mathjax_support: bool,
     /// An optional google analytics code.
@@ -425,6 +578,64 @@ pub struct HtmlConfig {
     pub livereload_url: Option<String>,
     /// Should section labels be rendered?
     pub no_section_label: bool,
+    /// Should headers be left as they are, instead of being wrapped in a
+    /// link to themselves?
+    pub no_heading_permalinks: bool,
+    /// Only use ASCII in the ids generated for headings, transliterating
+    /// accented letters (`Über uns` gets the id `uber-uns`).
+    pub transliterate_ids: bool,
+    /// Rewrite the `#fragment` of links within the book using the rules for
+    /// heading ids, so `#My-Section` points at `## My Section`.
+    pub normalize_link_fragments: bool,
+    /// Warn about headings which are given a numbered id (`foo-1`) because
+    /// an earlier heading in the chapter already has the plain one.
+    pub warn_duplicate_ids: bool,
+    /// The URL of the book's source repository, linked to from every page.
+    pub git_repository_url: Option<String>,
+    /// A URL for editing a chapter's source, where `{path}` is replaced with
+    /// the chapter's path relative to the `src` directory.
+    pub edit_url_template: Option<String>,
+    /// Pages which should redirect the reader somewhere else, mapping the
+    /// old path (relative to the build directory) to its new destination.
+    pub redirect: HashMap<String, String>,
+    /// What goes in `index.html` when there's no `index.md` chapter.
+    pub index_strategy: IndexStrategy,
+}
+
+/// What the HTML renderer does with raw HTML embedded in a chapter.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RawHtml {
+    /// Pass it through untouched.
+    Allow,
+    /// Escape it, so the markup is shown on the page as text.
+    Escape,
+    /// Remove it from the page.
+    Strip,
+}
+
+impl Default for RawHtml {
+    fn default() -> RawHtml {
+        RawHtml::Allow
+    }
+}
+
+/// How the HTML renderer makes an `index.html` for a book which doesn't have
+/// an `index.md` chapter.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IndexStrategy {
+    /// A copy of the first chapter's page, with its links working from the
+    /// root of the book.
+    Copy,
+    /// A page which redirects to the first chapter.
+    Redirect,
+}
+
+impl Default for IndexStrategy {
+    fn default() -> IndexStrategy {
+        IndexStrategy::Copy
+    }
 }
 
 /// Configuration for tweaking how the the HTML renderer handles the playpen.
@@ -494,6 +705,10 @@ mod tests {
         create-missing = false
         preprocess = ["first_preprocessor", "second_preprocessor"]
 
+        [build.hooks]
+        pre = ["./gen-docs.sh"]
+        post = ["./upload.sh", "echo done"]
+
         [output.html]
         theme = "./themedir"
         curly-quotes = true
@@ -522,6 +737,13 @@ mod tests {
             create_missing: false,
             preprocess: Some(vec!["first_preprocessor".to_string(),
                                   "second_preprocessor".to_string()]),
+            hooks: HooksConfig {
+                pre: vec![String::from("./gen-docs.sh")],
+                post: vec![String::from("./upload.sh"), String::from("echo done")],
+                pre_once: false,
+            },
+            incremental: true,
+            max_depth: 16,
         };
         let playpen_should_be = Playpen {
             editable: true,
@@ -543,6 +765,39 @@ mod tests {
         assert_eq!(got.html_config().unwrap(), html_should_be);
     }
 
+    #[test]
+    fn load_html_redirects() {
+        let src = r#"
+        [output.html.redirect]
+        "/old.html" = "new.html"
+        "/moved.html" = "https://example.com/"
+        "#;
+
+        let mut should_be = HashMap::new();
+        should_be.insert(String::from("/old.html"), String::from("new.html"));
+        should_be.insert(String::from("/moved.html"), String::from("https://example.com/"));
+
+        let got = Config::from_str(src).unwrap();
+        assert_eq!(got.html_config().unwrap().redirect, should_be);
+    }
+
+    #[test]
+    fn load_book_variables() {
+        let src = r#"
+        [book]
+        title = "Some Book"
+
+        [book.variables]
+        version = "1.2.0"
+        year = 2018
+        "#;
+
+        let got = Config::from_str(src).unwrap();
+
+        assert_eq!(got.book.variables.get("version"), Some(&Value::String(String::from("1.2.0"))));
+        assert_eq!(got.book.variables.get("year"), Some(&Value::Integer(2018)));
+    }
+
     #[test]
     fn load_arbitrary_output_type() {
         #[derive(Debug, Deserialize, PartialEq)]
@@ -624,6 +879,9 @@ mod tests {
             build_dir: PathBuf::from("my-book"),
             create_missing: true,
             preprocess: None,
+            hooks: HooksConfig::default(),
+            incremental: true,
+            max_depth: 16,
         };
 
         let html_should_be = HtmlConfig {
@@ -726,4 +984,81 @@ mod tests {
 
         assert_eq!(cfg.book.title, Some(should_be));
     }
+
+    const MULTILINGUAL_CONFIG: &'static str = r#"
+        [book]
+        title = "A Book"
+        language = "en"
+
+        [build]
+        build-dir = "out"
+
+        [language.en]
+        name = "English"
+
+        [language.ja]
+        name = "日本語"
+        title = "本"
+        src = "translations/ja"
+        "#;
+
+    #[test]
+    fn books_have_one_language_by_default() {
+        let cfg = Config::default();
+
+        assert!(cfg.languages().unwrap().is_empty());
+        assert_eq!(cfg.default_language().unwrap(), None);
+    }
+
+    #[test]
+    fn load_the_language_tables() {
+        let cfg = Config::from_str(MULTILINGUAL_CONFIG).unwrap();
+
+        let got = cfg.languages().unwrap();
+
+        assert_eq!(got.keys().collect::<Vec<_>>(), vec!["en", "ja"]);
+        assert_eq!(got["ja"].name, Some(String::from("日本語")));
+        assert_eq!(got["ja"].src, Some(PathBuf::from("translations/ja")));
+        assert_eq!(cfg.default_language().unwrap(), Some(String::from("en")));
+    }
+
+    #[test]
+    fn each_language_gets_its_own_src_and_build_dir() {
+        let cfg = Config::from_str(MULTILINGUAL_CONFIG).unwrap();
+
+        let en = cfg.for_language("en").unwrap();
+        assert_eq!(en.book.src, PathBuf::from("src/en"));
+        assert_eq!(en.book.title, Some(String::from("A Book")));
+        assert_eq!(en.book.language, Some(String::from("en")));
+        assert_eq!(en.build.build_dir, PathBuf::from("out/en"));
+
+        let ja = cfg.for_language("ja").unwrap();
+        assert_eq!(ja.book.src, PathBuf::from("translations/ja"));
+        assert_eq!(ja.book.title, Some(String::from("本")));
+        assert_eq!(ja.build.build_dir, PathBuf::from("out/ja"));
+
+        assert!(cfg.for_language("fr").is_err());
+    }
+
+    #[test]
+    fn languages_can_be_switched_from_a_translation() {
+        let cfg = Config::from_str(MULTILINGUAL_CONFIG).unwrap();
+        let en = cfg.for_language("en").unwrap();
+
+        assert!(en.is_translation());
+        assert!(!cfg.is_translation());
+        assert_eq!(en.for_language("en").unwrap(), en);
+        assert_eq!(en.for_language("ja").unwrap(), cfg.for_language("ja").unwrap());
+    }
+
+    #[test]
+    fn the_default_language_needs_a_table() {
+        let mut cfg = Config::from_str(MULTILINGUAL_CONFIG).unwrap();
+        cfg.book.language = Some(String::from("fr"));
+
+        assert!(cfg.default_language().is_err());
+
+        cfg.book.language = None;
+        assert_eq!(cfg.default_language().unwrap(), Some(String::from("en")));
+    }
 }
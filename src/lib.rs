@@ -50,6 +50,9 @@
 //! md.build().expect("Building failed");
 //! ```
 //!
+//! Tools which generate a book's contents can skip the files on disk (and
+//! `book.toml`) altogether with an [`MDBookBuilder`].
+//!
 //! ## Implementing a new Backend
 //!
 //! `mdbook` has a fairly flexible mechanism for creating additional backends 
@@ -72,9 +75,11 @@
 //! [`RenderContext`]: renderer/struct.RenderContext.html
 //! [relevant chapter]: https://rust-lang-nursery.github.io/mdBook/for_developers/backends.html
 //! [`Config`]: config/struct.Config.html
+//! [`MDBookBuilder`]: book/struct.MDBookBuilder.html
 
 #![deny(missing_docs)]
 
+extern crate chrono;
 #[macro_use]
 extern crate error_chain;
 extern crate handlebars;
@@ -106,8 +111,10 @@ pub mod config;
 pub mod renderer;
 pub mod theme;
 pub mod utils;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
 
-pub use book::MDBook;
+pub use book::{MDBook, MDBookBuilder};
 pub use book::BookItem;
 pub use renderer::Renderer;
 pub use config::Config;
@@ -122,6 +129,7 @@ pub mod errors {
             HandlebarsRender(::handlebars::RenderError) #[doc = "Handlebars rendering failed"];
             HandlebarsTemplate(Box<::handlebars::TemplateError>) #[doc = "Unable to parse the template"];
             Utf8(::std::string::FromUtf8Error) #[doc = "Invalid UTF-8"];
+            Json(::serde_json::Error) #[doc = "JSON (de)serialization failed"];
         }
 
         links {
@@ -141,11 +149,163 @@ pub mod errors {
                 display("Error at line {}, column {}: {}", line, col, message)
             }
 
+            /// One of the `[build.hooks]` commands exited unsuccessfully.
+            HookFailed(command: String, stderr: String) {
+                description("A build hook failed")
+                display("The \"{}\" hook failed: {}", command, stderr)
+            }
+
             /// The user tried to use a reserved filename.
             ReservedFilenameError(filename: PathBuf) {
                 description("Reserved Filename")
                 display("{} is reserved for internal use", filename.display())
             }
+
+            /// The book's configuration is invalid.
+            InvalidConfig(message: String) {
+                description("Invalid configuration")
+                display("{}", message)
+            }
+
+            /// A `{{#include}}` or `{{#playpen}}` link in a chapter couldn't
+            /// be expanded. The path is relative to the book's root and the
+            /// line is 1-based.
+            IncludeFailed(chapter: PathBuf, line: usize, link: String) {
+                description("An include failed")
+                display("{}:{}: Unable to expand {}", chapter.display(), line, link)
+            }
+
+            /// The TOML front matter at the top of a chapter couldn't be
+            /// parsed.
+            InvalidFrontMatter(chapter: PathBuf, message: String) {
+                description("Invalid front matter")
+                display("{}: Invalid front matter, {}", chapter.display(), message)
+            }
+        }
+    }
+
+    /// The broad kind of problem an error was caused by, so tools wrapping
+    /// mdBook can tell a broken book apart from a broken environment.
+    #[derive(Debug, Copy, Clone, PartialEq, Serialize)]
+    #[serde(rename_all = "kebab-case")]
+    pub enum ErrorCategory {
+        /// Something is wrong with `book.toml`.
+        Config,
+        /// Something is wrong with the book's contents (e.g. `SUMMARY.md`
+        /// can't be parsed or an include is missing).
+        Content,
+        /// Reading or writing a file failed.
+        Io,
+        /// Anything else.
+        Other,
+    }
+
+    impl ErrorCategory {
+        /// The exit code `mdbook` uses for errors in this category.
+        pub fn exit_code(&self) -> i32 {
+            match *self {
+                ErrorCategory::Config => 2,
+                ErrorCategory::Content => 3,
+                ErrorCategory::Io => 4,
+                ErrorCategory::Other => 101,
+            }
+        }
+    }
+
+    /// A machine readable description of an error and everything which
+    /// caused it.
+    #[derive(Debug, Clone, PartialEq, Serialize)]
+    pub struct ErrorReport {
+        /// The most specific kind of error in the chain (e.g.
+        /// `include-failed`), or `other` if nothing more is known.
+        pub kind: &'static str,
+        /// The error's category.
+        pub category: ErrorCategory,
+        /// The top-level error message.
+        pub message: String,
+        /// The file the error is about, relative to the book's root, if known.
+        pub path: Option<PathBuf>,
+        /// The (1-based) line the error is on, if known.
+        pub line: Option<usize>,
+        /// The (1-based) column the error is on, if known.
+        pub column: Option<usize>,
+        /// The messages of every error which caused this one, outermost
+        /// first.
+        pub causes: Vec<String>,
+    }
+
+    impl Error {
+        /// Describe this error (and the errors which caused it) in a way
+        /// other programs can make sense of.
+        pub fn report(&self) -> ErrorReport {
+            let mut report = ErrorReport {
+                kind: "other",
+                category: ErrorCategory::Other,
+                message: self.to_string(),
+                path: None,
+                line: None,
+                column: None,
+                causes: self.iter().skip(1).map(|cause| cause.to_string()).collect(),
+            };
+
+            // use the outermost error which says what went wrong, falling back
+            // to I/O if an I/O error is the underlying cause
+            let mut current = Some(self);
+            let mut caused_by_io = false;
+            while let Some(error) = current {
+                if report.kind == "other" {
+                    report.describe(error.kind());
+                }
+
+                let next = error.1.next_error.as_ref();
+                caused_by_io |= next.map_or(false, |next| next.is::<::std::io::Error>());
+                current = next.and_then(|next| next.downcast_ref::<Error>());
+            }
+
+            if report.kind == "other" && caused_by_io {
+                report.kind = "io";
+                report.category = ErrorCategory::Io;
+            }
+
+            report
+        }
+    }
+
+    impl ErrorReport {
+        fn describe(&mut self, kind: &ErrorKind) {
+            let (name, category) = match *kind {
+                ErrorKind::Io(_) => ("io", ErrorCategory::Io),
+                ErrorKind::Utf8(_) => ("utf8", ErrorCategory::Content),
+                ErrorKind::HandlebarsRender(_) | ErrorKind::HandlebarsTemplate(_) => {
+                    ("template", ErrorCategory::Other)
+                }
+                ErrorKind::TomlQuery(_) => ("config", ErrorCategory::Config),
+                ErrorKind::InvalidConfig(_) => ("invalid-config", ErrorCategory::Config),
+                ErrorKind::Subprocess(..) => ("subprocess", ErrorCategory::Other),
+                ErrorKind::HookFailed(..) => ("hook-failed", ErrorCategory::Other),
+                ErrorKind::ParseError(line, column, _) => {
+                    self.line = Some(line);
+                    self.column = Some(column);
+                    ("summary-parse-error", ErrorCategory::Content)
+                }
+                ErrorKind::ReservedFilenameError(ref filename) => {
+                    self.path = Some(filename.clone());
+                    ("reserved-filename", ErrorCategory::Content)
+                }
+                ErrorKind::IncludeFailed(ref chapter, line, _) => {
+                    self.path = Some(chapter.clone());
+                    self.line = Some(line);
+                    ("include-failed", ErrorCategory::Content)
+                }
+                ErrorKind::InvalidFrontMatter(ref chapter, _) => {
+                    self.path = Some(chapter.clone());
+                    ("invalid-front-matter", ErrorCategory::Content)
+                }
+                _ => return,
+            };
+
+            self.kind = name;
+            self.category = category;
         }
     }
 
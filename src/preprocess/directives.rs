@@ -0,0 +1,300 @@
+//! Finding the `{{#name arguments}}` directives in a chapter.
+//!
+//! Directives inside inline code (`` `{{#include file.md}}` ``) are never
+//! touched, so a chapter can talk about the syntax. Those inside a fenced
+//! code block are found, but marked as such so a preprocessor can decide
+//! whether they mean anything there (an `{{#include}}` filling in a code block
+//! does, an unknown directive probably doesn't). A directive can also be
+//! escaped with a backslash (`\{{#include file.md}}`), in which case it should
+//! be replaced by the literal text without the backslash.
+
+use std::ops::Range;
+
+use regex::Regex;
+
+/// A `{{#name arguments}}` directive.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Directive<'a> {
+    /// Where the directive starts in the chapter (including the backslash of
+    /// an escaped directive).
+    pub start: usize,
+    /// Where the directive ends in the chapter.
+    pub end: usize,
+    /// The directive's name, e.g. `include`.
+    pub name: &'a str,
+    /// Everything after the name, with the surrounding whitespace trimmed.
+    pub args: &'a str,
+    /// The directive's full text.
+    pub text: &'a str,
+    /// Was the directive escaped with a backslash?
+    pub escaped: bool,
+    /// Is the directive inside a fenced code block?
+    pub in_code_block: bool,
+}
+
+impl<'a> Directive<'a> {
+    /// What an escaped directive should be replaced with, i.e. its text
+    /// without the backslash.
+    pub fn unescaped(&self) -> &'a str {
+        if self.escaped {
+            &self.text[1..]
+        } else {
+            self.text
+        }
+    }
+}
+
+/// Find every directive in a chapter, skipping those inside inline code.
+pub fn find_directives(text: &str) -> Vec<Directive> {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r"(?x)   # insignificant whitespace mode
+            (\\)?                                  # an optional escape
+            \{\{\s*                                # opening parens and whitespace
+            \#([a-zA-Z][a-zA-Z0-9_\-]*)            # the directive's name
+            ([\ \t][^}\n]*)?                       # its arguments
+            \}\}                                   # closing parens
+        ").unwrap();
+    }
+
+    let code_blocks = fenced_code_blocks(text);
+    let code_spans = inline_code_spans(text, &code_blocks);
+    let inside = |ranges: &[Range<usize>], ix: usize| {
+        ranges.iter().any(|range| range.start <= ix && ix < range.end)
+    };
+
+    RE.captures_iter(text)
+      .filter_map(|cap| {
+          let whole = cap.get(0).expect("The whole match always exists");
+          if inside(&code_spans, whole.start()) {
+              return None;
+          }
+
+          Some(Directive {
+              start: whole.start(),
+              end: whole.end(),
+              name: cap.get(2).expect("Directives always have a name").as_str(),
+              args: cap.get(3).map_or("", |args| args.as_str().trim()),
+              text: whole.as_str(),
+              escaped: cap.get(1).is_some(),
+              in_code_block: inside(&code_blocks, whole.start()),
+          })
+      })
+      .collect()
+}
+
+/// The byte ranges of a chapter which are code, i.e. the fenced code blocks
+/// and inline code spans, in no particular order.
+pub fn code_ranges(text: &str) -> Vec<Range<usize>> {
+    let mut ranges = fenced_code_blocks(text);
+    let spans = inline_code_spans(text, &ranges);
+    ranges.extend(spans);
+    ranges
+}
+
+/// The byte ranges of every fenced code block, from the start of its opening
+/// fence to the end of its closing fence (or the end of the chapter).
+fn fenced_code_blocks(text: &str) -> Vec<Range<usize>> {
+    let mut blocks = Vec::new();
+    let mut open: Option<(usize, char, usize)> = None;
+    let mut offset = 0;
+
+    for line in text.split('\n') {
+        let line_end = offset + line.len();
+        // fences in list items are indented, so any indentation is allowed
+        let trimmed = line.trim_left();
+        let marker = trimmed.chars().next().unwrap_or(' ');
+        let fence_len = trimmed.chars().take_while(|&c| c == marker).count();
+        let is_fence = (marker == '`' || marker == '~') && fence_len >= 3;
+
+        match open {
+            None if is_fence => open = Some((offset, marker, fence_len)),
+            Some((start, open_marker, open_len))
+                if is_fence && marker == open_marker && fence_len >= open_len
+                    && trimmed.trim_right().len() == fence_len =>
+            {
+                blocks.push(start..line_end);
+                open = None;
+            }
+            _ => {}
+        }
+
+        offset = line_end + 1;
+    }
+
+    if let Some((start, _, _)) = open {
+        blocks.push(start..text.len());
+    }
+
+    blocks
+}
+
+/// The byte ranges of every inline code span outside of the fenced code
+/// blocks. A span can't continue past the end of a paragraph.
+fn inline_code_spans(text: &str, code_blocks: &[Range<usize>]) -> Vec<Range<usize>> {
+    let bytes = text.as_bytes();
+    let mut spans = Vec::new();
+    let mut ix = 0;
+
+    while ix < bytes.len() {
+        if let Some(block) = code_blocks.iter().find(|block| block.start <= ix && ix < block.end) {
+            ix = block.end;
+            continue;
+        }
+
+        if bytes[ix] == b'\\' {
+            ix += 2;
+            continue;
+        }
+        if bytes[ix] != b'`' {
+            ix += 1;
+            continue;
+        }
+
+        let len = backtick_run(&bytes[ix..]);
+        let limit = paragraph_end(text, ix, code_blocks);
+        match find_closing_run(&bytes[ix + len..limit], len) {
+            Some(closing) => {
+                let end = ix + len + closing + len;
+                spans.push(ix..end);
+                ix = end;
+            }
+            // an unmatched run of backticks is just text
+            None => ix += len,
+        }
+    }
+
+    spans
+}
+
+fn backtick_run(bytes: &[u8]) -> usize {
+    bytes.iter().take_while(|&&b| b == b'`').count()
+}
+
+/// Where a run of exactly `len` backticks starts.
+fn find_closing_run(bytes: &[u8], len: usize) -> Option<usize> {
+    let mut ix = 0;
+
+    while ix < bytes.len() {
+        if bytes[ix] == b'`' {
+            let run = backtick_run(&bytes[ix..]);
+            if run == len {
+                return Some(ix);
+            }
+            ix += run;
+        } else {
+            ix += 1;
+        }
+    }
+
+    None
+}
+
+/// Where the paragraph containing `ix` ends: at the next blank line or code
+/// block, whichever comes first.
+fn paragraph_end(text: &str, ix: usize, code_blocks: &[Range<usize>]) -> usize {
+    lazy_static! {
+        static ref BLANK_LINE: Regex = Regex::new(r"\n[ \t]*\r?\n").unwrap();
+    }
+
+    let blank_line = BLANK_LINE.find(&text[ix..]).map_or(text.len(), |m| ix + m.start());
+    let code_block = code_blocks.iter()
+                                .map(|block| block.start)
+                                .filter(|&start| start > ix)
+                                .min()
+                                .unwrap_or(text.len());
+
+    blank_line.min(code_block)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(text: &str) -> Vec<&str> {
+        find_directives(text).iter().map(|d| d.name).collect()
+    }
+
+    #[test]
+    fn directives_are_found_with_their_arguments() {
+        let got = find_directives("Some text {{#title My Custom Title }} and {{ #toc}}.");
+
+        assert_eq!(got.len(), 2);
+        assert_eq!(got[0].name, "title");
+        assert_eq!(got[0].args, "My Custom Title");
+        assert_eq!(got[0].text, "{{#title My Custom Title }}");
+        assert_eq!(got[0].start, 10);
+        assert_eq!(got[0].end, 37);
+        assert!(!got[0].escaped);
+        assert!(!got[0].in_code_block);
+        assert_eq!(got[1].name, "toc");
+        assert_eq!(got[1].args, "");
+    }
+
+    #[test]
+    fn things_which_arent_directives_are_ignored() {
+        let src = "{{title}} {{#}} {{#1abc}} {{#include\nfile.md}} {{#include file.md";
+
+        assert!(find_directives(src).is_empty());
+    }
+
+    #[test]
+    fn inline_code_is_skipped() {
+        let src = "Use `{{#include file.md}}` or ``{{#title `x` }}``, but {{#title Real}}.";
+
+        assert_eq!(names(src), vec!["title"]);
+        assert_eq!(find_directives(src)[0].args, "Real");
+    }
+
+    #[test]
+    fn inline_code_doesnt_continue_past_the_paragraph() {
+        let src = "An unmatched ` backtick\n\n{{#title Real}} and `{{#not-this}}`";
+
+        assert_eq!(names(src), vec!["title"]);
+    }
+
+    #[test]
+    fn directives_in_fenced_code_blocks_are_marked() {
+        let src = "{{#title Outside}}\n\n\
+                   ```hbs\n{{#each chapters}}\n```\n\n\
+                   ~~~~\n```\n{{#include file.rs}}\n~~~~\n\
+                   {{#toc}}\n";
+
+        let got = find_directives(src);
+
+        let summary: Vec<_> = got.iter().map(|d| (d.name, d.in_code_block)).collect();
+        assert_eq!(summary,
+                   vec![("title", false), ("each", true), ("include", true), ("toc", false)]);
+    }
+
+    #[test]
+    fn backticks_in_a_code_block_dont_start_inline_code() {
+        let src = "```\nlet s = \"`\";\n```\n\n{{#title Real}} `x`\n";
+
+        assert_eq!(names(src), vec!["title"]);
+    }
+
+    #[test]
+    fn an_unclosed_code_block_runs_to_the_end() {
+        let got = find_directives("```\n{{#include file.rs}}\n");
+
+        assert!(got[0].in_code_block);
+    }
+
+    #[test]
+    fn escaped_directives_lose_their_backslash() {
+        let src = "Write \\{{#include file.md}} to include a file.";
+
+        let got = find_directives(src);
+
+        assert_eq!(got.len(), 1);
+        assert!(got[0].escaped);
+        assert_eq!(got[0].start, 6);
+        assert_eq!(got[0].text, "\\{{#include file.md}}");
+        assert_eq!(got[0].unescaped(), "{{#include file.md}}");
+    }
+
+    #[test]
+    fn escaped_directives_in_inline_code_are_left_alone() {
+        assert!(find_directives("`\\{{#include file.md}}`").is_empty());
+    }
+}
@@ -1,17 +1,21 @@
 use std::ops::{Range, RangeFrom, RangeFull, RangeTo};
 use std::path::{Path, PathBuf};
-use regex::{CaptureMatches, Captures, Regex};
+use toml::Value;
 use utils::fs::file_to_string;
 use utils::take_lines;
+use utils::string::{byte_offset_to_line_col, LineMap};
 use errors::*;
 
 use super::{Preprocessor, PreprocessorContext};
+use super::directives::{find_directives, Directive};
 use book::{Book, BookItem};
 
-const ESCAPE_CHAR: char = '\\';
-
 /// A preprocessor for expanding the `{{# playpen}}` and `{{# include}}` 
 /// helpers in a chapter.
+///
+/// It also handles the other directives: `{{#title My Title}}` sets the
+/// chapter's title (as if it was in the front matter), and any directive it
+/// doesn't know is removed with a warning.
 pub struct LinkPreprocessor;
 
 impl LinkPreprocessor {
@@ -28,50 +32,183 @@ impl Preprocessor for LinkPreprocessor {
 
     fn run(&self, ctx: &PreprocessorContext, book: &mut Book) -> Result<()> {
         let src_dir = ctx.root.join(&ctx.config.book.src);
+        let mut error = None;
 
         book.for_each_mut(|section: &mut BookItem| {
+            if error.is_some() {
+                return;
+            }
+
             if let BookItem::Chapter(ref mut ch) = *section {
-                let base = ch.path
+                // drafts have no file to resolve includes against
+                let path = match ch.path {
+                    Some(ref path) => path,
+                    None => return,
+                };
+                let base = path
                     .parent()
                     .map(|dir| src_dir.join(dir))
                     .expect("All book items have a parent");
-
-                let content = replace_all(&ch.content, base);
-                ch.content = content;
+                let chapter = ctx.config.book.src.join(path);
+
+                match replace_all(&ch.content, base, &chapter, &ch.line_map) {
+                    Ok(expanded) => {
+                        ch.content = expanded.content;
+                        ch.line_map = ch.line_map.then(&expanded.line_map);
+                        if let Some(title) = expanded.title {
+                            ch.metadata.insert(String::from("title"), Value::String(title));
+                        }
+                    }
+                    Err(e) => error = Some(e),
+                }
             }
         });
 
-        Ok(())
+        match error {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
     }
 }
 
-fn replace_all<P: AsRef<Path>>(s: &str, path: P) -> String {
+/// A chapter's contents after the directives in it have been dealt with.
+#[derive(Debug, PartialEq)]
+struct Expanded {
+    content: String,
+    /// Set by a `{{#title}}` directive.
+    title: Option<String>,
+    /// Which line of the original contents each line of `content` came from.
+    line_map: LineMap,
+}
+
+/// Expand every link in a chapter's contents. `chapter` and `line_map` (the
+/// chapter's own map from its contents to its file) are only used to say
+/// where a link which can't be expanded is.
+fn replace_all<P: AsRef<Path>>(s: &str,
+                               path: P,
+                               chapter: &Path,
+                               line_map: &LineMap)
+                               -> Result<Expanded> {
     // When replacing one thing in a string by something with a different length,
     // the indices after that will not correspond,
     // we therefore have to store the difference to correct this
     let path = path.as_ref();
     let mut previous_end_index = 0;
-    let mut replaced = String::new();
+    let mut replaced = LineTracker::new(s);
+    let mut title = None;
+
+    for directive in find_directives(s) {
+        replaced.copy(previous_end_index, &s[previous_end_index..directive.start]);
+        previous_end_index = directive.end;
+
+        if let Some(link) = Link::from_directive(&directive) {
+            match link.render_with_path(&path) {
+                Ok(new_content) => replaced.insert(directive.start, &new_content),
+                Err(e) => {
+                    let (line, _) = location(s, link.start_index, line_map);
+                    let kind = ErrorKind::IncludeFailed(
+                        chapter.to_path_buf(),
+                        line,
+                        link.link_text.to_string(),
+                    );
+                    return Err(Error::with_chain(e, kind));
+                }
+            }
+            continue;
+        }
+
+        let (line, column) = location(s, directive.start, line_map);
+        match directive.name {
+            // include and playpen links without a file are left as they are
+            _ if directive.in_code_block || is_link(&directive) => {
+                replaced.copy(directive.start, directive.text)
+            }
+            "title" if !directive.args.is_empty() => title = Some(directive.args.to_string()),
+            "title" => warn!("{}:{}:{}: Ignoring {}, it needs a title",
+                             chapter.display(),
+                             line,
+                             column,
+                             directive.text),
+            name => warn!("{}:{}:{}: Ignoring the unknown directive \"{}\" ({})",
+                          chapter.display(),
+                          line,
+                          column,
+                          name,
+                          directive.text),
+        }
+    }
+
+    replaced.copy(previous_end_index, &s[previous_end_index..]);
+    let (content, lines) = replaced.finish();
+    Ok(Expanded {
+        content: content,
+        title: title,
+        line_map: LineMap::new(lines),
+    })
+}
+
+/// The line (in the chapter's file) and column of a byte offset in its
+/// contents.
+fn location(s: &str, offset: usize, line_map: &LineMap) -> (usize, usize) {
+    let (line, column) = byte_offset_to_line_col(s, offset);
+    (line_map.original_line(line), column)
+}
+
+/// Builds up the expanded contents of a chapter, keeping track of which line
+/// of the original each of its lines came from.
+struct LineTracker<'a> {
+    original: &'a str,
+    expanded: String,
+    lines: Vec<usize>,
+}
 
-    for playpen in find_links(s) {
-        replaced.push_str(&s[previous_end_index..playpen.start_index]);
+impl<'a> LineTracker<'a> {
+    fn new(original: &'a str) -> LineTracker<'a> {
+        LineTracker {
+            original: original,
+            expanded: String::with_capacity(original.len()),
+            lines: Vec::new(),
+        }
+    }
+
+    /// Add `text`, which is copied from byte `start` of the original.
+    fn copy(&mut self, start: usize, text: &str) {
+        self.push(start, text, true);
+    }
+
+    /// Add `text` in place of whatever's at byte `start` of the original.
+    fn insert(&mut self, start: usize, text: &str) {
+        self.push(start, text, false);
+    }
 
-        match playpen.render_with_path(&path) {
-            Ok(new_content) => {
-                replaced.push_str(&new_content);
-                previous_end_index = playpen.end_index;
+    fn push(&mut self, start: usize, text: &str, copied: bool) {
+        if text.is_empty() {
+            return;
+        }
+
+        let mut line = byte_offset_to_line_col(self.original, start).0;
+        let mut at_line_start = self.expanded.is_empty() || self.expanded.ends_with('\n');
+
+        for c in text.chars() {
+            if at_line_start {
+                self.lines.push(line);
             }
-            Err(e) => {
-                error!("Error updating \"{}\", {}", playpen.link_text, e);
-                // This should make sure we include the raw `{{# ... }}` snippet
-                // in the page content if there are any errors.
-                previous_end_index = playpen.start_index;
+            at_line_start = c == '\n';
+            if at_line_start && copied {
+                line += 1;
             }
         }
+
+        self.expanded.push_str(text);
     }
 
-    replaced.push_str(&s[previous_end_index..]);
-    replaced
+    fn finish(self) -> (String, Vec<usize>) {
+        (self.expanded, self.lines)
+    }
+}
+
+fn is_link(directive: &Directive) -> bool {
+    directive.name == "include" || directive.name == "playpen"
 }
 
 #[derive(PartialEq, Debug, Clone)]
@@ -116,32 +253,26 @@ struct Link<'a> {
 }
 
 impl<'a> Link<'a> {
-    fn from_capture(cap: Captures<'a>) -> Option<Link<'a>> {
-        let link_type = match (cap.get(0), cap.get(1), cap.get(2)) {
-            (_, Some(typ), Some(rest)) => {
-                let mut path_props = rest.as_str().split_whitespace();
-                let file_arg = path_props.next();
-                let props: Vec<&str> = path_props.collect();
-
-                match (typ.as_str(), file_arg) {
-                    ("include", Some(pth)) => Some(parse_include_path(pth)),
-                    ("playpen", Some(pth)) => Some(LinkType::Playpen(pth.into(), props)),
-                    _ => None,
-                }
+    fn from_directive(directive: &Directive<'a>) -> Option<Link<'a>> {
+        let link_type = if directive.escaped {
+            Some(LinkType::Escaped)
+        } else {
+            let mut path_props = directive.args.split_whitespace();
+            let file_arg = path_props.next();
+            let props: Vec<&str> = path_props.collect();
+
+            match (directive.name, file_arg) {
+                ("include", Some(pth)) => Some(parse_include_path(pth)),
+                ("playpen", Some(pth)) => Some(LinkType::Playpen(pth.into(), props)),
+                _ => None,
             }
-            (Some(mat), None, None) if mat.as_str().starts_with(ESCAPE_CHAR) => {
-                Some(LinkType::Escaped)
-            }
-            _ => None,
         };
 
-        link_type.and_then(|lnk| {
-            cap.get(0).map(|mat| Link {
-                start_index: mat.start(),
-                end_index: mat.end(),
-                link: lnk,
-                link_text: mat.as_str(),
-            })
+        link_type.map(|lnk| Link {
+            start_index: directive.start,
+            end_index: directive.end,
+            link: lnk,
+            link_text: directive.text,
         })
     }
 
@@ -176,40 +307,22 @@ impl<'a> Link<'a> {
     }
 }
 
-struct LinkIter<'a>(CaptureMatches<'a, 'a>);
-
-impl<'a> Iterator for LinkIter<'a> {
-    type Item = Link<'a>;
-    fn next(&mut self) -> Option<Link<'a>> {
-        for cap in &mut self.0 {
-            if let Some(inc) = Link::from_capture(cap) {
-                return Some(inc);
-            }
-        }
-        None
-    }
-}
-
-fn find_links(contents: &str) -> LinkIter {
-    // lazily compute following regex
-    // r"\\\{\{#.*\}\}|\{\{#([a-zA-Z0-9]+)\s*([a-zA-Z0-9_.\-:/\\\s]+)\}\}")?;
-    lazy_static! {
-        static ref RE: Regex = Regex::new(r"(?x) # insignificant whitespace mode
-                    \\\{\{\#.*\}\}               # match escaped link
-                    |                            # or
-                    \{\{\s*                      # link opening parens and whitespace
-                      \#([a-zA-Z0-9]+)           # link type
-                      \s+                        # separating whitespace
-                      ([a-zA-Z0-9\s_.\-:/\\]+)   # link target path and space separated properties
-                    \s*\}\}                      # whitespace and link closing parens
-                                 ").unwrap();
-    }
-    LinkIter(RE.captures_iter(contents))
+fn find_links(contents: &str) -> ::std::vec::IntoIter<Link> {
+    find_directives(contents)
+        .iter()
+        .filter_map(Link::from_directive)
+        .collect::<Vec<_>>()
+        .into_iter()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::io::Write;
+
+    fn expand<P: AsRef<Path>>(s: &str, path: P) -> Result<Expanded> {
+        replace_all(s, path, Path::new("src/chapter.md"), &LineMap::default())
+    }
 
     #[test]
     fn test_find_links_no_link() {
@@ -445,4 +558,105 @@ mod tests {
         );
     }
 
+    #[test]
+    fn a_missing_include_says_where_it_is() {
+        let s = "# Chapter\n\nSome text {{#include missing.md}}\n";
+
+        let err = expand(s, "/nonexistent").unwrap_err();
+
+        match *err.kind() {
+            ErrorKind::IncludeFailed(ref chapter, line, ref link) => {
+                assert_eq!(*chapter, Path::new("src/chapter.md"));
+                assert_eq!(line, 3);
+                assert_eq!(*link, "{{#include missing.md}}");
+            }
+            ref other => panic!("Unexpected error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn the_title_directive_is_removed_from_the_content() {
+        let s = "{{#title My Custom Title}}\n# Chapter\n";
+
+        let got = expand(s, "/nonexistent").unwrap();
+
+        assert_eq!(got.content, "\n# Chapter\n");
+        assert_eq!(got.title, Some(String::from("My Custom Title")));
+    }
+
+    #[test]
+    fn unknown_directives_are_removed() {
+        let s = "Some {{#frobnicate all the things}}text.\n";
+
+        let got = expand(s, "/nonexistent").unwrap();
+
+        assert_eq!(got.content, "Some text.\n");
+        assert_eq!(got.title, None);
+    }
+
+    #[test]
+    fn directives_in_code_are_left_alone() {
+        let s = "Use `{{#include missing.md}}` or `{{#title Foo}}`.\n\n\
+                 ```hbs\n{{#each chapters}}{{#title Foo}}\n```\n";
+
+        let got = expand(s, "/nonexistent").unwrap();
+
+        assert_eq!(got.content, s);
+        assert_eq!(got.title, None);
+    }
+
+    #[test]
+    fn escaped_directives_are_written_out_without_the_backslash() {
+        let s = "Write \\{{#title Foo}} or\n\n```\n\\{{#include file.rs}}\n```\n";
+
+        let got = expand(s, "/nonexistent").unwrap();
+
+        assert_eq!(got.content, "Write {{#title Foo}} or\n\n```\n{{#include file.rs}}\n```\n");
+        assert_eq!(got.title, None);
+    }
+
+    #[test]
+    fn links_in_code_blocks_are_still_expanded() {
+        let temp = ::tempdir::TempDir::new("mdbook").unwrap();
+        ::utils::fs::create_file(&temp.path().join("example.rs"))
+            .unwrap()
+            .write_all(b"fn main() {}")
+            .unwrap();
+        let s = "```rust\n{{#include example.rs}}\n```\n";
+
+        let got = expand(s, temp.path()).unwrap();
+
+        assert_eq!(got.content, "```rust\nfn main() {}\n```\n");
+    }
+
+    #[test]
+    fn included_lines_map_back_to_the_include() {
+        let temp = ::tempdir::TempDir::new("mdbook").unwrap();
+        ::utils::fs::create_file(&temp.path().join("part.md"))
+            .unwrap()
+            .write_all(b"a\nb\nc")
+            .unwrap();
+        let s = "# Chapter\n\n{{#include part.md}}\nAfter\n";
+
+        let got = expand(s, temp.path()).unwrap();
+
+        assert_eq!(got.content, "# Chapter\n\na\nb\nc\nAfter\n");
+        let lines: Vec<_> = (1..7).map(|line| got.line_map.original_line(line)).collect();
+        assert_eq!(lines, vec![1, 2, 3, 3, 3, 4]);
+    }
+
+    #[test]
+    fn a_missing_include_says_which_line_of_the_file_it_is_on() {
+        let s = "# Chapter\n\n{{#include missing.md}}\n";
+        // the chapter's file starts with 2 lines of front matter
+        let line_map = LineMap::skipping(2, 3);
+
+        let err = replace_all(s, "/nonexistent", Path::new("src/chapter.md"), &line_map)
+            .unwrap_err();
+
+        match *err.kind() {
+            ErrorKind::IncludeFailed(_, line, _) => assert_eq!(line, 5),
+            ref other => panic!("Unexpected error: {:?}", other),
+        }
+    }
 }
@@ -1,8 +1,14 @@
 //! Book preprocessing.
 
+pub use self::directives::{find_directives, Directive};
 pub use self::links::LinkPreprocessor;
+pub use self::variables::VariablesPreprocessor;
+pub use self::wiki_links::WikiLinkPreprocessor;
 
+mod directives;
 mod links;
+mod variables;
+mod wiki_links;
 
 use book::Book;
 use config::Config;
@@ -0,0 +1,163 @@
+use regex::{Captures, Regex};
+use toml::Value;
+
+use config::Config;
+use errors::*;
+
+use super::{Preprocessor, PreprocessorContext};
+use super::directives::code_ranges;
+use book::{Book, BookItem, Chapter};
+
+/// A preprocessor for filling in variables like `{{ book.title }}` or
+/// `{{ chapter.number }}` in a chapter.
+///
+/// The variables are:
+///
+/// - `book.title`, `book.authors` and `book.description`, from `book.toml`.
+/// - `chapter.title` and `chapter.number`, for the chapter they're in.
+/// - `var.<name>`, for anything in the `[book.variables]` table.
+///
+/// Anything inside a code span or fenced code block is left alone, and so is
+/// a variable which doesn't exist (or doesn't have a value), so handlebars
+/// templates and the like don't get mangled.
+pub struct VariablesPreprocessor;
+
+impl VariablesPreprocessor {
+    /// Create a new `VariablesPreprocessor`.
+    pub fn new() -> Self {
+        VariablesPreprocessor
+    }
+}
+
+impl Preprocessor for VariablesPreprocessor {
+    fn name(&self) -> &str {
+        "variables"
+    }
+
+    fn run(&self, ctx: &PreprocessorContext, book: &mut Book) -> Result<()> {
+        book.for_each_mut(|section: &mut BookItem| {
+            if let BookItem::Chapter(ref mut ch) = *section {
+                let replaced = replace_all(&ch.content, &ctx.config, ch);
+                ch.content = replaced;
+            }
+        });
+
+        Ok(())
+    }
+}
+
+fn replace_all(s: &str, config: &Config, chapter: &Chapter) -> String {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r"(?x)   # insignificant whitespace mode
+            (\\)?                                  # an optional escape
+            \{\{\s*                                # opening parens and whitespace
+            ([a-z]+)\.([a-zA-Z0-9_\-]+)            # the variable's name
+            \s*\}\}                                # whitespace and closing parens
+        ").unwrap();
+    }
+
+    let code = code_ranges(s);
+
+    RE.replace_all(s, |caps: &Captures| {
+        let whole = caps.get(0).expect("The whole match always exists");
+        let in_code = code.iter()
+                          .any(|range| range.start <= whole.start() && whole.start() < range.end);
+
+        let value = if caps.get(1).is_some() || in_code {
+            None
+        } else {
+            lookup(config, chapter, &caps[2], &caps[3])
+        };
+
+        value.unwrap_or_else(|| whole.as_str().to_string())
+    }).into_owned()
+}
+
+/// The value of a variable, if it exists.
+fn lookup(config: &Config, chapter: &Chapter, namespace: &str, name: &str) -> Option<String> {
+    match (namespace, name) {
+        ("book", "title") => config.book.title.clone(),
+        ("book", "authors") if !config.book.authors.is_empty() => {
+            Some(config.book.authors.join(", "))
+        }
+        ("book", "description") => config.book.description.clone(),
+        ("chapter", "title") => Some(chapter.name.clone()),
+        ("chapter", "number") => chapter.number.as_ref().map(|number| {
+            number.iter()
+                  .map(|n| n.to_string())
+                  .collect::<Vec<_>>()
+                  .join(".")
+        }),
+        ("var", _) => config.book.variables.get(name).map(|value| match *value {
+            Value::String(ref s) => s.clone(),
+            ref other => other.to_string(),
+        }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use book::SectionNumber;
+
+    fn config() -> Config {
+        let mut config = Config::default();
+        config.book.title = Some(String::from("My Book"));
+        config.book.authors = vec![String::from("Jane"), String::from("John")];
+        config.book
+              .variables
+              .insert(String::from("version"), Value::String(String::from("1.2.0")));
+        config.book.variables.insert(String::from("year"), Value::Integer(2018));
+        config
+    }
+
+    fn chapter() -> Chapter {
+        let mut ch = Chapter::new("Getting Started", String::new(), "getting-started.md");
+        ch.number = Some(SectionNumber(vec![2, 1]));
+        ch
+    }
+
+    fn replace(s: &str) -> String {
+        replace_all(s, &config(), &chapter())
+    }
+
+    #[test]
+    fn book_and_chapter_variables_are_filled_in() {
+        let got = replace("{{ book.title }} by {{book.authors}}, chapter {{ chapter.number }}: \
+                           {{ chapter.title }}");
+
+        assert_eq!(got, "My Book by Jane, John, chapter 2.1: Getting Started");
+    }
+
+    #[test]
+    fn user_defined_variables_are_filled_in() {
+        let got = replace("Version {{ var.version }} ({{ var.year }})");
+
+        assert_eq!(got, "Version 1.2.0 (2018)");
+    }
+
+    #[test]
+    fn unknown_variables_are_left_alone() {
+        let src = "{{ var.missing }}, {{ book.description }}, {{ this.name }} and {{#include x.md}}";
+
+        assert_eq!(replace(src), src);
+    }
+
+    #[test]
+    fn variables_in_code_are_left_alone() {
+        let src = "`{{ book.title }}` and ``a ` {{ book.title }}``\n\n\
+                   ```handlebars\n<title>{{ book.title }}</title>\n```\n\n{{ book.title }}\n";
+        let should_be = "`{{ book.title }}` and ``a ` {{ book.title }}``\n\n\
+                         ```handlebars\n<title>{{ book.title }}</title>\n```\n\nMy Book\n";
+
+        assert_eq!(replace(src), should_be);
+    }
+
+    #[test]
+    fn escaped_variables_are_left_alone() {
+        let src = r"\{{ book.title }}";
+
+        assert_eq!(replace(src), src);
+    }
+}
@@ -0,0 +1,214 @@
+use regex::{Captures, Regex};
+
+use utils;
+use errors::*;
+
+use super::{Preprocessor, PreprocessorContext};
+use book::{Book, BookItem};
+
+/// A preprocessor for turning wiki-style links (`[[Getting Started]]` or
+/// `[[Getting Started|intro]]`) into normal markdown links to the
+/// corresponding page (`getting-started.html` or `intro.html`).
+///
+/// Anything inside a code span or fenced code block is left alone.
+pub struct WikiLinkPreprocessor;
+
+impl WikiLinkPreprocessor {
+    /// Create a new `WikiLinkPreprocessor`.
+    pub fn new() -> Self {
+        WikiLinkPreprocessor
+    }
+}
+
+impl Preprocessor for WikiLinkPreprocessor {
+    fn name(&self) -> &str {
+        "wiki-links"
+    }
+
+    fn run(&self, _ctx: &PreprocessorContext, book: &mut Book) -> Result<()> {
+        book.for_each_mut(|section: &mut BookItem| {
+            if let BookItem::Chapter(ref mut ch) = *section {
+                ch.content = replace_all(&ch.content);
+            }
+        });
+
+        Ok(())
+    }
+}
+
+fn replace_all(s: &str) -> String {
+    let mut replaced = String::with_capacity(s.len());
+    // the text since the last fenced code block
+    let mut text = String::new();
+    // the backticks or tildes which opened the current fenced code block
+    let mut fence: Option<String> = None;
+
+    let mut rest = s;
+    while !rest.is_empty() {
+        let end = rest.find('\n').map(|ix| ix + 1).unwrap_or_else(|| rest.len());
+        let line = &rest[..end];
+        rest = &rest[end..];
+
+        let indent = line.len() - line.trim_left_matches(' ').len();
+        let trimmed = line.trim_left_matches(' ');
+
+        let closes_fence = match fence {
+            Some(ref marker) => indent < 4 && trimmed.starts_with(marker.as_str()),
+            None => false,
+        };
+        let opens_fence = if indent < 4 {
+            opening_fence(trimmed)
+        } else {
+            None
+        };
+
+        if fence.is_some() {
+            replaced.push_str(line);
+            if closes_fence {
+                fence = None;
+            }
+        } else if let Some(marker) = opens_fence {
+            replaced.push_str(&replace_outside_code_spans(&text));
+            text.clear();
+            replaced.push_str(line);
+            fence = Some(marker);
+        } else {
+            text.push_str(line);
+        }
+    }
+
+    replaced.push_str(&replace_outside_code_spans(&text));
+    replaced
+}
+
+/// If this (unindented) line starts a fenced code block, get the run of
+/// backticks or tildes which will close it.
+fn opening_fence(line: &str) -> Option<String> {
+    for fence_char in &['`', '~'] {
+        let len = line.len() - line.trim_left_matches(*fence_char).len();
+        if len >= 3 {
+            return Some(line[..len].to_string());
+        }
+    }
+
+    None
+}
+
+fn replace_outside_code_spans(text: &str) -> String {
+    let mut replaced = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('`') {
+        replaced.push_str(&replace_wiki_links(&rest[..start]));
+
+        let ticks = backtick_run(&rest[start..]);
+        let after = start + ticks;
+
+        match find_backtick_run(&rest[after..], ticks) {
+            Some(close) => {
+                let end = after + close + ticks;
+                replaced.push_str(&rest[start..end]);
+                rest = &rest[end..];
+            }
+            None => {
+                // an unmatched run of backticks is just text
+                replaced.push_str(&rest[start..after]);
+                rest = &rest[after..];
+            }
+        }
+    }
+
+    replaced.push_str(&replace_wiki_links(rest));
+    replaced
+}
+
+/// How many backticks does `s` start with?
+fn backtick_run(s: &str) -> usize {
+    s.len() - s.trim_left_matches('`').len()
+}
+
+/// Find a run of exactly `len` backticks, the end of a code span.
+fn find_backtick_run(s: &str, len: usize) -> Option<usize> {
+    let mut offset = 0;
+
+    while let Some(ix) = s[offset..].find('`') {
+        let start = offset + ix;
+        let run = backtick_run(&s[start..]);
+        if run == len {
+            return Some(start);
+        }
+        offset = start + run;
+    }
+
+    None
+}
+
+fn replace_wiki_links(s: &str) -> String {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r"\[\[([^\[\]|\n]+)(?:\|([^\[\]|\n]+))?\]\]").unwrap();
+    }
+
+    RE.replace_all(s, |caps: &Captures| {
+        let title = caps[1].trim();
+        let slug = match caps.get(2) {
+            Some(target) => target.as_str().trim().to_string(),
+            None => utils::normalize_id(title),
+        };
+
+        if title.is_empty() || slug.is_empty() {
+            caps[0].to_string()
+        } else {
+            format!("[{}]({}.html)", title, slug)
+        }
+    }).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_plain_wiki_link_uses_the_slugified_title() {
+        let got = replace_all("See [[Getting Started]] for more.");
+        assert_eq!(got, "See [Getting Started](getting-started.html) for more.");
+    }
+
+    #[test]
+    fn a_piped_wiki_link_uses_the_target() {
+        let got = replace_all("See [[the introduction|intro]].\n");
+        assert_eq!(got, "See [the introduction](intro.html).\n");
+    }
+
+    #[test]
+    fn wiki_links_in_code_spans_are_left_alone() {
+        let inputs = vec![
+            "Write `[[Getting Started]]` to link a page.",
+            "Or ``let x = `[[Page]]`;`` with more backticks.",
+            "A span\nwhich is `split over [[Two Lines]]\nof text`.",
+        ];
+
+        for src in inputs {
+            assert_eq!(replace_all(src), src);
+        }
+    }
+
+    #[test]
+    fn wiki_links_in_fenced_code_blocks_are_left_alone() {
+        let src = "[[Before]]\n\n```rust\nlet x = a[[0]];\n```\n\n~~~~\n[[Tilde]]\n~~~~\n[[After]]\n";
+        let should_be = "[Before](before.html)\n\n```rust\nlet x = a[[0]];\n```\n\n~~~~\n[[Tilde]]\n~~~~\n[After](after.html)\n";
+
+        assert_eq!(replace_all(src), should_be);
+    }
+
+    #[test]
+    fn text_around_unmatched_backticks_is_still_processed() {
+        let got = replace_all("A lone ` and [[A Page]]");
+        assert_eq!(got, "A lone ` and [A Page](a-page.html)");
+    }
+
+    #[test]
+    fn normal_links_and_references_are_untouched() {
+        let src = "[normal](./normal.md) and [ref][1] and [[]] and [[ ]]";
+        assert_eq!(replace_all(src), src);
+    }
+}
@@ -0,0 +1,585 @@
+//! The built-in EPUB renderer, which packs the whole book into a single
+//! `.epub` file for e-readers.
+
+mod zip;
+
+use std::fs::File;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+use chrono::Utc;
+use pulldown_cmark::html;
+
+use book::{BookItem, Chapter};
+use config::Config;
+use errors::*;
+use renderer::html_handlebars::theme_dir;
+use renderer::{RenderContext, Renderer};
+use theme::Theme;
+use utils::{self, AssetLinkFilter, ChangeExtLinkFilter, LinkFilter, MarkdownOptions,
+            RelativeLinkConverter};
+use self::zip::ZipWriter;
+
+/// Where everything except `mimetype` and `META-INF/container.xml` goes.
+const CONTENT_DIR: &str = "OEBPS";
+/// Where chapters and the files they link to go, relative to `CONTENT_DIR`.
+const TEXT_DIR: &str = "text";
+
+/// A renderer which writes the book as an EPUB 3 file, named after the
+/// book's title, with a page for every chapter and a table of contents
+/// following `SUMMARY.md`.
+///
+/// Chapters are rendered from plain markdown (none of the HTML renderer's
+/// options apply), and styled with the theme's `book.css`.
+#[derive(Default)]
+pub struct EpubRenderer;
+
+impl EpubRenderer {
+    /// Create a new `EpubRenderer` instance.
+    pub fn new() -> Self {
+        EpubRenderer
+    }
+}
+
+impl Renderer for EpubRenderer {
+    fn name(&self) -> &str {
+        "epub"
+    }
+
+    fn render(&self, ctx: &RenderContext) -> Result<()> {
+        let src_dir = ctx.source_dir();
+        let html_config = ctx.config.html_config().unwrap_or_default();
+        let theme = Theme::new(theme_dir(&ctx.root, &src_dir, &html_config));
+        let is_file = |path: &Path| src_dir.join(path).is_file();
+
+        let mut chapters = Vec::new();
+        let mut assets: Vec<PathBuf> = Vec::new();
+
+        for ch in ctx.book.reading_order() {
+            let path = match ch.path {
+                Some(ref path) => path,
+                None => continue,
+            };
+            debug!("Rendering {} for the EPUB", path.display());
+
+            for asset in utils::linked_assets(&ch.content, path, &ch.line_map, &is_file) {
+                if !assets.contains(&asset) {
+                    assets.push(asset);
+                }
+            }
+            chapters.push((chapter_page(path), render_page(ch, path, &is_file)));
+        }
+
+        let metadata = Metadata::from_config(&ctx.config);
+        let mut manifest = Vec::new();
+
+        for (i, &(ref page, _)) in chapters.iter().enumerate() {
+            manifest.push(ManifestItem {
+                id: format!("chapter-{}", i + 1),
+                href: href(page),
+                media_type: "application/xhtml+xml",
+            });
+        }
+        let spine = manifest.len();
+        for (i, asset) in assets.iter().enumerate() {
+            manifest.push(ManifestItem {
+                id: format!("asset-{}", i + 1),
+                href: href(&Path::new(TEXT_DIR).join(asset)),
+                media_type: media_type(asset),
+            });
+        }
+
+        let output = ctx.destination.join(format!("{}.epub", metadata.file_stem()));
+        debug!("Writing {}", output.display());
+        let file = utils::fs::create_file(&output)?;
+        let mut zip = ZipWriter::new(file, Utc::now().naive_utc());
+
+        // readers find out what the file is from `mimetype`, which has to
+        // come first
+        zip.add_file("mimetype", b"application/epub+zip")?;
+        zip.add_file("META-INF/container.xml", container_xml().as_bytes())?;
+        zip.add_file(&content_path("content.opf"),
+                     package_document(&metadata, &manifest, &manifest[..spine]).as_bytes())?;
+        zip.add_file(&content_path("nav.xhtml"),
+                     nav_document(&metadata, ctx.book.sections()).as_bytes())?;
+        zip.add_file(&content_path("toc.ncx"),
+                     ncx_document(&metadata, ctx.book.sections()).as_bytes())?;
+        zip.add_file(&content_path("book.css"), &theme.css)?;
+
+        for (page, content) in chapters {
+            let document = xhtml_page(&metadata, &page, &content);
+            zip.add_file(&content_path(&page.to_string_lossy()), document.as_bytes())?;
+        }
+        for asset in assets {
+            let mut contents = Vec::new();
+            File::open(src_dir.join(&asset))
+                .and_then(|mut f| f.read_to_end(&mut contents))
+                .chain_err(|| format!("Unable to read {}", asset.display()))?;
+            let name = Path::new(TEXT_DIR).join(&asset);
+            zip.add_file(&content_path(&name.to_string_lossy()), &contents)?;
+        }
+
+        zip.finish()?;
+        Ok(())
+    }
+}
+
+/// What the EPUB says about the book.
+struct Metadata {
+    title: String,
+    authors: Vec<String>,
+    description: Option<String>,
+    language: String,
+}
+
+impl Metadata {
+    fn from_config(config: &Config) -> Metadata {
+        Metadata {
+            title: config.book.title.clone().unwrap_or_else(|| "Untitled".to_string()),
+            authors: config.book.authors.clone(),
+            description: config.book.description.clone(),
+            language: config.book.language.clone().unwrap_or_else(|| "en".to_string()),
+        }
+    }
+
+    /// The name of the `.epub` file, without the extension.
+    fn file_stem(&self) -> String {
+        let slug = utils::slug::slugify(&self.title);
+        if slug.is_empty() {
+            "book".to_string()
+        } else {
+            slug
+        }
+    }
+}
+
+/// A file listed in the package document, other than the navigation
+/// document and the NCX (which are always there).
+struct ManifestItem {
+    id: String,
+    /// Relative to `CONTENT_DIR`, and already percent-encoded.
+    href: String,
+    media_type: &'static str,
+}
+
+/// Where a chapter's page is, relative to `CONTENT_DIR`.
+fn chapter_page(path: &Path) -> PathBuf {
+    Path::new(TEXT_DIR).join(path.with_extension("xhtml"))
+}
+
+/// A path relative to `CONTENT_DIR`, as a path inside the archive.
+fn content_path(path: &str) -> String {
+    format!("{}/{}", CONTENT_DIR, path.replace('\\', "/"))
+}
+
+/// A path relative to `CONTENT_DIR`, as a link from one of the files at the
+/// top of it (like `content.opf`).
+fn href(path: &Path) -> String {
+    path.components()
+        .map(|c| utils::percent_encode(&c.as_os_str().to_string_lossy()))
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// The media type of a file the chapters link to, going by its extension.
+fn media_type(path: &Path) -> &'static str {
+    let ext = path.extension()
+                  .map(|ext| ext.to_string_lossy().to_lowercase())
+                  .unwrap_or_default();
+
+    match ext.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "webp" => "image/webp",
+        "css" => "text/css",
+        "js" => "application/javascript",
+        "html" | "xhtml" => "application/xhtml+xml",
+        "ttf" => "font/ttf",
+        "otf" => "font/otf",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "mp3" => "audio/mpeg",
+        "mp4" => "video/mp4",
+        "pdf" => "application/pdf",
+        "txt" => "text/plain",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Makes the links another `LinkFilter` rewrites, which are relative to the
+/// root of the book, relative to the chapter's own page again. There's no
+/// `<base>` in an EPUB to take care of that.
+struct FromChapter<'a> {
+    inner: &'a LinkFilter,
+    to_root: &'a str,
+}
+
+impl<'a> LinkFilter for FromChapter<'a> {
+    fn apply(&self, dest: &str) -> Option<String> {
+        self.inner.apply(dest).map(|link| format!("{}{}", self.to_root, link))
+    }
+}
+
+/// Render a chapter's markdown, with links to other chapters pointing at
+/// their `.xhtml` pages.
+fn render_page<F>(ch: &Chapter, path: &Path, is_file: F) -> String
+    where F: Fn(&Path) -> bool
+{
+    let base = path.parent().unwrap_or_else(|| Path::new(""));
+    let depth = base.components().filter(|c| *c != Component::CurDir).count();
+    let to_root = "../".repeat(depth);
+
+    let pages = ChangeExtLinkFilter::with_extensions(base, &["md"], "xhtml", &is_file);
+    let assets = AssetLinkFilter::new(base, &is_file);
+    let pages = FromChapter { inner: &pages, to_root: &to_root };
+    let assets = FromChapter { inner: &assets, to_root: &to_root };
+    let page_links = RelativeLinkConverter::new(Some(&pages));
+    let asset_links = RelativeLinkConverter::new(Some(&assets)).and_images();
+
+    let events = utils::markdown_events(&ch.content, &MarkdownOptions::default(), None, |_| false)
+        .map(|event| page_links.convert(event))
+        .map(|event| asset_links.convert(event));
+
+    let events = utils::split_code_block_attributes(events);
+
+    let mut rendered = String::with_capacity(ch.content.len() * 2);
+    html::push_html(&mut rendered, events.into_iter());
+    rendered
+}
+
+/// The link to `CONTENT_DIR` from a page at `page`.
+fn to_content_dir(page: &Path) -> String {
+    let depth = page.parent().map(|dir| dir.components().count()).unwrap_or(0);
+    "../".repeat(depth)
+}
+
+fn xhtml_page(metadata: &Metadata, page: &Path, content: &str) -> String {
+    format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<!DOCTYPE html>
+<html xmlns=\"http://www.w3.org/1999/xhtml\" xmlns:epub=\"http://www.idpf.org/2007/ops\" \
+lang=\"{lang}\" xml:lang=\"{lang}\">
+<head>
+<meta charset=\"UTF-8\"/>
+<title>{title}</title>
+<link rel=\"stylesheet\" type=\"text/css\" href=\"{css}book.css\"/>
+</head>
+<body>
+{content}</body>
+</html>
+",
+            lang = utils::escape_html(&metadata.language),
+            title = utils::escape_html(&metadata.title),
+            css = to_content_dir(page),
+            content = content)
+}
+
+fn container_xml() -> String {
+    format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<container version=\"1.0\" xmlns=\"urn:oasis:names:tc:opendocument:xmlns:container\">
+<rootfiles>
+<rootfile full-path=\"{}\" media-type=\"application/oebps-package+xml\"/>
+</rootfiles>
+</container>
+",
+            content_path("content.opf"))
+}
+
+/// The `content.opf`, which lists every file in the book and the order
+/// they're read in (the `spine`).
+fn package_document(metadata: &Metadata, manifest: &[ManifestItem], spine: &[ManifestItem])
+                    -> String {
+    let mut meta = String::new();
+    for author in &metadata.authors {
+        meta.push_str(&format!("<dc:creator>{}</dc:creator>\n", utils::escape_html(author)));
+    }
+    if let Some(ref description) = metadata.description {
+        meta.push_str(&format!("<dc:description>{}</dc:description>\n",
+                               utils::escape_html(description)));
+    }
+
+    let items: String = manifest.iter()
+        .map(|item| {
+            format!("<item id=\"{}\" href=\"{}\" media-type=\"{}\"/>\n",
+                    item.id,
+                    item.href,
+                    item.media_type)
+        })
+        .collect();
+    let itemrefs: String = spine.iter()
+        .map(|item| format!("<itemref idref=\"{}\"/>\n", item.id))
+        .collect();
+
+    format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<package xmlns=\"http://www.idpf.org/2007/opf\" version=\"3.0\" unique-identifier=\"book-id\">
+<metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\">
+<dc:identifier id=\"book-id\">urn:mdbook:{id}</dc:identifier>
+<dc:title>{title}</dc:title>
+<dc:language>{lang}</dc:language>
+{meta}<meta property=\"dcterms:modified\">{modified}</meta>
+</metadata>
+<manifest>
+<item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>
+<item id=\"ncx\" href=\"toc.ncx\" media-type=\"application/x-dtbncx+xml\"/>
+<item id=\"css\" href=\"book.css\" media-type=\"text/css\"/>
+{items}</manifest>
+<spine toc=\"ncx\">
+{itemrefs}</spine>
+</package>
+",
+            id = metadata.file_stem(),
+            title = utils::escape_html(&metadata.title),
+            lang = utils::escape_html(&metadata.language),
+            meta = meta,
+            modified = Utc::now().format("%Y-%m-%dT%H:%M:%SZ"),
+            items = items,
+            itemrefs = itemrefs)
+}
+
+/// A chapter's name in the table of contents, with its section number.
+fn toc_label(ch: &Chapter) -> String {
+    match ch.number {
+        Some(ref number) => utils::escape_html(&format!("{} {}", number, ch.name)),
+        None => utils::escape_html(&ch.name),
+    }
+}
+
+/// The EPUB 3 navigation document, which is the table of contents.
+fn nav_document(metadata: &Metadata, sections: &[BookItem]) -> String {
+    format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<!DOCTYPE html>
+<html xmlns=\"http://www.w3.org/1999/xhtml\" xmlns:epub=\"http://www.idpf.org/2007/ops\" \
+lang=\"{lang}\" xml:lang=\"{lang}\">
+<head>
+<meta charset=\"UTF-8\"/>
+<title>{title}</title>
+</head>
+<body>
+<nav epub:type=\"toc\" id=\"toc\">
+<h1>{title}</h1>
+{list}</nav>
+</body>
+</html>
+",
+            lang = utils::escape_html(&metadata.language),
+            title = utils::escape_html(&metadata.title),
+            list = nav_list(sections))
+}
+
+/// The items of a level of the table of contents. Drafts and part titles
+/// don't have a page, so they're shown as plain text, and the chapters after
+/// a part title are nested inside it.
+fn nav_list(items: &[BookItem]) -> String {
+    let mut list = String::from("<ol>\n");
+    let mut in_part = false;
+
+    for item in items {
+        match *item {
+            BookItem::Chapter(ref ch) => {
+                match ch.path {
+                    Some(ref path) => {
+                        list.push_str(&format!("<li><a href=\"{}\">{}</a>",
+                                               href(&chapter_page(path)),
+                                               toc_label(ch)))
+                    }
+                    None => list.push_str(&format!("<li><span>{}</span>", toc_label(ch))),
+                }
+                if !ch.sub_items.is_empty() {
+                    list.push('\n');
+                    list.push_str(&nav_list(&ch.sub_items));
+                }
+                list.push_str("</li>\n");
+            }
+            BookItem::PartTitle(ref title) => {
+                if in_part {
+                    list.push_str("</ol>\n</li>\n");
+                }
+                list.push_str(&format!("<li><span>{}</span>\n<ol>\n", utils::escape_html(title)));
+                in_part = true;
+            }
+            BookItem::Separator => {}
+        }
+    }
+
+    if in_part {
+        list.push_str("</ol>\n</li>\n");
+    }
+    list.push_str("</ol>\n");
+    list
+}
+
+/// The EPUB 2 table of contents, for older readers.
+fn ncx_document(metadata: &Metadata, sections: &[BookItem]) -> String {
+    let mut points = String::new();
+    nav_points(sections, &mut 0, &mut points);
+
+    format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<ncx xmlns=\"http://www.daisy.org/z3986/2005/ncx/\" version=\"2005-1\">
+<head>
+<meta name=\"dtb:uid\" content=\"urn:mdbook:{id}\"/>
+</head>
+<docTitle><text>{title}</text></docTitle>
+<navMap>
+{points}</navMap>
+</ncx>
+",
+            id = metadata.file_stem(),
+            title = utils::escape_html(&metadata.title),
+            points = points)
+}
+
+/// Every NCX `navPoint` has to point somewhere, so the chapters nested
+/// inside a draft take its place.
+fn nav_points(items: &[BookItem], play_order: &mut usize, points: &mut String) {
+    for item in items {
+        let ch = match *item {
+            BookItem::Chapter(ref ch) => ch,
+            _ => continue,
+        };
+
+        match ch.path {
+            Some(ref path) => {
+                *play_order += 1;
+                points.push_str(&format!("<navPoint id=\"navpoint-{0}\" playOrder=\"{0}\">\
+                                          <navLabel><text>{1}</text></navLabel>\
+                                          <content src=\"{2}\"/>\n",
+                                         play_order,
+                                         toc_label(ch),
+                                         href(&chapter_page(path))));
+                nav_points(&ch.sub_items, play_order, points);
+                points.push_str("</navPoint>\n");
+            }
+            None => nav_points(&ch.sub_items, play_order, points),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::Write;
+    use tempdir::TempDir;
+    use book::{Book, SectionNumber};
+    use super::zip::{read_entries, ReadEntry};
+
+    fn write(root: &Path, path: &str, contents: &[u8]) {
+        let path = root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        File::create(path).unwrap().write_all(contents).unwrap();
+    }
+
+    fn chapter(name: &str, content: &str, path: &str, number: Vec<u32>) -> Chapter {
+        Chapter {
+            number: Some(SectionNumber(number)),
+            ..Chapter::new(name, content.to_string(), path)
+        }
+    }
+
+    /// Render a small book with a part title, a draft and an image, and
+    /// read the files back out of the EPUB.
+    fn render_book() -> Vec<ReadEntry> {
+        let temp = TempDir::new("mdbook-epub").unwrap();
+        let intro = "# Intro\n\nSee [usage](./guide/usage.md) and ![logo](images/logo.png).\n";
+        let usage = "# Usage\n\nBack to [the intro](../intro.md).\n";
+        write(temp.path(), "src/intro.md", intro.as_bytes());
+        write(temp.path(), "src/guide/usage.md", usage.as_bytes());
+        write(temp.path(), "src/outro.md", b"# Outro\n");
+        write(temp.path(), "src/images/logo.png", b"not really a png");
+
+        let mut usage = chapter("Usage", usage, "guide/usage.md", vec![1]);
+        usage.sub_items.push(BookItem::Chapter(Chapter::new_draft("Advanced")));
+        let mut book = Book::new();
+        book.push_item(Chapter::new("Intro", intro.to_string(), "intro.md"))
+            .push_item(BookItem::PartTitle("Guide".to_string()))
+            .push_item(usage)
+            .push_item(chapter("Outro", "# Outro\n", "outro.md", vec![2]));
+
+        let mut config = Config::default();
+        config.book.title = Some("Test Book".to_string());
+        config.book.authors = vec!["Jane Doe".to_string()];
+        config.book.language = Some("de".to_string());
+
+        let destination = temp.path().join("book");
+        let ctx = RenderContext::new(temp.path(), book, config, &destination);
+        EpubRenderer::new().render(&ctx).unwrap();
+
+        let mut archive = Vec::new();
+        File::open(destination.join("test-book.epub"))
+            .unwrap()
+            .read_to_end(&mut archive)
+            .unwrap();
+        read_entries(&archive)
+    }
+
+    fn contents<'a>(entries: &'a [ReadEntry], name: &str) -> &'a str {
+        let entry = entries.iter()
+                           .find(|entry| entry.name == name)
+                           .expect(&format!("{} isn't in the EPUB", name));
+        ::std::str::from_utf8(&entry.contents).unwrap()
+    }
+
+    #[test]
+    fn mimetype_comes_first_and_uncompressed() {
+        let entries = render_book();
+
+        assert_eq!(entries[0].name, "mimetype");
+        assert_eq!(entries[0].compression, 0);
+        assert_eq!(entries[0].contents, b"application/epub+zip");
+        assert!(contents(&entries, "META-INF/container.xml").contains("OEBPS/content.opf"));
+
+        for name in &["OEBPS/nav.xhtml",
+                      "OEBPS/toc.ncx",
+                      "OEBPS/book.css",
+                      "OEBPS/text/intro.xhtml",
+                      "OEBPS/text/guide/usage.xhtml",
+                      "OEBPS/text/outro.xhtml",
+                      "OEBPS/text/images/logo.png"] {
+            contents(&entries, name);
+        }
+    }
+
+    #[test]
+    fn the_spine_follows_the_summary_without_drafts() {
+        let entries = render_book();
+        let opf = contents(&entries, "OEBPS/content.opf");
+
+        assert!(opf.contains("<dc:title>Test Book</dc:title>"));
+        assert!(opf.contains("<dc:creator>Jane Doe</dc:creator>"));
+        assert!(opf.contains("<dc:language>de</dc:language>"));
+        assert!(opf.contains(r#"<item id="chapter-1" href="text/intro.xhtml""#));
+        assert!(opf.contains(r#"<item id="chapter-2" href="text/guide/usage.xhtml""#));
+        assert!(opf.contains(r#"<item id="chapter-3" href="text/outro.xhtml""#));
+        assert!(opf.contains(r#"href="text/images/logo.png" media-type="image/png""#));
+        assert!(opf.contains("<itemref idref=\"chapter-1\"/>\n\
+                              <itemref idref=\"chapter-2\"/>\n\
+                              <itemref idref=\"chapter-3\"/>\n</spine>"));
+    }
+
+    #[test]
+    fn the_table_of_contents_includes_parts_and_drafts() {
+        let entries = render_book();
+        let nav = contents(&entries, "OEBPS/nav.xhtml");
+        let ncx = contents(&entries, "OEBPS/toc.ncx");
+
+        assert!(nav.contains("<li><span>Guide</span>\n<ol>\n\
+                              <li><a href=\"text/guide/usage.xhtml\">1. Usage</a>"));
+        assert!(nav.contains("<li><span>Advanced</span></li>"));
+        assert!(!ncx.contains("Advanced"));
+        assert!(ncx.contains(r#"playOrder="3"><navLabel><text>2. Outro</text>"#));
+    }
+
+    #[test]
+    fn links_point_at_the_pages_relative_to_the_chapter() {
+        let entries = render_book();
+        let intro = contents(&entries, "OEBPS/text/intro.xhtml");
+        let usage = contents(&entries, "OEBPS/text/guide/usage.xhtml");
+
+        assert!(intro.contains(r#"<a href="guide/usage.xhtml">usage</a>"#));
+        assert!(intro.contains(r#"<img src="images/logo.png""#));
+        assert!(intro.contains(r#"href="../book.css""#));
+        assert!(usage.contains(r#"<a href="../intro.xhtml">the intro</a>"#));
+        assert!(usage.contains(r#"href="../../book.css""#));
+    }
+}
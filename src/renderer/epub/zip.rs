@@ -0,0 +1,290 @@
+//! Just enough of the ZIP format to write an EPUB: every file is stored as it
+//! is (EPUB requires that for the `mimetype` file, and it's allowed for
+//! everything else), so there's no need for a compression library.
+
+use std::io::Write;
+
+use chrono::{Datelike, NaiveDateTime, Timelike};
+
+use errors::*;
+
+const LOCAL_FILE_HEADER: u32 = 0x0403_4b50;
+const CENTRAL_DIRECTORY_HEADER: u32 = 0x0201_4b50;
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x0605_4b50;
+
+/// Version 2.0, the first one with directories in file names.
+const VERSION: u16 = 20;
+/// The file name is UTF-8 rather than IBM437.
+const UTF8_NAME: u16 = 1 << 11;
+
+/// Writes a ZIP archive of uncompressed files.
+pub struct ZipWriter<W> {
+    inner: W,
+    written: u64,
+    entries: Vec<Entry>,
+    time: u16,
+    date: u16,
+}
+
+/// What the central directory needs to know about a file we've written.
+struct Entry {
+    name: String,
+    crc: u32,
+    size: u32,
+    offset: u32,
+}
+
+impl<W: Write> ZipWriter<W> {
+    /// Create a new archive, where every file was last modified at
+    /// `modified`.
+    pub fn new(inner: W, modified: NaiveDateTime) -> ZipWriter<W> {
+        let (time, date) = dos_date_time(modified);
+
+        ZipWriter {
+            inner: inner,
+            written: 0,
+            entries: Vec::new(),
+            time: time,
+            date: date,
+        }
+    }
+
+    /// Add a file to the archive. `name` is its path inside the archive,
+    /// using `/` as the separator.
+    pub fn add_file(&mut self, name: &str, contents: &[u8]) -> Result<()> {
+        if self.entries.iter().any(|entry| entry.name == name) {
+            bail!("\"{}\" is already in the archive", name);
+        }
+        if self.entries.len() >= u16::max_value() as usize {
+            bail!("Too many files for a ZIP archive");
+        }
+
+        let entry = Entry {
+            name: name.to_string(),
+            crc: crc32(contents),
+            size: to_u32(contents.len() as u64)?,
+            offset: to_u32(self.written)?,
+        };
+
+        let mut header = Vec::with_capacity(30 + name.len());
+        push_u32(&mut header, LOCAL_FILE_HEADER);
+        push_u16(&mut header, VERSION);
+        self.push_common_fields(&mut header, &entry);
+        push_u16(&mut header, 0); // no extra field
+        header.extend_from_slice(name.as_bytes());
+
+        self.write(&header)?;
+        self.write(contents)?;
+        self.entries.push(entry);
+
+        Ok(())
+    }
+
+    /// Write the central directory, which finishes the archive off.
+    pub fn finish(mut self) -> Result<W> {
+        let start = to_u32(self.written)?;
+        let mut directory = Vec::new();
+
+        for entry in &self.entries {
+            push_u32(&mut directory, CENTRAL_DIRECTORY_HEADER);
+            push_u16(&mut directory, VERSION); // made by, on MS-DOS
+            push_u16(&mut directory, VERSION);
+            self.push_common_fields(&mut directory, entry);
+            push_u16(&mut directory, 0); // no extra field
+            push_u16(&mut directory, 0); // no comment
+            push_u16(&mut directory, 0); // on the first disk
+            push_u16(&mut directory, 0); // no internal attributes
+            push_u32(&mut directory, 0); // no external attributes
+            push_u32(&mut directory, entry.offset);
+            directory.extend_from_slice(entry.name.as_bytes());
+        }
+
+        let count = self.entries.len() as u16;
+        let size = to_u32(directory.len() as u64)?;
+        push_u32(&mut directory, END_OF_CENTRAL_DIRECTORY);
+        push_u16(&mut directory, 0); // this disk
+        push_u16(&mut directory, 0); // the disk the central directory starts on
+        push_u16(&mut directory, count);
+        push_u16(&mut directory, count);
+        push_u32(&mut directory, size);
+        push_u32(&mut directory, start);
+        push_u16(&mut directory, 0); // no comment
+
+        self.write(&directory)?;
+        Ok(self.inner)
+    }
+
+    /// The fields which are the same in a file's local header and its
+    /// central directory header, from the flags up to the file name's
+    /// length.
+    fn push_common_fields(&self, buffer: &mut Vec<u8>, entry: &Entry) {
+        let flags = if entry.name.is_ascii() { 0 } else { UTF8_NAME };
+
+        push_u16(buffer, flags);
+        push_u16(buffer, 0); // stored, not compressed
+        push_u16(buffer, self.time);
+        push_u16(buffer, self.date);
+        push_u32(buffer, entry.crc);
+        push_u32(buffer, entry.size); // compressed size
+        push_u32(buffer, entry.size);
+        push_u16(buffer, entry.name.len() as u16);
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<()> {
+        self.inner.write_all(bytes)?;
+        self.written += bytes.len() as u64;
+        Ok(())
+    }
+}
+
+/// Archives without the ZIP64 extensions have to be smaller than 4 GiB.
+fn to_u32(n: u64) -> Result<u32> {
+    if n > u64::from(u32::max_value()) {
+        bail!("The archive is too big");
+    }
+
+    Ok(n as u32)
+}
+
+fn push_u16(buffer: &mut Vec<u8>, n: u16) {
+    buffer.extend_from_slice(&[n as u8, (n >> 8) as u8]);
+}
+
+fn push_u32(buffer: &mut Vec<u8>, n: u32) {
+    push_u16(buffer, n as u16);
+    push_u16(buffer, (n >> 16) as u16);
+}
+
+/// A timestamp in MS-DOS's format, which only goes back to 1980 and counts
+/// seconds in twos.
+fn dos_date_time(timestamp: NaiveDateTime) -> (u16, u16) {
+    if timestamp.year() < 1980 {
+        return (0, (1 << 5) | 1);
+    }
+
+    let time = (timestamp.hour() << 11) | (timestamp.minute() << 5) | (timestamp.second() / 2);
+    let date = (((timestamp.year() - 1980) as u32) << 9) | (timestamp.month() << 5)
+        | timestamp.day();
+
+    (time as u16, date as u16)
+}
+
+/// The CRC-32 checksum ZIP uses (the same one as gzip and PNG).
+fn crc32(bytes: &[u8]) -> u32 {
+    lazy_static! {
+        static ref TABLE: Vec<u32> = (0..256u32)
+            .map(|n| {
+                (0..8).fold(n, |c, _| {
+                    if c & 1 == 1 {
+                        0xEDB8_8320 ^ (c >> 1)
+                    } else {
+                        c >> 1
+                    }
+                })
+            })
+            .collect();
+    }
+
+    !bytes.iter().fold(!0, |crc, &byte| {
+        TABLE[((crc ^ u32::from(byte)) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
+
+/// A file read back out of an archive, for checking what was written.
+#[cfg(test)]
+#[derive(Debug)]
+pub struct ReadEntry {
+    pub name: String,
+    pub compression: u16,
+    pub contents: Vec<u8>,
+}
+
+/// Read the files in an archive in the order they were written, using their
+/// local headers.
+#[cfg(test)]
+pub fn read_entries(archive: &[u8]) -> Vec<ReadEntry> {
+    fn u16_at(bytes: &[u8], ix: usize) -> u16 {
+        u16::from(bytes[ix]) | u16::from(bytes[ix + 1]) << 8
+    }
+    fn u32_at(bytes: &[u8], ix: usize) -> u32 {
+        u32::from(u16_at(bytes, ix)) | u32::from(u16_at(bytes, ix + 2)) << 16
+    }
+
+    let mut entries = Vec::new();
+    let mut ix = 0;
+
+    while u32_at(archive, ix) == LOCAL_FILE_HEADER {
+        let compression = u16_at(archive, ix + 8);
+        let crc = u32_at(archive, ix + 14);
+        let size = u32_at(archive, ix + 18) as usize;
+        let name_len = u16_at(archive, ix + 26) as usize;
+        let extra_len = u16_at(archive, ix + 28) as usize;
+        let name_start = ix + 30;
+        let data_start = name_start + name_len + extra_len;
+        let contents = archive[data_start..data_start + size].to_vec();
+        assert_eq!(crc, crc32(&contents));
+
+        entries.push(ReadEntry {
+            name: String::from_utf8(archive[name_start..name_start + name_len].to_vec()).unwrap(),
+            compression: compression,
+            contents: contents,
+        });
+        ix = data_start + size;
+    }
+
+    assert_eq!(u32_at(archive, ix), CENTRAL_DIRECTORY_HEADER);
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn modified() -> NaiveDateTime {
+        NaiveDate::from_ymd(2018, 3, 14).and_hms(15, 9, 26)
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn timestamps_use_the_dos_format() {
+        let time = (15 << 11) | (9 << 5) | 13;
+        let date = (38 << 9) | (3 << 5) | 14;
+
+        assert_eq!(dos_date_time(modified()), (time, date));
+    }
+
+    #[test]
+    fn files_are_written_in_order_without_compression() {
+        let mut zip = ZipWriter::new(Vec::new(), modified());
+        zip.add_file("mimetype", b"application/epub+zip").unwrap();
+        zip.add_file("OEBPS/über.xhtml", b"<html/>").unwrap();
+        let archive = zip.finish().unwrap();
+
+        let got = read_entries(&archive);
+
+        assert_eq!(got.len(), 2);
+        assert_eq!(got[0].name, "mimetype");
+        assert_eq!(got[0].compression, 0);
+        assert_eq!(got[0].contents, b"application/epub+zip");
+        assert_eq!(got[1].name, "OEBPS/über.xhtml");
+
+        // the end of central directory record says there are 2 files
+        let end = archive.len() - 22;
+        assert_eq!(&archive[end..end + 4], &[0x50, 0x4b, 0x05, 0x06]);
+        assert_eq!(&archive[end + 8..end + 12], &[2, 0, 2, 0]);
+    }
+
+    #[test]
+    fn the_same_file_cant_be_added_twice() {
+        let mut zip = ZipWriter::new(Vec::new(), modified());
+        zip.add_file("a.txt", b"a").unwrap();
+
+        assert!(zip.add_file("a.txt", b"b").is_err());
+    }
+}
@@ -0,0 +1,406 @@
+//! Keeping track of what was rendered last time, so chapters which haven't
+//! changed since the previous build don't need to be rendered again.
+//!
+//! Each chapter's hash covers its (preprocessed) contents, the options its
+//! markdown is rendered with, plus a fingerprint of everything else which
+//! ends up on the page: the configuration, the theme and the structure of the
+//! book (every page contains the table of contents). The chapter's rendered
+//! markdown is kept alongside the hash, so the print page can be put together
+//! without rendering every chapter again.
+//!
+//! Whether a link gets translated depends on whether the file it points at
+//! exists, so the cache also remembers which files each chapter's links were
+//! checked against. Creating or deleting one of them renders the chapter
+//! again.
+//!
+//! The hashes are saved between builds, so they use `StableHasher` rather than
+//! `DefaultHasher`, whose output can change from one Rust release to the
+//! next.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use serde_json;
+
+use book::{Book, BookItem};
+use config::Config;
+use errors::*;
+use theme::Theme;
+use utils::MarkdownOptions;
+use utils::fs::FileWriter;
+
+/// The name of the cache file, relative to the build directory.
+pub const CACHE_FILE: &str = ".mdbook-cache.json";
+
+/// Bump this whenever the way the hashes are calculated changes, so caches
+/// written by an older `mdbook` are thrown away.
+const CACHE_FORMAT: u32 = 2;
+
+/// The chapters rendered by a previous build.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct RenderCache {
+    /// Missing from caches written before the format had a number.
+    #[serde(default)]
+    format: u32,
+    version: String,
+    fingerprint: u64,
+    chapters: HashMap<String, CachedChapter>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct CachedChapter {
+    hash: u64,
+    html: String,
+    /// The files (relative to `src`) the chapter's links were checked
+    /// against, and whether they existed.
+    files: Vec<(String, bool)>,
+}
+
+impl RenderCache {
+    /// Create an empty cache for a build with the provided fingerprint.
+    pub fn new(fingerprint: u64) -> RenderCache {
+        RenderCache {
+            format: CACHE_FORMAT,
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            fingerprint: fingerprint,
+            chapters: HashMap::new(),
+        }
+    }
+
+    /// Load the cache left behind by the previous build.
+    ///
+    /// A cache which is missing, can't be read, was written by a different
+    /// version of `mdbook` (or in a different format) or belongs to a build
+    /// with a different fingerprint is ignored, meaning everything gets
+    /// rendered again.
+    pub fn load(destination: &Path, fingerprint: u64) -> Option<RenderCache> {
+        let path = destination.join(CACHE_FILE);
+        if !path.exists() {
+            return None;
+        }
+
+        let mut content = String::new();
+        if let Err(e) = File::open(&path).and_then(|mut f| f.read_to_string(&mut content)) {
+            debug!("Unable to read {}: {}", path.display(), e);
+            return None;
+        }
+
+        let cache: RenderCache = match serde_json::from_str(&content) {
+            Ok(cache) => cache,
+            Err(e) => {
+                debug!("Ignoring the corrupt render cache ({})", e);
+                return None;
+            }
+        };
+
+        if cache.format != CACHE_FORMAT {
+            debug!("Ignoring the render cache in format {}", cache.format);
+            None
+        } else if cache.version != env!("CARGO_PKG_VERSION") {
+            debug!("Ignoring the render cache from mdbook {}", cache.version);
+            None
+        } else if cache.fingerprint != fingerprint {
+            debug!("The config, theme or SUMMARY.md changed, rendering everything");
+            None
+        } else {
+            Some(cache)
+        }
+    }
+
+    /// Save the cache so the next build can use it.
+    pub fn save(&self, destination: &Path, writer: &FileWriter) -> Result<()> {
+        let content = serde_json::to_string(self)?;
+
+        writer.write_file(&destination.join(CACHE_FILE), content.as_bytes())
+    }
+
+    /// Get a chapter's rendered markdown, if it was rendered from the same
+    /// input (see `RenderCache::chapter_hash()`) last time and the files its
+    /// links point at still exist (or are still missing).
+    ///
+    /// `is_file` is called for each of those files, in the same way as when
+    /// the chapter was rendered.
+    pub fn get<F>(&self, path: &Path, hash: u64, is_file: F) -> Option<&str>
+        where F: Fn(&Path) -> bool
+    {
+        match self.chapters.get(&cache_key(path)) {
+            Some(cached) if cached.hash == hash
+                && cached.files.iter().all(|&(ref file, exists)| is_file(Path::new(file)) == exists) => {
+                Some(&cached.html)
+            }
+            _ => None,
+        }
+    }
+
+    /// Was the chapter rendered from the same input last time?
+    pub fn is_unchanged<F>(&self, path: &Path, hash: u64, is_file: F) -> bool
+        where F: Fn(&Path) -> bool
+    {
+        self.get(path, hash, is_file).is_some()
+    }
+
+    /// Record a chapter's rendered markdown, the hash of the input it was
+    /// rendered from and the files its links were checked against.
+    pub fn insert(&mut self, path: &Path, hash: u64, html: String, files: Vec<(PathBuf, bool)>) {
+        let mut files = files.into_iter()
+                             .map(|(file, exists)| (cache_key(&file), exists))
+                             .collect::<Vec<_>>();
+        files.sort();
+        files.dedup();
+
+        let cached = CachedChapter {
+            hash: hash,
+            html: html,
+            files: files,
+        };
+        self.chapters.insert(cache_key(path), cached);
+    }
+
+    /// Hash the input a chapter's page is rendered from.
+    pub fn chapter_hash(
+        fingerprint: u64,
+        path: &Path,
+        content: &str,
+        options: &MarkdownOptions,
+    ) -> u64 {
+        let mut hasher = StableHasher::new();
+        fingerprint.hash(&mut hasher);
+        path.hash(&mut hasher);
+        content.hash(&mut hasher);
+        RenderCache::options_hash(options).hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Hash every option which changes how markdown is rendered. This is the
+    /// same from one build to the next, so it can be saved in the cache.
+    pub fn options_hash(options: &MarkdownOptions) -> u64 {
+        let mut hasher = StableHasher::new();
+        options.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+fn cache_key(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
+
+/// A 64-bit FNV-1a hasher, whose output only depends on what gets hashed.
+struct StableHasher(u64);
+
+impl StableHasher {
+    fn new() -> StableHasher {
+        StableHasher(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for StableHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Hash everything apart from a chapter's own contents which affects the
+/// page it gets rendered to.
+pub fn fingerprint(config: &Config, theme: &Theme, book: &Book) -> Result<u64> {
+    let mut hasher = StableHasher::new();
+
+    serde_json::to_string(config)?.hash(&mut hasher);
+
+    theme.index.hash(&mut hasher);
+    theme.header.hash(&mut hasher);
+    theme.css.hash(&mut hasher);
+    theme.favicon.hash(&mut hasher);
+    theme.js.hash(&mut hasher);
+    theme.highlight_css.hash(&mut hasher);
+    theme.tomorrow_night_css.hash(&mut hasher);
+    theme.ayu_highlight_css.hash(&mut hasher);
+    theme.highlight_js.hash(&mut hasher);
+    theme.clipboard_js.hash(&mut hasher);
+
+    // the table of contents (and the previous/next links) depend on the
+    // name, number and location of every item in the book
+    for item in book.iter() {
+        match *item {
+            BookItem::Chapter(ref ch) => {
+                "chapter".hash(&mut hasher);
+                ch.name.hash(&mut hasher);
+                ch.number.as_ref().map(|n| n.to_string()).hash(&mut hasher);
+                ch.path.hash(&mut hasher);
+                ch.sub_items.len().hash(&mut hasher);
+            }
+            BookItem::Separator => "separator".hash(&mut hasher),
+            BookItem::PartTitle(ref title) => {
+                "part".hash(&mut hasher);
+                title.hash(&mut hasher);
+            }
+        }
+    }
+
+    Ok(hasher.finish())
+}
+
+/// Fold something else which ends up on every page into a fingerprint.
+pub fn extend_fingerprint(fingerprint: u64, data: &serde_json::Value) -> Result<u64> {
+    let mut hasher = StableHasher::new();
+    fingerprint.hash(&mut hasher);
+    serde_json::to_string(data)?.hash(&mut hasher);
+
+    Ok(hasher.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use book::Chapter;
+    use std::io::Write;
+    use utils;
+    use utils::fs::DiskWriter;
+    use tempdir::TempDir;
+
+    fn book_with(chapters: Vec<Chapter>) -> Book {
+        let mut book = Book::new();
+        for ch in chapters {
+            book.push_item(ch);
+        }
+        book
+    }
+
+    #[test]
+    fn the_cache_round_trips_through_the_build_directory() {
+        let temp = TempDir::new("mdbook-cache").unwrap();
+        let mut cache = RenderCache::new(42);
+        cache.insert(Path::new("nested/chapter.md"), 7, String::from("<p>Hello</p>\n"), Vec::new());
+
+        cache.save(temp.path(), &DiskWriter).unwrap();
+        let got = RenderCache::load(temp.path(), 42).unwrap();
+
+        assert_eq!(got, cache);
+        assert_eq!(got.get(Path::new("nested/chapter.md"), 7, |_| true), Some("<p>Hello</p>\n"));
+        assert!(got.is_unchanged(Path::new("nested/chapter.md"), 7, |_| true));
+        assert!(!got.is_unchanged(Path::new("nested/chapter.md"), 8, |_| true));
+        assert!(!got.is_unchanged(Path::new("other.md"), 7, |_| true));
+    }
+
+    #[test]
+    fn a_different_fingerprint_invalidates_the_cache() {
+        let temp = TempDir::new("mdbook-cache").unwrap();
+        RenderCache::new(42).save(temp.path(), &DiskWriter).unwrap();
+
+        assert!(RenderCache::load(temp.path(), 43).is_none());
+    }
+
+    #[test]
+    fn corrupt_and_outdated_caches_are_ignored() {
+        let temp = TempDir::new("mdbook-cache").unwrap();
+        let inputs = vec![
+            String::from("not json"),
+            String::from(r#"{"format": 2, "version": "0.0.0", "fingerprint": 42, "chapters": {}}"#),
+            format!(r#"{{"version": "{}", "fingerprint": 42, "chapters": {{}}}}"#,
+                    env!("CARGO_PKG_VERSION")),
+        ];
+
+        for src in inputs {
+            File::create(temp.path().join(CACHE_FILE))
+                .unwrap()
+                .write_all(src.as_bytes())
+                .unwrap();
+
+            assert!(RenderCache::load(temp.path(), 42).is_none());
+        }
+    }
+
+    #[test]
+    fn hashes_dont_change_between_builds() {
+        let mut hasher = StableHasher::new();
+        hasher.write(b"a");
+        assert_eq!(hasher.finish(), 0xaf63_dc4c_8601_ec8c);
+
+        assert_eq!(StableHasher::new().finish(), 0xcbf2_9ce4_8422_2325);
+    }
+
+    #[test]
+    fn the_fingerprint_ignores_chapter_contents_but_not_the_summary() {
+        let config = Config::default();
+        let theme = Theme::default();
+        let original = book_with(vec![Chapter::new("First", String::from("# First"), "first.md")]);
+        let edited = book_with(vec![Chapter::new("First", String::from("Edited"), "first.md")]);
+        let renamed = book_with(vec![Chapter::new("Renamed", String::from("# First"), "first.md")]);
+
+        let got = fingerprint(&config, &theme, &original).unwrap();
+
+        assert_eq!(fingerprint(&config, &theme, &edited).unwrap(), got);
+        assert_ne!(fingerprint(&config, &theme, &renamed).unwrap(), got);
+    }
+
+    #[test]
+    fn the_fingerprint_depends_on_the_config_and_theme() {
+        let book = book_with(vec![Chapter::new("First", String::new(), "first.md")]);
+        let config = Config::default();
+        let theme = Theme::default();
+        let got = fingerprint(&config, &theme, &book).unwrap();
+
+        let mut other_config = Config::default();
+        other_config.book.title = Some(String::from("Another Title"));
+        assert_ne!(fingerprint(&other_config, &theme, &book).unwrap(), got);
+
+        let mut other_theme = Theme::default();
+        other_theme.css = b"body { color: red; }".to_vec();
+        assert_ne!(fingerprint(&config, &other_theme, &book).unwrap(), got);
+    }
+
+    #[test]
+    fn a_cache_hit_returns_the_same_html() {
+        let options = MarkdownOptions::default();
+        let path = Path::new("chapter.md");
+        let content = "# Chapter\n\n'Quoted' text\n";
+        let html = utils::render_markdown_with_options(content, &options);
+
+        let mut cache = RenderCache::new(42);
+        cache.insert(path, RenderCache::chapter_hash(42, path, content, &options), html.clone(), Vec::new());
+
+        let hash = RenderCache::chapter_hash(42, path, content, &options);
+        assert_eq!(cache.get(path, hash, |_| true), Some(html.as_str()));
+    }
+
+    #[test]
+    fn changing_an_option_is_a_cache_miss() {
+        let options = MarkdownOptions::default();
+        let path = Path::new("chapter.md");
+        let content = "'Quoted' text";
+
+        let mut cache = RenderCache::new(42);
+        let html = utils::render_markdown_with_options(content, &options);
+        cache.insert(path, RenderCache::chapter_hash(42, path, content, &options), html, Vec::new());
+
+        let curly = MarkdownOptions {
+            curly_quotes: true,
+            ..Default::default()
+        };
+        assert_ne!(RenderCache::options_hash(&curly), RenderCache::options_hash(&options));
+        assert_eq!(cache.get(path, RenderCache::chapter_hash(42, path, content, &curly), |_| true), None);
+        assert_eq!(cache.get(path, RenderCache::chapter_hash(42, path, "Edited", &options), |_| true), None);
+    }
+
+    #[test]
+    fn creating_or_deleting_a_linked_file_is_a_cache_miss() {
+        let path = Path::new("chapter.md");
+        let files = vec![(PathBuf::from("img/x.png"), false), (PathBuf::from("other.md"), true)];
+
+        let mut cache = RenderCache::new(42);
+        cache.insert(path, 7, String::from("<p>Hello</p>\n"), files);
+
+        assert!(cache.is_unchanged(path, 7, |p| p == Path::new("other.md")));
+        assert!(!cache.is_unchanged(path, 7, |_| true));
+        assert!(!cache.is_unchanged(path, 7, |_| false));
+    }
+}
@@ -1,29 +1,47 @@
-use renderer::html_handlebars::helpers;
+use renderer::html_handlebars::{helpers, redirects};
+use renderer::html_handlebars::cache::{self, RenderCache};
 use renderer::{RenderContext, Renderer};
 use book::{Book, BookItem, Chapter};
-use config::{Config, HtmlConfig, Playpen};
+use config::{Config, HtmlConfig, IndexStrategy, Playpen};
 use {theme, utils};
+use utils::MarkdownOptions;
+use utils::fs::{DiskWriter, FileWriter};
+use utils::slug::Slugifier;
 use theme::{playpen_editor, Theme};
 use errors::*;
 use regex::{Captures, Regex};
 
-#[allow(unused_imports)] use std::ascii::AsciiExt;
+use std::cell::RefCell;
 use std::path::{Path, PathBuf};
-use std::fs::{self, File};
-use std::io::{Read, Write};
-use std::collections::BTreeMap;
-use std::collections::HashMap;
+use std::fs;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
 use handlebars::Handlebars;
 
 use serde_json;
 
-#[derive(Default)]
-pub struct HtmlHandlebars;
+pub struct HtmlHandlebars {
+    writer: Box<FileWriter>,
+}
+
+impl Default for HtmlHandlebars {
+    fn default() -> HtmlHandlebars {
+        HtmlHandlebars::new()
+    }
+}
 
 impl HtmlHandlebars {
     pub fn new() -> Self {
-        HtmlHandlebars
+        HtmlHandlebars::with_writer(DiskWriter)
+    }
+
+    /// Create a renderer which writes the files it generates through
+    /// `writer`. Files copied over from the book's `src` directory and the
+    /// theme's fonts are still copied directly.
+    pub fn with_writer<W: FileWriter + 'static>(writer: W) -> Self {
+        HtmlHandlebars {
+            writer: Box::new(writer),
+        }
     }
 
     fn write_file<P: AsRef<Path>>(
@@ -32,117 +50,138 @@ impl HtmlHandlebars {
         filename: P,
         content: &[u8],
     ) -> Result<()> {
-        let path = build_dir.join(filename);
-
-        utils::fs::create_file(&path)?
-            .write_all(content)
-            .map_err(|e| e.into())
+        self.writer.write_file(&build_dir.join(filename), content)
     }
 
+    /// Render a chapter's page, given its already rendered markdown.
     fn render_item(
         &self,
-                   item: &BookItem,
-                   mut ctx: RenderItemContext,
+        ch: &Chapter,
+        content: &str,
+        mut ctx: RenderItemContext,
         print_content: &mut String,
     ) -> Result<()> {
         // FIXME: This should be made DRY-er and rely less on mutable state
-        match *item {
-            BookItem::Chapter(ref ch) => {
-                let content = ch.content.clone();
-                let content = utils::render_markdown(&content, ctx.html_config.curly_quotes);
-                print_content.push_str(&content);
-
-                // Update the context with data for this file
-                let path = ch.path
-                    .to_str()
-                    .chain_err(|| "Could not convert path to str")?;
-
-                // "print.html" is used for the print page.
-                if ch.path == Path::new("print.md") {
-                    bail!(ErrorKind::ReservedFilenameError(ch.path.clone()));
-                };
-
-                // Non-lexical lifetimes needed :'(
-                let title: String;
-                {
-                    let book_title = ctx.data
-                                        .get("book_title")
-                                        .and_then(serde_json::Value::as_str)
-                                        .unwrap_or("");
-                    title = ch.name.clone() + " - " + book_title;
-                }
 
-                ctx.data.insert("path".to_owned(), json!(path));
-                ctx.data.insert("content".to_owned(), json!(content));
-                ctx.data.insert("chapter_title".to_owned(), json!(ch.name));
-                ctx.data.insert("title".to_owned(), json!(title));
-                ctx.data.insert("path_to_root".to_owned(),
-                                json!(utils::fs::path_to_root(&ch.path)));
-
-                // Render the handlebars template with the data
-                debug!("Render template");
-                let rendered = ctx.handlebars.render("index", &ctx.data)?;
-
-                let filepath = Path::new(&ch.path).with_extension("html");
-                let rendered = self.post_process(
-                    rendered,
-                    &normalize_path(filepath.to_str().ok_or_else(|| {
-                        Error::from(format!("Bad file name: {}", filepath.display()))
-                    })?),
-                    &ctx.html_config.playpen,
-                );
-
-                // Write to file
-                debug!("Creating {} ✓", filepath.display());
-                self.write_file(&ctx.destination, filepath, &rendered.into_bytes())?;
-
-                if ctx.is_index {
-                    self.render_index(ch, &ctx.destination)?;
-                }
-            }
-            _ => {}
-        }
+        // Draft chapters don't have a page of their own
+        let ch_path = match ch.path {
+            Some(ref path) => path,
+            None => return Ok(()),
+        };
 
-        Ok(())
-    }
+        print_content.push_str(content);
 
-    /// Create an index.html from the first element in SUMMARY.md
-    fn render_index(&self, ch: &Chapter, destination: &Path) -> Result<()> {
-        debug!("index.html");
+        // Update the context with data for this file
+        let path = ch_path
+            .to_str()
+            .chain_err(|| "Could not convert path to str")?;
 
-        let mut content = String::new();
+        // "print.html" is used for the print page.
+        if ch_path == Path::new("print.md") {
+            bail!(ErrorKind::ReservedFilenameError(ch_path.clone()));
+        };
 
-        File::open(destination.join(&ch.path.with_extension("html")))?
-            .read_to_string(&mut content)?;
+        if ctx.is_unchanged {
+            debug!("{} hasn't changed since the last build", ch_path.display());
+            return Ok(());
+        }
 
-        // This could cause a problem when someone displays
-        // code containing <base href=...>
-        // on the front page, however this case should be very very rare...
-        content = content.lines()
-                         .filter(|line| !line.contains("<base href="))
-                         .collect::<Vec<&str>>()
-                         .join("\n");
+        // Non-lexical lifetimes needed :'(
+        let title: String;
+        {
+            let book_title = ctx.data
+                                .get("book_title")
+                                .and_then(serde_json::Value::as_str)
+                                .unwrap_or("");
+            title = chapter_title(ch) + " - " + book_title;
+        }
 
-        self.write_file(destination, "index.html", content.as_bytes())?;
+        ctx.data.insert("path".to_owned(), json!(path));
+        ctx.data.insert("content".to_owned(), json!(content));
+        ctx.data.insert("chapter_title".to_owned(), json!(ch.name));
+        ctx.data.insert("title".to_owned(), json!(title));
+        ctx.data.insert("path_to_root".to_owned(),
+                        json!(utils::fs::path_to_root(ch_path)));
+        ctx.data.insert("metadata".to_owned(), json!(ch.metadata));
+        if let Some(ref template) = ctx.html_config.edit_url_template {
+            ctx.data.insert("git_repository_edit_url".to_owned(),
+                            json!(edit_url(template, ch_path)));
+        }
 
-        debug!(
-            "Creating index.html from {} ✓",
-            destination.join(&ch.path.with_extension("html")).display()
+        // Render the handlebars template with the data
+        debug!("Render template");
+        let rendered = ctx.handlebars.render("index", &ctx.data)?;
+
+        let filepath = ch_path.with_extension("html");
+        let rendered = self.post_process(
+            rendered,
+            &normalize_path(filepath.to_str().ok_or_else(|| {
+                Error::from(format!("Bad file name: {}", filepath.display()))
+            })?),
+            &ctx.html_config,
+            true,
         );
 
+        // Write to file
+        debug!("Creating {} ✓", filepath.display());
+        self.write_file(&ctx.destination, &filepath, &rendered.into_bytes())?;
+
+        if ctx.is_index {
+            // the generated index.html has no source file of its own to edit
+            ctx.data.remove("git_repository_edit_url");
+            let rendered = ctx.handlebars.render("index", &ctx.data)?;
+            let rendered = self.post_process(rendered, "index.html", &ctx.html_config, false);
+
+            // every link on the page is relative to the book's root, so
+            // without its <base> the index works from there.
+            // This could cause a problem when someone displays
+            // code containing <base href=...>
+            // on the front page, however this case should be very very rare...
+            let rendered = rendered.lines()
+                                   .filter(|line| !line.contains("<base href="))
+                                   .collect::<Vec<&str>>()
+                                   .join("\n");
+
+            debug!("Creating index.html from {} ✓", filepath.display());
+            self.write_file(&ctx.destination, "index.html", rendered.as_bytes())?;
+        }
+
         Ok(())
     }
 
+    /// Write an `index.html` which redirects to the first chapter, for a book
+    /// which doesn't have an `index.md` chapter (see
+    /// `output.html.index-strategy`). The other strategy, copying the first
+    /// chapter, happens while that chapter is rendered.
+    fn redirect_index(&self, chapter_path: &Path, destination: &Path) -> Result<()> {
+        let page = chapter_path.with_extension("html");
+        let page = page.to_str()
+            .chain_err(|| "Could not convert path to str")?;
+        self.write_file(destination,
+                        "index.html",
+                        redirects::redirect_page(&normalize_path(page)).as_bytes())?;
+        debug!("Creating index.html redirecting to {} ✓", page);
+
+        Ok(())
+    }
+
+    /// `is_chapter` is false for the print page, where headings from
+    /// different chapters sharing an id is expected.
     #[cfg_attr(feature = "cargo-clippy", allow(let_and_return))]
     fn post_process(&self,
                     rendered: String,
                     filepath: &str,
-                    playpen_config: &Playpen)
+                    html_config: &HtmlConfig,
+                    is_chapter: bool)
                     -> String {
-        let rendered = build_header_links(&rendered, filepath);
+        let rendered = build_header_links(&rendered,
+                                          filepath,
+                                          !html_config.no_heading_permalinks,
+                                          html_config.transliterate_ids,
+                                          is_chapter && html_config.warn_duplicate_ids);
         let rendered = fix_anchor_links(&rendered, filepath);
         let rendered = fix_code_blocks(&rendered);
-        let rendered = add_playpen_pre(&rendered, playpen_config);
+        let rendered = add_playpen_pre(&rendered, &html_config.playpen);
 
         rendered
     }
@@ -238,27 +277,37 @@ impl HtmlHandlebars {
 
     /// Copy across any additional CSS and JavaScript files which the book
     /// has been configured to use.
-    fn copy_additional_css_and_js(&self, html: &HtmlConfig, destination: &Path) -> Result<()> {
+    fn copy_additional_css_and_js(&self,
+                                  root: &Path,
+                                  html: &HtmlConfig,
+                                  destination: &Path)
+                                  -> Result<()> {
         let custom_files = html.additional_css.iter().chain(html.additional_js.iter());
 
         debug!("Copying additional CSS and JS");
 
         for custom_file in custom_files {
-            let output_location = destination.join(custom_file);
+            let input_location = root.join(custom_file);
+            if !input_location.is_file() {
+                bail!("The additional CSS or JS file \"{}\" doesn't exist",
+                      custom_file.display());
+            }
+
+            let output_location = destination.join(additional_file_path(root, custom_file));
             if let Some(parent) = output_location.parent() {
                 fs::create_dir_all(parent)
                     .chain_err(|| format!("Unable to create {}", parent.display()))?;
             }
             debug!(
                 "Copying {} -> {}",
-                custom_file.display(),
+                input_location.display(),
                 output_location.display()
             );
 
-            fs::copy(custom_file, &output_location).chain_err(|| {
+            fs::copy(&input_location, &output_location).chain_err(|| {
                 format!(
                     "Unable to copy {} to {}",
-                    custom_file.display(),
+                    input_location.display(),
                     output_location.display()
                 )
             })?;
@@ -273,6 +322,10 @@ impl Renderer for HtmlHandlebars {
         "html"
     }
 
+    fn is_incremental(&self) -> bool {
+        true
+    }
+
     fn render(&self, ctx: &RenderContext) -> Result<()> {
         let html_config = ctx.config.html_config().unwrap_or_default();
         let src_dir = ctx.root.join(&ctx.config.book.src);
@@ -282,12 +335,7 @@ impl Renderer for HtmlHandlebars {
         trace!("render");
         let mut handlebars = Handlebars::new();
 
-        let theme_dir = match html_config.theme {
-            Some(ref theme) => theme.to_path_buf(),
-            None => src_dir.join("theme"),
-        };
-
-        let theme = theme::Theme::new(theme_dir);
+        let theme = theme::Theme::new(theme_dir(&ctx.root, &src_dir, &html_config));
 
         debug!("Register the index handlebars template");
         handlebars.register_template_string("index", String::from_utf8(theme.index.clone())?)?;
@@ -303,57 +351,338 @@ impl Renderer for HtmlHandlebars {
         // Print version
         let mut print_content = String::new();
 
+        redirects::check_for_conflicts(&html_config.redirect, &generated_pages(book))?;
+
         fs::create_dir_all(&destination)
             .chain_err(|| "Unexpected error when constructing destination path")?;
 
-        for (i, item) in book.iter().enumerate() {
-            let ctx = RenderItemContext {
+        let reading_order = book.reading_order();
+        let translations = translations(&ctx.root, &ctx.config)?;
+        let language_links = reading_order.iter()
+            .map(|ch| language_links(&translations, &ctx.config, ch))
+            .collect::<Result<Vec<_>>>()?;
+
+        // the language picker on every page depends on which chapters the
+        // other languages have
+        let fingerprint = cache::fingerprint(&ctx.config, &theme, book)?;
+        let fingerprint = if translations.is_empty() {
+            fingerprint
+        } else {
+            cache::extend_fingerprint(fingerprint, &json!(language_links))?
+        };
+        let previous = if ctx.config.build.incremental {
+            RenderCache::load(&destination, fingerprint)
+        } else {
+            None
+        };
+        let mut cache = RenderCache::new(fingerprint);
+
+        if previous.is_none() {
+            // Start from scratch so nothing is left over from a previous build
+            utils::fs::remove_dir_content(&destination)
+                .chain_err(|| "Unable to clear output directory")?;
+        }
+
+        // Drafts aren't rendered, so the first written chapter is the index
+        // (unless there's an index.md somewhere else in the book)
+        let index_md = Some(PathBuf::from("index.md"));
+        let needs_index = !reading_order.iter().any(|ch| ch.path == index_md);
+        let copy_to_index = needs_index && html_config.index_strategy == IndexStrategy::Copy;
+
+        let options = markdown_options(&html_config);
+        let mut anything_changed = false;
+        let mut assets = BTreeSet::new();
+
+        for (i, ch) in reading_order.iter().enumerate() {
+            let ch_path = ch.path
+                .as_ref()
+                .chain_err(|| "Drafts aren't part of the reading order")?;
+            // the front matter ends up on the page too
+            let page_fingerprint = if ch.metadata.is_empty() {
+                fingerprint
+            } else {
+                cache::extend_fingerprint(fingerprint, &json!(ch.metadata))?
+            };
+            let hash = RenderCache::chapter_hash(page_fingerprint, ch_path, &ch.content, &options);
+
+            // remember which files the links were checked against, so the
+            // chapter is rendered again once one of them appears or goes away
+            let checked_files = RefCell::new(Vec::new());
+            let is_file = |p: &Path| {
+                let exists = src_dir.join(p).is_file();
+                checked_files.borrow_mut().push((p.to_path_buf(), exists));
+                exists
+            };
+
+            let cached = previous.as_ref().and_then(|previous| previous.get(ch_path, hash, &is_file));
+            let is_index = copy_to_index && i == 0;
+            let is_unchanged = cached.is_some()
+                && destination.join(ch_path.with_extension("html")).exists()
+                && (!is_index || destination.join("index.html").exists());
+            let content = match cached {
+                Some(html) => html.to_string(),
+                None => {
+                    checked_files.borrow_mut().clear();
+                    utils::render_markdown_with_line_map(&ch.content,
+                                                         &options,
+                                                         Some(ch_path),
+                                                         &ch.line_map,
+                                                         &is_file)
+                }
+            };
+            cache.insert(ch_path, hash, content.clone(), checked_files.into_inner());
+            assets.extend(utils::linked_assets(&ch.content,
+                                               ch_path,
+                                               &ch.line_map,
+                                               |p| src_dir.join(p).is_file()));
+            anything_changed |= !is_unchanged;
+
+            let mut ctx = RenderItemContext {
                 handlebars: &handlebars,
                 destination: destination.to_path_buf(),
                 data: data.clone(),
-                is_index: i == 0,
+                is_unchanged: is_unchanged,
+                is_index: is_index,
                 html_config: html_config.clone(),
             };
-            self.render_item(item, ctx, &mut print_content)?;
+
+            if i > 0 {
+                ctx.data.insert("previous_chapter".to_owned(),
+                                chapter_link(reading_order[i - 1])?);
+            }
+            if let Some(next) = reading_order.get(i + 1) {
+                ctx.data.insert("next_chapter".to_owned(), chapter_link(next)?);
+            }
+            if !translations.is_empty() {
+                ctx.data.insert("languages".to_owned(), json!(language_links[i]));
+            }
+
+            self.render_item(ch, &content, ctx, &mut print_content)?;
         }
 
-        // Print version
-        self.configure_print_version(&mut data, &print_content);
-        if let Some(ref title) = ctx.config.book.title {
-            data.insert("title".to_owned(), json!(title));
+        if needs_index && html_config.index_strategy == IndexStrategy::Redirect {
+            if let Some(first) = reading_order.first() {
+                let first_path = first.path
+                    .as_ref()
+                    .chain_err(|| "Drafts aren't part of the reading order")?;
+                self.redirect_index(first_path, &destination)?;
+            }
         }
 
-        // Render the handlebars template with the data
-        debug!("Render template");
+        // Print version
+        if anything_changed || !destination.join("print.html").exists() {
+            self.configure_print_version(&mut data, &print_content);
+            if let Some(ref title) = ctx.config.book.title {
+                data.insert("title".to_owned(), json!(title));
+            }
+
+            // Render the handlebars template with the data
+            debug!("Render template");
 
-        let rendered = handlebars.render("index", &data)?;
+            let rendered = handlebars.render("index", &data)?;
 
-        let rendered = self.post_process(rendered,
-                                         "print.html",
-                                         &html_config.playpen);
+            let rendered = self.post_process(rendered,
+                                             "print.html",
+                                             &html_config,
+                                             false);
 
-        self.write_file(&destination, "print.html", &rendered.into_bytes())?;
-        debug!("Creating print.html ✓");
+            self.write_file(&destination, "print.html", &rendered.into_bytes())?;
+            debug!("Creating print.html ✓");
+        }
 
         debug!("Copy static files");
         self.copy_static_files(&destination, &theme, &html_config)
             .chain_err(|| "Unable to copy across static files")?;
-        self.copy_additional_css_and_js(&html_config, &destination)
+        self.copy_additional_css_and_js(&ctx.root, &html_config, &destination)
             .chain_err(|| "Unable to copy across additional CSS and JS")?;
 
-        // Copy all remaining files
-        utils::fs::copy_files_except_ext(&src_dir, &destination, true, &["md"])?;
+        debug!("Copy the files linked from chapters");
+        for asset in &assets {
+            utils::fs::copy_file_to(&src_dir, &destination, asset)
+                .chain_err(|| format!("Unable to copy {}", asset.display()))?;
+        }
+
+        // Copy all remaining files (a book built in memory may not have a
+        // source directory)
+        if src_dir.is_dir() {
+            utils::fs::copy_files_except_ext(&src_dir, &destination, true, &["md"])?;
+        }
+
+        redirects::emit_redirects(&destination, &html_config.redirect, &*self.writer)
+            .chain_err(|| "Unable to emit redirects")?;
+
+        cache.save(&destination, &*self.writer)
+            .chain_err(|| "Unable to save the render cache")?;
 
         Ok(())
     }
 }
 
+/// Where to look for files overriding the bundled theme.
+///
+/// That's `output.html.theme` (relative to the book's root) if it is set,
+/// otherwise the `theme/` directory in the book's root. Older books keep their
+/// theme in `src/theme/`, which is still used if there's no `theme/`.
+pub(crate) fn theme_dir(root: &Path, src_dir: &Path, html_config: &HtmlConfig) -> PathBuf {
+    match html_config.theme {
+        Some(ref theme) => {
+            let theme = root.join(theme);
+            if !theme.is_dir() {
+                warn!("The theme directory {} doesn't exist, using the default theme",
+                      theme.display());
+            }
+            theme
+        }
+        None => {
+            let theme = root.join("theme");
+            let legacy = src_dir.join("theme");
+
+            if !theme.is_dir() && legacy.is_dir() {
+                legacy
+            } else {
+                theme
+            }
+        }
+    }
+}
+
+/// Where an `additional-css` or `additional-js` file gets copied to,
+/// relative to the build directory.
+fn additional_file_path(root: &Path, file: &Path) -> PathBuf {
+    if !file.is_absolute() {
+        return file.to_path_buf();
+    }
+
+    match file.strip_prefix(root) {
+        Ok(relative) => relative.to_path_buf(),
+        Err(_) => match file.file_name() {
+            Some(name) => PathBuf::from(name),
+            None => file.to_path_buf(),
+        },
+    }
+}
+
+/// The link to an `additional-css` or `additional-js` file, as used by the
+/// template.
+fn additional_file_link(root: &Path, file: &Path) -> String {
+    normalize_path(&additional_file_path(root, file).to_string_lossy())
+}
+
+/// Substitute a chapter's source path into the `edit-url-template`.
+fn edit_url(template: &str, path: &Path) -> String {
+    let path = path.components()
+        .map(|c| utils::percent_encode(&c.as_os_str().to_string_lossy()))
+        .collect::<Vec<_>>()
+        .join("/");
+
+    template.replace("{path}", &path)
+}
+
+/// The title of a chapter's page: `title` from its front matter, otherwise its
+/// first heading, otherwise its name in `SUMMARY.md`.
+fn chapter_title(ch: &Chapter) -> String {
+    match ch.metadata.get("title").and_then(|title| title.as_str()) {
+        Some(title) => title.to_string(),
+        None => utils::first_heading(&ch.content).unwrap_or_else(|| ch.name.clone()),
+    }
+}
+
+/// The title and link (relative to the book's root) of a chapter, as used for
+/// the previous/next chapter navigation.
+fn chapter_link(ch: &Chapter) -> Result<serde_json::Value> {
+    let path = ch.path
+        .as_ref()
+        .chain_err(|| format!("\"{}\" is a draft and has no page to link to", ch.name))?
+        .with_extension("html");
+    let link = path.to_str()
+        .chain_err(|| "Could not convert path to str")?;
+
+    Ok(json!({
+        "title": ch.name,
+        "link": normalize_path(link),
+    }))
+}
+
+/// Another language of a book with more than one.
+struct Translation {
+    code: String,
+    name: String,
+    /// Where the language's chapters are.
+    src_dir: PathBuf,
+}
+
+/// Every language of the book, when it has `[language.<code>]` tables and
+/// one of them is being rendered.
+fn translations(root: &Path, config: &Config) -> Result<Vec<Translation>> {
+    if config.book.language.is_none() {
+        return Ok(Vec::new());
+    }
+
+    let mut translations = Vec::new();
+    for (code, language) in config.languages()? {
+        let src = config.for_language(&code)?.book.src;
+        translations.push(Translation {
+            name: language.name.unwrap_or_else(|| code.clone()),
+            code: code,
+            src_dir: root.join(src),
+        });
+    }
+
+    Ok(translations)
+}
+
+/// A link (relative to the book's root) to a chapter in each language, for
+/// the language picker. A language the chapter hasn't been translated to
+/// gets a link to its index page instead.
+fn language_links(translations: &[Translation],
+                  config: &Config,
+                  ch: &Chapter)
+                  -> Result<Vec<serde_json::Value>> {
+    let path = ch.path
+        .as_ref()
+        .chain_err(|| "Drafts aren't part of the reading order")?;
+    let page = path.with_extension("html");
+    let page = page.to_str()
+        .chain_err(|| "Could not convert path to str")?;
+
+    let links = translations.iter().map(|translation| {
+        let page = if translation.src_dir.join(path).is_file() {
+            normalize_path(page)
+        } else {
+            String::from("index.html")
+        };
+
+        json!({
+            "code": translation.code,
+            "name": translation.name,
+            "current": config.book.language.as_ref() == Some(&translation.code),
+            "link": format!("../{}/{}", translation.code, page),
+        })
+    });
+
+    Ok(links.collect())
+}
+
+/// The output path of every page rendered from the book's contents.
+fn generated_pages(book: &Book) -> Vec<PathBuf> {
+    let mut pages = vec![PathBuf::from("index.html"), PathBuf::from("print.html")];
+
+    for item in book.iter() {
+        if let BookItem::Chapter(Chapter { path: Some(ref path), .. }) = *item {
+            pages.push(path.with_extension("html"));
+        }
+    }
+
+    pages
+}
+
 fn make_data(root: &Path, book: &Book, config: &Config, html_config: &HtmlConfig) -> Result<serde_json::Map<String, serde_json::Value>> {
     trace!("make_data");
     let html = config.html_config().unwrap_or_default();
 
     let mut data = serde_json::Map::new();
-    data.insert("language".to_owned(), json!("en"));
+    let language = config.book.language.clone().unwrap_or_else(|| String::from("en"));
+    data.insert("language".to_owned(), json!(language));
     data.insert("book_title".to_owned(), json!(config.book.title.clone().unwrap_or_default()));
     data.insert("description".to_owned(), json!(config.book.description.clone().unwrap_or_default()));
     data.insert("favicon".to_owned(), json!("favicon.png"));
@@ -366,41 +695,29 @@ fn make_data(root: &Path, book: &Book, config: &Config, html_config: &HtmlConfig
         data.insert("google_analytics".to_owned(), json!(ga));
     }
 
+    if let Some(ref url) = html.git_repository_url {
+        data.insert("git_repository_url".to_owned(), json!(url));
+    }
+
     if html.mathjax_support {
         data.insert("mathjax_support".to_owned(), json!(true));
     }
 
     // Add check to see if there is an additional style
     if !html.additional_css.is_empty() {
-        let mut css = Vec::new();
-        for style in &html.additional_css {
-            match style.strip_prefix(root) {
-                Ok(p) => css.push(p.to_str().expect("Could not convert to str")),
-                Err(_) => {
-                    css.push(style.file_name()
-                                  .expect("File has a file name")
-                                  .to_str()
-                                  .expect("Could not convert to str"))
-                }
-            }
-        }
+        let css: Vec<_> = html.additional_css
+            .iter()
+            .map(|style| additional_file_link(root, style))
+            .collect();
         data.insert("additional_css".to_owned(), json!(css));
     }
 
     // Add check to see if there is an additional script
     if !html.additional_js.is_empty() {
-        let mut js = Vec::new();
-        for script in &html.additional_js {
-            match script.strip_prefix(root) {
-                Ok(p) => js.push(p.to_str().expect("Could not convert to str")),
-                Err(_) => {
-                    js.push(script.file_name()
-                                  .expect("File has a file name")
-                                  .to_str()
-                                  .expect("Could not convert to str"))
-                }
-            }
-        }
+        let js: Vec<_> = html.additional_js
+            .iter()
+            .map(|script| additional_file_link(root, script))
+            .collect();
         data.insert("additional_js".to_owned(), json!(js));
     }
 
@@ -427,14 +744,23 @@ fn make_data(root: &Path, book: &Book, config: &Config, html_config: &HtmlConfig
                 }
 
                 chapter.insert("name".to_owned(), json!(ch.name));
-                let path = ch.path
-                    .to_str()
-                    .chain_err(|| "Could not convert path to str")?;
-                chapter.insert("path".to_owned(), json!(path));
+                match ch.path {
+                    Some(ref path) => {
+                        let path = path.to_str()
+                            .chain_err(|| "Could not convert path to str")?;
+                        chapter.insert("path".to_owned(), json!(path));
+                    }
+                    None => {
+                        chapter.insert("draft".to_owned(), json!("true"));
+                    }
+                }
             }
             BookItem::Separator => {
                 chapter.insert("spacer".to_owned(), json!("_spacer_"));
             }
+            BookItem::PartTitle(ref title) => {
+                chapter.insert("part".to_owned(), json!(title));
+            }
         }
 
         chapters.push(chapter);
@@ -446,38 +772,68 @@ fn make_data(root: &Path, book: &Book, config: &Config, html_config: &HtmlConfig
     Ok(data)
 }
 
-/// Goes through the rendered HTML, making sure all header tags are wrapped in
-/// an anchor so people can link to sections directly.
-fn build_header_links(html: &str, filepath: &str) -> String {
+/// Goes through the rendered HTML, making sure all header tags get an ID so
+/// people can link to sections directly. With `permalinks` the header is also
+/// wrapped in a link to itself.
+///
+/// With `warn_duplicates` a warning is logged whenever a heading's id is
+/// already taken and it has to be given a numbered one instead, because
+/// links to the second heading probably expected the plain id.
+fn build_header_links(html: &str,
+                      filepath: &str,
+                      permalinks: bool,
+                      transliterate: bool,
+                      warn_duplicates: bool)
+                      -> String {
     let regex = Regex::new(r"<h(\d)>(.*?)</h\d>").unwrap();
-    let mut id_counter = HashMap::new();
+    let mut slugs = Slugifier::new();
+    // the heading each id was given to
+    let mut headings: HashMap<String, String> = HashMap::new();
 
     regex.replace_all(html, |caps: &Captures| {
         let level = caps[1].parse()
                            .expect("Regex should ensure we only ever get numbers here");
+        let content = &caps[2];
+
+        let raw_id = if transliterate {
+            utils::ascii_id_from_content(content)
+        } else {
+            utils::id_from_content(content)
+        };
+        let id = slugs.unique(raw_id.clone());
+
+        if warn_duplicates && id != raw_id {
+            let first = headings.get(&raw_id).map(|h| h.as_str()).unwrap_or("");
+            warn!("{}: The heading \"{}\" has the same id as \"{}\" (\"{}\"), so it gets the \
+                   id \"{}\" instead",
+                  filepath,
+                  content,
+                  first,
+                  raw_id,
+                  id);
+        }
+        headings.insert(id.clone(), content.to_string());
 
-        wrap_header_with_link(level, &caps[2], &mut id_counter, filepath)
+        wrap_header_with_link(level, content, &id, filepath, permalinks)
     })
          .into_owned()
 }
 
-/// Wraps a single header tag with a link, making sure each tag gets its own
-/// unique ID by appending an auto-incremented number (if necessary).
+/// Wraps a single header tag with a link to its (unique) `id`.
 fn wrap_header_with_link(level: usize,
                          content: &str,
-                         id_counter: &mut HashMap<String, usize>,
-                         filepath: &str)
+                         id: &str,
+                         filepath: &str,
+                         permalink: bool)
                          -> String {
-    let raw_id = id_from_content(content);
-
-    let id_count = id_counter.entry(raw_id.clone()).or_insert(0);
-
-    let id = match *id_count {
-        0 => raw_id,
-        other => format!("{}-{}", raw_id, other),
-    };
-
-    *id_count += 1;
+    if !permalink {
+        return format!(
+            r##"<h{level} id="{id}">{text}</h{level}>"##,
+            level = level,
+            id = id,
+            text = content
+        );
+    }
 
     format!(
         r##"<a class="header" href="{filepath}#{id}" id="{id}"><h{level}>{text}</h{level}></a>"##,
@@ -488,33 +844,6 @@ fn wrap_header_with_link(level: usize,
     )
 }
 
-/// Generate an id for use with anchors which is derived from a "normalised"
-/// string.
-fn id_from_content(content: &str) -> String {
-    let mut content = content.to_string();
-
-    // Skip any tags or html-encoded stuff
-    const REPL_SUB: &[&str] = &["<em>",
-                                "</em>",
-                                "<code>",
-                                "</code>",
-                                "<strong>",
-                                "</strong>",
-                                "&lt;",
-                                "&gt;",
-                                "&amp;",
-                                "&#39;",
-                                "&quot;"];
-    for sub in REPL_SUB {
-        content = content.replace(sub, "");
-    }
-
-    // Remove spaces and hastags indicating a header
-    let trimmed = content.trim().trim_left_matches('#').trim();
-
-    normalize_id(trimmed)
-}
-
 // anchors to the same page (href="#anchor") do not work because of
 // <base href="../"> pointing to the root folder. This function *fixes*
 // that in a very inelegant way
@@ -542,20 +871,21 @@ fn fix_anchor_links(html: &str, filepath: &str) -> String {
 //     // Code here
 // }
 // ```
-// This function replaces all commas by spaces in the code block classes
+// These end up in the code block's `data-attributes`, and this function adds
+// them to its classes as well, which is where the theme's scripts look for
+// things like `no_run` and `editable`
 fn fix_code_blocks(html: &str) -> String {
-    let regex = Regex::new(r##"<code([^>]+)class="([^"]+)"([^>]*)>"##).unwrap();
+    let regex = Regex::new(r#"<code(?: class="([^"]*)")? data-attributes="([^"]*)""#).unwrap();
     regex.replace_all(html, |caps: &Captures| {
-        let before = &caps[1];
-        let classes = &caps[2].replace(",", " ");
-        let after = &caps[3];
+        let attributes = &caps[2];
+        let classes = match caps.get(1) {
+            Some(class) => format!("{} {}", class.as_str(), attributes),
+            None => attributes.to_string(),
+        };
 
-        format!(r#"<code{before}class="{classes}"{after}>"#,
-                before = before,
-                classes = classes,
-                after = after)
+        format!(r#"<code class="{}" data-attributes="{}""#, classes, attributes)
     })
-         .into_owned()
+        .into_owned()
 }
 
 fn add_playpen_pre(html: &str, playpen_config: &Playpen) -> String {
@@ -619,10 +949,33 @@ struct RenderItemContext<'a> {
     handlebars: &'a Handlebars,
     destination: PathBuf,
     data: serde_json::Map<String, serde_json::Value>,
+    /// The chapter was rendered from exactly the same input last time, so
+    /// there's no need to write it out again.
+    is_unchanged: bool,
+    /// The page is copied to `index.html` too.
     is_index: bool,
     html_config: HtmlConfig,
 }
 
+/// The options a chapter's markdown is rendered with.
+fn markdown_options(html_config: &HtmlConfig) -> MarkdownOptions {
+    MarkdownOptions {
+        curly_quotes: html_config.curly_quotes,
+        sanitize_html: html_config.sanitize_html,
+        raw_html: html_config.raw_html,
+        remember_code_tabs: html_config.remember_code_tabs,
+        external_links_new_tab: html_config.external_links_new_tab,
+        extensionless_links: html_config.extensionless_links,
+        emoji: html_config.emoji,
+        autolink: html_config.autolink,
+        line_numbers: html_config.line_numbers,
+        toc_depth: html_config.toc_depth,
+        transliterate_ids: html_config.transliterate_ids,
+        normalize_link_fragments: html_config.normalize_link_fragments,
+        ..Default::default()
+    }
+}
+
 pub fn normalize_path(path: &str) -> String {
     use std::path::is_separator;
     path.chars()
@@ -630,23 +983,38 @@ pub fn normalize_path(path: &str) -> String {
         .collect::<String>()
 }
 
-pub fn normalize_id(content: &str) -> String {
-    content.chars()
-           .filter_map(|ch| if ch.is_alphanumeric() || ch == '_' || ch == '-' {
-                           Some(ch.to_ascii_lowercase())
-                       } else if ch.is_whitespace() {
-                           Some('-')
-                       } else {
-                           None
-                       })
-           .collect::<String>()
-}
-
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn substitute_the_chapter_path_into_the_edit_url() {
+        let template = "https://github.com/me/book/edit/master/src/{path}";
+        let inputs = vec![
+            ("intro.md", "https://github.com/me/book/edit/master/src/intro.md"),
+            ("first/nested.md", "https://github.com/me/book/edit/master/src/first/nested.md"),
+            (
+                "with spaces/ünïcode.md",
+                "https://github.com/me/book/edit/master/src/with%20spaces/%C3%BCn%C3%AFcode.md",
+            ),
+        ];
+
+        for (path, should_be) in inputs {
+            assert_eq!(edit_url(template, Path::new(path)), should_be);
+        }
+    }
+
+    #[test]
+    fn code_block_attributes_are_added_to_the_classes() {
+        let html = r#"<pre><code class="language-rust" data-attributes="no_run editable">a</code></pre>"#;
+
+        assert_eq!(fix_code_blocks(html),
+                   r#"<pre><code class="language-rust no_run editable" data-attributes="no_run editable">a</code></pre>"#);
+        assert_eq!(fix_code_blocks(r#"<code class="language-rust">b</code>"#),
+                   r#"<code class="language-rust">b</code>"#);
+    }
+
     #[test]
     fn original_build_header_links() {
         let inputs = vec![
@@ -660,7 +1028,7 @@ mod tests {
             ),
             (
                 "<h3>Foo^bar</h3>",
-                r##"<a class="header" href="./some_chapter/some_section.html#foobar" id="foobar"><h3>Foo^bar</h3></a>"##,
+                r##"<a class="header" href="./some_chapter/some_section.html#foo-bar" id="foo-bar"><h3>Foo^bar</h3></a>"##,
             ),
             (
                 "<h4></h4>",
@@ -678,7 +1046,7 @@ mod tests {
 
         for (src, should_be) in inputs {
             let filepath = "./some_chapter/some_section.html";
-            let got = build_header_links(&src, filepath);
+            let got = build_header_links(&src, filepath, true, false, false);
             assert_eq!(got, should_be);
 
             // This is redundant for most cases
@@ -688,10 +1056,58 @@ mod tests {
     }
 
     #[test]
-    fn anchor_generation() {
-        assert_eq!(id_from_content("## `--passes`: add more rustdoc passes"),
-                   "--passes-add-more-rustdoc-passes");
-        assert_eq!(id_from_content("## Method-call expressions"),
-                   "method-call-expressions");
+    fn header_permalinks_point_at_the_header_id() {
+        let filepath = "./some_chapter/some_section.html";
+        let inputs = vec![
+            ("<h2>Getting Started</h2>", "getting-started"),
+            ("<h2>The <code>foo()</code> function</h2>", "the-foo-function"),
+        ];
+
+        for (src, id) in inputs {
+            let got = build_header_links(src, filepath, true, false, false);
+            let href = format!(r#"href="{}#{}""#, filepath, id);
+
+            assert!(got.starts_with(r#"<a class="header" "#), "{}", got);
+            assert!(got.contains(&href), "{}", got);
+            assert!(got.contains(&format!(r#"id="{}""#, id)), "{}", got);
+        }
+    }
+
+    #[test]
+    fn headers_are_left_plain_without_permalinks() {
+        let got = build_header_links("<h1>Foo</h1><h3>Foo</h3>",
+                                     "some_section.html",
+                                     false,
+                                     false,
+                                     false);
+
+        assert_eq!(got, r#"<h1 id="foo">Foo</h1><h3 id="foo-1">Foo</h3>"#);
+    }
+
+    #[test]
+    fn header_ids_can_be_transliterated() {
+        let src = "<h1>Café au lait</h1><h2>Über uns</h2>";
+
+        let got = build_header_links(src, "some_section.html", false, true, false);
+        assert_eq!(got, r#"<h1 id="cafe-au-lait">Café au lait</h1><h2 id="uber-uns">Über uns</h2>"#);
+
+        let got = build_header_links(src, "some_section.html", false, false, false);
+        assert_eq!(got, r#"<h1 id="café-au-lait">Café au lait</h1><h2 id="über-uns">Über uns</h2>"#);
+    }
+
+    #[test]
+    fn additional_files_keep_their_location_relative_to_the_root() {
+        let root = Path::new("/book");
+        let inputs = vec![
+            ("custom.css", "custom.css"),
+            ("styles/custom.css", "styles/custom.css"),
+            ("/book/styles/custom.css", "styles/custom.css"),
+            ("/somewhere/else/custom.css", "custom.css"),
+        ];
+
+        for (src, should_be) in inputs {
+            let got = additional_file_path(root, Path::new(src));
+            assert_eq!(got, Path::new(should_be), "{}", src);
+        }
     }
 }
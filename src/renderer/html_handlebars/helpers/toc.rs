@@ -5,6 +5,8 @@ use serde_json;
 use handlebars::{Handlebars, Helper, HelperDef, RenderContext, RenderError};
 use pulldown_cmark::{html, Event, Parser, Tag};
 
+use utils;
+
 // Handlebars helper to construct TOC
 #[derive(Clone, Copy)]
 pub struct RenderToc {
@@ -36,6 +38,20 @@ impl HelperDef for RenderToc {
                 continue;
             }
 
+            // Part titles always live at the top level
+            if let Some(title) = item.get("part") {
+                while current_level > 1 {
+                    rc.writer.write_all(b"</ol>")?;
+                    rc.writer.write_all(b"</li>")?;
+                    current_level -= 1;
+                }
+
+                rc.writer.write_all(b"<li class=\"part-title\">")?;
+                rc.writer.write_all(utils::escape_html(title).as_bytes())?;
+                rc.writer.write_all(b"</li>")?;
+                continue;
+            }
+
             let level = if let Some(s) = item.get("section") {
                 s.matches('.').count()
             } else {
@@ -93,6 +109,12 @@ impl HelperDef for RenderToc {
                 false
             };
 
+            // Drafts don't have a page to link to
+            let is_draft = item.get("draft").is_some();
+            if is_draft {
+                rc.writer.write_all(b"<span class=\"draft\">")?;
+            }
+
             if !self.no_section_label {
                 // Section does not necessarily exist
                 if let Some(section) = item.get("section") {
@@ -126,6 +148,10 @@ impl HelperDef for RenderToc {
                 rc.writer.write_all(b"</a>")?;
             }
 
+            if is_draft {
+                rc.writer.write_all(b"</span>")?;
+            }
+
             rc.writer.write_all(b"</li>")?;
         }
         while current_level > 1 {
@@ -1,6 +1,10 @@
 #![allow(missing_docs)] // FIXME: Document this
 
+pub use self::cache::RenderCache;
 pub use self::hbs_renderer::HtmlHandlebars;
+pub(crate) use self::hbs_renderer::theme_dir;
 
+mod cache;
 mod hbs_renderer;
 mod helpers;
+mod redirects;
@@ -0,0 +1,252 @@
+//! Emitting the small pages which redirect readers from a chapter's old
+//! location to wherever it lives now (see `output.html.redirect`).
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+use errors::*;
+use utils;
+use utils::fs::FileWriter;
+
+/// Make sure none of the redirects would overwrite a page we generate
+/// ourselves.
+///
+/// `generated` contains the output paths (relative to the build directory) of
+/// every page which was rendered from the book.
+pub fn check_for_conflicts(redirects: &HashMap<String, String>, generated: &[PathBuf]) -> Result<()> {
+    for original in redirects.keys() {
+        let path = redirect_path(original)?;
+
+        if generated.iter().any(|g| *g == path) {
+            bail!(
+                "Unable to redirect \"{}\" because a chapter is rendered to that location",
+                original
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Write a redirect page for each of the entries in the `redirects` map,
+/// checking that relative destinations point at a file which was actually
+/// produced by the build.
+pub fn emit_redirects(destination: &Path,
+                      redirects: &HashMap<String, String>,
+                      writer: &FileWriter)
+                      -> Result<()> {
+    // sort so the build (and any error messages) are deterministic
+    let mut redirects: Vec<_> = redirects.iter().collect();
+    redirects.sort();
+
+    for (original, new) in redirects {
+        debug!("Redirecting \"{}\" to \"{}\"", original, new);
+
+        let path = redirect_path(original)?;
+        let filename = destination.join(&path);
+        let page = redirect_page(new);
+
+        // an incremental build leaves the previous build's redirects behind
+        if filename.exists() && !is_redirect_page(&filename, &page) {
+            bail!(
+                "Not redirecting \"{}\" to \"{}\" because {} already exists",
+                original,
+                new,
+                filename.display()
+            );
+        }
+
+        if !utils::is_absolute_url(new) {
+            let target = resolve_relative(&path, new)
+                .chain_err(|| format!("Invalid redirect destination \"{}\"", new))?;
+
+            // a link to a directory gets its index page
+            let target = if destination.join(&target).is_dir() {
+                target.join("index.html")
+            } else {
+                target
+            };
+
+            if !destination.join(&target).is_file() {
+                bail!(
+                    "The redirect from \"{}\" points to \"{}\", which wasn't generated by the build",
+                    original,
+                    new
+                );
+            }
+        }
+
+        writer.write_file(&filename, page.as_bytes())?;
+    }
+
+    Ok(())
+}
+
+/// Does the file contain exactly this redirect page?
+fn is_redirect_page(filename: &Path, page: &str) -> bool {
+    let mut content = String::new();
+
+    match File::open(filename).and_then(|mut f| f.read_to_string(&mut content)) {
+        Ok(_) => content == page,
+        Err(_) => false,
+    }
+}
+
+/// The contents of a page which immediately sends the reader to `url`.
+pub fn redirect_page(url: &str) -> String {
+    let url = utils::escape_html(url);
+
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+    <head>
+        <meta charset="UTF-8">
+        <meta http-equiv="refresh" content="0; URL={url}">
+        <link rel="canonical" href="{url}">
+        <title>Redirecting...</title>
+    </head>
+    <body>
+        <p>This page has moved to <a href="{url}">{url}</a>.</p>
+    </body>
+</html>
+"#,
+        url = url
+    )
+}
+
+/// Where the redirect page for `original` goes, relative to the build
+/// directory.
+///
+/// All paths are relative to the build directory, so a leading slash doesn't
+/// mean anything (and would make `Path::join()` replace the build directory).
+fn redirect_path(original: &str) -> Result<PathBuf> {
+    let path = in_build_dir(Path::new(original.trim_left_matches('/')))
+        .chain_err(|| format!("Invalid redirect \"{}\"", original))?;
+
+    if path.file_name().is_none() {
+        bail!("Invalid redirect \"{}\": It doesn't name a file", original);
+    }
+
+    Ok(path)
+}
+
+/// Figure out which file (relative to the build directory) a relative
+/// redirect destination refers to, ignoring any fragment or query string.
+/// A destination ending in a `/` refers to that directory's `index.html`.
+fn resolve_relative(original: &Path, destination: &str) -> Result<PathBuf> {
+    let destination = destination
+        .split(|c| c == '#' || c == '?')
+        .next()
+        .unwrap_or("");
+    let destination = match utils::percent_decode(destination) {
+        Some(decoded) => decoded,
+        None => bail!("The escaped characters in it aren't valid UTF-8"),
+    };
+    let destination = if destination.ends_with('/') {
+        Cow::Owned(format!("{}index.html", destination))
+    } else {
+        destination
+    };
+
+    let joined = if destination.starts_with('/') {
+        PathBuf::from(destination.trim_left_matches('/'))
+    } else {
+        original
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
+            .join(&*destination)
+    };
+
+    in_build_dir(&joined)
+}
+
+/// Resolve the `.` and `..` components of a path relative to the build
+/// directory, making sure it doesn't point outside of it.
+fn in_build_dir(path: &Path) -> Result<PathBuf> {
+    let mut resolved = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::Normal(c) => resolved.push(c),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !resolved.pop() {
+                    bail!("It points outside of the build directory");
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => {
+                bail!("It isn't a relative path");
+            }
+        }
+    }
+
+    Ok(resolved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relative_destinations_are_resolved_against_the_redirect_page() {
+        let inputs = vec![
+            ("old.html", "new.html", "new.html"),
+            ("nested/old.html", "new.html#intro", "nested/new.html"),
+            ("nested/old.html", "../new.html", "new.html"),
+            ("nested/old.html", "/other/new.html?x=1", "other/new.html"),
+            ("old.html", "new%20page.html", "new page.html"),
+            ("old.html", "guide/", "guide/index.html"),
+            ("nested/old.html", "../#top", "index.html"),
+        ];
+
+        for (original, destination, should_be) in inputs {
+            let got = resolve_relative(Path::new(original), destination).unwrap();
+            assert_eq!(got, Path::new(should_be));
+        }
+    }
+
+    #[test]
+    fn destinations_cant_escape_the_build_directory() {
+        assert!(resolve_relative(Path::new("old.html"), "../new.html").is_err());
+    }
+
+    #[test]
+    fn redirects_are_written_inside_the_build_directory() {
+        let inputs = vec![
+            ("old.html", "old.html"),
+            ("/nested/old.html", "nested/old.html"),
+            ("nested/../old.html", "old.html"),
+            ("./nested/./old.html", "nested/old.html"),
+        ];
+
+        for (original, should_be) in inputs {
+            assert_eq!(redirect_path(original).unwrap(), Path::new(should_be));
+        }
+    }
+
+    #[test]
+    fn redirects_cant_escape_the_build_directory() {
+        let inputs = vec![
+            "../../etc/x.html",
+            "nested/../../x.html",
+            "//x/../../y.html",
+            "/",
+            "nested/..",
+        ];
+
+        for original in inputs {
+            assert!(redirect_path(original).is_err(), "{}", original);
+        }
+    }
+
+    #[test]
+    fn redirect_pages_escape_the_url() {
+        let got = redirect_page("new.html?a=1&b=\"2\"");
+
+        assert!(got.contains(r#"content="0; URL=new.html?a=1&amp;b=&quot;2&quot;""#));
+        assert!(!got.contains("b=\"2\""));
+    }
+}
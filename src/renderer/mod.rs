@@ -11,8 +11,10 @@
 //! [For Developers]: https://rust-lang-nursery.github.io/mdBook/lib/index.html
 //! [RenderContext]: struct.RenderContext.html
 
-pub use self::html_handlebars::HtmlHandlebars;
+pub use self::html_handlebars::{HtmlHandlebars, RenderCache};
+pub use self::epub::EpubRenderer;
 
+mod epub;
 mod html_handlebars;
 
 use std::fs;
@@ -47,6 +49,16 @@ pub trait Renderer {
     /// Invoke the `Renderer`, passing in all the necessary information for
     /// describing a book.
     fn render(&self, ctx: &RenderContext) -> Result<()>;
+
+    /// Does the `Renderer` skip re-rendering the parts of the book which
+    /// haven't changed since the last build?
+    ///
+    /// If so, the build directory isn't cleared before rendering (unless
+    /// `build.incremental` is turned off) and the `Renderer` becomes
+    /// responsible for cleaning up anything left over from previous builds.
+    fn is_incremental(&self) -> bool {
+        false
+    }
 }
 
 /// The context provided to all renderers.
@@ -0,0 +1,544 @@
+//! Helpers for testing preprocessors, renderers and mdBook itself against
+//! realistic book data.
+//!
+//! This module is only available when the `test-utils` feature is enabled.
+//!
+//! # Examples
+//!
+//! Build a small two-chapter book in a temporary directory, render it with the
+//! default HTML renderer and check what came out the other end.
+//!
+//! ```rust
+//! # extern crate mdbook;
+//! use mdbook::test_utils::FixtureBook;
+//!
+//! # fn main() {
+//! let rendered = FixtureBook::new()
+//!     .with_title("Fixture")
+//!     .chapter("Introduction", "intro.md", "# Introduction\n\nHello, World!")
+//!     .chapter("Getting Started", "getting-started.md", "# Getting Started")
+//!     .render()
+//!     .unwrap();
+//!
+//! assert!(rendered.contains_file("intro.html"));
+//! rendered.assert_contains("intro.html", &["<p>Hello, World!</p>", "Fixture"]);
+//! rendered.assert_contains("getting-started.html", &["Getting Started"]);
+//! # }
+//! ```
+
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::{self, Level, LevelFilter, Log, Metadata, Record};
+use tempdir::TempDir;
+use toml;
+
+use book::{Book, BookItem, Chapter, MDBook, SectionNumber};
+use config::Config;
+use preprocess::PreprocessorContext;
+use renderer::RenderContext;
+use utils::fs::{DiskWriter, FileWriter};
+use errors::*;
+
+/// A builder for small books used as test fixtures.
+///
+/// The book can either be used in-memory (`book()`), or written to disk so it
+/// can be loaded and rendered like a normal book (`write_to()`, `build()` and
+/// `render()`).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FixtureBook {
+    config: Config,
+    chapters: Vec<FixtureChapter>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct FixtureChapter {
+    name: String,
+    path: Option<PathBuf>,
+    content: String,
+    sub_chapters: Vec<FixtureChapter>,
+}
+
+impl FixtureChapter {
+    fn new<P: Into<PathBuf>>(name: &str, path: P, content: &str) -> FixtureChapter {
+        FixtureChapter {
+            name: name.to_string(),
+            path: Some(path.into()),
+            content: content.to_string(),
+            sub_chapters: Vec::new(),
+        }
+    }
+
+    fn draft(name: &str) -> FixtureChapter {
+        FixtureChapter {
+            name: name.to_string(),
+            path: None,
+            content: String::new(),
+            sub_chapters: Vec::new(),
+        }
+    }
+
+    fn to_chapter(&self, number: Vec<u32>) -> Chapter {
+        let mut chapter = match self.path {
+            Some(ref path) => Chapter::new(&self.name, self.content.clone(), path.clone()),
+            None => Chapter::new_draft(&self.name),
+        };
+
+        for (i, sub) in self.sub_chapters.iter().enumerate() {
+            let mut sub_number = number.clone();
+            sub_number.push(i as u32 + 1);
+            chapter.sub_items.push(BookItem::Chapter(sub.to_chapter(sub_number)));
+        }
+
+        chapter.number = Some(SectionNumber(number));
+        chapter
+    }
+}
+
+impl FixtureBook {
+    /// Create an empty fixture using the default configuration.
+    pub fn new() -> FixtureBook {
+        FixtureBook::default()
+    }
+
+    /// Set the book's title.
+    pub fn with_title(&mut self, title: &str) -> &mut FixtureBook {
+        self.config.book.title = Some(title.to_string());
+        self
+    }
+
+    /// Use this configuration instead of the default one.
+    pub fn with_config(&mut self, config: Config) -> &mut FixtureBook {
+        self.config = config;
+        self
+    }
+
+    /// Get a mutable reference to the book's configuration.
+    pub fn config_mut(&mut self) -> &mut Config {
+        &mut self.config
+    }
+
+    /// Add a top-level chapter whose source lives at `path` (relative to the
+    /// `src` directory).
+    pub fn chapter<P: Into<PathBuf>>(&mut self, name: &str, path: P, content: &str) -> &mut FixtureBook {
+        self.chapters.push(FixtureChapter::new(name, path, content));
+        self
+    }
+
+    /// Add a top-level draft chapter, which has no source file.
+    pub fn draft_chapter(&mut self, name: &str) -> &mut FixtureBook {
+        self.chapters.push(FixtureChapter::draft(name));
+        self
+    }
+
+    /// Nest a chapter under the most recently added top-level chapter.
+    ///
+    /// # Panics
+    ///
+    /// If there is no top-level chapter to nest it under.
+    pub fn sub_chapter<P: Into<PathBuf>>(&mut self, name: &str, path: P, content: &str) -> &mut FixtureBook {
+        self.chapters
+            .last_mut()
+            .expect("Sub-chapters need a chapter to be nested under")
+            .sub_chapters
+            .push(FixtureChapter::new(name, path, content));
+        self
+    }
+
+    /// Add `chapters` generated top-level chapters, each with `sub_chapters`
+    /// generated sub-chapters, for when only the book's size and shape
+    /// matter.
+    pub fn generated(&mut self, chapters: usize, sub_chapters: usize) -> &mut FixtureBook {
+        let start = self.chapters.len() + 1;
+
+        for i in start..start + chapters {
+            let content = format!("# Chapter {}\n\nSome text for chapter {}.\n", i, i);
+            self.chapter(&format!("Chapter {}", i), format!("chapter_{}.md", i), &content);
+
+            for j in 1..sub_chapters + 1 {
+                let content = format!("# Section {}.{}\n\nSome nested text.\n", i, j);
+                self.sub_chapter(
+                    &format!("Section {}.{}", i, j),
+                    format!("chapter_{}/section_{}.md", i, j),
+                    &content,
+                );
+            }
+        }
+
+        self
+    }
+
+    /// Get the in-memory `Book` this fixture describes, numbered the same way
+    /// as if it were loaded from disk.
+    pub fn book(&self) -> Book {
+        let mut book = Book::new();
+
+        for (i, chapter) in self.chapters.iter().enumerate() {
+            book.push_item(chapter.to_chapter(vec![i as u32 + 1]));
+        }
+
+        book
+    }
+
+    /// Get the contents of the `SUMMARY.md` for this book.
+    pub fn summary(&self) -> String {
+        let mut summary = String::from("# Summary\n\n");
+
+        for chapter in &self.chapters {
+            write_summary_entry(&mut summary, chapter, 0);
+        }
+
+        summary
+    }
+
+    /// Write the book (`book.toml`, `SUMMARY.md` and every chapter) to the
+    /// `root` directory.
+    pub fn write_to(&self, root: &Path) -> Result<()> {
+        let src = root.join(&self.config.book.src);
+
+        let config = toml::to_string(&self.config).chain_err(|| "Unable to serialize the config")?;
+        write_file(&root.join("book.toml"), &config)?;
+        write_file(&src.join("SUMMARY.md"), &self.summary())?;
+
+        for item in self.book().iter() {
+            if let BookItem::Chapter(Chapter { path: Some(ref path), ref content, .. }) = *item {
+                write_file(&src.join(path), content)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write the book to a temporary directory and load it.
+    ///
+    /// The `TempDir` needs to be kept alive for as long as the book is used.
+    pub fn build(&self) -> Result<(MDBook, TempDir)> {
+        let temp = TempDir::new("fixture-book").chain_err(|| "Unable to create temp directory")?;
+        self.write_to(temp.path())?;
+
+        // book.toml doesn't contain everything (e.g. the `[build]` table), so
+        // pass the config along explicitly
+        let md = MDBook::load_with_config(temp.path(), self.config.clone())?;
+
+        Ok((md, temp))
+    }
+
+    /// Write the book to a temporary directory, build it, and read back
+    /// everything which was rendered.
+    pub fn render(&self) -> Result<RenderedBook> {
+        let (md, temp) = self.build()?;
+        md.build()?;
+
+        RenderedBook::read_from(temp, &md.config.build.build_dir)
+    }
+
+    /// A `RenderContext` for this book, as if it were located at `root`.
+    pub fn render_context<P: Into<PathBuf>>(&self, root: P) -> RenderContext {
+        let root = root.into();
+        let destination = root.join(&self.config.build.build_dir);
+
+        RenderContext::new(root, self.book(), self.config.clone(), destination)
+    }
+
+    /// A `PreprocessorContext` for this book, as if it were located at `root`.
+    pub fn preprocessor_context<P: Into<PathBuf>>(&self, root: P) -> PreprocessorContext {
+        PreprocessorContext::new(root.into(), self.config.clone())
+    }
+}
+
+fn write_summary_entry(summary: &mut String, chapter: &FixtureChapter, depth: usize) {
+    let path = match chapter.path {
+        Some(ref path) => path.components()
+            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+            .collect::<Vec<_>>()
+            .join("/"),
+        None => String::new(),
+    };
+
+    summary.push_str(&format!("{}- [{}]({})\n", "    ".repeat(depth), chapter.name, path));
+
+    for sub in &chapter.sub_chapters {
+        write_summary_entry(summary, sub, depth + 1);
+    }
+}
+
+fn write_file(path: &Path, content: &str) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    File::create(path)?.write_all(content.as_bytes())?;
+    Ok(())
+}
+
+/// Everything a book's renderers wrote to the build directory.
+#[derive(Debug)]
+pub struct RenderedBook {
+    root: TempDir,
+    files: BTreeMap<PathBuf, Vec<u8>>,
+}
+
+impl RenderedBook {
+    fn read_from(root: TempDir, build_dir: &Path) -> Result<RenderedBook> {
+        let mut files = BTreeMap::new();
+        let build_dir = root.path().join(build_dir);
+        read_dir_recursive(&build_dir, &build_dir, &mut files)?;
+
+        Ok(RenderedBook {
+            root: root,
+            files: files,
+        })
+    }
+
+    /// The book's root directory.
+    pub fn root(&self) -> &Path {
+        self.root.path()
+    }
+
+    /// The paths of every output file, relative to the build directory.
+    pub fn paths(&self) -> Vec<&Path> {
+        self.files.keys().map(|p| p.as_path()).collect()
+    }
+
+    /// Was this file (relative to the build directory) rendered?
+    pub fn contains_file<P: AsRef<Path>>(&self, path: P) -> bool {
+        self.files.contains_key(path.as_ref())
+    }
+
+    /// Get the raw contents of an output file.
+    pub fn get<P: AsRef<Path>>(&self, path: P) -> Option<&[u8]> {
+        self.files.get(path.as_ref()).map(|v| v.as_slice())
+    }
+
+    /// Get the contents of an output file as a string, if it exists and is
+    /// valid UTF-8.
+    pub fn get_str<P: AsRef<Path>>(&self, path: P) -> Option<&str> {
+        self.get(path).and_then(|bytes| ::std::str::from_utf8(bytes).ok())
+    }
+
+    /// Assert that the output file exists and contains each of the `strings`.
+    pub fn assert_contains<P: AsRef<Path>>(&self, path: P, strings: &[&str]) {
+        let path = path.as_ref();
+        let content = match self.get_str(path) {
+            Some(content) => content,
+            None => panic!("{} wasn't rendered. Got {:?}", path.display(), self.paths()),
+        };
+
+        for s in strings {
+            assert!(
+                content.contains(s),
+                "Searching for {:?} in {}\n\n{}",
+                s,
+                path.display(),
+                content
+            );
+        }
+    }
+}
+
+fn read_dir_recursive(root: &Path, dir: &Path, files: &mut BTreeMap<PathBuf, Vec<u8>>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+
+        if path.is_dir() {
+            read_dir_recursive(root, &path, files)?;
+        } else {
+            let mut content = Vec::new();
+            File::open(&path)?.read_to_end(&mut content)?;
+
+            let relative = path.strip_prefix(root)
+                .expect("Everything we find is inside the root")
+                .to_path_buf();
+            files.insert(relative, content);
+        }
+    }
+
+    Ok(())
+}
+
+/// A `FileWriter` which writes to disk, except the `n`th write (counting from
+/// 1) and every one after it fail.
+///
+/// Hand it to a renderer (e.g. `HtmlHandlebars::with_writer()`) to check
+/// what happens when the disk fills up partway through a build.
+#[derive(Debug)]
+pub struct FailingFileWriter {
+    writes: Cell<usize>,
+    fail_on: usize,
+}
+
+impl FailingFileWriter {
+    /// Create a writer which fails on the `n`th write.
+    pub fn new(n: usize) -> FailingFileWriter {
+        FailingFileWriter {
+            writes: Cell::new(0),
+            fail_on: n,
+        }
+    }
+}
+
+impl FileWriter for FailingFileWriter {
+    fn write_file(&self, path: &Path, content: &[u8]) -> Result<()> {
+        self.writes.set(self.writes.get() + 1);
+
+        if self.writes.get() >= self.fail_on {
+            bail!("Injected failure writing {}", path.display());
+        }
+        DiskWriter.write_file(path, content)
+    }
+}
+
+/// Run a command, killing it and returning an error if it takes longer than
+/// `timeout`.
+///
+/// The command's output is only read once it exits, so this isn't suitable
+/// for commands which write a lot to `stdout` or `stderr`.
+pub fn run_with_timeout(cmd: &mut Command, timeout: Duration) -> Result<Output> {
+    let mut child = cmd.stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .chain_err(|| "Unable to start the command")?;
+    let started = Instant::now();
+
+    loop {
+        if child.try_wait()?.is_some() {
+            return child.wait_with_output().map_err(|e| e.into());
+        }
+
+        if started.elapsed() >= timeout {
+            // it may have exited in the meantime, which is fine
+            let _ = child.kill();
+            let _ = child.wait();
+            bail!("The command timed out after {:?}", timeout);
+        }
+
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// A command which never finishes in any reasonable amount of time, for
+/// exercising timeouts with `run_with_timeout()`.
+pub fn hanging_command() -> Command {
+    if cfg!(windows) {
+        let mut cmd = Command::new("ping");
+        cmd.args(&["-n", "3600", "127.0.0.1"]);
+        cmd
+    } else {
+        let mut cmd = Command::new("sleep");
+        cmd.arg("3600");
+        cmd
+    }
+}
+
+thread_local! {
+    static CAPTURED_LOGS: RefCell<Option<Vec<(Level, String)>>> = RefCell::new(None);
+}
+
+/// A logger which keeps the warnings and errors logged on each thread, for
+/// `capture_warnings()`.
+struct CapturingLogger;
+
+impl Log for CapturingLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= Level::Warn
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        CAPTURED_LOGS.with(|captured| {
+            if let Some(ref mut logs) = *captured.borrow_mut() {
+                logs.push((record.level(), record.args().to_string()));
+            }
+        });
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: CapturingLogger = CapturingLogger;
+
+/// Run `f`, returning the warnings and errors it logged as `(level,
+/// message)` pairs.
+///
+/// Only messages logged on the current thread are captured, so tests running
+/// in parallel don't see each other's warnings. A process can only have one
+/// logger, which means nothing is captured if something else (like
+/// `env_logger`) was set up first.
+pub fn capture_warnings<F: FnOnce()>(f: F) -> Vec<(Level, String)> {
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(LevelFilter::Warn);
+    }
+
+    CAPTURED_LOGS.with(|captured| *captured.borrow_mut() = Some(Vec::new()));
+    f();
+    CAPTURED_LOGS.with(|captured| captured.borrow_mut().take().unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use renderer::{HtmlHandlebars, Renderer};
+
+    #[test]
+    fn generated_books_have_the_requested_shape() {
+        let book = FixtureBook::new().generated(3, 2).book();
+
+        let chapters: Vec<_> = book.iter()
+            .filter_map(|item| match *item {
+                BookItem::Chapter(ref ch) => Some((ch.number.clone().unwrap().to_string(), ch.path.clone().unwrap())),
+                _ => None,
+            })
+            .collect();
+
+        assert_eq!(chapters.len(), 9);
+        assert_eq!(chapters[0], (String::from("1."), PathBuf::from("chapter_1.md")));
+        assert_eq!(
+            chapters[2],
+            (String::from("1.2."), PathBuf::from("chapter_1/section_2.md"))
+        );
+    }
+
+    #[test]
+    fn the_book_on_disk_matches_the_one_in_memory() {
+        let fixture = {
+            let mut f = FixtureBook::new();
+            f.generated(2, 1);
+            f
+        };
+
+        let (md, _temp) = fixture.build().unwrap();
+
+        assert_eq!(md.book, fixture.book());
+    }
+
+    #[test]
+    fn slow_commands_time_out() {
+        let got = run_with_timeout(&mut hanging_command(), Duration::from_millis(50));
+
+        assert!(got.is_err());
+    }
+
+    #[test]
+    fn a_build_fails_on_the_nth_write() {
+        let fixture = FixtureBook::new().generated(2, 0).clone();
+        let (_, temp) = fixture.build().unwrap();
+        let ctx = fixture.render_context(temp.path());
+
+        let got = HtmlHandlebars::with_writer(FailingFileWriter::new(3)).render(&ctx);
+
+        let err = got.unwrap_err();
+        assert!(err.iter().any(|e| e.to_string().starts_with("Injected failure writing")),
+                "{:?}", err);
+        assert!(HtmlHandlebars::with_writer(FailingFileWriter::new(1000)).render(&ctx).is_ok());
+    }
+}
@@ -479,6 +479,32 @@ function playpen_text(playpen) {
     });
 })();
 
+(function codeTabs() {
+    // Only groups rendered with `remember-code-tabs` share the reader's choice
+    var inputs = Array.from(document.querySelectorAll('.code-tabs-remember .code-tab-input'));
+
+    function selectTab(name) {
+        inputs.forEach(function (input) {
+            if (input.dataset.tabName === name) {
+                input.checked = true;
+            }
+        });
+    }
+
+    var preferred;
+    try { preferred = localStorage.getItem('mdbook-code-tab'); } catch (e) { }
+    if (preferred) {
+        selectTab(preferred);
+    }
+
+    inputs.forEach(function (input) {
+        input.addEventListener('change', function () {
+            try { localStorage.setItem('mdbook-code-tab', input.dataset.tabName); } catch (e) { }
+            selectTab(input.dataset.tabName);
+        });
+    });
+})();
+
 (function clipboard() {
     var clipButtons = document.querySelectorAll('.clip-button');
 
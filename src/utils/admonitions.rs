@@ -0,0 +1,169 @@
+//! GitHub style alerts, where a blockquote starting with a marker like
+//! `[!NOTE]` or `[!WARNING]` is shown as a callout.
+//!
+//! ```markdown
+//! > [!WARNING]
+//! > This will delete everything.
+//! ```
+
+use std::borrow::Cow;
+
+use pulldown_cmark::{Event, Tag};
+use regex::Regex;
+
+use super::escape_html;
+
+/// The types of admonition the theme has styles for. Anything else gets the
+/// generic `admonition` styling.
+const KNOWN_TYPES: &[&str] = &["caution", "important", "note", "tip", "warning"];
+
+/// Wrap every blockquote which starts with an admonition marker in a
+/// `<div class="admonition ...">` with a title, removing the marker itself.
+pub fn convert_admonitions<'a>(events: Vec<Event<'a>>) -> Vec<Event<'a>> {
+    let mut converted = Vec::with_capacity(events.len());
+    let mut events = events.into_iter();
+
+    while let Some(event) = events.next() {
+        match event {
+            Event::Start(Tag::BlockQuote) => {
+                let body = take_blockquote_body(&mut events);
+                converted.extend(convert_blockquote(body));
+            }
+            other => converted.push(other),
+        }
+    }
+
+    converted
+}
+
+/// Take everything up to (and including) the end of the blockquote which was
+/// just started, skipping over any nested blockquotes.
+fn take_blockquote_body<'a, I>(events: &mut I) -> Vec<Event<'a>>
+where
+    I: Iterator<Item = Event<'a>>,
+{
+    let mut body = Vec::new();
+    let mut depth = 1;
+
+    for event in events {
+        match event {
+            Event::Start(Tag::BlockQuote) => depth += 1,
+            Event::End(Tag::BlockQuote) => depth -= 1,
+            _ => {}
+        }
+
+        body.push(event);
+        if depth == 0 {
+            break;
+        }
+    }
+
+    body
+}
+
+/// Convert a single blockquote, given everything after its start event.
+fn convert_blockquote(mut body: Vec<Event>) -> Vec<Event> {
+    let end = match body.pop() {
+        Some(end @ Event::End(Tag::BlockQuote)) => Some(end),
+        // an unterminated blockquote at the end of the document
+        Some(other) => {
+            body.push(other);
+            None
+        }
+        None => None,
+    };
+
+    let kind = take_marker(&mut body);
+    let body = convert_admonitions(body);
+
+    let mut converted = Vec::with_capacity(body.len() + 4);
+    if let Some(ref kind) = kind {
+        converted.push(Event::Html(Cow::from(opening_html(kind))));
+    }
+    converted.push(Event::Start(Tag::BlockQuote));
+    converted.extend(body);
+    converted.extend(end);
+    if kind.is_some() {
+        converted.push(Event::Html(Cow::from("</div>\n")));
+    }
+
+    converted
+}
+
+/// If the blockquote's first paragraph starts with an admonition marker,
+/// remove it and return the admonition's type.
+fn take_marker(body: &mut Vec<Event>) -> Option<String> {
+    lazy_static! {
+        static ref MARKER: Regex = Regex::new(r"^\[!([A-Za-z]+)\]").unwrap();
+    }
+
+    match body.first() {
+        Some(&Event::Start(Tag::Paragraph)) => {}
+        _ => return None,
+    }
+
+    // pulldown-cmark may split the marker over several text events
+    let mut lead = String::new();
+    let mut text_events = 0;
+    for event in &body[1..] {
+        match *event {
+            Event::Text(ref text) => lead.push_str(text),
+            _ => break,
+        }
+        text_events += 1;
+    }
+
+    let (kind, marker_len) = match MARKER.captures(&lead) {
+        Some(caps) => (caps[1].to_lowercase(), caps[0].len()),
+        None => return None,
+    };
+
+    let rest = lead[marker_len..].trim_left().to_string();
+    let after = body.split_off(1 + text_events);
+    body.truncate(1);
+    if !rest.is_empty() {
+        body.push(Event::Text(Cow::from(rest)));
+    }
+    body.extend(after);
+
+    // the marker was on a line of its own
+    if body.get(1).map_or(false, is_soft_break) {
+        body.remove(1);
+    }
+    if body.get(1).map_or(false, is_paragraph_end) {
+        body.drain(..2);
+    }
+
+    Some(kind)
+}
+
+fn is_soft_break(event: &Event) -> bool {
+    match *event {
+        Event::SoftBreak => true,
+        _ => false,
+    }
+}
+
+fn is_paragraph_end(event: &Event) -> bool {
+    match *event {
+        Event::End(Tag::Paragraph) => true,
+        _ => false,
+    }
+}
+
+fn opening_html(kind: &str) -> String {
+    let class = if KNOWN_TYPES.contains(&kind) {
+        format!("admonition {}", kind)
+    } else {
+        String::from("admonition")
+    };
+
+    // the marker only matches ASCII letters, so slicing off the first one is fine
+    let title = kind[..1].to_uppercase() + &kind[1..];
+
+    format!(
+        "<div class=\"{}\">\n<p class=\"admonition-title\">{}</p>\n",
+        class,
+        escape_html(&title)
+    )
+}
@@ -0,0 +1,166 @@
+//! Turning bare `http://` and `https://` URLs in a chapter's text into links,
+//! like GitHub does.
+
+use std::borrow::Cow;
+
+use pulldown_cmark::{Event, Tag};
+use regex::Regex;
+
+use super::code_tag;
+
+/// Wrap every bare URL in the text in a link to itself. URLs inside code,
+/// links (including `<https://...>` autolinks and raw `<a>` tags) and image
+/// descriptions are left alone, so nothing gets linked twice.
+pub fn autolink_urls<'a>(events: Vec<Event<'a>>) -> Vec<Event<'a>> {
+    let mut converted = Vec::with_capacity(events.len());
+    // the parser often splits text up (at a `&` or `_`, say), so consecutive
+    // text is joined back together before looking for URLs in it
+    let mut text: Vec<Cow<'a, str>> = Vec::new();
+    let mut skip_depth = 0;
+    let mut html_depth = 0;
+
+    for event in events {
+        if let Event::Text(t) = event {
+            text.push(t);
+            continue;
+        }
+
+        flush_text(&mut text, skip_depth + html_depth > 0, &mut converted);
+
+        match event {
+            Event::Start(Tag::Code) |
+            Event::Start(Tag::CodeBlock(_)) |
+            Event::Start(Tag::Link(..)) |
+            Event::Start(Tag::Image(..)) => skip_depth += 1,
+            Event::End(Tag::Code) |
+            Event::End(Tag::CodeBlock(_)) |
+            Event::End(Tag::Link(..)) |
+            Event::End(Tag::Image(..)) => skip_depth -= 1,
+            Event::InlineHtml(ref html) => {
+                match (code_tag(html), link_tag(html)) {
+                    (Some(true), _) | (_, Some(true)) => html_depth += 1,
+                    (Some(false), _) | (_, Some(false)) if html_depth > 0 => {
+                        html_depth -= 1
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+
+        converted.push(event);
+    }
+
+    flush_text(&mut text, skip_depth + html_depth > 0, &mut converted);
+    converted
+}
+
+/// Push the text collected so far, with any URLs in it turned into links
+/// (unless it's somewhere links aren't wanted).
+fn flush_text<'a>(text: &mut Vec<Cow<'a, str>>, skip: bool, converted: &mut Vec<Event<'a>>) {
+    if skip || !text.iter().any(|t| t.contains("://")) {
+        converted.extend(text.drain(..).map(Event::Text));
+        return;
+    }
+
+    let joined: String = text.drain(..).collect();
+    let mut last = 0;
+
+    for (start, end) in find_urls(&joined) {
+        if last < start {
+            converted.push(Event::Text(Cow::from(joined[last..start].to_string())));
+        }
+
+        let url = &joined[start..end];
+        converted.push(Event::Start(Tag::Link(Cow::from(url.to_string()), Cow::from(""))));
+        converted.push(Event::Text(Cow::from(url.to_string())));
+        converted.push(Event::End(Tag::Link(Cow::from(url.to_string()), Cow::from(""))));
+        last = end;
+    }
+
+    if last < joined.len() {
+        converted.push(Event::Text(Cow::from(joined[last..].to_string())));
+    }
+}
+
+/// The byte ranges of the bare URLs in some text.
+fn find_urls(text: &str) -> Vec<(usize, usize)> {
+    lazy_static! {
+        static ref URL: Regex = Regex::new(r"\bhttps?://[^\s<>]+").unwrap();
+    }
+
+    URL.find_iter(text)
+       .filter_map(|m| {
+           let end = m.start() + url_len(m.as_str());
+           // there has to be more to it than the scheme
+           if text[m.start()..end].ends_with("://") {
+               None
+           } else {
+               Some((m.start(), end))
+           }
+       })
+       .collect()
+}
+
+/// How much of a candidate URL is really part of it. Punctuation at the end
+/// (`see https://example.com.`) usually belongs to the sentence, and so does
+/// a closing parenthesis without a matching opening one.
+fn url_len(candidate: &str) -> usize {
+    let mut url = candidate;
+
+    loop {
+        let trimmed = url.trim_right_matches(|c: char| ".,:;!?'\"*_~".contains(c));
+        let trimmed = if trimmed.ends_with(')')
+            && trimmed.matches(')').count() > trimmed.matches('(').count()
+        {
+            &trimmed[..trimmed.len() - 1]
+        } else {
+            trimmed
+        };
+
+        if trimmed.len() == url.len() {
+            return url.len();
+        }
+        url = trimmed;
+    }
+}
+
+/// Is this inline HTML an opening (`Some(true)`) or closing (`Some(false)`)
+/// `<a>` tag?
+fn link_tag(html: &str) -> Option<bool> {
+    let html = html.trim().to_lowercase();
+
+    if html == "</a>" {
+        Some(false)
+    } else if html.starts_with("<a>") || html.starts_with("<a ") {
+        Some(true)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn urls(text: &str) -> Vec<&str> {
+        find_urls(text).into_iter().map(|(start, end)| &text[start..end]).collect()
+    }
+
+    #[test]
+    fn trailing_punctuation_isnt_part_of_the_url() {
+        assert_eq!(urls("See https://example.com/a.html, or http://example.com."),
+                   vec!["https://example.com/a.html", "http://example.com"]);
+    }
+
+    #[test]
+    fn only_balanced_parentheses_are_part_of_the_url() {
+        assert_eq!(urls("(https://en.wikipedia.org/wiki/Rust_(programming_language))"),
+                   vec!["https://en.wikipedia.org/wiki/Rust_(programming_language)"]);
+    }
+
+    #[test]
+    fn a_scheme_on_its_own_isnt_a_url() {
+        assert!(urls("Starts with https:// or xhttps://example.com").is_empty());
+    }
+}
@@ -0,0 +1,310 @@
+//! Parsing the info string of a fenced code block (the `rust,no_run` in
+//! ` ```rust,no_run `).
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use pulldown_cmark::{Event, Tag};
+
+use super::escape_html;
+
+/// The attributes of a fenced code block, taken from its info string.
+///
+/// The info string is a comma separated list of bare flags (`rust`,
+/// `no_run`) and named `key=value` attributes (`title="Example"`). Values can
+/// be quoted with `"` or `'`, in which case they may contain commas.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CodeBlockInfo {
+    /// The code block's language. This is the `lang` attribute if there is
+    /// one, otherwise the first bare flag.
+    pub language: Option<String>,
+    /// Every bare flag apart from the language, in order.
+    pub flags: Vec<String>,
+    /// The named attributes, with the quotes around their values removed.
+    pub attributes: HashMap<String, String>,
+}
+
+impl CodeBlockInfo {
+    /// Parse a code block's info string.
+    ///
+    /// An attribute without a value (`title=`) doesn't mean anything and is
+    /// skipped.
+    pub fn parse(info: &str) -> CodeBlockInfo {
+        let mut parsed = CodeBlockInfo::default();
+        let mut bare = Vec::new();
+
+        for attribute in split_attributes(info) {
+            match attribute.find('=') {
+                Some(ix) => {
+                    let key = attribute[..ix].trim();
+                    let value = unquote(attribute[ix + 1..].trim());
+
+                    if key.is_empty() || value.is_empty() {
+                        debug!("Ignoring the code block attribute \"{}\" in \"{}\"", attribute, info);
+                        continue;
+                    }
+                    parsed.attributes.insert(key.to_string(), value.to_string());
+                }
+                None => bare.push(attribute.to_string()),
+            }
+        }
+
+        parsed.language = match parsed.attributes.get("lang") {
+            Some(lang) => Some(lang.clone()),
+            None if !bare.is_empty() => Some(bare.remove(0)),
+            None => None,
+        };
+        parsed.flags = bare;
+
+        parsed
+    }
+
+    /// Get the value of a named attribute.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.attributes.get(key).map(|value| value.as_str())
+    }
+
+    /// Was this bare flag (e.g. `no_run`) given?
+    pub fn has_flag(&self, flag: &str) -> bool {
+        self.flags.iter().any(|f| f == flag)
+    }
+}
+
+/// Split an info string on the commas which aren't inside a quoted value,
+/// trimming whitespace and skipping empty attributes.
+pub fn split_attributes(info: &str) -> Vec<&str> {
+    let mut attributes = Vec::new();
+    let mut quote = None;
+    let mut start = 0;
+
+    for (ix, c) in info.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == ',' => {
+                attributes.push(&info[start..ix]);
+                start = ix + 1;
+            }
+            None => {}
+        }
+    }
+    attributes.push(&info[start..]);
+
+    attributes
+        .into_iter()
+        .map(|attribute| attribute.trim())
+        .filter(|attribute| !attribute.is_empty())
+        .collect()
+}
+
+/// Put a `<div class="code-caption">` above every code block with a `title`
+/// attribute. The attribute itself is removed from the info string so it
+/// doesn't end up as a class on the code block.
+pub fn add_code_captions<'a>(events: Vec<Event<'a>>) -> Vec<Event<'a>> {
+    let mut captioned = Vec::with_capacity(events.len());
+
+    for event in events {
+        let title = match event {
+            Event::Start(Tag::CodeBlock(ref info)) => CodeBlockInfo::parse(info)
+                .get("title")
+                .map(|title| (title.to_string(), without_title(info))),
+            _ => None,
+        };
+
+        match title {
+            Some((title, info)) => {
+                captioned.push(Event::Html(Cow::from(format!(
+                    "<div class=\"code-caption\">{}</div>\n",
+                    escape_html(&title)
+                ))));
+                captioned.push(Event::Start(Tag::CodeBlock(Cow::from(info))));
+            }
+            None => captioned.push(event),
+        }
+    }
+
+    captioned
+}
+
+fn without_title(info: &str) -> String {
+    split_attributes(info)
+        .into_iter()
+        .filter(|attribute| !attribute.starts_with("title="))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Write the opening tag of every code block with more than a language in
+/// its info string ourselves, because pulldown-cmark would put the whole
+/// thing in one class (`language-rust,no_run`), which syntax highlighters
+/// don't recognise. Only the first attribute becomes a `language-*` class,
+/// and the rest are kept in a space separated `data-attributes` attribute
+/// (`data-attributes="no_run should_panic"`).
+pub fn split_code_block_attributes<'a, I>(events: I) -> Vec<Event<'a>>
+    where I: IntoIterator<Item = Event<'a>>
+{
+    let mut converted = Vec::new();
+    let mut in_split_block = false;
+
+    for event in events {
+        let start = match event {
+            Event::Start(Tag::CodeBlock(ref info)) => code_block_start(info),
+            Event::End(Tag::CodeBlock(_)) if in_split_block => {
+                in_split_block = false;
+                converted.push(Event::Html(Cow::from("</code></pre>\n")));
+                continue;
+            }
+            _ => None,
+        };
+
+        match start {
+            Some(start) => {
+                in_split_block = true;
+                converted.push(Event::Html(Cow::from(start)));
+            }
+            None => converted.push(event),
+        }
+    }
+
+    converted
+}
+
+/// The opening tags for a code block, if its info string has more than just
+/// the language in it.
+fn code_block_start(info: &str) -> Option<String> {
+    let attributes = split_attributes(info);
+    if attributes.len() < 2 {
+        return None;
+    }
+
+    Some(code_block_tag(&attributes, ""))
+}
+
+/// The `<pre><code>` for a code block with these attributes, the first of
+/// which is its language. `extra` is added to the `<code>` tag as it is.
+pub fn code_block_tag(attributes: &[&str], extra: &str) -> String {
+    let mut tag = String::from("<pre><code");
+
+    if let Some(language) = attributes.first() {
+        tag.push_str(&format!(" class=\"language-{}\"", escape_html(language)));
+    }
+    if attributes.len() > 1 {
+        tag.push_str(&format!(" data-attributes=\"{}\"", escape_html(&attributes[1..].join(" "))));
+    }
+    tag.push_str(extra);
+    tag.push('>');
+
+    tag
+}
+
+/// Remove the quotes around a value. An unterminated quote runs to the end
+/// of the info string.
+fn unquote(value: &str) -> &str {
+    for quote in &['"', '\''] {
+        if value.starts_with(*quote) {
+            let value = &value[1..];
+            return if value.ends_with(*quote) {
+                &value[..value.len() - 1]
+            } else {
+                value
+            };
+        }
+    }
+
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quoted_values_can_contain_commas() {
+        let got = CodeBlockInfo::parse(r#"rust,title="Hello, world""#);
+
+        assert_eq!(got.language, Some(String::from("rust")));
+        assert_eq!(got.get("title"), Some("Hello, world"));
+        assert!(got.flags.is_empty());
+    }
+
+    #[test]
+    fn bare_flags_and_named_attributes_can_be_mixed() {
+        let got = CodeBlockInfo::parse("no_run, lang=rust ,title='Example'");
+
+        assert_eq!(got.language, Some(String::from("rust")));
+        assert_eq!(got.flags, vec!["no_run"]);
+        assert!(got.has_flag("no_run"));
+        assert_eq!(got.get("title"), Some("Example"));
+        assert_eq!(got.get("lang"), Some("rust"));
+    }
+
+    #[test]
+    fn the_first_flag_is_the_language() {
+        let got = CodeBlockInfo::parse("python,ignore,tab=example");
+
+        assert_eq!(got.language, Some(String::from("python")));
+        assert_eq!(got.flags, vec!["ignore"]);
+        assert_eq!(got.get("tab"), Some("example"));
+    }
+
+    #[test]
+    fn attributes_without_a_value_are_skipped() {
+        let got = CodeBlockInfo::parse("rust,title=,tab=a");
+
+        assert_eq!(got.get("title"), None);
+        assert_eq!(got.get("tab"), Some("a"));
+        assert!(got.flags.is_empty());
+    }
+
+    #[test]
+    fn an_empty_info_string_has_no_attributes() {
+        assert_eq!(CodeBlockInfo::parse(""), CodeBlockInfo::default());
+    }
+
+    fn split(info: &str) -> Vec<String> {
+        let events = vec![Event::Start(Tag::CodeBlock(Cow::Borrowed(info))),
+                          Event::Text(Cow::Borrowed("code")),
+                          Event::End(Tag::CodeBlock(Cow::Borrowed(info)))];
+
+        split_code_block_attributes(events)
+            .into_iter()
+            .map(|event| match event {
+                Event::Html(html) => html.into_owned(),
+                Event::Text(text) => text.into_owned(),
+                other => format!("{:?}", other),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn only_the_language_is_a_class() {
+        assert_eq!(split("rust,no_run,should_panic"),
+                   vec![r#"<pre><code class="language-rust" data-attributes="no_run should_panic">"#,
+                        "code",
+                        "</code></pre>\n"]);
+    }
+
+    #[test]
+    fn a_block_with_only_a_language_is_left_alone() {
+        let got = split("rust");
+
+        assert!(got[0].starts_with("Start(CodeBlock("), "{:?}", got);
+        assert!(got[2].starts_with("End(CodeBlock("), "{:?}", got);
+    }
+
+    #[test]
+    fn attributes_are_escaped() {
+        let got = split(r#"rust,name="<b>""#);
+
+        assert_eq!(got[0],
+                   r#"<pre><code class="language-rust" data-attributes="name=&quot;&lt;b&gt;&quot;">"#);
+    }
+
+    #[test]
+    fn unterminated_quotes_run_to_the_end() {
+        let got = CodeBlockInfo::parse(r#"rust,title="Oops, no end"#);
+
+        assert_eq!(got.get("title"), Some("Oops, no end"));
+    }
+}
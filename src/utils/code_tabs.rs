@@ -0,0 +1,181 @@
+//! Grouping consecutive fenced code blocks which share a `tab=<group>`
+//! attribute into a single tabbed widget.
+//!
+//! The tabs are plain radio inputs and labels, so they work without any
+//! javascript.
+
+use std::borrow::Cow;
+use std::path::Path;
+
+use pulldown_cmark::{Event, Tag};
+
+use super::escape_html;
+use super::code_block_info::{split_attributes, CodeBlockInfo};
+use super::slug::slugify;
+
+/// A single code block in a tab group.
+struct Tab<'a> {
+    group: String,
+    label: String,
+    events: Vec<Event<'a>>,
+}
+
+/// Wrap runs of consecutive code blocks belonging to the same tab group in a
+/// tab container. A group with only one member is rendered as a normal code
+/// block.
+///
+/// When `remember` is set the container gets the `code-tabs-remember` class
+/// so the theme's javascript can keep the reader's preferred tab selected
+/// across groups and pages.
+///
+/// The ids of the tabs start with the chapter's `path`, so they're still
+/// unique when several chapters end up on the same page (the print page).
+pub fn group_code_tabs<'a>(events: Vec<Event<'a>>,
+                           remember: bool,
+                           path: Option<&Path>)
+                           -> Vec<Event<'a>> {
+    let prefix = match path {
+        Some(path) => format!("code-tabs-{}", slugify(&path.to_string_lossy())),
+        None => String::from("code-tabs"),
+    };
+    let mut grouped = Vec::with_capacity(events.len());
+    let mut pending: Vec<Tab<'a>> = Vec::new();
+    let mut current: Option<Tab<'a>> = None;
+    let mut groups_emitted = 0;
+
+    for event in events {
+        if let Some(mut tab) = current.take() {
+            match event {
+                Event::End(Tag::CodeBlock(_)) => {
+                    let info = code_block_info(&tab.events[0]);
+                    tab.events.push(Event::End(Tag::CodeBlock(Cow::from(info))));
+                    pending.push(tab);
+                }
+                other => {
+                    tab.events.push(other);
+                    current = Some(tab);
+                }
+            }
+            continue;
+        }
+
+        let info = match event {
+            Event::Start(Tag::CodeBlock(ref info)) => Some(info.to_string()),
+            _ => None,
+        };
+
+        match info.as_ref().and_then(|info| parse_tab(info)) {
+            Some(mut tab) => {
+                if pending.last().map_or(false, |t| t.group != tab.group) {
+                    emit_group(&mut grouped, &mut pending, remember, &prefix, &mut groups_emitted);
+                }
+
+                let info = strip_info(info.as_ref().expect("Tabs always come from a code block"));
+                tab.events.push(Event::Start(Tag::CodeBlock(Cow::from(info))));
+                current = Some(tab);
+            }
+            None => {
+                emit_group(&mut grouped, &mut pending, remember, &prefix, &mut groups_emitted);
+                grouped.push(event);
+            }
+        }
+    }
+
+    // an unterminated code block at the end of the document
+    if let Some(tab) = current {
+        pending.push(tab);
+    }
+    emit_group(&mut grouped, &mut pending, remember, &prefix, &mut groups_emitted);
+
+    grouped
+}
+
+fn emit_group<'a>(
+    out: &mut Vec<Event<'a>>,
+    pending: &mut Vec<Tab<'a>>,
+    remember: bool,
+    prefix: &str,
+    groups_emitted: &mut usize,
+) {
+    if pending.len() < 2 {
+        for tab in pending.drain(..) {
+            out.extend(tab.events);
+        }
+        return;
+    }
+
+    let group = escape_html(&pending[0].group);
+    // the group is only part of the name to make the markup easier to read
+    let name = format!("{}-{}-{}", prefix, slugify(&pending[0].group), groups_emitted);
+    *groups_emitted += 1;
+
+    let class = if remember {
+        "code-tabs code-tabs-remember"
+    } else {
+        "code-tabs"
+    };
+    out.push(Event::Html(Cow::from(format!(
+        "<div class=\"{}\" data-tab-group=\"{}\">\n",
+        class, group
+    ))));
+
+    for (i, tab) in pending.drain(..).enumerate() {
+        let id = format!("{}-{}", name, i);
+        let label = escape_html(&tab.label);
+
+        out.push(Event::Html(Cow::from(format!(
+            "<input type=\"radio\" class=\"code-tab-input\" name=\"{name}\" id=\"{id}\" \
+             data-tab-name=\"{label}\"{checked}>\n\
+             <label class=\"code-tab-label\" for=\"{id}\">{label}</label>\n\
+             <div class=\"code-tab-panel\">\n",
+            name = name,
+            id = id,
+            label = label,
+            checked = if i == 0 { " checked" } else { "" }
+        ))));
+        out.extend(tab.events);
+        out.push(Event::Html(Cow::from("</div>\n")));
+    }
+
+    out.push(Event::Html(Cow::from("</div>\n")));
+}
+
+/// Get the tab group (and label) from a code block's info string, if it is
+/// part of one.
+fn parse_tab<'a>(info: &str) -> Option<Tab<'a>> {
+    let info = CodeBlockInfo::parse(info);
+
+    let group = match info.get("tab") {
+        Some(group) => group.to_string(),
+        None => return None,
+    };
+
+    let label = match (info.get("tabname"), info.language.as_ref()) {
+        (Some(label), _) => label.to_string(),
+        (None, Some(language)) => language.clone(),
+        (None, None) => group.clone(),
+    };
+
+    Some(Tab {
+        group: group,
+        label: label,
+        events: Vec::new(),
+    })
+}
+
+/// Remove the `tab=` and `tabname=` attributes so they don't end up as
+/// classes on the rendered code block.
+fn strip_info(info: &str) -> String {
+    split_attributes(info)
+        .into_iter()
+        .filter(|a| !a.starts_with("tab=") && !a.starts_with("tabname="))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn code_block_info(start: &Event) -> String {
+    match *start {
+        Event::Start(Tag::CodeBlock(ref info)) => info.to_string(),
+        _ => String::new(),
+    }
+}
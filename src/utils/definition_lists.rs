@@ -0,0 +1,125 @@
+//! Definition lists, which pulldown-cmark doesn't know about.
+//!
+//! A paragraph whose second line starts with `: ` is turned into a
+//! definition list, with the first line as the term:
+//!
+//! ```markdown
+//! Term
+//! : The first definition
+//!   which carries on over a second line.
+//! : Another definition
+//! ```
+
+use std::borrow::Cow;
+
+use pulldown_cmark::{Event, Tag};
+
+const MARKER: &str = ": ";
+
+/// Rewrite every paragraph which looks like a definition list into
+/// `<dl>`, `<dt>` and `<dd>` elements. Everything else is left alone.
+pub fn convert_definition_lists<'a>(events: Vec<Event<'a>>) -> Vec<Event<'a>> {
+    let mut converted = Vec::with_capacity(events.len());
+    let mut paragraph: Option<Vec<Event<'a>>> = None;
+
+    for event in events {
+        if let Some(mut buffered) = paragraph.take() {
+            match event {
+                Event::End(Tag::Paragraph) => {
+                    buffered.push(event);
+                    converted.extend(convert_paragraph(buffered));
+                }
+                other => {
+                    buffered.push(other);
+                    paragraph = Some(buffered);
+                }
+            }
+            continue;
+        }
+
+        match event {
+            Event::Start(Tag::Paragraph) => paragraph = Some(vec![event]),
+            other => converted.push(other),
+        }
+    }
+
+    // an unterminated paragraph at the end of the document
+    if let Some(buffered) = paragraph {
+        converted.extend(buffered);
+    }
+
+    converted
+}
+
+/// Convert a single paragraph (including its start and end events), if it is
+/// a definition list.
+fn convert_paragraph(paragraph: Vec<Event>) -> Vec<Event> {
+    let lines = split_lines(&paragraph[1..paragraph.len() - 1]);
+
+    if lines.len() < 2 || !starts_with_marker(&lines[1]) {
+        return paragraph;
+    }
+
+    let mut lines = lines.into_iter();
+    let mut events = vec![Event::Html(Cow::from("<dl>\n<dt>"))];
+    events.extend(lines.next().expect("There are at least two lines"));
+    events.push(Event::Html(Cow::from("</dt>\n")));
+
+    let mut first = true;
+    for line in lines {
+        if starts_with_marker(&line) {
+            if !first {
+                events.push(Event::Html(Cow::from("</dd>\n")));
+            }
+            events.push(Event::Html(Cow::from("<dd>")));
+            events.extend(strip_marker(line));
+            first = false;
+        } else {
+            // the definition carries on over another line
+            events.push(Event::SoftBreak);
+            events.extend(line);
+        }
+    }
+
+    events.push(Event::Html(Cow::from("</dd>\n</dl>\n")));
+    events
+}
+
+/// Split the contents of a paragraph into lines, dropping the line breaks.
+fn split_lines<'a>(events: &[Event<'a>]) -> Vec<Vec<Event<'a>>> {
+    let mut lines = vec![Vec::new()];
+
+    for event in events {
+        match *event {
+            Event::SoftBreak | Event::HardBreak => lines.push(Vec::new()),
+            _ => lines
+                .last_mut()
+                .expect("There is always a line")
+                .push(event.clone()),
+        }
+    }
+
+    lines
+}
+
+fn starts_with_marker(line: &[Event]) -> bool {
+    match line.first() {
+        Some(&Event::Text(ref text)) => text.starts_with(MARKER),
+        _ => false,
+    }
+}
+
+fn strip_marker(mut line: Vec<Event>) -> Vec<Event> {
+    let text = match line[0] {
+        Event::Text(ref text) => text[MARKER.len()..].to_string(),
+        _ => unreachable!("Only called for lines starting with the marker"),
+    };
+
+    if text.is_empty() {
+        line.remove(0);
+    } else {
+        line[0] = Event::Text(Cow::from(text));
+    }
+
+    line
+}
@@ -0,0 +1,150 @@
+//! The `:shortcode:` names of the emoji we know about, as used on GitHub.
+
+/// Sorted by shortcode so we can binary search it.
+const EMOJI: &[(&str, &str)] = &[
+    ("+1", "👍"),
+    ("-1", "👎"),
+    ("100", "💯"),
+    ("bangbang", "‼️"),
+    ("beetle", "🐞"),
+    ("bell", "🔔"),
+    ("book", "📖"),
+    ("books", "📚"),
+    ("boom", "💥"),
+    ("bug", "🐛"),
+    ("bulb", "💡"),
+    ("calendar", "📆"),
+    ("clap", "👏"),
+    ("construction", "🚧"),
+    ("crab", "🦀"),
+    ("cry", "😢"),
+    ("exclamation", "❗"),
+    ("eyes", "👀"),
+    ("fire", "🔥"),
+    ("gear", "⚙️"),
+    ("grin", "😁"),
+    ("hammer", "🔨"),
+    ("heart", "❤️"),
+    ("heavy_check_mark", "✔️"),
+    ("hourglass", "⌛"),
+    ("information_source", "ℹ️"),
+    ("joy", "😂"),
+    ("key", "🔑"),
+    ("laughing", "😆"),
+    ("link", "🔗"),
+    ("lock", "🔒"),
+    ("mag", "🔍"),
+    ("memo", "📝"),
+    ("no_entry", "⛔"),
+    ("ok_hand", "👌"),
+    ("package", "📦"),
+    ("pencil", "📝"),
+    ("point_right", "👉"),
+    ("pushpin", "📌"),
+    ("question", "❓"),
+    ("rocket", "🚀"),
+    ("sad", "😞"),
+    ("scroll", "📜"),
+    ("smile", "😄"),
+    ("smiley", "😃"),
+    ("sparkles", "✨"),
+    ("star", "⭐"),
+    ("tada", "🎉"),
+    ("thinking", "🤔"),
+    ("thumbsdown", "👎"),
+    ("thumbsup", "👍"),
+    ("warning", "⚠️"),
+    ("white_check_mark", "✅"),
+    ("wink", "😉"),
+    ("wrench", "🔧"),
+    ("x", "❌"),
+    ("zap", "⚡"),
+];
+
+/// Look up the emoji for a shortcode (without the surrounding colons).
+pub fn lookup(shortcode: &str) -> Option<&'static str> {
+    EMOJI
+        .binary_search_by(|&(code, _)| code.cmp(shortcode))
+        .ok()
+        .map(|ix| EMOJI[ix].1)
+}
+
+/// Replace every known `:shortcode:` in some text with its emoji, leaving
+/// unknown shortcodes untouched.
+pub fn replace_shortcodes(text: &str) -> String {
+    let mut replaced = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find(':') {
+        replaced.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+
+        let len = after
+            .find(|c: char| !is_shortcode_char(c))
+            .unwrap_or_else(|| after.len());
+
+        if len > 0 && after[len..].starts_with(':') {
+            if let Some(emoji) = lookup(&after[..len]) {
+                replaced.push_str(emoji);
+                rest = &after[len + 1..];
+                continue;
+            }
+        }
+
+        // not a shortcode, but the next colon might start one
+        replaced.push(':');
+        rest = after;
+    }
+
+    replaced.push_str(rest);
+    replaced
+}
+
+fn is_shortcode_char(c: char) -> bool {
+    match c {
+        'a'...'z' | '0'...'9' | '_' | '+' | '-' => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_table_is_sorted() {
+        for pair in EMOJI.windows(2) {
+            assert!(pair[0].0 < pair[1].0, "{} should come after {}", pair[0].0, pair[1].0);
+        }
+    }
+
+    #[test]
+    fn known_shortcodes_are_replaced() {
+        let inputs = vec![
+            (":rocket:", "🚀"),
+            ("Careful :warning: here", "Careful ⚠️ here"),
+            (":+1::tada:", "👍🎉"),
+            ("a:b:rocket:", "a:b🚀"),
+        ];
+
+        for (src, should_be) in inputs {
+            assert_eq!(replace_shortcodes(src), should_be, "{}", src);
+        }
+    }
+
+    #[test]
+    fn everything_else_is_left_alone() {
+        let inputs = vec![
+            ":not_an_emoji:",
+            "It's 10:30:45 already",
+            "std::fmt::Display",
+            ":Rocket:",
+            ": rocket :",
+            "trailing:",
+        ];
+
+        for src in inputs {
+            assert_eq!(replace_shortcodes(src), src);
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use std::path::{Component, Path, PathBuf};
 use errors::*;
-use std::io::Read;
+use std::io::{Read, Write};
 use std::fs::{self, File};
 
 /// Takes a path to a file and try to read the file into a String
@@ -75,6 +75,38 @@ pub fn create_file(path: &Path) -> Result<File> {
     File::create(path).map_err(|e| e.into())
 }
 
+/// Something the renderers write their output through, so the writes can be
+/// intercepted (e.g. to make one of them fail in a test).
+pub trait FileWriter {
+    /// Write `content` to the file at `path`, creating any directories it
+    /// needs.
+    fn write_file(&self, path: &Path, content: &[u8]) -> Result<()>;
+}
+
+/// A `FileWriter` which writes straight to disk.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct DiskWriter;
+
+impl FileWriter for DiskWriter {
+    fn write_file(&self, path: &Path, content: &[u8]) -> Result<()> {
+        create_file(path)?.write_all(content).map_err(|e| e.into())
+    }
+}
+
+/// Copy the file at `relative` (a path relative to `from`) to the same place
+/// under `to`, creating any directories it needs.
+pub fn copy_file_to(from: &Path, to: &Path, relative: &Path) -> Result<()> {
+    let destination = to.join(relative);
+    debug!("Copying {} to {}", from.join(relative).display(), destination.display());
+
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::copy(from.join(relative), &destination)?;
+
+    Ok(())
+}
+
 /// Removes all the content of a directory but not the directory itself
 
 pub fn remove_dir_content(dir: &Path) -> Result<()> {
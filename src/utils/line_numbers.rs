@@ -0,0 +1,156 @@
+//! Numbering the lines of code blocks, so the theme can show them in a
+//! gutter next to the code.
+
+use std::borrow::Cow;
+
+use pulldown_cmark::{Event, Tag};
+
+use super::code_block_info::{code_block_tag, split_attributes, CodeBlockInfo};
+use super::escape_html;
+
+/// Wrap every line of the code blocks with a `linenos` attribute (or of
+/// every code block, with `all_blocks`) in a `<span class="linenos-line">`,
+/// which the theme numbers with a CSS counter. `linenos=<n>` starts counting
+/// from `n` instead of 1, and the first number is also put in the block's
+/// `data-linenos-start` attribute.
+pub fn number_code_lines<'a>(events: Vec<Event<'a>>, all_blocks: bool) -> Vec<Event<'a>> {
+    let mut converted = Vec::with_capacity(events.len());
+    let mut block: Option<NumberedBlock> = None;
+
+    for event in events {
+        let start = match event {
+            Event::Start(Tag::CodeBlock(ref info)) => NumberedBlock::start(info, all_blocks),
+            _ => None,
+        };
+        if start.is_some() {
+            block = start;
+            continue;
+        }
+
+        match (block.take(), event) {
+            (Some(mut current), Event::Text(text)) => {
+                current.code.push_str(&text);
+                block = Some(current);
+            }
+            (Some(current), Event::End(Tag::CodeBlock(_))) => {
+                converted.push(Event::Html(Cow::from(current.finish())));
+            }
+            (current, event) => {
+                block = current;
+                converted.push(event);
+            }
+        }
+    }
+
+    converted
+}
+
+/// A code block whose lines are being numbered.
+struct NumberedBlock {
+    first_line: usize,
+    /// The info string's attributes, without `linenos`.
+    attributes: Vec<String>,
+    code: String,
+}
+
+impl NumberedBlock {
+    fn start(info: &str, all_blocks: bool) -> Option<NumberedBlock> {
+        let parsed = CodeBlockInfo::parse(info);
+        let first_line = match parsed.get("linenos") {
+            Some(start) => {
+                start.parse::<usize>().unwrap_or_else(|_| {
+                    warn!("\"{}\" isn't a line number, the code block's lines will be \
+                           numbered from 1",
+                          start);
+                    1
+                })
+            }
+            None if all_blocks || parsed.has_flag("linenos") => 1,
+            None => return None,
+        };
+
+        let attributes = split_attributes(info)
+            .into_iter()
+            .filter(|a| *a != "linenos" && !a.starts_with("linenos="))
+            .map(|a| a.to_string())
+            .collect();
+
+        Some(NumberedBlock {
+            first_line: first_line,
+            attributes: attributes,
+            code: String::new(),
+        })
+    }
+
+    fn finish(self) -> String {
+        let attributes: Vec<&str> = self.attributes.iter().map(|a| a.as_str()).collect();
+        // the counter is incremented before each line's number is shown
+        let extra = format!(" data-linenos-start=\"{}\" style=\"counter-reset: linenos {}\"",
+                            self.first_line,
+                            self.first_line.saturating_sub(1));
+        let mut html = code_block_tag(&attributes, &extra);
+
+        // `split_terminator()` so a final newline doesn't count as another
+        // (empty) line, but a last line without one still counts
+        for line in self.code.split_terminator('\n') {
+            html.push_str("<span class=\"linenos-line\">");
+            html.push_str(&escape_html(line));
+            html.push_str("</span>\n");
+        }
+        html.push_str("</code></pre>\n");
+
+        html
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn number(info: &str, code: &str, all_blocks: bool) -> String {
+        let events = vec![Event::Start(Tag::CodeBlock(Cow::Borrowed(info))),
+                          Event::Text(Cow::Borrowed(code)),
+                          Event::End(Tag::CodeBlock(Cow::Borrowed(info)))];
+
+        let got = number_code_lines(events, all_blocks);
+
+        assert_eq!(got.len(), 1, "{:?}", got);
+        match got[0] {
+            Event::Html(ref html) => html.to_string(),
+            ref other => panic!("The block wasn't numbered: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn a_last_line_without_a_newline_still_counts() {
+        let with_newline = number("rust,linenos", "a\nb\n", false);
+        let without_newline = number("rust,linenos", "a\nb", false);
+
+        assert_eq!(with_newline, without_newline);
+        assert_eq!(with_newline.matches("linenos-line").count(), 2);
+    }
+
+    #[test]
+    fn blank_lines_are_numbered_too() {
+        let got = number("linenos=1", "a\n\nb\n", true);
+
+        assert_eq!(got.matches("<span class=\"linenos-line\"></span>").count(), 1);
+        assert_eq!(got.matches("linenos-line").count(), 3);
+    }
+
+    #[test]
+    fn the_code_is_escaped() {
+        let got = number("html", "<b>&</b>\n", true);
+
+        assert!(got.contains("<span class=\"linenos-line\">&lt;b&gt;&amp;&lt;/b&gt;</span>"),
+                "{}",
+                got);
+    }
+
+    #[test]
+    fn an_invalid_start_counts_from_one() {
+        let got = number("rust,linenos=x", "a\n", false);
+
+        assert!(got.contains("data-linenos-start=\"1\""), "{}", got);
+    }
+}
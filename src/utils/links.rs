@@ -0,0 +1,1077 @@
+//! Rewriting links while rendering markdown, so things like
+//! `[next](./next.md)` point at the rendered page instead of its source.
+
+use std::borrow::Cow;
+use std::path::{Component, Path, PathBuf};
+
+use pulldown_cmark::{Event, Tag};
+use regex::Regex;
+
+/// Something which may want to change where a link points to.
+pub trait LinkFilter {
+    /// Get the new destination for a link, or `None` if it should be left
+    /// alone.
+    fn apply(&self, dest: &str) -> Option<String>;
+}
+
+/// Which source files are chapters, and the extension of their rendered
+/// pages.
+const DEFAULT_MAPPINGS: &[(&str, &str)] = &[("md", "html")];
+
+/// A `LinkFilter` which rewrites relative links to one of the book's markdown
+/// files (e.g. `nested.md#section`) so they point at the corresponding
+/// rendered page (`first/nested.html#section`).
+///
+/// Translated links are relative to the book's root because every rendered
+/// page sets a `<base>` pointing there.
+pub struct ChangeExtLinkFilter<'a, F> {
+    base: &'a Path,
+    /// Source extensions and the extensions they're rendered to, longest
+    /// source extension first.
+    mappings: Vec<(&'a str, &'a str)>,
+    is_file: F,
+    extensionless: bool,
+    on_missing: Option<Box<Fn(&str) + 'a>>,
+}
+
+impl<'a, F> ChangeExtLinkFilter<'a, F>
+where
+    F: Fn(&Path) -> bool,
+{
+    /// Create a filter for links in a chapter living in the `base` directory
+    /// (relative to `src`). `is_file` is used to check whether a link's
+    /// target (also relative to `src`) actually exists.
+    pub fn new(base: &'a Path, is_file: F) -> ChangeExtLinkFilter<'a, F> {
+        ChangeExtLinkFilter::with_mappings(base, DEFAULT_MAPPINGS, is_file)
+    }
+
+    /// Like `new()`, but for links to files with any of the `expected_exts`
+    /// extensions (e.g. `md` and `markdown`), which are rewritten to point at
+    /// the page with the `new_ext` extension (e.g. `xhtml`) instead.
+    pub fn with_extensions(
+        base: &'a Path,
+        expected_exts: &[&'a str],
+        new_ext: &'a str,
+        is_file: F,
+    ) -> ChangeExtLinkFilter<'a, F> {
+        let mappings: Vec<_> = expected_exts.iter().map(|&ext| (ext, new_ext)).collect();
+        ChangeExtLinkFilter::with_mappings(base, &mappings, is_file)
+    }
+
+    /// Like `new()`, but with a mapping from source extensions to the
+    /// extensions of the pages they're rendered to (e.g. `("md", "html")` and
+    /// `("rs.md", "rs.html")`). An extension can have more than one part, and
+    /// the longest one which matches a link is used.
+    pub fn with_mappings(
+        base: &'a Path,
+        mappings: &[(&'a str, &'a str)],
+        is_file: F,
+    ) -> ChangeExtLinkFilter<'a, F> {
+        let mut mappings = mappings.to_vec();
+        mappings.sort_by(|a, b| b.0.len().cmp(&a.0.len()));
+
+        ChangeExtLinkFilter {
+            base: base,
+            mappings: mappings,
+            is_file: is_file,
+            extensionless: false,
+            on_missing: None,
+        }
+    }
+
+    /// Also translate relative links without an extension: `./chapter`
+    /// points at `chapter.md` (or `chapter/index.md`), and `./chapter/` at
+    /// `chapter/index.md`. Those which don't point at a chapter are left
+    /// unchanged without a warning, because they may well be something else.
+    pub fn with_extensionless_links(mut self) -> ChangeExtLinkFilter<'a, F> {
+        self.extensionless = true;
+        self
+    }
+
+    /// Call `on_missing` with the destination of every link which looks like
+    /// it points at one of the book's markdown files, but doesn't. Those
+    /// links are still left unchanged.
+    pub fn on_missing_link<M>(mut self, on_missing: M) -> ChangeExtLinkFilter<'a, F>
+    where
+        M: Fn(&str) + 'a,
+    {
+        self.on_missing = Some(Box::new(on_missing));
+        self
+    }
+}
+
+impl<'a, F> LinkFilter for ChangeExtLinkFilter<'a, F>
+where
+    F: Fn(&Path) -> bool,
+{
+    fn apply(&self, dest: &str) -> Option<String> {
+        let translation = translate(self.base,
+                                    dest,
+                                    &self.mappings,
+                                    self.extensionless,
+                                    &self.is_file);
+
+        match translation {
+            Translation::Translated(new_dest) => Some(new_dest),
+            Translation::Missing => {
+                if let Some(ref on_missing) = self.on_missing {
+                    on_missing(dest);
+                }
+                None
+            }
+            Translation::Ignored => None,
+        }
+    }
+}
+
+/// A `LinkFilter` which turns the fragment of a link within the book
+/// (`#My-Section` or `other.md#My-Section`) into the id the heading it refers
+/// to gets, using the same rules as when heading ids are generated. Only the
+/// fragment is changed, and links to other websites are left alone.
+///
+/// The fragment is percent-decoded first, so `#caf%C3%A9` is seen as `#café`.
+/// Anchors added by hand (`<a id="Foo_Bar">`) don't follow the rules, which
+/// is why this filter is only used when `normalize-link-fragments` is set.
+pub struct NormalizeFragmentLinkFilter {
+    transliterate: bool,
+}
+
+impl NormalizeFragmentLinkFilter {
+    /// Create a filter, which makes ASCII-only ids (see
+    /// `normalize_ascii_id()`) when `transliterate` is set.
+    pub fn new(transliterate: bool) -> NormalizeFragmentLinkFilter {
+        NormalizeFragmentLinkFilter {
+            transliterate: transliterate,
+        }
+    }
+}
+
+impl LinkFilter for NormalizeFragmentLinkFilter {
+    fn apply(&self, dest: &str) -> Option<String> {
+        if has_non_path_scheme(dest) || is_absolute_url(dest) {
+            return None;
+        }
+
+        let ix = match dest.find('#') {
+            Some(ix) => ix,
+            None => return None,
+        };
+        let path = &dest[..ix];
+        let fragment = match percent_decode(&dest[ix + 1..]) {
+            Some(fragment) => fragment,
+            None => return None,
+        };
+        let normalized = if self.transliterate {
+            super::normalize_ascii_id(&fragment)
+        } else {
+            super::normalize_id(&fragment)
+        };
+
+        if normalized.is_empty() || normalized == fragment {
+            None
+        } else {
+            Some(format!("{}#{}", path, normalized))
+        }
+    }
+}
+
+/// Translate a relative link to a file with the `expected_ext` extension into
+/// a link (relative to the book's root) to the same file with `new_ext`.
+///
+/// The link is first resolved against the `base` directory and then, for
+/// backwards compatibility, against the root. If neither of those is a file
+/// or the link isn't a relative link to the right kind of file, `None` is
+/// returned.
+///
+/// The link may have `%XX` escapes in it (`My%20Notes.md`), and anything in
+/// the translated path which can't be in a URL as it is (like a space) gets
+/// escaped, without touching the `?query` or `#fragment`.
+///
+/// Links which climb above the book's root (e.g. `../../foo.md` from a
+/// chapter one level deep) can't be turned into a working URL, so they are
+/// left unchanged and a warning is emitted.
+pub fn translate_relative_link<F>(
+    base: &Path,
+    dest: &str,
+    expected_ext: &str,
+    new_ext: &str,
+    is_file: F,
+) -> Option<String>
+where
+    F: Fn(&Path) -> bool,
+{
+    match translate(base, dest, &[(expected_ext, new_ext)], false, is_file) {
+        Translation::Translated(new_dest) => Some(new_dest),
+        Translation::Missing | Translation::Ignored => None,
+    }
+}
+
+/// The outcome of trying to translate a single link.
+enum Translation {
+    Translated(String),
+    /// A relative link to a file with the expected extension which doesn't
+    /// exist.
+    Missing,
+    /// Something which isn't a relative link to the right kind of file.
+    Ignored,
+}
+
+/// `mappings` has to be sorted by the length of the source extension, longest
+/// first.
+fn translate<F>(
+    base: &Path,
+    dest: &str,
+    mappings: &[(&str, &str)],
+    extensionless: bool,
+    is_file: F,
+) -> Translation
+where
+    F: Fn(&Path) -> bool,
+{
+    if has_non_path_scheme(dest) {
+        return Translation::Ignored;
+    }
+
+    if dest.is_empty() || dest.starts_with('#') || dest.starts_with('/') || is_absolute_url(dest) {
+        return Translation::Ignored;
+    }
+
+    let (path, rest) = split_path(dest);
+    let decoded = match percent_decode(path) {
+        Some(decoded) => decoded,
+        None => return Translation::Ignored,
+    };
+    let is_directory = decoded.ends_with('/');
+    let path = Path::new(&*decoded);
+    let file_name = path.file_name().and_then(|name| name.to_str()).unwrap_or("");
+
+    // dotfiles (`.gitignore`) are never chapters
+    if file_name.starts_with('.') {
+        return Translation::Ignored;
+    }
+
+    // the files the link could be pointing at, and their mappings
+    let explicit = find_mapping(file_name, mappings);
+    let files = match explicit {
+        Some(mapping) => vec![(path.to_path_buf(), mapping)],
+        None if extensionless && path.extension().is_none() && !file_name.is_empty() => {
+            let index = path.join("index");
+            let mut files = Vec::new();
+            if !is_directory {
+                files.extend(mappings.iter().map(|&m| (with_suffix(path, m.0), m)));
+            }
+            files.extend(mappings.iter().map(|&m| (with_suffix(&index, m.0), m)));
+            files
+        }
+        None => return Translation::Ignored,
+    };
+
+    if normalize(&base.join(path)).is_none() {
+        warn!("Not translating the link \"{}\" because it points outside of the book", dest);
+        return Translation::Ignored;
+    }
+
+    let candidates = files.iter()
+                          .map(|&(ref file, m)| (normalize(&base.join(file)), m))
+                          .chain(files.iter().map(|&(ref file, m)| (normalize(file), m)))
+                          .filter_map(|(file, m)| file.map(|file| (file, m)))
+                          .collect::<Vec<_>>();
+    let (target, (source_ext, page_ext)) = match candidates.into_iter().find(|c| is_file(&c.0)) {
+        Some(found) => found,
+        None if explicit.is_some() => return Translation::Missing,
+        // an extensionless link could be pointing at anything
+        None => return Translation::Ignored,
+    };
+
+    let page = replace_suffix(&target, source_ext, page_ext);
+    Translation::Translated(to_link(&page) + rest)
+}
+
+/// The mapping for the longest source extension a file name has. There has to
+/// be something in front of the extension, so `md` on its own isn't a
+/// markdown file.
+fn find_mapping<'a>(
+    file_name: &str,
+    mappings: &[(&'a str, &'a str)],
+) -> Option<(&'a str, &'a str)> {
+    mappings.iter().cloned().find(|&(source_ext, _)| {
+        let stem_len = file_name.len().saturating_sub(source_ext.len() + 1);
+        stem_len > 0 && file_name.ends_with(source_ext)
+            && file_name[..file_name.len() - source_ext.len()].ends_with('.')
+    })
+}
+
+/// `path` with `.suffix` added to the end.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_os_string();
+    path.push(".");
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+/// Swap the `.old` suffix at the end of `path` for `.new`.
+fn replace_suffix(path: &Path, old: &str, new: &str) -> PathBuf {
+    let path = path.to_string_lossy();
+    let stem = &path[..path.len() - old.len() - 1];
+    PathBuf::from(format!("{}.{}", stem, new))
+}
+
+/// A `LinkFilter` which rewrites relative links (and images) pointing at one
+/// of the book's other files, like `../images/logo.svg`, so they are
+/// relative to the book's root instead of the chapter. The file itself is
+/// copied across by the renderer (see `linked_assets()`).
+pub struct AssetLinkFilter<'a, F> {
+    base: &'a Path,
+    is_file: F,
+}
+
+impl<'a, F> AssetLinkFilter<'a, F>
+where
+    F: Fn(&Path) -> bool,
+{
+    /// Create a filter for links in a chapter living in the `base` directory
+    /// (relative to `src`). `is_file` is used to check whether a link's
+    /// target (also relative to `src`) actually exists.
+    pub fn new(base: &'a Path, is_file: F) -> AssetLinkFilter<'a, F> {
+        AssetLinkFilter {
+            base: base,
+            is_file: is_file,
+        }
+    }
+}
+
+impl<'a, F> LinkFilter for AssetLinkFilter<'a, F>
+where
+    F: Fn(&Path) -> bool,
+{
+    fn apply(&self, dest: &str) -> Option<String> {
+        match resolve_asset_link(self.base, dest, &self.is_file) {
+            AssetLink::Found(target) => Some(to_link(&target) + split_path(dest).1),
+            _ => None,
+        }
+    }
+}
+
+/// What a relative link to a file which isn't a page (e.g. an image or a
+/// PDF) points at.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AssetLink {
+    /// The file, relative to `src`.
+    Found(PathBuf),
+    /// There's no such file.
+    Missing,
+    /// The link climbs above `src`.
+    OutsideTheBook,
+    /// Not a relative link to a file, or a link to a page (`.md` or
+    /// `.html`).
+    Ignored,
+}
+
+/// Work out which of the book's files a link (or image) in a chapter living
+/// in the `base` directory points at. Like links to chapters, the link is
+/// resolved against `base` first and then against the root.
+pub fn resolve_asset_link<F>(base: &Path, dest: &str, is_file: F) -> AssetLink
+where
+    F: Fn(&Path) -> bool,
+{
+    if has_non_path_scheme(dest) || is_absolute_url(dest) {
+        return AssetLink::Ignored;
+    }
+    if dest.is_empty() || dest.starts_with('#') || dest.starts_with('/') {
+        return AssetLink::Ignored;
+    }
+
+    let decoded = match percent_decode(split_path(dest).0) {
+        Some(decoded) => decoded,
+        None => return AssetLink::Ignored,
+    };
+    let path = Path::new(&*decoded);
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("md") | Some("html") | Some("htm") | None => return AssetLink::Ignored,
+        Some(_) => {}
+    }
+
+    let relative = match normalize(&base.join(path)) {
+        Some(relative) => relative,
+        None => return AssetLink::OutsideTheBook,
+    };
+
+    let candidates = vec![Some(relative), normalize(path)];
+    match candidates.into_iter().filter_map(|c| c).find(|c| is_file(c)) {
+        Some(target) => AssetLink::Found(target),
+        None => AssetLink::Missing,
+    }
+}
+
+/// Split a link into its path and the `?query` or `#fragment` after it.
+fn split_path(dest: &str) -> (&str, &str) {
+    match dest.find(|c| c == '#' || c == '?') {
+        Some(ix) => (&dest[..ix], &dest[ix..]),
+        None => (dest, ""),
+    }
+}
+
+/// Turn a path into a link, always using `/` as the separator and escaping
+/// anything (like spaces) which can't be in a URL as it is.
+fn to_link(path: &Path) -> String {
+    path.components()
+        .map(|c| percent_encode(&c.as_os_str().to_string_lossy()))
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Escape everything in a path segment which isn't allowed to appear
+/// verbatim in a URL.
+pub(crate) fn percent_encode(segment: &str) -> String {
+    let mut encoded = String::with_capacity(segment.len());
+
+    for byte in segment.bytes() {
+        match byte {
+            b'A'...b'Z' | b'a'...b'z' | b'0'...b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+
+    encoded
+}
+
+/// Undo any `%XX` escapes in the path of a link, so the file it points at
+/// can be found (a link to `My%20Notes.md` is a link to `My Notes.md`).
+/// Returns `None` if the escaped bytes aren't valid UTF-8.
+pub(crate) fn percent_decode(path: &str) -> Option<Cow<str>> {
+    if !path.contains('%') {
+        return Some(Cow::Borrowed(path));
+    }
+
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let escaped = if bytes[i] == b'%' && i + 2 < bytes.len() {
+            hex_value(bytes[i + 1]).and_then(|high| {
+                hex_value(bytes[i + 2]).map(|low| high * 16 + low)
+            })
+        } else {
+            None
+        };
+
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            // a `%` which isn't followed by two hex digits is just a `%`
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8(decoded).ok().map(Cow::Owned)
+}
+
+fn hex_value(digit: u8) -> Option<u8> {
+    (digit as char).to_digit(16).map(|value| value as u8)
+}
+
+/// Schemes which never refer to a file in the book, no matter what the rest
+/// of the link looks like.
+const NON_PATH_SCHEMES: &[&str] = &["mailto:", "tel:", "ftp:", "javascript:", "data:"];
+
+/// Does the link use one of the `NON_PATH_SCHEMES`? Browsers ignore leading
+/// whitespace and the case of the scheme, so we do too.
+fn has_non_path_scheme(dest: &str) -> bool {
+    let dest = dest.trim_left().to_lowercase();
+    NON_PATH_SCHEMES.iter().any(|scheme| dest.starts_with(scheme))
+}
+
+/// Does this look like a URL with a scheme (`https://...`, `mailto:...`) or a
+/// protocol-relative URL (`//example.com/...`)?
+pub(crate) fn is_absolute_url(url: &str) -> bool {
+    lazy_static! {
+        static ref SCHEME: Regex = Regex::new(r"^[a-zA-Z][a-zA-Z0-9+.-]*:").unwrap();
+    }
+
+    url.starts_with("//") || SCHEME.is_match(url)
+}
+
+/// Remove any `.` components and resolve `..` against the preceding
+/// component, returning `None` if the path would climb above the root.
+fn normalize(path: &Path) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    return None;
+                }
+            }
+            _ => normalized.push(component.as_os_str()),
+        }
+    }
+
+    Some(normalized)
+}
+
+/// Run the destination of every link through a `LinkFilter`.
+///
+/// pulldown-cmark has already resolved reference-style links (`[text][ref]`)
+/// by the time we see them, so those are handled here too. References
+/// without a definition are emitted as plain text and never reach us.
+pub struct RelativeLinkConverter<'a> {
+    filter: Option<&'a LinkFilter>,
+    images: bool,
+}
+
+impl<'a> RelativeLinkConverter<'a> {
+    pub fn new(filter: Option<&'a LinkFilter>) -> RelativeLinkConverter<'a> {
+        RelativeLinkConverter {
+            filter: filter,
+            images: false,
+        }
+    }
+
+    /// Run the source of every image through the filter too.
+    pub fn and_images(mut self) -> RelativeLinkConverter<'a> {
+        self.images = true;
+        self
+    }
+
+    pub fn convert<'e>(&self, event: Event<'e>) -> Event<'e> {
+        let filter = match self.filter {
+            Some(filter) => filter,
+            None => return event,
+        };
+
+        match event {
+            Event::Start(Tag::Link(dest, title)) => {
+                let dest = match filter.apply(&dest) {
+                    Some(new_dest) => {
+                        trace!("Translating link \"{}\" to \"{}\"", dest, new_dest);
+                        Cow::from(new_dest)
+                    }
+                    None => dest,
+                };
+
+                Event::Start(Tag::Link(dest, title))
+            }
+            Event::Start(Tag::Image(dest, title)) => {
+                if !self.images {
+                    return Event::Start(Tag::Image(dest, title));
+                }
+
+                let dest = match filter.apply(&dest) {
+                    Some(new_dest) => {
+                        trace!("Translating image \"{}\" to \"{}\"", dest, new_dest);
+                        Cow::from(new_dest)
+                    }
+                    None => dest,
+                };
+
+                Event::Start(Tag::Image(dest, title))
+            }
+            _ => event,
+        }
+    }
+}
+
+/// Make links to other websites (`http://` and `https://` URLs) open in a new
+/// tab, by emitting the opening `<a>` tag ourselves with `target="_blank"` and
+/// `rel="noopener noreferrer"` attributes.
+pub struct ExternalLinkConverter {
+    enabled: bool,
+    /// Whether each of the links we're currently inside was rewritten, so the
+    /// matching `End` events can be rewritten too.
+    open_links: Vec<bool>,
+}
+
+impl ExternalLinkConverter {
+    pub fn new(enabled: bool) -> ExternalLinkConverter {
+        ExternalLinkConverter {
+            enabled: enabled,
+            open_links: Vec::new(),
+        }
+    }
+
+    pub fn convert<'e>(&mut self, event: Event<'e>) -> Event<'e> {
+        if !self.enabled {
+            return event;
+        }
+
+        match event {
+            Event::Start(Tag::Link(dest, title)) => {
+                let external = is_external_link(&dest);
+                self.open_links.push(external);
+
+                if external {
+                    let mut html = format!("<a href=\"{}\"", super::escape_html(&dest));
+                    if !title.is_empty() {
+                        html.push_str(&format!(" title=\"{}\"", super::escape_html(&title)));
+                    }
+                    html.push_str(" target=\"_blank\" rel=\"noopener noreferrer\">");
+
+                    Event::InlineHtml(Cow::from(html))
+                } else {
+                    Event::Start(Tag::Link(dest, title))
+                }
+            }
+            Event::End(Tag::Link(dest, title)) => {
+                if self.open_links.pop().unwrap_or(false) {
+                    Event::InlineHtml(Cow::from("</a>"))
+                } else {
+                    Event::End(Tag::Link(dest, title))
+                }
+            }
+            _ => event,
+        }
+    }
+}
+
+/// Is this an `http://`, `https://` or protocol-relative URL?
+fn is_external_link(dest: &str) -> bool {
+    let lower = dest.to_lowercase();
+
+    lower.starts_with("http://") || lower.starts_with("https://") || lower.starts_with("//")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    fn exists(files: &[&str], path: &Path) -> bool {
+        files.iter().any(|f| Path::new(f) == path)
+    }
+
+    #[test]
+    fn relative_md_links_are_translated() {
+        let is_file = |p: &Path| exists(&["first/nested.md", "intro.md", "first/index.md"], p);
+        let base = Path::new("first");
+
+        let inputs = vec![
+            ("nested.md", Some("first/nested.html")),
+            ("./nested.md#some-section", Some("first/nested.html#some-section")),
+            ("../intro.md", Some("intro.html")),
+            // relative to the root, like people wrote before links were
+            // translated
+            ("intro.md", Some("intro.html")),
+        ];
+
+        for (dest, should_be) in inputs {
+            let got = translate_relative_link(base, dest, "md", "html", &is_file);
+            assert_eq!(got.as_ref().map(|s| s.as_str()), should_be, "{}", dest);
+        }
+    }
+
+    #[test]
+    fn other_links_are_left_alone() {
+        let is_file = |p: &Path| exists(&["intro.md"], p);
+        let base = Path::new("");
+
+        let inputs = vec![
+            "",
+            "#some-section",
+            "https://example.com/intro.md",
+            "mailto:someone@example.com",
+            "/intro.md",
+            "intro.html",
+            "missing.md",
+        ];
+
+        for dest in inputs {
+            assert_eq!(translate_relative_link(base, dest, "md", "html", &is_file), None, "{}", dest);
+        }
+    }
+
+    #[test]
+    fn fragments_are_normalized_like_heading_ids() {
+        let filter = NormalizeFragmentLinkFilter::new(false);
+
+        let inputs = vec![
+            ("#My-Section", Some("#my-section")),
+            ("#What's New", Some("#what-s-new")),
+            ("Nested/Other.md#My-Section", Some("Nested/Other.md#my-section")),
+            ("other.html?x=Y#Some-Section", Some("other.html?x=Y#some-section")),
+            ("#already-normal", None),
+            ("#My%20Section", Some("#my-section")),
+            ("#caf%C3%A9", None),
+            ("#Caf%C3%A9", Some("#café")),
+            ("#bad%FF", None),
+            ("Other.md", None),
+            ("https://example.com/page#Some-Section", None),
+            ("//example.com/page#Some-Section", None),
+            ("mailto:someone@example.com#Subject", None),
+        ];
+
+        for (dest, should_be) in inputs {
+            let got = filter.apply(dest);
+            assert_eq!(got.as_ref().map(|s| s.as_str()), should_be, "{}", dest);
+        }
+    }
+
+    #[test]
+    fn fragments_can_be_transliterated() {
+        let filter = NormalizeFragmentLinkFilter::new(true);
+
+        let got = filter.apply("other.md#Café-au-lait");
+        assert_eq!(got, Some(String::from("other.md#cafe-au-lait")));
+        assert_eq!(NormalizeFragmentLinkFilter::new(false).apply("#Café"),
+                   Some(String::from("#café")));
+    }
+
+    #[test]
+    fn links_to_a_sibling_directory_are_translated() {
+        let is_file = |p: &Path| exists(&["second/sibling.md"], p);
+        let base = Path::new("first");
+
+        let got = translate_relative_link(base, "../second/sibling.md", "md", "html", &is_file);
+        assert_eq!(got, Some(String::from("second/sibling.html")));
+    }
+
+    #[test]
+    fn links_climbing_above_the_root_are_left_alone() {
+        // even if something with that name happens to exist
+        let is_file = |_: &Path| true;
+        let base = Path::new("first");
+
+        let got = translate_relative_link(base, "../../../x.md", "md", "html", &is_file);
+        assert_eq!(got, None);
+    }
+
+    #[test]
+    fn same_directory_links_are_unaffected() {
+        let is_file = |p: &Path| exists(&["first/other.md"], p);
+        let base = Path::new("first");
+
+        let got = translate_relative_link(base, "other.md#intro", "md", "html", &is_file);
+        assert_eq!(got, Some(String::from("first/other.html#intro")));
+    }
+
+    #[test]
+    fn translated_paths_are_percent_encoded() {
+        let files = ["notes/My Notes.md", "notes/C#.md", "notes/100%.md", "notes/my logo.png"];
+        let is_file = |p: &Path| exists(&files, p);
+        let base = Path::new("notes");
+
+        let inputs = vec![
+            ("My Notes.md", "notes/My%20Notes.html"),
+            ("./My Notes.md#Some-Section", "notes/My%20Notes.html#Some-Section"),
+            // a `#` in the name has to be escaped in the link too
+            ("C%23.md", "notes/C%23.html"),
+            // already escaped, so it isn't escaped again
+            ("My%20Notes.md", "notes/My%20Notes.html"),
+            ("100%.md?x=1", "notes/100%25.html?x=1"),
+        ];
+
+        for (dest, should_be) in inputs {
+            let got = translate_relative_link(base, dest, "md", "html", &is_file);
+            assert_eq!(got.as_ref().map(|s| s.as_str()), Some(should_be), "{}", dest);
+        }
+
+        let filter = AssetLinkFilter::new(base, &is_file);
+        assert_eq!(filter.apply("my%20logo.png"), Some(String::from("notes/my%20logo.png")));
+        assert_eq!(resolve_asset_link(base, "my%20logo.png", &is_file),
+                   AssetLink::Found(PathBuf::from("notes/my logo.png")));
+    }
+
+    #[test]
+    fn several_extensions_can_be_translated_to_another() {
+        let files = ["first/one.md", "first/two.markdown", "first/notes.txt"];
+        let filter = ChangeExtLinkFilter::with_extensions(Path::new("first"),
+                                                          &["md", "markdown"],
+                                                          "xhtml",
+                                                          |p: &Path| exists(&files, p));
+
+        let inputs = vec![
+            ("./one.md", Some("first/one.xhtml")),
+            ("two.markdown#section", Some("first/two.xhtml#section")),
+            ("./notes.txt", None),
+        ];
+
+        for (dest, should_be) in inputs {
+            let got = filter.apply(dest);
+            assert_eq!(got.as_ref().map(|s| s.as_str()), should_be, "{}", dest);
+        }
+    }
+
+    #[test]
+    fn each_source_extension_has_its_own_page_extension() {
+        let files = ["first/one.md", "first/two.markdown", "first/three.txt"];
+        let mappings = [("md", "html"), ("markdown", "htm"), ("txt", "text.html")];
+        let filter = ChangeExtLinkFilter::with_mappings(Path::new("first"),
+                                                        &mappings,
+                                                        |p: &Path| exists(&files, p));
+
+        let inputs = vec![
+            ("./one.md", Some("first/one.html")),
+            ("two.markdown#section", Some("first/two.htm#section")),
+            ("three.txt", Some("first/three.text.html")),
+            ("./missing.markdown", None),
+            ("./one.MD", None),
+        ];
+
+        for (dest, should_be) in inputs {
+            let got = filter.apply(dest);
+            assert_eq!(got.as_ref().map(|s| s.as_str()), should_be, "{}", dest);
+        }
+    }
+
+    #[test]
+    fn the_longest_source_extension_wins() {
+        let files = ["first/lib.rs.md", "first/notes.md"];
+        let mappings = [("md", "html"), ("rs.md", "rs.html")];
+        let filter = ChangeExtLinkFilter::with_mappings(Path::new("first"),
+                                                        &mappings,
+                                                        |p: &Path| exists(&files, p));
+
+        assert_eq!(filter.apply("./lib.rs.md"), Some(String::from("first/lib.rs.html")));
+        assert_eq!(filter.apply("./notes.md"), Some(String::from("first/notes.html")));
+
+        // without a mapping for it, a literate source is just another `.md`
+        let filter = ChangeExtLinkFilter::new(Path::new("first"), |p: &Path| exists(&files, p));
+        assert_eq!(filter.apply("./lib.rs.md"), Some(String::from("first/lib.rs.html")));
+    }
+
+    #[test]
+    fn dotfiles_and_files_without_an_extension_are_never_rewritten() {
+        let files = ["first/.gitignore", "first/.md", "first/.hidden.md", "first/LICENSE", "first/md"];
+        let mappings = [("md", "html"), ("gitignore", "html")];
+        let missing = RefCell::new(Vec::new());
+        let filter = ChangeExtLinkFilter::with_mappings(Path::new("first"),
+                                                        &mappings,
+                                                        |p: &Path| exists(&files, p))
+            .on_missing_link(|dest| missing.borrow_mut().push(dest.to_string()));
+
+        for dest in vec!["./.gitignore", ".md", "./.hidden.md", "./LICENSE", "md"] {
+            assert_eq!(filter.apply(dest), None, "{}", dest);
+        }
+        assert!(missing.borrow().is_empty());
+    }
+
+    #[test]
+    fn extensionless_links_are_translated_when_asked_for() {
+        let files = ["first/chapter/index.md", "first/chapter.md", "first/section/index.md"];
+        let filter = ChangeExtLinkFilter::new(Path::new("first"), |p: &Path| exists(&files, p))
+            .with_extensionless_links();
+
+        let inputs = vec![
+            ("./chapter/", Some("first/chapter/index.html")),
+            ("./chapter", Some("first/chapter.html")),
+            ("./chapter#intro", Some("first/chapter.html#intro")),
+            ("./section", Some("first/section/index.html")),
+            ("./chapter.md", Some("first/chapter.html")),
+            ("./nothing-here", None),
+            ("./nothing-here/", None),
+            ("?query", None),
+        ];
+
+        for (dest, should_be) in inputs {
+            let got = filter.apply(dest);
+            assert_eq!(got.as_ref().map(|s| s.as_str()), should_be, "{}", dest);
+        }
+    }
+
+    #[test]
+    fn extensionless_links_are_left_alone_by_default() {
+        let missing = RefCell::new(Vec::new());
+        let filter = ChangeExtLinkFilter::new(Path::new("first"), |_: &Path| true)
+            .on_missing_link(|dest| missing.borrow_mut().push(dest.to_string()));
+
+        assert_eq!(filter.apply("./chapter/"), None);
+        assert_eq!(filter.apply("./chapter"), None);
+        assert!(missing.borrow().is_empty());
+    }
+
+    #[test]
+    fn links_to_assets_are_resolved() {
+        let is_file = |p: &Path| exists(&["images/logo.svg", "first/assets/cheatsheet.pdf"], p);
+        let base = Path::new("first");
+
+        let inputs = vec![
+            ("../images/logo.svg", AssetLink::Found(PathBuf::from("images/logo.svg"))),
+            ("./assets/cheatsheet.pdf#page=2",
+             AssetLink::Found(PathBuf::from("first/assets/cheatsheet.pdf"))),
+            // relative to the root, like links to chapters
+            ("images/logo.svg", AssetLink::Found(PathBuf::from("images/logo.svg"))),
+            ("missing.png", AssetLink::Missing),
+            ("../../other-project/file.png", AssetLink::OutsideTheBook),
+            ("other.md", AssetLink::Ignored),
+            ("other.html", AssetLink::Ignored),
+            ("some-directory/", AssetLink::Ignored),
+            ("#some-section", AssetLink::Ignored),
+            ("/images/logo.svg", AssetLink::Ignored),
+            ("https://example.com/logo.svg", AssetLink::Ignored),
+        ];
+
+        for (dest, should_be) in inputs {
+            assert_eq!(resolve_asset_link(base, dest, &is_file), should_be, "{}", dest);
+        }
+    }
+
+    #[test]
+    fn images_are_only_converted_when_asked_for() {
+        let filter = AssetLinkFilter::new(Path::new("first"),
+                                          |p: &Path| exists(&["images/logo.svg"], p));
+        let image = || Event::Start(Tag::Image(Cow::from("../images/logo.svg"), Cow::from("")));
+
+        match RelativeLinkConverter::new(Some(&filter)).convert(image()) {
+            Event::Start(Tag::Image(dest, _)) => assert_eq!(dest, "../images/logo.svg"),
+            other => panic!("Unexpected event {:?}", other),
+        }
+        match RelativeLinkConverter::new(Some(&filter)).and_images().convert(image()) {
+            Event::Start(Tag::Image(dest, _)) => assert_eq!(dest, "images/logo.svg"),
+            other => panic!("Unexpected event {:?}", other),
+        }
+    }
+
+    #[test]
+    fn titles_survive_link_translation() {
+        let filter = ChangeExtLinkFilter::new(Path::new(""), |p: &Path| exists(&["y.md"], p));
+        let converter = RelativeLinkConverter::new(Some(&filter));
+
+        let inputs = vec!["Tooltip", "", "A \"quoted\" 'title'"];
+
+        for title in inputs {
+            let link = Event::Start(Tag::Link(Cow::from("./y.md"), Cow::from(title)));
+
+            match converter.convert(link) {
+                Event::Start(Tag::Link(dest, got)) => {
+                    assert_eq!(dest, "y.html");
+                    assert_eq!(got, title);
+                }
+                other => panic!("Unexpected event {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn titles_survive_image_translation() {
+        let filter = AssetLinkFilter::new(Path::new("first"),
+                                          |p: &Path| exists(&["images/logo.svg"], p));
+        let converter = RelativeLinkConverter::new(Some(&filter)).and_images();
+
+        let inputs = vec!["The \"logo\"", ""];
+
+        for title in inputs {
+            let image = Event::Start(Tag::Image(Cow::from("../images/logo.svg"), Cow::from(title)));
+
+            match converter.convert(image) {
+                Event::Start(Tag::Image(dest, got)) => {
+                    assert_eq!(dest, "images/logo.svg");
+                    assert_eq!(got, title);
+                }
+                other => panic!("Unexpected event {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn normalizing_refuses_to_climb_above_the_root() {
+        let inputs = vec![
+            ("a/./b/../c.md", Some("a/c.md")),
+            ("a/../c.md", Some("c.md")),
+            ("../c.md", None),
+            ("a/../../c.md", None),
+        ];
+
+        for (src, should_be) in inputs {
+            let got = normalize(Path::new(src));
+            assert_eq!(got, should_be.map(PathBuf::from), "{}", src);
+        }
+    }
+
+    #[test]
+    fn missing_md_files_are_reported() {
+        let missing = RefCell::new(Vec::new());
+        let filter = ChangeExtLinkFilter::new(Path::new("first"), |p: &Path| exists(&["intro.md"], p))
+            .on_missing_link(|dest| missing.borrow_mut().push(dest.to_string()));
+
+        let inputs = vec![
+            ("intro.md", Some("intro.html")),
+            ("missing.md#section", None),
+            ("https://example.com/missing.md", None),
+            ("missing.html", None),
+            ("../../outside.md", None),
+        ];
+
+        for (dest, should_be) in inputs {
+            let got = filter.apply(dest);
+            assert_eq!(got.as_ref().map(|s| s.as_str()), should_be, "{}", dest);
+        }
+
+        assert_eq!(*missing.borrow(), vec![String::from("missing.md#section")]);
+    }
+
+    #[test]
+    fn links_with_non_path_schemes_are_left_verbatim() {
+        let missing = RefCell::new(Vec::new());
+        // pretend every file exists, so only the scheme stops the translation
+        let filter = ChangeExtLinkFilter::new(Path::new("first"), |_: &Path| true)
+            .on_missing_link(|dest| missing.borrow_mut().push(dest.to_string()));
+
+        let inputs = vec![
+            "mailto:someone@example.md",
+            "MAILTO:someone@example.md",
+            "tel:+123.md",
+            "ftp://example.com/chapter.md",
+            "javascript:alert('chapter.md')",
+            " javascript:void(0);x.md",
+        ];
+
+        for dest in inputs {
+            assert!(has_non_path_scheme(dest), "{}", dest);
+            assert_eq!(filter.apply(dest), None, "{}", dest);
+            let got = translate_relative_link(Path::new(""), dest, "md", "html", |_: &Path| true);
+            assert_eq!(got, None, "{}", dest);
+        }
+
+        assert!(missing.borrow().is_empty());
+    }
+
+    #[test]
+    fn relative_paths_dont_have_a_non_path_scheme() {
+        let inputs = vec!["chapter.md", "./mailto.md", "tel/chapter.md", "#javascript:x"];
+
+        for dest in inputs {
+            assert!(!has_non_path_scheme(dest), "{}", dest);
+        }
+    }
+
+    #[test]
+    fn only_http_links_are_external() {
+        let inputs = vec![
+            ("https://example.com/", true),
+            ("HTTP://EXAMPLE.COM/", true),
+            ("//example.com/foo.html", true),
+            ("mailto:someone@example.com", false),
+            ("./x.html", false),
+            ("#section", false),
+        ];
+
+        for (src, should_be) in inputs {
+            assert_eq!(is_external_link(src), should_be, "{}", src);
+        }
+    }
+
+    #[test]
+    fn recognise_absolute_urls() {
+        let inputs = vec![
+            ("https://example.com/", true),
+            ("mailto:someone@example.com", true),
+            ("//example.com/foo.html", true),
+            ("new-name.html", false),
+            ("./nested/new-name.html#section", false),
+            ("/new-name.html", false),
+        ];
+
+        for (src, should_be) in inputs {
+            assert_eq!(is_absolute_url(src), should_be, "{}", src);
+        }
+    }
+}
@@ -1,40 +1,895 @@
 #![allow(missing_docs)] // FIXME: Document this
 
 pub mod fs;
-mod string;
+pub mod slug;
+pub mod string;
+mod admonitions;
+mod autolink;
+mod code_block_info;
+mod code_tabs;
+mod definition_lists;
+mod emoji;
+mod line_numbers;
+mod links;
+mod sanitize;
+mod tables;
+mod toc;
+mod transliterate;
+use config::RawHtml;
 use errors::Error;
+use memchr;
+use serde_json;
 
 use pulldown_cmark::{html, Event, Options, Parser, Tag, OPTION_ENABLE_FOOTNOTES,
                      OPTION_ENABLE_TABLES};
 use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+#[allow(unused_imports)] use std::ascii::AsciiExt;
 
-pub use self::string::{RangeArgument, take_lines};
+pub use self::code_block_info::CodeBlockInfo;
+pub use self::string::{RangeArgument, take_lines, LineMap};
+pub use self::links::{resolve_asset_link, translate_relative_link, AssetLink, AssetLinkFilter,
+                      ChangeExtLinkFilter, LinkFilter, NormalizeFragmentLinkFilter,
+                      RelativeLinkConverter};
+pub(crate) use self::code_block_info::split_code_block_attributes;
+pub(crate) use self::links::{is_absolute_url, percent_decode, percent_encode};
+use self::links::ExternalLinkConverter;
+use self::sanitize::HtmlSanitizer;
+use self::tables::TableConverter;
+
+/// Options which tweak how markdown gets rendered to HTML.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct MarkdownOptions {
+    /// Convert straight quotes to curly quotes, except inside code.
+    pub curly_quotes: bool,
+    /// Filter raw HTML through an allowlist of safe tags and attributes,
+    /// dropping anything which isn't on it (e.g. `<script>` or `onclick`).
+    pub sanitize_html: bool,
+    /// Whether raw HTML is passed through, escaped or stripped. This happens
+    /// before `sanitize_html`.
+    pub raw_html: RawHtml,
+    /// Mark tabbed code groups so the theme can remember which tab the reader
+    /// picked last.
+    pub remember_code_tabs: bool,
+    /// Leave relative links to `.md` files untouched instead of translating
+    /// them into links to the rendered `.html` pages.
+    pub keep_md_links: bool,
+    /// Also translate relative links without an extension (`./chapter` or
+    /// `./chapter/`) which point at a chapter.
+    pub extensionless_links: bool,
+    /// Open links to other websites in a new tab (with
+    /// `rel="noopener noreferrer"`).
+    pub external_links_new_tab: bool,
+    /// Replace GitHub style emoji shortcodes (`:rocket:`) with the emoji
+    /// itself, except inside code.
+    pub emoji: bool,
+    /// Turn bare `http://` and `https://` URLs in the text into links, except
+    /// inside code or another link.
+    pub autolink: bool,
+    /// Number the lines of every code block, rather than only the ones with
+    /// a `linenos` attribute.
+    pub line_numbers: bool,
+    /// How many levels of headings the table of contents a `<!-- toc -->`
+    /// marker is replaced with lists (3 if it isn't set).
+    pub toc_depth: Option<u32>,
+    /// Shift every heading down this many levels (so `#` becomes `<h2>` with
+    /// an offset of 1), e.g. when the result is embedded in a page which has
+    /// its own `<h1>`. Headings never go below `<h6>`.
+    pub heading_offset: u32,
+    /// Link fragments are turned into ASCII-only ids (see
+    /// `normalize_ascii_id()`), to match headings rendered that way.
+    pub transliterate_ids: bool,
+    /// Rewrite the `#fragment` of links within the book with the rules used
+    /// for heading ids (`#My-Section` becomes `#my-section`).
+    pub normalize_link_fragments: bool,
+}
 
 /// Wrapper around the pulldown-cmark parser for rendering markdown to HTML.
 pub fn render_markdown(text: &str, curly_quotes: bool) -> String {
-    let mut s = String::with_capacity(text.len() * 3 / 2);
+    let options = MarkdownOptions {
+        curly_quotes: curly_quotes,
+        ..Default::default()
+    };
+
+    render_markdown_with_options(text, &options)
+}
+
+/// Render some of the lines of `text` (counting from 0, with the end of the
+/// range excluded), e.g. to show an excerpt of a chapter.
+///
+/// Code fences are kept balanced, so the excerpt renders the way it looks in
+/// the whole text: if the first line is inside a fenced code block, that
+/// block's opening fence is added back, and a block which is still open at
+/// the end is closed.
+pub fn render_markdown_range<R: RangeArgument<usize>>(text: &str,
+                                                      range: R,
+                                                      curly_quotes: bool)
+                                                      -> String {
+    let start = range.start().cloned().unwrap_or(0);
+    let lines = text.lines().skip(start);
+    let lines: Vec<&str> = match range.end() {
+        Some(&end) => lines.take(end.saturating_sub(start)).collect(),
+        None => lines.collect(),
+    };
+
+    let mut excerpt = String::new();
+    if let Some((opening, _)) = open_fence(text.lines().take(start)) {
+        excerpt.push_str(opening);
+        excerpt.push('\n');
+    }
+    excerpt.push_str(&lines.join("\n"));
+
+    let dangling = open_fence(excerpt.lines()).map(|(_, fence)| fence.to_string());
+    if let Some(fence) = dangling {
+        excerpt.push('\n');
+        excerpt.push_str(&fence);
+    }
+
+    render_markdown(&excerpt, curly_quotes)
+}
+
+/// The line which opened the fenced code block still open after `lines`, and
+/// its fence (e.g. ` ``` `), if there is one.
+fn open_fence<'a, I: Iterator<Item = &'a str>>(lines: I) -> Option<(&'a str, &'a str)> {
+    let mut open: Option<(&str, &str)> = None;
+
+    for line in lines {
+        let fence = match code_fence(line) {
+            Some(fence) => fence,
+            None => continue,
+        };
+
+        open = match open {
+            None => Some((line, fence)),
+            // only a bare fence made of the same character, which is at least
+            // as long as the opening one, closes a block
+            Some((_, opening)) if fence.starts_with(&opening[..1]) && fence.len() >= opening.len()
+                                  && line.trim() == fence => None,
+            still_open => still_open,
+        };
+    }
+
+    open
+}
+
+/// The fence at the start of a line which opens or closes a fenced code
+/// block: at least three backticks or tildes, indented by no more than three
+/// spaces.
+fn code_fence(line: &str) -> Option<&str> {
+    let trimmed = line.trim_left_matches(' ');
+    if line.len() - trimmed.len() > 3 {
+        return None;
+    }
+
+    let fence_char = match trimmed.chars().next() {
+        Some(c) if c == '`' || c == '~' => c,
+        _ => return None,
+    };
+    let len = trimmed.len() - trimmed.trim_left_matches(fence_char).len();
+
+    // the info string after a backtick fence can't have backticks in it, so
+    // ```` ```inline``` ```` isn't a fence
+    if len < 3 || (fence_char == '`' && trimmed[len..].contains('`')) {
+        None
+    } else {
+        Some(&trimmed[..len])
+    }
+}
+
+/// Render markdown to HTML using the provided `MarkdownOptions`.
+pub fn render_markdown_with_options(text: &str, options: &MarkdownOptions) -> String {
+    render_markdown_with_path(text, options, None, |_| false)
+}
+
+/// Render the markdown for the chapter at `path` (relative to the `src`
+/// directory), translating relative links to other markdown files into links
+/// to their rendered pages. `is_file` checks whether a path (also relative to
+/// `src`) is a file which exists.
+///
+/// Links are only translated when a `path` is provided and
+/// `MarkdownOptions::keep_md_links` isn't set. Links and images pointing at
+/// the book's other files (see `linked_assets()`) are made relative to the
+/// book's root whenever a `path` is provided.
+pub fn render_markdown_with_path<F>(text: &str,
+                                    options: &MarkdownOptions,
+                                    path: Option<&Path>,
+                                    is_file: F)
+                                    -> String
+    where F: Fn(&Path) -> bool
+{
+    render_markdown_with_line_map(text, options, path, &LineMap::default(), is_file)
+}
+
+/// The same as `render_markdown_with_path()`, for a chapter whose contents
+/// have moved around since they were read from its file (see `LineMap`), so
+/// warnings about broken links can say which line of the file they're on.
+pub fn render_markdown_with_line_map<F>(text: &str,
+                                        options: &MarkdownOptions,
+                                        path: Option<&Path>,
+                                        line_map: &LineMap,
+                                        is_file: F)
+                                        -> String
+    where F: Fn(&Path) -> bool
+{
+    let events = chapter_events(text, options, path, line_map, is_file);
+    let mut s = String::with_capacity(rendered_capacity(text));
+    html::push_html(&mut s, split_code_block_attributes(events).into_iter());
+    s
+}
+
+/// Room for the tags around the first block (e.g. `<p>` and `</p>\n`), on
+/// top of what `rendered_capacity()` reserves for each line.
+const BLOCK_OVERHEAD: usize = 16;
+
+/// A guess at how long the HTML for some markdown will be, so the output
+/// rarely needs to grow while it's being rendered. Most of the extra length
+/// comes from the tags around each line (paragraphs, list items, table
+/// cells), plus a bit for escaping, and each link (`[text](dest)` becomes
+/// `<a href="dest">text</a>`) adds some more. So do code spans (two
+/// backticks become `<code></code>`) and emphasis (`*a*` becomes
+/// `<em>a</em>`, `**a**` becomes `<strong>a</strong>`). Empty text doesn't
+/// need any room at all.
+///
+/// This is what `render_markdown()` and friends start out with, and is handy
+/// for callers rendering lots of markdown into one buffer.
+pub fn rendered_capacity(text: &str) -> usize {
+    if text.is_empty() {
+        return 0;
+    }
+
+    let bytes = text.as_bytes();
+    let lines = memchr::memchr_iter(b'\n', bytes).count() + 1;
+    let links = memchr::memchr_iter(b']', bytes).count();
+    let backticks = memchr::memchr_iter(b'`', bytes).count();
+    let emphasis = memchr::memchr2_iter(b'*', b'_', bytes).count();
+
+    BLOCK_OVERHEAD + text.len() + text.len() / 8 + lines * 16 + links * 16 + backticks * 7
+        + emphasis * 5
+}
+
+/// The markdown events for a chapter, after going through exactly the same
+/// transforms `render_markdown_with_path()` uses (code block cleanup, link
+/// translation, quote conversion, and so on), for when you want to look at
+/// the parsed markdown rather than render it.
+///
+/// This isn't a streaming API. Several of the transforms (grouping code
+/// tabs, definition lists, tables of contents, ...) need to look ahead, so
+/// the whole chapter is parsed and transformed before this returns, and the
+/// events are held in memory until they've all been consumed. The events
+/// borrow from `text` wherever they can.
+pub fn markdown_events<'a, F>(text: &'a str,
+                              options: &MarkdownOptions,
+                              path: Option<&Path>,
+                              is_file: F)
+                              -> MarkdownEvents<'a>
+    where F: Fn(&Path) -> bool
+{
+    chapter_events(text, options, path, &LineMap::default(), is_file)
+}
+
+/// `markdown_events()`, with a `line_map` for saying where broken links are.
+fn chapter_events<'a, F>(text: &'a str,
+                         options: &MarkdownOptions,
+                         path: Option<&Path>,
+                         line_map: &LineMap,
+                         is_file: F)
+                         -> MarkdownEvents<'a>
+    where F: Fn(&Path) -> bool
+{
+    let mut converter = EventQuoteConverter::new(options.curly_quotes);
+    let mut emoji = EmojiConverter::new(options.emoji);
+    let raw_html = EventRawHtmlFilter::new(options.raw_html);
+    let headings = EventHeadingShifter::new(options.heading_offset);
+    let mut sanitizer = EventHtmlSanitizer::new(options.sanitize_html);
+
+    // `<!-- toc -->` markers are raw HTML too, but they're replaced by the
+    // table of contents at the very end
+    let mut p = SourceEvents::new(text, options.curly_quotes);
+    let events: Vec<_> = p.by_ref()
+                          .map(clean_codeblock_headers)
+                          .filter_map(|event| if toc::is_marker(&event) {
+                              Some(event)
+                          } else {
+                              raw_html.convert(event).and_then(|event| sanitizer.convert(event))
+                          })
+                          .collect();
+    let link_offsets = p.link_offsets;
+
+    let base = path.and_then(Path::parent).unwrap_or_else(|| Path::new(""));
+    // how many links to each destination have been missing so far, so we
+    // can tell which one it is
+    let missing = RefCell::new(HashMap::new());
+    let filter = match path {
+        Some(path) if !options.keep_md_links => {
+            let missing = &missing;
+            let link_offsets = &link_offsets;
+            let filter = ChangeExtLinkFilter::new(base, &is_file).on_missing_link(move |dest| {
+                let mut missing = missing.borrow_mut();
+                let nth = missing.entry(dest.to_string()).or_insert(0);
+                let line = nth_link_line(link_offsets, text, dest, *nth, line_map);
+                *nth += 1;
+
+                warn!("{} links to \"{}\", which doesn't exist",
+                      warning_location(path, line),
+                      dest);
+            });
+            if options.extensionless_links {
+                Some(filter.with_extensionless_links())
+            } else {
+                Some(filter)
+            }
+        }
+        _ => None,
+    };
+    let links = RelativeLinkConverter::new(filter.as_ref().map(|f| f as &LinkFilter));
+    // links to images and other files are rebased even when links to
+    // chapters are kept as they are
+    let asset_filter = path.map(|_| AssetLinkFilter::new(base, &is_file));
+    let assets = RelativeLinkConverter::new(asset_filter.as_ref().map(|f| f as &LinkFilter))
+        .and_images();
+    let fragment_filter = if options.normalize_link_fragments {
+        Some(NormalizeFragmentLinkFilter::new(options.transliterate_ids))
+    } else {
+        None
+    };
+    let fragments = RelativeLinkConverter::new(fragment_filter.as_ref().map(|f| f as &LinkFilter));
+    let mut external_links = ExternalLinkConverter::new(options.external_links_new_tab);
+    let mut tables = TableConverter::new();
+
+    // after the raw HTML has been dealt with, so the markup we add isn't
+    // escaped or stripped
+    let events = definition_lists::convert_definition_lists(events);
+    let events = admonitions::convert_admonitions(events);
+    // before the link transforms, so new links to other sites get opened in
+    // a new tab too
+    let events = if options.autolink {
+        autolink::autolink_urls(events)
+    } else {
+        events
+    };
+    let events = events.into_iter()
+                       .map(|event| links.convert(event))
+                       .map(|event| assets.convert(event))
+                       .map(|event| fragments.convert(event))
+                       .map(|event| external_links.convert(event))
+                       .map(|event| converter.convert(event))
+                       .map(|event| emoji.convert(event))
+                       .map(|event| headings.convert(event))
+                       .map(|event| tables.convert(event));
+    let events = code_tabs::group_code_tabs(events.collect(),
+                                             options.remember_code_tabs,
+                                             path);
+    let events = code_block_info::add_code_captions(events);
+    let events = line_numbers::number_code_lines(events, options.line_numbers);
+    let events = toc::insert_tocs(events, options.toc_depth, options.transliterate_ids);
+
+    MarkdownEvents(events.into_iter())
+}
+
+/// Parses some markdown, keeping track of the things which need the source
+/// text and are gone once the events are transformed:
+///
+/// - The backslash in front of escaped quotes (`\'` and `\"`) is put back
+///   when `curly_quotes` is set. The parser drops it, and
+///   `EventQuoteConverter` needs it to know which quotes to keep straight.
+/// - Where each link is, so a warning about a broken link can say which line
+///   it's on without parsing the text again.
+struct SourceEvents<'a> {
+    parser: Parser<'a>,
+    text: &'a str,
+    curly_quotes: bool,
+    /// The byte offsets of the links to each destination, in order.
+    link_offsets: HashMap<String, Vec<usize>>,
+}
+
+impl<'a> SourceEvents<'a> {
+    fn new(text: &'a str, curly_quotes: bool) -> SourceEvents<'a> {
+        SourceEvents {
+            parser: Parser::new_ext(text, parser_options()),
+            text: text,
+            curly_quotes: curly_quotes,
+            link_offsets: HashMap::new(),
+        }
+    }
+
+    /// Does the quote which ends at `end` in the original text have an odd
+    /// number of backslashes in front of it?
+    fn is_escaped(&self, end: usize) -> bool {
+        let before = match self.text.get(..end) {
+            Some(before) if before.ends_with(|c| c == '\'' || c == '"') => &before[..end - 1],
+            _ => return false,
+        };
+        let backslashes = before.len() - before.trim_right_matches('\\').len();
+
+        backslashes % 2 == 1
+    }
+}
+
+impl<'a> Iterator for SourceEvents<'a> {
+    type Item = Event<'a>;
+
+    fn next(&mut self) -> Option<Event<'a>> {
+        let event = match self.parser.next() {
+            Some(event) => event,
+            None => return None,
+        };
+
+        if let Event::Start(Tag::Link(ref dest, _)) = event {
+            self.link_offsets
+                .entry(dest.to_string())
+                .or_insert_with(Vec::new)
+                .push(self.parser.get_offset());
+        }
 
+        // an escaped character is a text event of its own
+        let escaped = match event {
+            Event::Text(ref text) if self.curly_quotes && (&**text == "'" || &**text == "\"") => {
+                self.is_escaped(self.parser.get_offset())
+            }
+            _ => false,
+        };
+        if escaped {
+            match event {
+                Event::Text(text) => Some(Event::Text(Cow::from(format!("\\{}", text)))),
+                _ => unreachable!(),
+            }
+        } else {
+            Some(event)
+        }
+    }
+}
+
+/// The markdown extensions chapters are parsed with.
+fn parser_options() -> Options {
     let mut opts = Options::empty();
     opts.insert(OPTION_ENABLE_TABLES);
     opts.insert(OPTION_ENABLE_FOOTNOTES);
+    opts
+}
 
-    let p = Parser::new_ext(text, opts);
-    let mut converter = EventQuoteConverter::new(curly_quotes);
-    let events = p.map(clean_codeblock_headers)
-                  .map(|event| converter.convert(event));
+/// An iterator over a chapter's transformed markdown events, created by
+/// `markdown_events()`. Every event has already been produced by the time
+/// this is returned, so iterating over it only hands them out.
+#[derive(Debug)]
+pub struct MarkdownEvents<'a>(::std::vec::IntoIter<Event<'a>>);
 
-    html::push_html(&mut s, events);
-    s
+impl<'a> Iterator for MarkdownEvents<'a> {
+    type Item = Event<'a>;
+
+    fn next(&mut self) -> Option<Event<'a>> {
+        self.0.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+/// The files (other than chapters) the chapter at `path` links to or shows as
+/// an image, relative to the `src` directory, so the renderer can copy them
+/// next to the rendered page. `is_file` checks whether a path (also relative
+/// to `src`) is a file which exists.
+///
+/// A warning is emitted for every link to a file which doesn't exist, or
+/// which is outside the book's `src` directory, saying which line of the
+/// chapter's file it's on (`line_map` maps the lines of `text` back to it).
+pub fn linked_assets<F>(text: &str, path: &Path, line_map: &LineMap, is_file: F) -> Vec<PathBuf>
+    where F: Fn(&Path) -> bool
+{
+    let base = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut assets = Vec::new();
+    let mut parser = Parser::new_ext(text, Options::empty());
+
+    while let Some(event) = parser.next() {
+        let dest = match event {
+            Event::Start(Tag::Link(dest, _)) | Event::Start(Tag::Image(dest, _)) => dest,
+            _ => continue,
+        };
+        let offset = parser.get_offset();
+        let location = || warning_location(path, Some(line_in_file(text, offset, line_map)));
+
+        match resolve_asset_link(base, &dest, &is_file) {
+            AssetLink::Found(asset) => {
+                if !assets.contains(&asset) {
+                    assets.push(asset);
+                }
+            }
+            AssetLink::Missing => {
+                warn!("{} links to \"{}\", which doesn't exist", location(), dest);
+            }
+            AssetLink::OutsideTheBook => {
+                warn!("{} links to \"{}\", which is outside the book's source directory \
+                       and won't be copied",
+                      location(),
+                      dest);
+            }
+            AssetLink::Ignored => {}
+        }
+    }
+
+    assets
+}
+
+/// The line in the chapter's file which a byte offset in its contents is on.
+fn line_in_file(text: &str, offset: usize, line_map: &LineMap) -> usize {
+    let (line, _) = string::byte_offset_to_line_col(text, offset);
+    line_map.original_line(line)
+}
+
+/// The line in the chapter's file of the `nth` (starting at 0) link to
+/// `dest` in its contents, given the `link_offsets` found by `SourceEvents`.
+fn nth_link_line(link_offsets: &HashMap<String, Vec<usize>>,
+                 text: &str,
+                 dest: &str,
+                 nth: usize,
+                 line_map: &LineMap)
+                 -> Option<usize> {
+    link_offsets.get(dest)
+                .and_then(|offsets| offsets.get(nth))
+                .map(|&offset| line_in_file(text, offset, line_map))
+}
+
+/// `path:line` for a warning, or just the `path` if the line isn't known.
+fn warning_location(path: &Path, line: Option<usize>) -> String {
+    match line {
+        Some(line) => format!("{}:{}", path.display(), line),
+        None => path.display().to_string(),
+    }
+}
+
+/// A link or image in some markdown, as found by `extract_links()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkRef {
+    /// Where the link points, exactly as it's written. For a reference-style
+    /// link this is the destination of the reference.
+    pub dest: String,
+    /// Is this an image rather than a link?
+    pub is_image: bool,
+    /// Is `dest` a URL with a scheme (`https://...`, `mailto:...`), a
+    /// protocol-relative URL (`//example.com/...`) or an absolute path
+    /// (`/...`)? Anything else is relative to the chapter.
+    pub absolute: bool,
+    /// The byte offset in the text where the parser found the link (the
+    /// start of the link, or somewhere inside its text), if it's known.
+    pub offset: Option<usize>,
+}
+
+/// Every link and image in some markdown, in the order they appear. The
+/// markdown is parsed the same way as by `render_markdown()`, but none of
+/// its transforms are applied, so the destinations are the ones in the
+/// text (e.g. links to `.md` files aren't translated).
+pub fn extract_links(text: &str) -> Vec<LinkRef> {
+    let mut parser = Parser::new_ext(text, parser_options());
+    let mut links = Vec::new();
+
+    while let Some(event) = parser.next() {
+        let (dest, is_image) = match event {
+            Event::Start(Tag::Link(dest, _)) => (dest, false),
+            Event::Start(Tag::Image(dest, _)) => (dest, true),
+            _ => continue,
+        };
+
+        links.push(LinkRef {
+            absolute: is_absolute_url(&dest) || dest.starts_with('/'),
+            dest: dest.into_owned(),
+            is_image: is_image,
+            offset: Some(parser.get_offset()),
+        });
+    }
+
+    links
+}
+
+/// The text of the first heading in some markdown, if there is one.
+pub fn first_heading(text: &str) -> Option<String> {
+    let mut heading: Option<String> = None;
+
+    for event in Parser::new(text) {
+        match event {
+            Event::Start(Tag::Header(_)) => heading = Some(String::new()),
+            Event::End(Tag::Header(_)) => break,
+            Event::Text(ref text) => {
+                if let Some(ref mut heading) = heading {
+                    heading.push_str(text);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    heading.map(|heading| heading.trim().to_string())
+           .and_then(|heading| if heading.is_empty() { None } else { Some(heading) })
+}
+
+/// Turn some text into something suitable for use as an anchor or slug (see
+/// `slug::slugify()` for the rules).
+pub fn normalize_id(content: &str) -> String {
+    slug::slugify(content)
+}
+
+/// Like `normalize_id()`, but only ASCII ends up in the id. Accented Latin
+/// letters are transliterated (`Café` becomes `cafe`), and if the text has
+/// anything else which isn't ASCII (e.g. a Japanese title) that is dropped,
+/// and a hash of the whole text is added so the id is still unique.
+pub fn normalize_ascii_id(content: &str) -> String {
+    let id = normalize_id(&transliterate::transliterate(content));
+    if id.is_ascii() {
+        return id;
+    }
+
+    let ascii: Vec<_> = id.split(|c: char| !c.is_ascii())
+                          .flat_map(|part| part.split('-'))
+                          .filter(|part| !part.is_empty())
+                          .collect();
+    let hash = transliterate::stable_hash(content);
+
+    if ascii.is_empty() {
+        hash
+    } else {
+        format!("{}-{}", ascii.join("-"), hash)
+    }
+}
+
+/// Generate an id for use with anchors which is derived from a "normalised"
+/// string.
+pub fn id_from_content(content: &str) -> String {
+    normalize_id(&heading_text(content))
+}
+
+/// Like `id_from_content()`, but the id only contains ASCII (see
+/// `normalize_ascii_id()`).
+pub fn ascii_id_from_content(content: &str) -> String {
+    normalize_ascii_id(&heading_text(content))
+}
+
+/// The text of a rendered heading, without any markup.
+fn heading_text(content: &str) -> String {
+    let mut content = content.to_string();
+
+    // Skip any tags or html-encoded stuff
+    const REPL_SUB: &[&str] = &["<em>",
+                                "</em>",
+                                "<code>",
+                                "</code>",
+                                "<strong>",
+                                "</strong>",
+                                "&lt;",
+                                "&gt;",
+                                "&amp;",
+                                "&#39;",
+                                "&quot;"];
+    for sub in REPL_SUB {
+        content = content.replace(sub, "");
+    }
+
+    // Remove spaces and hastags indicating a header
+    content.trim().trim_left_matches('#').trim().to_string()
+}
+
+/// Escape the characters which have a special meaning in HTML text and
+/// attribute values.
+pub(crate) fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+     .replace('"', "&quot;")
+     .replace('<', "&lt;")
+     .replace('>', "&gt;")
+}
+
+/// Applies the `RawHtml` policy to the raw HTML in a chapter.
+///
+/// Each chunk of raw HTML is handled on its own, so a block of HTML which
+/// pulldown-cmark splits over several events (and any markdown nested inside
+/// it) is treated the same way as a single inline tag. Code blocks and code
+/// spans are text events, which means HTML inside them is never touched.
+struct EventRawHtmlFilter {
+    policy: RawHtml,
+}
+
+impl EventRawHtmlFilter {
+    fn new(policy: RawHtml) -> Self {
+        EventRawHtmlFilter { policy: policy }
+    }
+
+    fn convert<'a>(&self, event: Event<'a>) -> Option<Event<'a>> {
+        match (self.policy, event) {
+            (RawHtml::Allow, event) => Some(event),
+            (RawHtml::Strip, Event::Html(_)) | (RawHtml::Strip, Event::InlineHtml(_)) => None,
+            // Emitting escaped HTML (rather than a text event) means the curly
+            // quote conversion leaves the markup alone
+            (RawHtml::Escape, Event::Html(html)) => Some(Event::Html(Cow::from(escape_html(&html)))),
+            (RawHtml::Escape, Event::InlineHtml(html)) => {
+                Some(Event::InlineHtml(Cow::from(escape_html(&html))))
+            }
+            (_, event) => Some(event),
+        }
+    }
+}
+
+struct EventHtmlSanitizer {
+    enabled: bool,
+    sanitizer: HtmlSanitizer,
+}
+
+impl EventHtmlSanitizer {
+    fn new(enabled: bool) -> Self {
+        EventHtmlSanitizer {
+            enabled: enabled,
+            sanitizer: HtmlSanitizer::new(),
+        }
+    }
+
+    fn convert<'a>(&mut self, event: Event<'a>) -> Option<Event<'a>> {
+        if !self.enabled {
+            return Some(event);
+        }
+
+        match event {
+            Event::Html(html) => self.sanitize(&html).map(Event::Html),
+            Event::InlineHtml(html) => self.sanitize(&html).map(Event::InlineHtml),
+            // the text between an inline `<script>` and `</script>`
+            Event::Text(_) if self.sanitizer.is_dropping() => None,
+            Event::End(Tag::Paragraph) => {
+                // an unclosed inline element shouldn't swallow the rest of
+                // the document
+                self.sanitizer.reset();
+                Some(event)
+            }
+            _ => Some(event),
+        }
+    }
+
+    fn sanitize<'a>(&mut self, html: &str) -> Option<Cow<'a, str>> {
+        let sanitized = self.sanitizer.sanitize(html);
+
+        if sanitized.is_empty() {
+            None
+        } else {
+            Some(Cow::from(sanitized))
+        }
+    }
+}
+
+/// Moves headings down by a fixed number of levels.
+struct EventHeadingShifter {
+    offset: u32,
+}
+
+impl EventHeadingShifter {
+    fn new(offset: u32) -> Self {
+        EventHeadingShifter { offset: offset }
+    }
+
+    fn convert<'a>(&self, event: Event<'a>) -> Event<'a> {
+        match event {
+            Event::Start(Tag::Header(level)) => Event::Start(Tag::Header(self.shift(level))),
+            Event::End(Tag::Header(level)) => Event::End(Tag::Header(self.shift(level))),
+            _ => event,
+        }
+    }
+
+    fn shift(&self, level: i32) -> i32 {
+        let offset = if self.offset > 5 { 5 } else { self.offset as i32 };
+        let shifted = level + offset;
+
+        if shifted > 6 {
+            6
+        } else {
+            shifted
+        }
+    }
 }
 
+/// Curls the quotes in text, except in code. Whether a quote opens or closes
+/// depends on the character before it, which can be in an earlier event
+/// (e.g. a code span or emphasis just before an apostrophe).
 struct EventQuoteConverter {
     enabled: bool,
-    convert_text: bool,
+    /// How many code spans and code blocks (including raw `<code>` tags)
+    /// we're in.
+    code_depth: usize,
+    preceded_by_whitespace: bool,
 }
 
 impl EventQuoteConverter {
     fn new(enabled: bool) -> Self {
         EventQuoteConverter {
+            enabled: enabled,
+            code_depth: 0,
+            preceded_by_whitespace: true,
+        }
+    }
+
+    fn convert<'a>(&mut self, event: Event<'a>) -> Event<'a> {
+        if !self.enabled {
+            return event;
+        }
+
+        match event {
+            Event::Start(Tag::CodeBlock(_)) | Event::Start(Tag::Code) => {
+                self.code_depth += 1;
+                event
+            }
+            Event::End(Tag::CodeBlock(_)) | Event::End(Tag::Code) => {
+                self.code_depth = self.code_depth.saturating_sub(1);
+                event
+            }
+            Event::Start(Tag::Emphasis) | Event::End(Tag::Emphasis) | Event::Start(Tag::Strong)
+            | Event::End(Tag::Strong) | Event::Start(Tag::Link(..)) | Event::End(Tag::Link(..)) => {
+                event
+            }
+            Event::InlineHtml(html) => {
+                match code_tag(&html) {
+                    Some(true) => self.code_depth += 1,
+                    Some(false) => self.code_depth = self.code_depth.saturating_sub(1),
+                    None => {}
+                }
+                Event::InlineHtml(html)
+            }
+            Event::Text(text) => {
+                let preceded_by_whitespace = self.preceded_by_whitespace;
+                if let Some(last) = text.chars().next_back() {
+                    self.preceded_by_whitespace = last.is_whitespace();
+                }
+
+                if self.code_depth > 0 {
+                    return Event::Text(text);
+                }
+
+                // most text doesn't have any quotes, and can be passed on as
+                // it is
+                let converted = match curl_quotes(&text, preceded_by_whitespace) {
+                    Cow::Owned(converted) => Some(converted),
+                    Cow::Borrowed(_) => None,
+                };
+                match converted {
+                    Some(converted) => Event::Text(Cow::from(converted)),
+                    None => Event::Text(text),
+                }
+            }
+            // anything else (a new paragraph, a line break, ...) starts
+            // afresh
+            _ => {
+                self.preceded_by_whitespace = true;
+                event
+            }
+        }
+    }
+}
+
+/// Is this inline HTML an opening (`Some(true)`) or closing (`Some(false)`)
+/// `<code>` tag?
+fn code_tag(html: &str) -> Option<bool> {
+    let html = html.trim().to_lowercase();
+    let (opening, name) = if html.starts_with("</") {
+        (false, &html[2..])
+    } else if html.starts_with('<') {
+        (true, &html[1..])
+    } else {
+        return None;
+    };
+
+    let is_code = name.starts_with("code")
+        && name[4..].starts_with(|c: char| c == '>' || c.is_whitespace());
+    if is_code {
+        Some(opening)
+    } else {
+        None
+    }
+}
+
+/// Replaces `:shortcode:`s in text with the corresponding emoji. Both colons
+/// need to be in the same text event, and anything inside a code span or
+/// code block is left alone.
+struct EmojiConverter {
+    enabled: bool,
+    convert_text: bool,
+}
+
+impl EmojiConverter {
+    fn new(enabled: bool) -> Self {
+        EmojiConverter {
             enabled: enabled,
             convert_text: true,
         }
@@ -54,8 +909,8 @@ impl EventQuoteConverter {
                 self.convert_text = true;
                 event
             }
-            Event::Text(ref text) if self.convert_text => {
-                Event::Text(Cow::from(convert_quotes_to_curly(text)))
+            Event::Text(ref text) if self.convert_text && text.contains(':') => {
+                Event::Text(Cow::from(emoji::replace_shortcodes(text)))
             }
             _ => event,
         }
@@ -64,8 +919,14 @@ impl EventQuoteConverter {
 
 fn clean_codeblock_headers(event: Event) -> Event {
     match event {
-        Event::Start(Tag::CodeBlock(ref info)) => {
-            let info: String = info.chars().filter(|ch| !ch.is_whitespace()).collect();
+        Event::Start(Tag::CodeBlock(ref info)) if needs_cleaning(info) => {
+            // whitespace inside a quoted value (`title="Hello, world"`) is
+            // part of the value
+            let info = code_block_info::split_attributes(info)
+                .into_iter()
+                .map(|attribute| strip_unquoted_whitespace(attribute))
+                .collect::<Vec<_>>()
+                .join(",");
 
             Event::Start(Tag::CodeBlock(Cow::from(info)))
         }
@@ -73,14 +934,57 @@ fn clean_codeblock_headers(event: Event) -> Event {
     }
 }
 
+/// Would cleaning up this info string change it? Without any whitespace or
+/// empty attributes, splitting it on the commas and joining it back together
+/// gives the same string.
+fn needs_cleaning(info: &str) -> bool {
+    info.contains(char::is_whitespace) || info.starts_with(',') || info.ends_with(',')
+        || info.contains(",,")
+}
+
+fn strip_unquoted_whitespace(attribute: &str) -> String {
+    let mut quote = None;
+
+    attribute
+        .chars()
+        .filter(|&c| {
+            match quote {
+                Some(q) if c == q => quote = None,
+                Some(_) => return true,
+                None if c == '"' || c == '\'' => quote = Some(c),
+                None => return !c.is_whitespace(),
+            }
+            true
+        })
+        .collect()
+}
 
-fn convert_quotes_to_curly(original_text: &str) -> String {
+#[cfg(test)]
+fn convert_quotes_to_curly(original_text: &str) -> Cow<str> {
     // We'll consider the start to be "whitespace".
-    let mut preceded_by_whitespace = true;
+    curl_quotes(original_text, true)
+}
+
+/// Curl the quotes in some text, which comes straight after whitespace (or
+/// the start of a paragraph) if `preceded_by_whitespace` is set.
+fn curl_quotes(original_text: &str, mut preceded_by_whitespace: bool) -> Cow<str> {
+    if !original_text.contains(|c| c == '\'' || c == '"' || c == '\\') {
+        return Cow::Borrowed(original_text);
+    }
 
-    original_text.chars()
-                 .map(|original_char| {
+    let mut converted = String::with_capacity(original_text.len());
+    let mut chars = original_text.chars().peekable();
+
+    while let Some(original_char) = chars.next() {
         let converted_char = match original_char {
+            // a backslash keeps a quote straight, and can escape itself
+            '\\' => match chars.peek().cloned() {
+                Some(next @ '\'') | Some(next @ '"') | Some(next @ '\\') => {
+                    chars.next();
+                    next
+                }
+                _ => original_char,
+            },
             '\'' => {
                 if preceded_by_whitespace {
                     '‘'
@@ -99,10 +1003,10 @@ fn convert_quotes_to_curly(original_text: &str) -> String {
         };
 
         preceded_by_whitespace = original_char.is_whitespace();
+        converted.push(converted_char);
+    }
 
-        converted_char
-    })
-                 .collect()
+    Cow::Owned(converted)
 }
 
 /// Prints a "backtrace" of some `Error`.
@@ -114,32 +1018,139 @@ pub fn log_backtrace(e: &Error) {
     }
 }
 
+/// Prints an `Error` (and its causes) to stderr as a single line of JSON, for
+/// tools which need to know what went wrong. See `Error::report()` for the
+/// fields.
+pub fn print_error_json(e: &Error) {
+    match serde_json::to_string(&e.report()) {
+        Ok(json) => eprintln!("{}", json),
+        // fall back to something readable rather than losing the error
+        Err(_) => log_backtrace(e),
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    mod render_markdown {
-        use super::super::render_markdown;
+    mod render_markdown_range {
+        use super::super::{render_markdown, render_markdown_range};
+
+        const CHAPTER: &str = "# Example
+
+Some text.
+
+```rust
+fn main() {
+    run();
+}
+```
+
+More text.";
 
         #[test]
-        fn it_can_keep_quotes_straight() {
-            assert_eq!(render_markdown("'one'", false), "<p>'one'</p>\n");
+        fn a_range_inside_a_code_block_is_still_code() {
+            let got = render_markdown_range(CHAPTER, 5..7, false);
+
+            assert_eq!(got, "<pre><code class=\"language-rust\">fn main() {\n    run();\n</code></pre>\n");
         }
 
         #[test]
-        fn it_can_make_quotes_curly_except_when_they_are_in_code() {
-            let input = r#"
-'one'
-```
-'two'
-```
-`'three'` 'four'"#;
-            let expected = r#"<p>‘one’</p>
-<pre><code>'two'
+        fn a_block_which_is_cut_off_is_closed() {
+            let got = render_markdown_range(CHAPTER, 2..6, false);
+
+            assert_eq!(got, "<p>Some text.</p>\n<pre><code class=\"language-rust\">fn main() {\n</code></pre>\n");
+        }
+
+        #[test]
+        fn a_range_from_inside_a_block_to_after_it() {
+            let got = render_markdown_range(CHAPTER, 6.., false);
+
+            assert_eq!(got, "<pre><code class=\"language-rust\">    run();\n}\n</code></pre>\n<p>More text.</p>\n");
+        }
+
+        #[test]
+        fn prose_is_rendered_as_usual() {
+            assert_eq!(render_markdown_range(CHAPTER, 2..3, true), render_markdown("Some text.", true));
+            assert_eq!(render_markdown_range(CHAPTER, ..1, false), "<h1>Example</h1>\n");
+        }
+
+        #[test]
+        fn longer_fences_arent_closed_by_shorter_ones() {
+            let text = "````markdown\n```rust\n```\nstill code\n````\n";
+
+            let got = render_markdown_range(text, 3..4, false);
+
+            assert_eq!(got, "<pre><code class=\"language-markdown\">still code\n</code></pre>\n");
+        }
+    }
+
+    mod render_markdown {
+        use super::super::{render_markdown, rendered_capacity};
+
+        #[test]
+        fn empty_text_renders_to_nothing() {
+            assert_eq!(rendered_capacity(""), 0);
+            assert_eq!(render_markdown("", false), "");
+            assert_eq!(render_markdown("", true), "");
+        }
+
+        #[test]
+        fn there_is_room_for_tiny_inputs() {
+            let inputs = ["a", "Intro", "# Title", "`code`", "`a` `b`", "*a*", "**a**", "_a_ __b__"];
+
+            for text in &inputs {
+                assert!(rendered_capacity(text) >= render_markdown(text, false).len(), "{}", text);
+            }
+        }
+
+        #[test]
+        fn there_is_room_for_the_markup_links_add() {
+            let text = "See [a](a.md), [b](b.md) and [c](c.md).\n\n".repeat(20);
+
+            assert!(rendered_capacity(&text) >= render_markdown(&text, false).len());
+        }
+
+        #[test]
+        fn it_can_keep_quotes_straight() {
+            assert_eq!(render_markdown("'one'", false), "<p>'one'</p>\n");
+        }
+
+        #[test]
+        fn it_can_make_quotes_curly_except_when_they_are_in_code() {
+            let input = r#"
+'one'
+```
+'two'
+```
+`'three'` 'four'"#;
+            let expected = r#"<p>‘one’</p>
+<pre><code>'two'
 </code></pre>
 <p><code>'three'</code> ‘four’</p>
 "#;
             assert_eq!(render_markdown(input, true), expected);
         }
 
+        #[test]
+        fn double_backtick_code_spans_are_left_alone() {
+            let got = render_markdown("Use `` it's a `tick` -- really ``, 'okay'?", true);
+
+            assert_eq!(got, "<p>Use <code>it's a `tick` -- really</code>, ‘okay’?</p>\n");
+        }
+
+        #[test]
+        fn quotes_straight_after_code_close_rather_than_open() {
+            let got = render_markdown("The `Vec`'s length and *emphasis*'s too", true);
+
+            assert_eq!(got, "<p>The <code>Vec</code>’s length and <em>emphasis</em>’s too</p>\n");
+        }
+
+        #[test]
+        fn quotes_in_raw_code_tags_are_left_alone() {
+            let got = render_markdown("<code>it's</code> and <CODE class=\"x\">'a'</CODE> 'b'", true);
+
+            assert_eq!(got, "<p><code>it's</code> and <CODE class=\"x\">'a'</CODE> ‘b’</p>\n");
+        }
+
         #[test]
         fn whitespace_outside_of_codeblock_header_is_preserved() {
             let input = r#"
@@ -164,28 +1175,35 @@ more text with spaces
         }
 
         #[test]
-        fn rust_code_block_properties_are_passed_as_space_delimited_class() {
+        fn rust_code_block_properties_are_passed_as_data_attributes() {
             let input = r#"
 ```rust,no_run,should_panic,property_3
 ```
 "#;
 
-            let expected =
-                r#"<pre><code class="language-rust,no_run,should_panic,property_3"></code></pre>
+            let expected = r#"<pre><code class="language-rust" data-attributes="no_run should_panic property_3"></code></pre>
 "#;
             assert_eq!(render_markdown(input, false), expected);
             assert_eq!(render_markdown(input, true), expected);
         }
 
         #[test]
-        fn rust_code_block_properties_with_whitespace_are_passed_as_space_delimited_class() {
+        fn a_no_run_block_only_has_the_language_as_its_class() {
+            let input = "```rust,no_run\nfn main() {}\n```\n";
+
+            let expected = "<pre><code class=\"language-rust\" data-attributes=\"no_run\">\
+                            fn main() {}\n</code></pre>\n";
+            assert_eq!(render_markdown(input, false), expected);
+        }
+
+        #[test]
+        fn rust_code_block_properties_with_whitespace_are_passed_as_data_attributes() {
             let input = r#"
 ```rust,    no_run,,,should_panic , ,property_3
 ```
 "#;
 
-            let expected =
-                r#"<pre><code class="language-rust,no_run,,,should_panic,,property_3"></code></pre>
+            let expected = r#"<pre><code class="language-rust" data-attributes="no_run should_panic property_3"></code></pre>
 "#;
             assert_eq!(render_markdown(input, false), expected);
             assert_eq!(render_markdown(input, true), expected);
@@ -212,24 +1230,1201 @@ more text with spaces
         }
     }
 
-    mod convert_quotes_to_curly {
-        use super::super::convert_quotes_to_curly;
+    mod code_captions {
+        use super::super::render_markdown;
 
         #[test]
-        fn it_converts_single_quotes() {
-            assert_eq!(convert_quotes_to_curly("'one', 'two'"),
-                       "‘one’, ‘two’");
+        fn a_title_becomes_a_caption() {
+            let input = "```rust,title=\"listing 1\"\nfn main() {}\n```\n";
+            let expected = "<div class=\"code-caption\">listing 1</div>\n\
+                            <pre><code class=\"language-rust\">fn main() {}\n</code></pre>\n";
+
+            assert_eq!(render_markdown(input, false), expected);
         }
 
         #[test]
-        fn it_converts_double_quotes() {
-            assert_eq!(convert_quotes_to_curly(r#""one", "two""#),
-                       "“one”, “two”");
+        fn captions_are_escaped() {
+            let input = "```rust,title=\"<b>Tom & Jerry's</b>\"\nfn main() {}\n```\n";
+
+            let got = render_markdown(input, false);
+
+            assert!(got.contains(r#"<div class="code-caption">&lt;b&gt;Tom &amp; Jerry's&lt;/b&gt;</div>"#),
+                    "{}",
+                    got);
+            assert!(!got.contains("<b>"), "{}", got);
         }
 
         #[test]
-        fn it_treats_tab_as_whitespace() {
-            assert_eq!(convert_quotes_to_curly("\t'one'"), "\t‘one’");
+        fn blocks_without_a_title_have_no_caption() {
+            let input = "```rust,no_run\nfn main() {}\n```\n";
+
+            let got = render_markdown(input, false);
+
+            assert!(got.starts_with("<pre><code class=\"language-rust"), "{}", got);
+            assert!(!got.contains("code-caption"), "{}", got);
+        }
+    }
+
+    mod admonitions {
+        use super::super::render_markdown;
+
+        #[test]
+        fn a_note() {
+            let got = render_markdown("> [!NOTE]\n> Useful information.\n", false);
+
+            assert_eq!(
+                got,
+                "<div class=\"admonition note\">\n<p class=\"admonition-title\">Note</p>\n\
+                 <blockquote>\n<p>Useful information.</p>\n</blockquote>\n</div>\n"
+            );
+        }
+
+        #[test]
+        fn a_warning_with_more_content() {
+            let src = "> [!WARNING]\n>\n> This will delete *everything*.\n>\n> > Nested quote\n";
+
+            let got = render_markdown(src, false);
+
+            assert!(got.starts_with("<div class=\"admonition warning\">\n\
+                                     <p class=\"admonition-title\">Warning</p>\n<blockquote>"),
+                    "{}",
+                    got);
+            assert!(got.contains("<p>This will delete <em>everything</em>.</p>"), "{}", got);
+            assert!(got.contains("<blockquote>\n<p>Nested quote</p>\n</blockquote>"), "{}", got);
+            assert!(!got.contains("[!WARNING]"), "{}", got);
+            assert!(got.ends_with("</blockquote>\n</div>\n"), "{}", got);
+        }
+
+        #[test]
+        fn unknown_types_get_a_generic_admonition() {
+            let got = render_markdown("> [!Custom] Something else.\n", false);
+
+            assert!(got.starts_with("<div class=\"admonition\">\n\
+                                     <p class=\"admonition-title\">Custom</p>\n"),
+                    "{}",
+                    got);
+            assert!(got.contains("<p>Something else.</p>"), "{}", got);
+        }
+
+        #[test]
+        fn normal_blockquotes_are_untouched() {
+            let inputs = vec![
+                "> Just a quote.\n",
+                "> [not a marker]\n",
+                "> Text before [!NOTE]\n",
+            ];
+
+            for src in inputs {
+                let got = render_markdown(src, false);
+                assert!(got.starts_with("<blockquote>"), "{}", got);
+                assert!(!got.contains("admonition"), "{}", got);
+            }
+        }
+    }
+
+    mod emoji {
+        use super::super::{render_markdown_with_options, MarkdownOptions};
+
+        fn render(src: &str) -> String {
+            let options = MarkdownOptions {
+                emoji: true,
+                ..Default::default()
+            };
+
+            render_markdown_with_options(src, &options)
+        }
+
+        #[test]
+        fn known_shortcodes_become_emoji() {
+            assert_eq!(render("Ship it :rocket:"), "<p>Ship it 🚀</p>\n");
+        }
+
+        #[test]
+        fn unknown_shortcodes_are_untouched() {
+            assert_eq!(render("Ship it :spaceship:"), "<p>Ship it :spaceship:</p>\n");
+        }
+
+        #[test]
+        fn shortcodes_in_code_are_untouched() {
+            let got = render("Write `:rocket:` to get :rocket:\n\n```\n:warning:\n```\n");
+
+            assert!(got.contains("<code>:rocket:</code> to get 🚀"), "{}", got);
+            assert!(got.contains(":warning:\n</code></pre>"), "{}", got);
+        }
+
+        #[test]
+        fn timestamps_and_paths_are_untouched() {
+            assert_eq!(render("Meet at 10:30:00 about std::fmt"),
+                       "<p>Meet at 10:30:00 about std::fmt</p>\n");
+        }
+
+        #[test]
+        fn shortcodes_are_left_alone_by_default() {
+            let got = super::super::render_markdown(":rocket:", false);
+            assert_eq!(got, "<p>:rocket:</p>\n");
+        }
+    }
+
+    mod toc {
+        use super::super::{render_markdown, render_markdown_with_options, MarkdownOptions};
+        use config::RawHtml;
+
+        #[test]
+        fn the_marker_becomes_a_list_of_the_chapters_headings() {
+            let input = "# Guide\n\n<!-- toc -->\n\n## Setup\n\n### On *Linux*\n\n## Setup\n";
+
+            let got = render_markdown(input, false);
+
+            assert_eq!(got,
+                       "<h1>Guide</h1>\n\
+                        <ul class=\"toc\">\n\
+                        <li><a href=\"#guide\">Guide</a>\n<ul>\n\
+                        <li><a href=\"#setup\">Setup</a>\n<ul>\n\
+                        <li><a href=\"#on-linux\">On Linux</a></li>\n</ul>\n</li>\n\
+                        <li><a href=\"#setup-1\">Setup</a></li>\n</ul>\n</li>\n\
+                        </ul>\n\
+                        <h2>Setup</h2>\n\
+                        <h3>On <em>Linux</em></h3>\n\
+                        <h2>Setup</h2>\n");
+        }
+
+        #[test]
+        fn every_marker_gets_the_whole_list() {
+            let input = "<!-- toc -->\n\n# A\n\n<!-- toc -->\n\n## B\n";
+
+            let got = render_markdown(input, false);
+
+            assert_eq!(got.matches("<ul class=\"toc\">").count(), 2, "{}", got);
+            assert_eq!(got.matches("href=\"#b\"").count(), 2, "{}", got);
+        }
+
+        #[test]
+        fn markers_in_code_blocks_are_left_alone() {
+            let input = "# A\n\n```markdown\n<!-- toc -->\n```\n";
+
+            let got = render_markdown(input, false);
+
+            assert!(got.contains("<code class=\"language-markdown\">&lt;!-- toc --&gt;\n</code>"),
+                    "{}",
+                    got);
+            assert!(!got.contains("class=\"toc\""), "{}", got);
+        }
+
+        #[test]
+        fn the_depth_can_be_changed() {
+            let options = MarkdownOptions {
+                toc_depth: Some(1),
+                ..Default::default()
+            };
+
+            let got = render_markdown_with_options("<!-- toc -->\n\n# A\n\n## B\n", &options);
+
+            assert!(got.contains("href=\"#a\""), "{}", got);
+            assert!(!got.contains("href=\"#b\""), "{}", got);
+        }
+
+        #[test]
+        fn escaping_raw_html_doesnt_affect_the_marker() {
+            let options = MarkdownOptions {
+                raw_html: RawHtml::Escape,
+                ..Default::default()
+            };
+
+            let got = render_markdown_with_options("<!-- toc -->\n\n# A\n", &options);
+
+            assert!(got.starts_with("<ul class=\"toc\">"), "{}", got);
+        }
+    }
+
+    mod line_numbers {
+        use super::super::{render_markdown, render_markdown_with_options, MarkdownOptions};
+
+        #[test]
+        fn every_line_of_a_linenos_block_is_wrapped() {
+            let input = "```rust,linenos\nfn main() {\n    run();\n}\n```\n";
+
+            let expected = "<pre><code class=\"language-rust\" data-linenos-start=\"1\" \
+                            style=\"counter-reset: linenos 0\">\
+                            <span class=\"linenos-line\">fn main() {</span>\n\
+                            <span class=\"linenos-line\">    run();</span>\n\
+                            <span class=\"linenos-line\">}</span>\n\
+                            </code></pre>\n";
+            assert_eq!(render_markdown(input, false), expected);
+        }
+
+        #[test]
+        fn numbering_can_start_somewhere_else() {
+            let input = "```rust,no_run,linenos=10\na\nb\n```\n";
+
+            let got = render_markdown(input, false);
+
+            assert!(got.starts_with("<pre><code class=\"language-rust\" data-attributes=\"no_run\" \
+                                     data-linenos-start=\"10\" \
+                                     style=\"counter-reset: linenos 9\">"),
+                    "{}",
+                    got);
+            assert_eq!(got.matches("<span class=\"linenos-line\">").count(), 2);
+        }
+
+        #[test]
+        fn blocks_without_the_attribute_are_untouched() {
+            let input = "```rust\nfn main() {}\n```\n";
+
+            let expected = "<pre><code class=\"language-rust\">fn main() {}\n</code></pre>\n";
+            assert_eq!(render_markdown(input, false), expected);
+        }
+
+        #[test]
+        fn every_block_can_be_numbered() {
+            let options = MarkdownOptions {
+                line_numbers: true,
+                ..Default::default()
+            };
+
+            let got = render_markdown_with_options("```\na\n```\n", &options);
+
+            assert_eq!(got,
+                       "<pre><code data-linenos-start=\"1\" style=\"counter-reset: linenos 0\">\
+                        <span class=\"linenos-line\">a</span>\n</code></pre>\n");
+        }
+    }
+
+    mod autolink {
+        use super::super::{render_markdown_with_options, MarkdownOptions};
+
+        fn render(src: &str) -> String {
+            let options = MarkdownOptions {
+                autolink: true,
+                ..Default::default()
+            };
+
+            render_markdown_with_options(src, &options)
+        }
+
+        #[test]
+        fn bare_urls_become_links() {
+            assert_eq!(render("See https://example.com/a_b?c=1&d=2 for more."),
+                       "<p>See <a href=\"https://example.com/a_b?c=1&amp;d=2\">\
+                        https://example.com/a_b?c=1&amp;d=2</a> for more.</p>\n");
+        }
+
+        #[test]
+        fn urls_in_code_are_left_alone() {
+            let got = render("Run `curl https://example.com`\n\n```\nhttps://example.com\n```\n");
+
+            assert!(!got.contains("<a"), "{}", got);
+        }
+
+        #[test]
+        fn existing_links_arent_linked_again() {
+            let inputs = [
+                ("[https://example.com](https://example.com)",
+                 "<p><a href=\"https://example.com\">https://example.com</a></p>\n"),
+                ("<https://example.com>",
+                 "<p><a href=\"https://example.com\">https://example.com</a></p>\n"),
+                ("<a href=\"https://example.com\">https://example.com</a>",
+                 "<p><a href=\"https://example.com\">https://example.com</a></p>\n"),
+            ];
+
+            for &(src, should_be) in inputs.iter() {
+                assert_eq!(render(src), should_be);
+            }
+        }
+
+        #[test]
+        fn urls_are_left_alone_by_default() {
+            let got = super::super::render_markdown("https://example.com", false);
+            assert_eq!(got, "<p>https://example.com</p>\n");
+        }
+    }
+
+    mod tables {
+        use super::super::render_markdown;
+
+        #[test]
+        fn cells_get_their_columns_alignment() {
+            let src = "|*Left*|Center|Right|None|\n|:--|:-:|--:|---|\n|a|b|c|`d`|\n";
+
+            let got = render_markdown(src, false);
+
+            assert_eq!(
+                got,
+                "<div class=\"table-wrapper\">\n<table><thead><tr>\
+                 <th style=\"text-align: left\"><em>Left</em></th>\
+                 <th style=\"text-align: center\">Center</th>\
+                 <th style=\"text-align: right\">Right</th>\
+                 <th>None</th>\
+                 </tr></thead><tbody>\n\
+                 <tr>\
+                 <td style=\"text-align: left\">a</td>\
+                 <td style=\"text-align: center\">b</td>\
+                 <td style=\"text-align: right\">c</td>\
+                 <td><code>d</code></td>\
+                 </tr>\n\
+                 </tbody></table>\n</div>\n"
+            );
+        }
+
+        #[test]
+        fn every_table_is_wrapped() {
+            let src = "|a|b|\n|-|-|\n|1|2|\n\nText\n\n|c|d|\n|:-|-:|\n|3|4|\n";
+
+            let got = render_markdown(src, false);
+
+            assert_eq!(got.matches("<div class=\"table-wrapper\">\n<table>").count(), 2, "{}", got);
+            assert_eq!(got.matches("</table>\n</div>").count(), 2, "{}", got);
+            assert!(got.contains("</div>\n<p>Text</p>\n<div"), "{}", got);
+            assert!(got.contains("<td style=\"text-align: right\">4</td>"), "{}", got);
+        }
+
+        #[test]
+        fn alignment_applies_to_every_row_of_its_own_table() {
+            let src = "|a|b|\n|--:|:-:|\n|1|2|\n|3|4|\n\n|c|d|\n|-|-|\n|5|6|\n";
+
+            let got = render_markdown(src, false);
+
+            assert_eq!(got.matches("<td style=\"text-align: right\">").count(), 2, "{}", got);
+            assert_eq!(got.matches("<td style=\"text-align: center\">").count(), 2, "{}", got);
+            assert!(got.contains("<tr><td>5</td><td>6</td></tr>"), "{}", got);
+            assert!(got.contains("<thead><tr><th>c</th><th>d</th></tr></thead>"), "{}", got);
+        }
+    }
+
+    mod definition_lists {
+        use super::super::render_markdown;
+
+        #[test]
+        fn a_term_and_its_definition() {
+            let got = render_markdown("Markdown\n: A lightweight markup language.\n", false);
+
+            assert_eq!(got, "<dl>\n<dt>Markdown</dt>\n<dd>A lightweight markup language.</dd>\n</dl>\n");
+        }
+
+        #[test]
+        fn a_term_with_two_definitions() {
+            let src = "Rust\n: A programming language.\n: A kind of *corrosion*\n  on iron.\n";
+
+            let got = render_markdown(src, false);
+
+            assert_eq!(
+                got,
+                "<dl>\n<dt>Rust</dt>\n\
+                 <dd>A programming language.</dd>\n\
+                 <dd>A kind of <em>corrosion</em>\non iron.</dd>\n</dl>\n"
+            );
+        }
+
+        #[test]
+        fn colons_which_arent_definitions_are_left_alone() {
+            let inputs = vec![
+                (": on its own\n", "<p>: on its own</p>\n"),
+                ("A ratio of\n:3 is not a definition\n", "<p>A ratio of\n:3 is not a definition</p>\n"),
+                ("Some text\nand: more\n", "<p>Some text\nand: more</p>\n"),
+            ];
+
+            for (src, should_be) in inputs {
+                assert_eq!(render_markdown(src, false), should_be);
+            }
+        }
+    }
+
+    mod heading_offset {
+        use super::super::{render_markdown_with_options, MarkdownOptions};
+
+        fn render(src: &str, offset: u32) -> String {
+            let options = MarkdownOptions {
+                heading_offset: offset,
+                ..Default::default()
+            };
+
+            render_markdown_with_options(src, &options)
+        }
+
+        #[test]
+        fn headings_are_left_alone_by_default() {
+            assert_eq!(render("# Title", 0), "<h1>Title</h1>\n");
+        }
+
+        #[test]
+        fn headings_are_shifted_by_the_offset() {
+            assert_eq!(render("# Title", 1), "<h2>Title</h2>\n");
+            assert_eq!(render("### Section", 2), "<h5>Section</h5>\n");
+        }
+
+        #[test]
+        fn setext_headings_are_shifted_too() {
+            assert_eq!(render("Title\n=====\n", 1), "<h2>Title</h2>\n");
+            assert_eq!(render("Section\n-------\n", 1), "<h3>Section</h3>\n");
+        }
+
+        #[test]
+        fn headings_stop_at_h6() {
+            assert_eq!(render("###### Deep", 1), "<h6>Deep</h6>\n");
+            assert_eq!(render("## Section", 100), "<h6>Section</h6>\n");
+        }
+    }
+
+    mod raw_html {
+        use super::super::{render_markdown_with_options, MarkdownOptions};
+        use config::RawHtml;
+
+        fn render(src: &str, policy: RawHtml) -> String {
+            let options = MarkdownOptions {
+                raw_html: policy,
+                ..Default::default()
+            };
+
+            render_markdown_with_options(src, &options)
+        }
+
+        #[test]
+        fn inline_html() {
+            let src = "Some <span class=\"x\">text</span> here";
+
+            assert_eq!(render(src, RawHtml::Allow),
+                       "<p>Some <span class=\"x\">text</span> here</p>\n");
+            assert_eq!(render(src, RawHtml::Escape),
+                       "<p>Some &lt;span class=&quot;x&quot;&gt;text&lt;/span&gt; here</p>\n");
+            assert_eq!(render(src, RawHtml::Strip), "<p>Some text here</p>\n");
+        }
+
+        #[test]
+        fn block_html_wrapping_markdown() {
+            let src = "<div class=\"note\">\n\n*Important*\n\n</div>\n";
+
+            let got = render(src, RawHtml::Allow);
+            assert!(got.contains("<div class=\"note\">"), "{}", got);
+            assert!(got.contains("<p><em>Important</em></p>"), "{}", got);
+
+            let got = render(src, RawHtml::Escape);
+            assert!(got.contains("&lt;div class=&quot;note&quot;&gt;"), "{}", got);
+            assert!(got.contains("&lt;/div&gt;"), "{}", got);
+            assert!(got.contains("<p><em>Important</em></p>"), "{}", got);
+            assert!(!got.contains("<div"), "{}", got);
+
+            let got = render(src, RawHtml::Strip);
+            assert!(got.contains("<p><em>Important</em></p>"), "{}", got);
+            assert!(!got.contains("div"), "{}", got);
+        }
+
+        #[test]
+        fn scripts() {
+            let src = "Before\n\n<script>\nalert(\"hi\");\n</script>\n\nAfter\n";
+
+            let got = render(src, RawHtml::Allow);
+            assert!(got.contains("<script>\nalert(\"hi\");\n</script>"), "{}", got);
+
+            let got = render(src, RawHtml::Escape);
+            assert!(got.contains("&lt;script&gt;\nalert(&quot;hi&quot;);\n&lt;/script&gt;"), "{}", got);
+            assert!(!got.contains("<script>"), "{}", got);
+
+            let got = render(src, RawHtml::Strip);
+            assert!(got.contains("<p>Before</p>") && got.contains("<p>After</p>"), "{}", got);
+            assert!(!got.contains("script") && !got.contains("alert"), "{}", got);
+        }
+
+        #[test]
+        fn html_in_code_is_not_escaped_twice() {
+            let src = "`<b>`\n\n```html\n<script></script>\n```\n";
+
+            for policy in vec![RawHtml::Allow, RawHtml::Escape, RawHtml::Strip] {
+                let got = render(src, policy);
+                assert!(got.contains("<code>&lt;b&gt;</code>"), "{}", got);
+                assert!(got.contains("&lt;script&gt;&lt;/script&gt;"), "{}", got);
+                assert!(!got.contains("&amp;lt;"), "{}", got);
+            }
+        }
+    }
+
+    mod sanitize_html {
+        use super::super::{render_markdown, render_markdown_with_options, MarkdownOptions};
+
+        fn sanitized() -> MarkdownOptions {
+            MarkdownOptions {
+                sanitize_html: true,
+                ..Default::default()
+            }
+        }
+
+        #[test]
+        fn script_tags_are_removed() {
+            let input = "<script>alert('pwned')</script>\n\nSome text";
+
+            let got = render_markdown_with_options(input, &sanitized());
+
+            assert!(!got.contains("<script"), "{}", got);
+            assert!(!got.contains("pwned"), "{}", got);
+            assert!(got.contains("<p>Some text</p>"), "{}", got);
+        }
+
+        #[test]
+        fn allowed_tags_survive() {
+            let input = "Some <em>emphasised</em> text";
+            let expected = "<p>Some <em>emphasised</em> text</p>\n";
+
+            assert_eq!(render_markdown_with_options(input, &sanitized()), expected);
+        }
+
+        #[test]
+        fn event_handlers_are_stripped_from_allowed_tags() {
+            let input = r#"Click <span class="x" onclick="evil()">here</span>"#;
+
+            let got = render_markdown_with_options(input, &sanitized());
+
+            assert!(got.contains(r#"<span class="x">here</span>"#), "{}", got);
+            assert!(!got.contains("onclick"), "{}", got);
+        }
+
+        #[test]
+        fn raw_html_is_untouched_when_disabled() {
+            let input = "<script>alert('pwned')</script>\n\nSome text";
+
+            let got = render_markdown(input, false);
+
+            assert!(got.contains("<script>alert('pwned')</script>"), "{}", got);
+        }
+    }
+
+    mod relative_links {
+        use std::path::{Path, PathBuf};
+        use pulldown_cmark::{Event, Tag};
+        use super::super::{linked_assets, markdown_events, render_markdown_with_path, LineMap,
+                           MarkdownOptions};
+
+        fn render(input: &str) -> String {
+            let is_file = |p: &Path| p == Path::new("first/other.md");
+
+            render_markdown_with_path(input,
+                                      &MarkdownOptions::default(),
+                                      Some(Path::new("first/index.md")),
+                                      is_file)
+        }
+
+        #[test]
+        fn inline_links_to_chapters_are_translated() {
+            let got = render("[other](./other.md#section)");
+
+            assert_eq!(got, "<p><a href=\"first/other.html#section\">other</a></p>\n");
+        }
+
+        fn render_normalizing_fragments(input: &str) -> String {
+            let options = MarkdownOptions {
+                normalize_link_fragments: true,
+                ..Default::default()
+            };
+
+            render_markdown_with_path(input,
+                                      &options,
+                                      Some(Path::new("first/index.md")),
+                                      |p| p == Path::new("first/other.md"))
+        }
+
+        #[test]
+        fn fragments_are_left_alone_by_default() {
+            let got = render("<a id=\"Foo_Bar\"></a>\n\n[x](#Foo_Bar) and [y](#caf%C3%A9)");
+
+            assert!(got.contains("<a href=\"#Foo_Bar\">x</a>"), "{}", got);
+            assert!(got.contains("<a href=\"#caf%C3%A9\">y</a>"), "{}", got);
+        }
+
+        #[test]
+        fn fragments_match_the_generated_heading_ids() {
+            let got = render_normalizing_fragments("[here](#My-Section) and \
+                                                    [there](./other.md#My-Section)");
+
+            assert_eq!(got,
+                       "<p><a href=\"#my-section\">here</a> and \
+                        <a href=\"first/other.html#my-section\">there</a></p>\n");
+        }
+
+        #[test]
+        fn fragments_are_decoded_before_being_normalized() {
+            let got = render_normalizing_fragments("[x](#caf%C3%A9) and [y](#Caf%C3%A9)");
+
+            assert_eq!(got, "<p><a href=\"#caf%C3%A9\">x</a> and <a href=\"#caf%C3%A9\">y</a></p>\n");
+        }
+
+        #[test]
+        fn fragments_of_other_websites_are_left_alone() {
+            let got = render_normalizing_fragments("[docs](https://example.com/docs#My-Section)");
+
+            assert_eq!(got, "<p><a href=\"https://example.com/docs#My-Section\">docs</a></p>\n");
+        }
+
+        #[test]
+        fn resolved_reference_links_are_translated() {
+            let input = "[other][ref]\n\n[ref]: ./other.md";
+
+            let got = render(input);
+
+            assert!(got.contains(r#"<a href="first/other.html">other</a>"#), "{}", got);
+        }
+
+        #[test]
+        fn unresolved_references_are_left_as_text() {
+            let input = "[other][missing]";
+
+            let got = render(input);
+
+            assert_eq!(got, "<p>[other][missing]</p>\n");
+        }
+
+        #[test]
+        fn empty_links_are_skipped() {
+            let got = render("[nowhere]()");
+
+            assert!(got.contains(r#"<a href="">nowhere</a>"#), "{}", got);
+        }
+
+        #[test]
+        fn references_to_urls_are_untouched() {
+            let input = "[rust][ref]\n\n[ref]: http://example.com/other.md";
+
+            let got = render(input);
+
+            assert!(got.contains(r#"<a href="http://example.com/other.md">rust</a>"#), "{}", got);
+        }
+
+        #[test]
+        fn md_links_can_be_kept_verbatim() {
+            let is_file = |p: &Path| p == Path::new("first/y.md");
+            let path = Some(Path::new("first/index.md"));
+            let mut options = MarkdownOptions::default();
+
+            let got = render_markdown_with_path("[x](./y.md)", &options, path, &is_file);
+            assert_eq!(got, "<p><a href=\"first/y.html\">x</a></p>\n");
+
+            options.keep_md_links = true;
+            let got = render_markdown_with_path("[x](./y.md)", &options, path, &is_file);
+            assert_eq!(got, "<p><a href=\"./y.md\">x</a></p>\n");
+        }
+
+        #[test]
+        fn extensionless_links_are_an_option() {
+            let is_file = |p: &Path| p == Path::new("first/y/index.md");
+            let path = Some(Path::new("first/index.md"));
+            let mut options = MarkdownOptions::default();
+
+            let got = render_markdown_with_path("[x](./y/)", &options, path, &is_file);
+            assert_eq!(got, "<p><a href=\"./y/\">x</a></p>\n");
+
+            options.extensionless_links = true;
+            let got = render_markdown_with_path("[x](./y/)", &options, path, &is_file);
+            assert_eq!(got, "<p><a href=\"first/y/index.html\">x</a></p>\n");
+        }
+
+        #[test]
+        fn link_destinations_can_be_collected_from_the_events() {
+            let input = "[other](./other.md#section), [missing](./missing.md), \
+                         [rust](https://www.rust-lang.org/) and [\"quoted\"](../up.md)";
+            let is_file = |p: &Path| p == Path::new("first/other.md") || p == Path::new("up.md");
+
+            let got: Vec<String> = markdown_events(input,
+                                                   &MarkdownOptions::default(),
+                                                   Some(Path::new("first/index.md")),
+                                                   is_file)
+                .filter_map(|event| match event {
+                    Event::Start(Tag::Link(dest, _)) => Some(dest.into_owned()),
+                    _ => None,
+                })
+                .collect();
+
+            assert_eq!(got,
+                       vec!["first/other.html#section",
+                            "./missing.md",
+                            "https://www.rust-lang.org/",
+                            "up.html"]);
+        }
+
+        #[test]
+        fn links_are_untouched_without_a_path() {
+            let options = MarkdownOptions::default();
+            let got = render_markdown_with_path("[other](./other.md)", &options, None, |_| true);
+
+            assert!(got.contains(r#"<a href="./other.md">other</a>"#), "{}", got);
+        }
+
+        #[test]
+        fn links_to_assets_are_made_relative_to_the_root() {
+            let input = "![logo](../images/logo.svg) and [cheatsheet](./cheatsheet.pdf)";
+            let is_file = |p: &Path| {
+                p == Path::new("images/logo.svg") || p == Path::new("first/cheatsheet.pdf")
+            };
+            let mut options = MarkdownOptions::default();
+            options.keep_md_links = true;
+
+            let got = render_markdown_with_path(input,
+                                                &options,
+                                                Some(Path::new("first/index.md")),
+                                                is_file);
+
+            assert_eq!(got,
+                       "<p><img src=\"images/logo.svg\" alt=\"logo\" /> and \
+                        <a href=\"first/cheatsheet.pdf\">cheatsheet</a></p>\n");
+        }
+
+        #[test]
+        fn linked_assets_are_collected_once() {
+            let input = "![logo](../images/logo.svg), ![again](/images/logo.svg), \
+                         [same](../images/logo.svg), [chapter](./other.md), \
+                         [missing](./missing.png) and [outside](../../elsewhere/file.png)";
+            let is_file = |p: &Path| p == Path::new("images/logo.svg");
+
+            let got = linked_assets(input,
+                                    Path::new("first/index.md"),
+                                    &LineMap::default(),
+                                    is_file);
+
+            assert_eq!(got, vec![PathBuf::from("images/logo.svg")]);
+        }
+    }
+
+    mod code_tabs {
+        use std::path::Path;
+        use super::super::{render_markdown, render_markdown_with_options, render_markdown_with_path,
+                           MarkdownOptions};
+
+        #[test]
+        fn consecutive_blocks_in_a_group_become_tabs() {
+            let input = r#"
+```rust,tab=example
+fn main() {}
+```
+```python,tab=example,tabname=Python3
+print()
+```
+"#;
+
+            let got = render_markdown(input, false);
+
+            assert!(got.contains(r#"<div class="code-tabs" data-tab-group="example">"#), "{}", got);
+            assert!(got.contains(r#"name="code-tabs-example-0" id="code-tabs-example-0-0" data-tab-name="rust" checked>"#), "{}", got);
+            assert!(got.contains(r#"<label class="code-tab-label" for="code-tabs-example-0-0">rust</label>"#), "{}", got);
+            assert!(got.contains(r#"<label class="code-tab-label" for="code-tabs-example-0-1">Python3</label>"#), "{}", got);
+            assert!(got.contains(r#"<code class="language-rust">fn main() {}"#), "{}", got);
+            assert!(got.contains(r#"<code class="language-python">print()"#), "{}", got);
+            assert_eq!(got.matches(" checked>").count(), 1);
+        }
+
+        #[test]
+        fn two_groups_on_one_page_are_kept_separate() {
+            let input = r#"
+```rust,tab=first
+a
+```
+```python,tab=first
+b
+```
+```rust,tab=second
+c
+```
+```python,tab=second
+d
+```
+
+Some prose.
+
+```rust,tab=first
+e
+```
+```python,tab=first
+f
+```
+"#;
+
+            let got = render_markdown(input, false);
+
+            assert_eq!(got.matches(r#"<div class="code-tabs""#).count(), 3);
+            assert!(got.contains(r#"data-tab-group="first">"#), "{}", got);
+            assert!(got.contains(r#"data-tab-group="second">"#), "{}", got);
+            // a group reused later on the page still gets unique input names
+            assert!(got.contains(r#"name="code-tabs-first-0""#), "{}", got);
+            assert!(got.contains(r#"name="code-tabs-first-2""#), "{}", got);
+        }
+
+        #[test]
+        fn tab_names_can_be_quoted() {
+            let input = r#"
+```rust,tab=example,tabname="Rust, 2018 edition"
+a
+```
+```rust, tab=example, tabname="Rust 2015"
+b
+```
+"#;
+
+            let got = render_markdown(input, false);
+
+            assert!(got.contains(r#"for="code-tabs-example-0-0">Rust, 2018 edition</label>"#), "{}", got);
+            assert!(got.contains(r#"for="code-tabs-example-0-1">Rust 2015</label>"#), "{}", got);
+            assert!(got.contains(r#"<code class="language-rust">a"#), "{}", got);
+        }
+
+        #[test]
+        fn group_names_with_spaces_give_valid_ids() {
+            let input = r#"
+```rust,tab="Two Words"
+a
+```
+```python,tab="Two Words"
+b
+```
+"#;
+
+            let got = render_markdown(input, false);
+
+            assert!(got.contains(r#"data-tab-group="Two Words""#), "{}", got);
+            assert!(got.contains(r#"name="code-tabs-two-words-0" id="code-tabs-two-words-0-0""#), "{}", got);
+        }
+
+        #[test]
+        fn names_start_with_the_chapters_path() {
+            let input = r#"
+```rust,tab=example
+a
+```
+```python,tab=example
+b
+```
+"#;
+            let options = MarkdownOptions::default();
+
+            let first = render_markdown_with_path(input, &options, Some(Path::new("first.md")), |_| false);
+            let nested = render_markdown_with_path(input, &options, Some(Path::new("first/nested.md")), |_| false);
+
+            assert!(first.contains(r#"name="code-tabs-first-md-example-0" id="code-tabs-first-md-example-0-0""#), "{}", first);
+            assert!(nested.contains(r#"name="code-tabs-first-nested-md-example-0""#), "{}", nested);
+        }
+
+        #[test]
+        fn a_group_with_a_single_member_is_a_normal_block() {
+            let input = r#"
+```rust,tab=lonely
+fn main() {}
+```
+"#;
+            let expected = r#"<pre><code class="language-rust">fn main() {}
+</code></pre>
+"#;
+
+            assert_eq!(render_markdown(input, false), expected);
+        }
+
+        #[test]
+        fn blocks_separated_by_prose_are_not_merged() {
+            let input = r#"
+```rust,tab=example
+a
+```
+
+Some prose.
+
+```python,tab=example
+b
+```
+"#;
+
+            let got = render_markdown(input, false);
+
+            assert!(!got.contains("code-tabs"), "{}", got);
+        }
+
+        #[test]
+        fn remembered_groups_get_an_extra_class() {
+            let input = "```rust,tab=x\na\n```\n```python,tab=x\nb\n```\n";
+            let options = MarkdownOptions {
+                remember_code_tabs: true,
+                ..Default::default()
+            };
+
+            let got = render_markdown_with_options(input, &options);
+
+            assert!(got.contains(r#"<div class="code-tabs code-tabs-remember""#), "{}", got);
+        }
+    }
+
+    mod external_links {
+        use super::super::{render_markdown, render_markdown_with_options, MarkdownOptions};
+
+        fn new_tab() -> MarkdownOptions {
+            MarkdownOptions {
+                external_links_new_tab: true,
+                ..Default::default()
+            }
+        }
+
+        #[test]
+        fn https_links_open_in_a_new_tab() {
+            let input = r#"See [the docs](https://example.com/docs?a=1&b=2 "The Docs")."#;
+            let expected = "<p>See <a href=\"https://example.com/docs?a=1&amp;b=2\" title=\"The Docs\" \
+                            target=\"_blank\" rel=\"noopener noreferrer\">the docs</a>.</p>\n";
+
+            assert_eq!(render_markdown_with_options(input, &new_tab()), expected);
+        }
+
+        #[test]
+        fn relative_links_are_left_alone() {
+            let input = "See [x](./x.html).";
+            let expected = "<p>See <a href=\"./x.html\">x</a>.</p>\n";
+
+            assert_eq!(render_markdown_with_options(input, &new_tab()), expected);
+        }
+
+        #[test]
+        fn mailto_links_are_left_alone() {
+            let input = "[Email me](mailto:someone@example.com)";
+            let expected = "<p><a href=\"mailto:someone@example.com\">Email me</a></p>\n";
+
+            assert_eq!(render_markdown_with_options(input, &new_tab()), expected);
+        }
+
+        #[test]
+        fn external_links_are_untouched_when_disabled() {
+            let input = "[docs](https://example.com/)";
+            let expected = "<p><a href=\"https://example.com/\">docs</a></p>\n";
+
+            assert_eq!(render_markdown(input, false), expected);
+        }
+    }
+
+    mod footnotes {
+        use super::super::render_markdown;
+
+        #[test]
+        fn footnotes_have_no_backreference_links() {
+            let got = render_markdown("Some text[^note].\n\n[^note]: The footnote.\n", false);
+
+            assert!(got.contains(r##"<sup class="footnote-reference"><a href="#note">1</a></sup>"##),
+                    "{}",
+                    got);
+            assert!(got.contains(r#"<div class="footnote-definition" id="note">"#), "{}", got);
+            assert!(got.contains("The footnote."), "{}", got);
+            assert!(!got.contains("↩"), "{}", got);
+            assert!(!got.contains("footnote-backref"), "{}", got);
+        }
+    }
+
+    mod extract_links {
+        use super::super::{extract_links, LinkRef};
+
+        #[test]
+        fn every_kind_of_link_is_collected() {
+            let input = "See [the next chapter](./next.md#usage) and [Rust](https://www.rust-lang.org/).\n\n\
+                         ![A diagram](../images/diagram.png \"Diagram\") and [the docs][docs].\n\n\
+                         [docs]: /docs/index.html\n";
+
+            let got: Vec<_> = extract_links(input)
+                .into_iter()
+                .map(|link| (link.dest, link.is_image, link.absolute))
+                .collect();
+
+            assert_eq!(got,
+                       vec![(String::from("./next.md#usage"), false, false),
+                            (String::from("https://www.rust-lang.org/"), false, true),
+                            (String::from("../images/diagram.png"), true, false),
+                            (String::from("/docs/index.html"), false, true)]);
+        }
+
+        #[test]
+        fn links_know_roughly_where_they_are() {
+            let input = "[first](./first.md)\n\nSome more text, then [second](./second.md).\n";
+
+            let got: Vec<LinkRef> = extract_links(input);
+
+            let first = got[0].offset.unwrap();
+            let second = got[1].offset.unwrap();
+            assert!(first < input.find("Some").unwrap(), "{:?}", got);
+            assert!(second >= input.find("[second]").unwrap(), "{:?}", got);
+        }
+
+        #[test]
+        fn links_in_code_arent_links() {
+            let input = "`[not a link](./nope.md)`\n\n    [also not](./nope.md)\n";
+
+            assert!(extract_links(input).is_empty());
+        }
+    }
+
+    mod link_lines {
+        use super::super::{LineMap, SourceEvents};
+
+        fn nth_link_line(text: &str, dest: &str, nth: usize, line_map: &LineMap) -> Option<usize> {
+            let mut events = SourceEvents::new(text, false);
+            while let Some(_) = events.next() {}
+
+            super::super::nth_link_line(&events.link_offsets, text, dest, nth, line_map)
+        }
+
+        #[test]
+        fn broken_links_below_an_include_are_on_their_line_in_the_file() {
+            // "{{#include part.md}}" on line 3 became three lines
+            let content = "# Chapter\n\na\nb\nc\n\nSee [the part](./part.md).\n";
+            let line_map = LineMap::new(vec![1, 2, 3, 3, 3, 4, 5]);
+
+            assert_eq!(nth_link_line(content, "./part.md", 0, &line_map), Some(5));
+            assert_eq!(nth_link_line(content, "./part.md", 0, &LineMap::default()), Some(7));
+        }
+
+        #[test]
+        fn each_link_to_the_same_place_is_found() {
+            let content = "[one](./x.md)\n\n![an image](./x.md)\n\n[two](./x.md)\n";
+
+            assert_eq!(nth_link_line(content, "./x.md", 0, &LineMap::default()), Some(1));
+            assert_eq!(nth_link_line(content, "./x.md", 1, &LineMap::default()), Some(5));
+            assert_eq!(nth_link_line(content, "./x.md", 2, &LineMap::default()), None);
+        }
+    }
+
+    mod first_heading {
+        use super::super::first_heading;
+
+        #[test]
+        fn the_first_heading_of_any_level_is_used() {
+            let src = "Some text\n\n## A *Nice* `Heading`\n\n# Another one\n";
+            assert_eq!(first_heading(src), Some(String::from("A Nice Heading")));
+        }
+
+        #[test]
+        fn there_may_not_be_a_heading() {
+            assert_eq!(first_heading("Just a paragraph\n"), None);
+            assert_eq!(first_heading("#\n\nAn empty heading\n"), None);
+        }
+    }
+
+    mod ids {
+        use super::super::{ascii_id_from_content, id_from_content, normalize_ascii_id,
+                           normalize_id};
+
+        #[test]
+        fn anchor_generation() {
+            assert_eq!(id_from_content("## `--passes`: add more rustdoc passes"),
+                       "passes-add-more-rustdoc-passes");
+            assert_eq!(id_from_content("## Method-call expressions"),
+                       "method-call-expressions");
+        }
+
+        #[test]
+        fn normalize_titles() {
+            assert_eq!(normalize_id("Getting Started"), "getting-started");
+            assert_eq!(normalize_id("What's New?"), "what-s-new");
+        }
+
+        #[test]
+        fn unicode_is_kept_by_default() {
+            assert_eq!(normalize_id("Café au lait"), "café-au-lait");
+            assert_eq!(normalize_id("Über uns"), "über-uns");
+        }
+
+        #[test]
+        fn accents_can_be_transliterated() {
+            assert_eq!(normalize_ascii_id("Café au lait"), "cafe-au-lait");
+            assert_eq!(normalize_ascii_id("Über uns"), "uber-uns");
+            assert_eq!(normalize_ascii_id("Die Straße"), "die-strasse");
+            assert_eq!(ascii_id_from_content("<em>Crème</em> brûlée"), "creme-brulee");
+        }
+
+        #[test]
+        fn text_which_cant_be_transliterated_gets_a_stable_hash() {
+            let got = normalize_ascii_id("はじめに");
+
+            assert_eq!(got.len(), 8);
+            assert!(got.chars().all(|c| c.is_digit(16)), "{}", got);
+            assert_eq!(normalize_ascii_id("はじめに"), got);
+            assert_ne!(normalize_ascii_id("おわりに"), got);
+        }
+
+        #[test]
+        fn the_ascii_parts_are_kept_alongside_the_hash() {
+            let got = normalize_ascii_id("第1章 Rust入門");
+
+            assert!(got.starts_with("1-rust-"), "{}", got);
+            assert_eq!(got.len(), "1-rust-".len() + 8);
+        }
+    }
+
+    mod clean_codeblock_headers {
+        use std::borrow::Cow;
+        use pulldown_cmark::{Event, Tag};
+        use super::super::clean_codeblock_headers;
+
+        fn clean(info: &str) -> Cow<str> {
+            match clean_codeblock_headers(Event::Start(Tag::CodeBlock(Cow::Borrowed(info)))) {
+                Event::Start(Tag::CodeBlock(info)) => info,
+                other => panic!("Unexpected event {:?}", other),
+            }
+        }
+
+        #[test]
+        fn whitespace_and_empty_attributes_are_removed() {
+            assert_eq!(clean(" rust , ignore,,title=\"Hello, world\""),
+                       "rust,ignore,title=\"Hello, world\"");
+            assert_eq!(clean(",rust,"), "rust");
+        }
+
+        #[test]
+        fn clean_info_strings_arent_copied() {
+            match clean("rust,ignore,title=\"x\"") {
+                Cow::Borrowed(info) => assert_eq!(info, "rust,ignore,title=\"x\""),
+                Cow::Owned(info) => panic!("{} was copied", info),
+            }
+        }
+    }
+
+    mod convert_quotes_to_curly {
+        use std::borrow::Cow;
+        use super::super::{convert_quotes_to_curly, render_markdown};
+
+        #[test]
+        fn it_converts_single_quotes() {
+            assert_eq!(convert_quotes_to_curly("'one', 'two'"),
+                       "‘one’, ‘two’");
+        }
+
+        #[test]
+        fn it_converts_double_quotes() {
+            assert_eq!(convert_quotes_to_curly(r#""one", "two""#),
+                       "“one”, “two”");
+        }
+
+        #[test]
+        fn it_treats_tab_as_whitespace() {
+            assert_eq!(convert_quotes_to_curly("\t'one'"), "\t‘one’");
+        }
+
+        #[test]
+        fn text_without_quotes_isnt_copied() {
+            match convert_quotes_to_curly("No quotes here") {
+                Cow::Borrowed(text) => assert_eq!(text, "No quotes here"),
+                Cow::Owned(text) => panic!("{} was copied", text),
+            }
+        }
+
+        #[test]
+        fn escaped_quotes_stay_straight() {
+            assert_eq!(convert_quotes_to_curly(r"\'x\'"), "'x'");
+            assert_eq!(convert_quotes_to_curly(r#"\"y\""#), r#""y""#);
+        }
+
+        #[test]
+        fn an_escaped_backslash_doesnt_escape_the_quote() {
+            assert_eq!(convert_quotes_to_curly(r"\\'"), "\\’");
+            assert_eq!(convert_quotes_to_curly(r"a\b"), r"a\b");
+        }
+
+        #[test]
+        fn escaped_quotes_in_markdown_stay_straight() {
+            assert_eq!(render_markdown(r"\'x\' and 'y'", true),
+                       "<p>'x' and ‘y’</p>\n");
+            assert_eq!(render_markdown(r#"\"y\" and "z""#, true),
+                       "<p>&quot;y&quot; and “z”</p>\n");
+            assert_eq!(render_markdown(r"a \\'b'", true), "<p>a \\’b’</p>\n");
+            assert_eq!(render_markdown(r"*\'x\'* it\'s", true),
+                       "<p><em>'x'</em> it's</p>\n");
+        }
+
+        #[test]
+        fn escaped_quotes_are_left_alone_without_curly_quotes() {
+            assert_eq!(render_markdown(r"\'x\'", false), "<p>'x'</p>\n");
         }
     }
 }
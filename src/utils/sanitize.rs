@@ -0,0 +1,338 @@
+//! Filtering of raw HTML embedded in markdown through an allowlist of safe
+//! tags and attributes.
+
+use regex::{Captures, Regex};
+
+/// Elements which may appear in sanitized HTML.
+const ALLOWED_TAGS: &[&str] = &[
+    "a", "abbr", "b", "blockquote", "br", "caption", "cite", "code", "col", "colgroup", "dd",
+    "del", "details", "dfn", "div", "dl", "dt", "em", "figcaption", "figure", "h1", "h2", "h3",
+    "h4", "h5", "h6", "hr", "i", "img", "ins", "kbd", "li", "mark", "ol", "p", "pre", "q", "s",
+    "samp", "small", "span", "strike", "strong", "sub", "summary", "sup", "table", "tbody", "td",
+    "tfoot", "th", "thead", "tr", "tt", "u", "ul", "var",
+];
+
+/// Attributes which are allowed on any of the `ALLOWED_TAGS`.
+const GLOBAL_ATTRIBUTES: &[&str] = &["class", "dir", "id", "lang", "title"];
+
+/// Attributes which are only allowed on specific elements.
+const TAG_ATTRIBUTES: &[(&str, &str)] = &[
+    ("a", "href"),
+    ("a", "name"),
+    ("img", "alt"),
+    ("img", "height"),
+    ("img", "src"),
+    ("img", "width"),
+    ("ol", "start"),
+    ("td", "colspan"),
+    ("td", "rowspan"),
+    ("th", "colspan"),
+    ("th", "rowspan"),
+];
+
+/// Elements which are removed along with everything inside them.
+const DROPPED_CONTENT_TAGS: &[&str] = &[
+    "embed", "iframe", "noscript", "object", "script", "style", "template",
+];
+
+/// URL schemes which must never end up in an `href` or `src`.
+const FORBIDDEN_SCHEMES: &[&str] = &["data:", "javascript:", "vbscript:"];
+
+/// A stateful filter for the chunks of raw HTML pulldown-cmark hands us.
+///
+/// Block-level HTML is often split over several events, so we need to
+/// remember whether we're currently inside something like a `<script>`
+/// element whose contents should be dropped.
+#[derive(Debug, Default)]
+pub struct HtmlSanitizer {
+    dropping: Option<String>,
+}
+
+impl HtmlSanitizer {
+    pub fn new() -> HtmlSanitizer {
+        HtmlSanitizer::default()
+    }
+
+    /// Are we currently skipping over the contents of a disallowed element?
+    pub fn is_dropping(&self) -> bool {
+        self.dropping.is_some()
+    }
+
+    /// Forget about any unclosed element we were dropping the contents of.
+    pub fn reset(&mut self) {
+        self.dropping = None;
+    }
+
+    /// Sanitize a chunk of HTML, removing any disallowed tags and attributes.
+    ///
+    /// Any `<` which doesn't start a complete tag (e.g. because the tag is
+    /// split over several chunks) is escaped, so a browser can't read a tag
+    /// into it that we didn't see.
+    pub fn sanitize(&mut self, html: &str) -> String {
+        lazy_static! {
+            static ref TAG: Regex = Regex::new(
+                r#"(?s)<!--.*?-->|<(/?)([a-zA-Z][a-zA-Z0-9]*)((?:[^>"']|"[^"]*"|'[^']*')*)>"#
+            ).unwrap();
+        }
+
+        let mut sanitized = String::with_capacity(html.len());
+        let mut previous_end = 0;
+
+        for caps in TAG.captures_iter(html) {
+            let whole = caps.get(0).expect("The whole match is always present");
+
+            if !self.is_dropping() {
+                sanitized.push_str(&escape_lt(&html[previous_end..whole.start()]));
+            }
+            previous_end = whole.end();
+
+            // comments are always removed
+            let name = match caps.get(2) {
+                Some(name) => name.as_str().to_lowercase(),
+                None => continue,
+            };
+            let closing = &caps[1] == "/";
+
+            if self.is_dropping() {
+                if closing && self.dropping.as_ref() == Some(&name) {
+                    self.dropping = None;
+                }
+                continue;
+            }
+
+            if DROPPED_CONTENT_TAGS.contains(&name.as_str()) {
+                if !closing {
+                    self.dropping = Some(name);
+                }
+            } else if ALLOWED_TAGS.contains(&name.as_str()) {
+                sanitized.push_str(&rebuild_tag(&name, closing, &caps));
+            }
+        }
+
+        if !self.is_dropping() {
+            sanitized.push_str(&escape_lt(&html[previous_end..]));
+        }
+
+        sanitized
+    }
+}
+
+fn escape_lt(text: &str) -> String {
+    text.replace('<', "&lt;")
+}
+
+/// Write out an allowed tag, keeping only the attributes which are safe.
+fn rebuild_tag(name: &str, closing: bool, caps: &Captures) -> String {
+    if closing {
+        return format!("</{}>", name);
+    }
+
+    let raw_attributes = &caps[3];
+    let self_closing = raw_attributes.trim_right().ends_with('/');
+
+    format!(
+        "<{}{}{}>",
+        name,
+        filter_attributes(name, raw_attributes),
+        if self_closing { " /" } else { "" }
+    )
+}
+
+fn filter_attributes(tag: &str, attributes: &str) -> String {
+    lazy_static! {
+        static ref ATTRIBUTE: Regex = Regex::new(
+            r#"([a-zA-Z_:][-a-zA-Z0-9_:.]*)(?:\s*=\s*("[^"]*"|'[^']*'|[^\s"'=<>`]+))?"#
+        ).unwrap();
+    }
+
+    let mut filtered = String::new();
+
+    for caps in ATTRIBUTE.captures_iter(attributes) {
+        let name = caps[1].to_lowercase();
+
+        if !attribute_is_allowed(tag, &name) {
+            trace!("Removing the \"{}\" attribute from a <{}>", name, tag);
+            continue;
+        }
+
+        match caps.get(2) {
+            Some(value) => {
+                let value = unquote(value.as_str());
+
+                if (name == "href" || name == "src") && !url_is_safe(value) {
+                    trace!("Removing unsafe URL from a <{}>: {}", tag, value);
+                    continue;
+                }
+
+                filtered.push_str(&format!(" {}=\"{}\"", name, value.replace('"', "&quot;")));
+            }
+            None => filtered.push_str(&format!(" {}", name)),
+        }
+    }
+
+    filtered
+}
+
+fn attribute_is_allowed(tag: &str, attribute: &str) -> bool {
+    GLOBAL_ATTRIBUTES.contains(&attribute)
+        || TAG_ATTRIBUTES
+            .iter()
+            .any(|&(t, a)| t == tag && a == attribute)
+}
+
+fn unquote(value: &str) -> &str {
+    let quoted = value.len() >= 2
+        && ((value.starts_with('"') && value.ends_with('"'))
+            || (value.starts_with('\'') && value.ends_with('\'')));
+
+    if quoted {
+        &value[1..value.len() - 1]
+    } else {
+        value
+    }
+}
+
+fn url_is_safe(url: &str) -> bool {
+    // browsers decode entities in attribute values, and then ignore
+    // whitespace and control characters when figuring out the scheme, so
+    // `java&#x09;script:` is still javascript
+    let normalized: String = decode_entities(url)
+        .chars()
+        .filter(|c| !c.is_whitespace() && !c.is_control())
+        .collect::<String>()
+        .to_lowercase();
+
+    !FORBIDDEN_SCHEMES
+        .iter()
+        .any(|scheme| normalized.starts_with(scheme))
+}
+
+/// Decode the character references in an attribute value which could be
+/// used to hide a URL's scheme: numeric ones (with or without the trailing
+/// `;`, like browsers accept them) and the named ones for `:` and whitespace.
+/// Anything else is left as it is.
+fn decode_entities(value: &str) -> String {
+    lazy_static! {
+        static ref ENTITY: Regex = Regex::new(
+            r"&(?:#[xX]([0-9a-fA-F]+);?|#([0-9]+);?|(colon|Tab|NewLine|amp);)"
+        ).unwrap();
+    }
+
+    ENTITY
+        .replace_all(value, |caps: &Captures| {
+            let code = match (caps.get(1), caps.get(2)) {
+                (Some(hex), _) => u32::from_str_radix(hex.as_str(), 16).ok(),
+                (None, Some(decimal)) => decimal.as_str().parse().ok(),
+                (None, None) => {
+                    return String::from(match &caps[3] {
+                        "colon" => ":",
+                        "Tab" => "\t",
+                        "NewLine" => "\n",
+                        _ => "&",
+                    });
+                }
+            };
+
+            code.and_then(::std::char::from_u32)
+                .unwrap_or('\u{FFFD}')
+                .to_string()
+        })
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn script_elements_and_their_contents_are_removed() {
+        let mut sanitizer = HtmlSanitizer::new();
+        let got = sanitizer.sanitize("<p>before</p><script>alert('hi')</script><p>after</p>");
+
+        assert_eq!(got, "<p>before</p><p>after</p>");
+        assert!(!sanitizer.is_dropping());
+    }
+
+    #[test]
+    fn dropping_continues_across_chunks() {
+        let mut sanitizer = HtmlSanitizer::new();
+
+        assert_eq!(sanitizer.sanitize("<script>\n"), "");
+        assert!(sanitizer.is_dropping());
+        assert_eq!(sanitizer.sanitize("alert('hi');\n"), "");
+        assert_eq!(sanitizer.sanitize("</script>\n"), "\n");
+        assert!(!sanitizer.is_dropping());
+    }
+
+    #[test]
+    fn unknown_tags_are_removed_but_their_text_is_kept() {
+        let mut sanitizer = HtmlSanitizer::new();
+        let got = sanitizer.sanitize("<blink>text</blink>");
+
+        assert_eq!(got, "text");
+    }
+
+    #[test]
+    fn event_handlers_and_dangerous_urls_are_stripped() {
+        let inputs = vec![
+            (r#"<em onclick="evil()">"#, "<em>"),
+            (r#"<a href="javascript:evil()" title='x'>"#, r#"<a title="x">"#),
+            (r#"<a href="./other.html">"#, r#"<a href="./other.html">"#),
+            (r#"<img src="logo.png" onerror="evil()" />"#, r#"<img src="logo.png" />"#),
+            (r#"<div class="note" style="color: red">"#, r#"<div class="note">"#),
+        ];
+
+        for (src, should_be) in inputs {
+            let got = HtmlSanitizer::new().sanitize(src);
+            assert_eq!(got, should_be);
+        }
+    }
+
+    #[test]
+    fn entities_dont_hide_dangerous_schemes() {
+        let inputs = vec![
+            r#"<a href="java&#115;cript:evil()">"#,
+            r#"<a href="&#106;avascript:evil()">"#,
+            r#"<a href="&#x6A;avascript:evil()">"#,
+            r#"<a href="&#0000106avascript:evil()">"#,
+            r#"<a href="java&#x09;script:evil()">"#,
+            r#"<a href="javascript&colon;evil()">"#,
+            r#"<a href="java&Tab;script:evil()">"#,
+            r#"<img src="&#100;ata:image/svg+xml,evil">"#,
+        ];
+
+        for src in inputs {
+            let got = HtmlSanitizer::new().sanitize(src);
+            assert!(!got.contains("href") && !got.contains("src"), "{} => {}", src, got);
+        }
+    }
+
+    #[test]
+    fn entities_in_safe_urls_are_kept() {
+        let got = HtmlSanitizer::new().sanitize(r#"<a href="./a&amp;b.html">"#);
+        assert_eq!(got, r#"<a href="./a&amp;b.html">"#);
+    }
+
+    #[test]
+    fn unterminated_tags_are_escaped() {
+        let got = HtmlSanitizer::new().sanitize("<img src=x onerror=alert(1)\n");
+        assert_eq!(got, "&lt;img src=x onerror=alert(1)\n");
+
+        let got = HtmlSanitizer::new().sanitize("a < b and <em>c</em>");
+        assert_eq!(got, "a &lt; b and <em>c</em>");
+    }
+
+    #[test]
+    fn tags_split_across_chunks_are_escaped() {
+        let mut sanitizer = HtmlSanitizer::new();
+
+        assert_eq!(sanitizer.sanitize("<img src=x"), "&lt;img src=x");
+        assert_eq!(sanitizer.sanitize(" onerror=alert(1)>"), " onerror=alert(1)>");
+    }
+
+    #[test]
+    fn comments_are_removed() {
+        let got = HtmlSanitizer::new().sanitize("a<!-- <script> -->b");
+        assert_eq!(got, "ab");
+    }
+}
@@ -0,0 +1,131 @@
+//! Turning text (usually a heading) into a slug, for use in ids and URLs.
+//!
+//! The rules are stable, so links to a slug keep working from one version of
+//! mdBook to the next:
+//!
+//! 1. Everything is lowercased.
+//! 2. Every run of characters which aren't alphanumeric (spaces, punctuation,
+//!    `_`, ...) becomes a single `-`.
+//! 3. Any `-` at the start or end is removed.
+//!
+//! "Alphanumeric" follows Unicode, so letters from other alphabets are kept:
+//! `What's New?` becomes `what-s-new` and `Über uns` becomes `über-uns`.
+
+use std::collections::HashMap;
+
+/// Turn some text into a slug, following the rules described in the
+/// [module documentation](index.html).
+///
+/// ```rust
+/// # use mdbook::utils::slug::slugify;
+/// assert_eq!(slugify("Getting Started"), "getting-started");
+/// assert_eq!(slugify("`--passes`: add more passes"), "passes-add-more-passes");
+/// ```
+pub fn slugify(text: &str) -> String {
+    let mut slug = String::with_capacity(text.len());
+    let mut pending_hyphen = false;
+
+    for c in text.chars() {
+        if c.is_alphanumeric() {
+            if pending_hyphen && !slug.is_empty() {
+                slug.push('-');
+            }
+            pending_hyphen = false;
+            slug.extend(c.to_lowercase());
+        } else {
+            pending_hyphen = true;
+        }
+    }
+
+    slug
+}
+
+/// Hands out slugs which are unique within a page (or whatever else the
+/// `Slugifier` is used for). The first time a slug comes up it's used as it
+/// is, and after that `-1`, `-2`, and so on are added to it.
+#[derive(Debug, Clone, Default)]
+pub struct Slugifier {
+    seen: HashMap<String, usize>,
+}
+
+impl Slugifier {
+    /// Create a `Slugifier` which hasn't seen any slugs yet.
+    pub fn new() -> Slugifier {
+        Slugifier::default()
+    }
+
+    /// A unique slug for some text.
+    pub fn slugify(&mut self, text: &str) -> String {
+        let slug = slugify(text);
+        self.unique(slug)
+    }
+
+    /// Make a slug which was created some other way (e.g. an ASCII-only id)
+    /// unique.
+    pub fn unique(&mut self, slug: String) -> String {
+        let previous = self.seen.get(&slug).cloned();
+        let mut count = match previous {
+            Some(count) => count,
+            None => {
+                self.seen.insert(slug.clone(), 1);
+                return slug;
+            }
+        };
+
+        // a heading called "Foo 1" could have taken "foo-1" already
+        let mut unique = format!("{}-{}", slug, count);
+        while self.seen.contains_key(&unique) {
+            count += 1;
+            unique = format!("{}-{}", slug, count);
+        }
+
+        self.seen.insert(slug, count + 1);
+        self.seen.insert(unique.clone(), 1);
+        unique
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spaces_become_hyphens() {
+        assert_eq!(slugify("Getting Started"), "getting-started");
+        assert_eq!(slugify("Lots   of \t space"), "lots-of-space");
+        assert_eq!(slugify("Über uns"), "über-uns");
+    }
+
+    #[test]
+    fn runs_of_punctuation_become_one_hyphen() {
+        assert_eq!(slugify("What's New?"), "what-s-new");
+        assert_eq!(slugify("Foo^bar"), "foo-bar");
+        assert_eq!(slugify("snake_case -- and/or kebab-case"), "snake-case-and-or-kebab-case");
+    }
+
+    #[test]
+    fn leading_and_trailing_symbols_are_trimmed() {
+        assert_eq!(slugify("`--passes`: add more rustdoc passes"),
+                   "passes-add-more-rustdoc-passes");
+        assert_eq!(slugify("  (Optional) Extras!!  "), "optional-extras");
+        assert_eq!(slugify("?!"), "");
+    }
+
+    #[test]
+    fn duplicates_get_a_suffix() {
+        let mut slugs = Slugifier::new();
+
+        let got: Vec<_> = ["Foo", "Foo", "foo!", "Bar"].iter().map(|t| slugs.slugify(t)).collect();
+
+        assert_eq!(got, vec!["foo", "foo-1", "foo-2", "bar"]);
+    }
+
+    #[test]
+    fn suffixes_dont_collide_with_other_slugs() {
+        let mut slugs = Slugifier::new();
+
+        let got: Vec<_> = ["Foo 1", "Foo", "Foo", "Foo 1"].iter().map(|t| slugs.slugify(t)).collect();
+
+        assert_eq!(got, vec!["foo-1", "foo", "foo-2", "foo-1-1"]);
+    }
+}
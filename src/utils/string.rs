@@ -1,5 +1,6 @@
 use std::ops::{Range, RangeFrom, RangeFull, RangeTo};
 use itertools::Itertools;
+use memchr;
 
 // This trait is already contained in the standard lib, however it is unstable.
 // TODO: Remove when the `collections_range` feature stabilises
@@ -55,9 +56,81 @@ pub fn take_lines<R: RangeArgument<usize>>(s: &str, range: R) -> String {
     }
 }
 
+/// The line and column (both starting at 1) of a byte offset in some text.
+/// The column counts characters rather than bytes, so it's still right when
+/// there's multi-byte UTF-8 earlier on the line. An offset past the end of
+/// the text is the end of the text.
+pub fn byte_offset_to_line_col(text: &str, offset: usize) -> (usize, usize) {
+    let mut offset = offset.min(text.len());
+    while !text.is_char_boundary(offset) {
+        offset -= 1;
+    }
+
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map_or(0, |ix| ix + 1);
+    let line = memchr::memchr_iter(b'\n', before.as_bytes()).count() + 1;
+    let column = before[line_start..].chars().count() + 1;
+
+    (line, column)
+}
+
+/// Which line of a chapter's source file each line of its contents came
+/// from, for when preprocessing (e.g. expanding an `{{#include}}`) has moved
+/// things around. Every line of included text comes from the line of the
+/// directive which included it.
+///
+/// An empty map means each line is where it was in the source.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LineMap(Vec<usize>);
+
+impl LineMap {
+    /// Create a map from the source line of each line of the contents, in
+    /// order. Lines which are all where they were give an empty map.
+    pub fn new(lines: Vec<usize>) -> LineMap {
+        if lines.iter().enumerate().all(|(ix, &line)| line == ix + 1) {
+            LineMap::default()
+        } else {
+            LineMap(lines)
+        }
+    }
+
+    /// A map for contents which start `skipped` lines into the source (e.g.
+    /// after the front matter), and have `lines` lines.
+    pub fn skipping(skipped: usize, lines: usize) -> LineMap {
+        LineMap::new((1..lines + 1).map(|line| line + skipped).collect())
+    }
+
+    /// Is each line where it was in the source?
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The line in the source which a line of the contents came from. Lines
+    /// past the end of the map carry on from its last line.
+    pub fn original_line(&self, line: usize) -> usize {
+        match line.checked_sub(1).and_then(|ix| self.0.get(ix)) {
+            Some(&original) => original,
+            None => match self.0.last() {
+                Some(&last) if line > self.0.len() => last + line - self.0.len(),
+                _ => line,
+            },
+        }
+    }
+
+    /// The map for contents which were made from these ones, with `next`
+    /// saying which of these lines each of their lines came from.
+    pub fn then(&self, next: &LineMap) -> LineMap {
+        if next.is_empty() {
+            return self.clone();
+        }
+
+        LineMap::new(next.0.iter().map(|&line| self.original_line(line)).collect())
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::take_lines;
+    use super::{byte_offset_to_line_col, take_lines, LineMap};
 
     #[test]
     fn take_lines_test() {
@@ -67,4 +140,50 @@ mod tests {
         assert_eq!(take_lines(s, ..3), "Lorem\nipsum\ndolor");
         assert_eq!(take_lines(s, ..), s);
     }
+
+    #[test]
+    fn offsets_are_turned_into_lines_and_columns() {
+        let s = "Lorem\nipsum dolor\n\nsit";
+        assert_eq!(byte_offset_to_line_col(s, 0), (1, 1));
+        assert_eq!(byte_offset_to_line_col(s, 5), (1, 6));
+        assert_eq!(byte_offset_to_line_col(s, 6), (2, 1));
+        assert_eq!(byte_offset_to_line_col(s, 12), (2, 7));
+        assert_eq!(byte_offset_to_line_col(s, 19), (4, 1));
+        assert_eq!(byte_offset_to_line_col(s, 100), (4, 4));
+    }
+
+    #[test]
+    fn columns_count_characters_not_bytes() {
+        // "ü" and "日" are 2 and 3 bytes long
+        let s = "Über\n日本語 [link](x.md)";
+        assert_eq!(byte_offset_to_line_col(s, 6), (2, 1));
+        assert_eq!(byte_offset_to_line_col(s, s.find('[').unwrap()), (2, 5));
+        // an offset in the middle of a character is that character
+        assert_eq!(byte_offset_to_line_col(s, 8), (2, 1));
+        assert_eq!(byte_offset_to_line_col(s, 1), (1, 1));
+    }
+
+    #[test]
+    fn line_maps_find_the_original_line() {
+        // line 2 included three lines
+        let map = LineMap::new(vec![1, 2, 2, 2, 3]);
+        assert_eq!(map.original_line(1), 1);
+        assert_eq!(map.original_line(4), 2);
+        assert_eq!(map.original_line(5), 3);
+        assert_eq!(map.original_line(7), 5);
+
+        assert!(LineMap::new(vec![1, 2, 3]).is_empty());
+        assert_eq!(LineMap::default().original_line(42), 42);
+    }
+
+    #[test]
+    fn line_maps_can_be_chained() {
+        let front_matter = LineMap::skipping(3, 4);
+        let included = LineMap::new(vec![1, 1, 1, 2, 3, 4]);
+
+        let both = front_matter.then(&included);
+        assert_eq!(both.original_line(2), 4);
+        assert_eq!(both.original_line(5), 6);
+        assert_eq!(front_matter.then(&LineMap::default()), front_matter);
+    }
 }
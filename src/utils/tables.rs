@@ -0,0 +1,73 @@
+//! Rendering tables ourselves, so every cell gets its column's alignment and
+//! the table is wrapped in a `<div class="table-wrapper">` which the theme
+//! can make scroll horizontally.
+
+use std::borrow::Cow;
+
+use pulldown_cmark::{Alignment, Event, Tag};
+
+/// Replaces the table events with the equivalent HTML, keeping the contents
+/// of each cell as they are.
+#[derive(Debug, Default)]
+pub struct TableConverter {
+    alignments: Vec<Alignment>,
+    in_head: bool,
+    column: usize,
+}
+
+impl TableConverter {
+    pub fn new() -> TableConverter {
+        TableConverter::default()
+    }
+
+    pub fn convert<'a>(&mut self, event: Event<'a>) -> Event<'a> {
+        let html = match event {
+            Event::Start(Tag::Table(ref alignments)) => {
+                self.alignments = alignments.clone();
+                String::from("<div class=\"table-wrapper\">\n<table>")
+            }
+            Event::End(Tag::Table(_)) => {
+                self.alignments.clear();
+                String::from("</tbody></table>\n</div>\n")
+            }
+            Event::Start(Tag::TableHead) => {
+                self.in_head = true;
+                self.column = 0;
+                String::from("<thead><tr>")
+            }
+            Event::End(Tag::TableHead) => {
+                self.in_head = false;
+                String::from("</tr></thead><tbody>\n")
+            }
+            Event::Start(Tag::TableRow) => {
+                self.column = 0;
+                String::from("<tr>")
+            }
+            Event::End(Tag::TableRow) => String::from("</tr>\n"),
+            Event::Start(Tag::TableCell) => {
+                let style = match self.alignments.get(self.column) {
+                    Some(&Alignment::Left) => " style=\"text-align: left\"",
+                    Some(&Alignment::Center) => " style=\"text-align: center\"",
+                    Some(&Alignment::Right) => " style=\"text-align: right\"",
+                    _ => "",
+                };
+                format!("<{}{}>", self.cell_tag(), style)
+            }
+            Event::End(Tag::TableCell) => {
+                self.column += 1;
+                format!("</{}>", self.cell_tag())
+            }
+            _ => return event,
+        };
+
+        Event::Html(Cow::from(html))
+    }
+
+    fn cell_tag(&self) -> &'static str {
+        if self.in_head {
+            "th"
+        } else {
+            "td"
+        }
+    }
+}
@@ -0,0 +1,194 @@
+//! Replacing `<!-- toc -->` markers with a table of contents for the
+//! chapter they're in.
+
+use std::borrow::Cow;
+
+use pulldown_cmark::{html, Event, Tag};
+use regex::Regex;
+
+use super::slug::Slugifier;
+use super::{ascii_id_from_content, id_from_content};
+
+/// How many levels of headings are listed when the depth isn't configured.
+const DEFAULT_DEPTH: u32 = 3;
+
+/// Is this a `<!-- toc -->` marker on a line of its own?
+pub fn is_marker(event: &Event) -> bool {
+    match *event {
+        Event::Html(ref html) => html.trim() == "<!-- toc -->",
+        _ => false,
+    }
+}
+
+/// Replace every marker with a nested list of links to the chapter's
+/// headings, `depth` levels deep (counting from the chapter's biggest
+/// heading). The links use the same ids the HTML renderer gives the
+/// headings, so `transliterate` has to match its `transliterate-ids`
+/// option.
+///
+/// Headings both before and after a marker are listed, so each heading's
+/// content is rendered to HTML on the way through to work out its id.
+pub fn insert_tocs<'a>(events: Vec<Event<'a>>,
+                       depth: Option<u32>,
+                       transliterate: bool)
+                       -> Vec<Event<'a>> {
+    if !events.iter().any(is_marker) {
+        return events;
+    }
+
+    let mut converted = Vec::with_capacity(events.len());
+    let mut slugs = Slugifier::new();
+    let mut headings = Vec::new();
+    let mut heading: Option<Vec<Event<'a>>> = None;
+
+    for event in events {
+        match event {
+            Event::Start(Tag::Header(level)) => {
+                heading = Some(Vec::new());
+                converted.push(Event::Start(Tag::Header(level)));
+            }
+            Event::End(Tag::Header(level)) => {
+                let mut content = String::new();
+                html::push_html(&mut content, heading.take().unwrap_or_default().into_iter());
+
+                let id = if transliterate {
+                    ascii_id_from_content(&content)
+                } else {
+                    id_from_content(&content)
+                };
+                headings.push(Heading {
+                    level: level,
+                    id: slugs.unique(id),
+                    label: without_tags(&content),
+                });
+
+                converted.push(Event::InlineHtml(Cow::from(content)));
+                converted.push(Event::End(Tag::Header(level)));
+            }
+            event => {
+                match heading {
+                    Some(ref mut inner) => inner.push(event),
+                    None => converted.push(event),
+                }
+            }
+        }
+    }
+
+    let toc = toc_html(&headings, depth.unwrap_or(DEFAULT_DEPTH));
+    converted.into_iter()
+             .map(|event| if is_marker(&event) {
+                 Event::Html(Cow::from(toc.clone()))
+             } else {
+                 event
+             })
+             .collect()
+}
+
+struct Heading {
+    level: i32,
+    id: String,
+    /// The heading's HTML without any tags, so links in it don't end up
+    /// nested inside the link to it.
+    label: String,
+}
+
+fn without_tags(html: &str) -> String {
+    lazy_static! {
+        static ref TAG: Regex = Regex::new(r"<[^>]*>").unwrap();
+    }
+
+    TAG.replace_all(html, "").trim().to_string()
+}
+
+/// The nested `<ul>` for the headings which are at most `depth` levels
+/// below the biggest one.
+fn toc_html(headings: &[Heading], depth: u32) -> String {
+    let top = match headings.iter().map(|h| h.level).min() {
+        Some(top) => top,
+        None => return String::new(),
+    };
+    let mut toc = String::new();
+    // the levels of the lists which are still open
+    let mut open: Vec<i32> = Vec::new();
+
+    for heading in headings.iter().filter(|h| h.level - top < depth as i32) {
+        let innermost = open.last().cloned();
+
+        match innermost {
+            None => {
+                toc.push_str("<ul class=\"toc\">\n");
+                open.push(heading.level);
+            }
+            Some(level) if heading.level > level => {
+                toc.push_str("\n<ul>\n");
+                open.push(heading.level);
+            }
+            Some(_) => {
+                toc.push_str("</li>\n");
+                while open.len() > 1 && heading.level < open[open.len() - 1] {
+                    open.pop();
+                    toc.push_str("</ul>\n</li>\n");
+                }
+            }
+        }
+
+        toc.push_str(&format!("<li><a href=\"#{}\">{}</a>", heading.id, heading.label));
+    }
+
+    if !open.is_empty() {
+        toc.push_str("</li>\n");
+        for _ in 1..open.len() {
+            toc.push_str("</ul>\n</li>\n");
+        }
+        toc.push_str("</ul>\n");
+    }
+
+    toc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn heading(level: i32, id: &str) -> Heading {
+        Heading {
+            level: level,
+            id: id.to_string(),
+            label: id.to_uppercase(),
+        }
+    }
+
+    #[test]
+    fn deeper_headings_are_nested() {
+        let headings = vec![heading(2, "a"), heading(3, "b"), heading(4, "c"), heading(2, "d")];
+
+        assert_eq!(toc_html(&headings, 3),
+                   "<ul class=\"toc\">\n\
+                    <li><a href=\"#a\">A</a>\n<ul>\n\
+                    <li><a href=\"#b\">B</a>\n<ul>\n\
+                    <li><a href=\"#c\">C</a></li>\n</ul>\n</li>\n</ul>\n</li>\n\
+                    <li><a href=\"#d\">D</a></li>\n\
+                    </ul>\n");
+    }
+
+    #[test]
+    fn headings_below_the_depth_are_left_out() {
+        let headings = vec![heading(1, "a"), heading(2, "b"), heading(3, "c")];
+
+        let got = toc_html(&headings, 2);
+
+        assert!(got.contains("#b"), "{}", got);
+        assert!(!got.contains("#c"), "{}", got);
+    }
+
+    #[test]
+    fn a_chapter_without_headings_has_an_empty_toc() {
+        assert_eq!(toc_html(&[], 3), "");
+    }
+
+    #[test]
+    fn tags_are_removed_from_the_labels() {
+        assert_eq!(without_tags("Using <code>&lt;b&gt;</code> <a href=\"x\">here</a>"),
+                   "Using &lt;b&gt; here");
+    }
+}
@@ -0,0 +1,105 @@
+//! Turning headings into ASCII-only ids, for books which don't want accented
+//! letters (or any other non-ASCII text) in their URLs.
+
+/// Replace accented Latin letters with their unaccented ASCII equivalents
+/// (`é` becomes `e`, `ß` becomes `ss`), lowercasing everything on the way.
+/// Anything else is left as it is.
+pub fn transliterate(text: &str) -> String {
+    let mut transliterated = String::with_capacity(text.len());
+
+    for c in text.chars().flat_map(char::to_lowercase) {
+        match ascii_equivalent(c) {
+            Some(replacement) => transliterated.push_str(replacement),
+            // combining accents (e.g. from a decomposed "é") are dropped
+            None if is_combining_mark(c) => {}
+            None => transliterated.push(c),
+        }
+    }
+
+    transliterated
+}
+
+/// A hash of some text which stays the same from one build (and version of
+/// Rust) to the next, written as 8 hex digits. This is 32-bit FNV-1a.
+pub fn stable_hash(text: &str) -> String {
+    let mut hash: u32 = 0x811c_9dc5;
+
+    for byte in text.bytes() {
+        hash ^= u32::from(byte);
+        hash = hash.wrapping_mul(0x0100_0193);
+    }
+
+    format!("{:08x}", hash)
+}
+
+fn is_combining_mark(c: char) -> bool {
+    match c {
+        '\u{0300}'...'\u{036f}' => true,
+        _ => false,
+    }
+}
+
+fn ascii_equivalent(c: char) -> Option<&'static str> {
+    let replacement = match c {
+        'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ā' | 'ă' | 'ą' => "a",
+        'æ' => "ae",
+        'ç' | 'ć' | 'ĉ' | 'ċ' | 'č' => "c",
+        'ď' | 'đ' | 'ð' => "d",
+        'è' | 'é' | 'ê' | 'ë' | 'ē' | 'ĕ' | 'ė' | 'ę' | 'ě' => "e",
+        'ĝ' | 'ğ' | 'ġ' | 'ģ' => "g",
+        'ĥ' | 'ħ' => "h",
+        'ì' | 'í' | 'î' | 'ï' | 'ĩ' | 'ī' | 'ĭ' | 'į' | 'ı' => "i",
+        'ĳ' => "ij",
+        'ĵ' => "j",
+        'ķ' => "k",
+        'ĺ' | 'ļ' | 'ľ' | 'ŀ' | 'ł' => "l",
+        'ñ' | 'ń' | 'ņ' | 'ň' => "n",
+        'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'ō' | 'ŏ' | 'ő' => "o",
+        'œ' => "oe",
+        'ŕ' | 'ŗ' | 'ř' => "r",
+        'ś' | 'ŝ' | 'ş' | 'š' => "s",
+        'ß' => "ss",
+        'ţ' | 'ť' | 'ŧ' => "t",
+        'þ' => "th",
+        'ù' | 'ú' | 'û' | 'ü' | 'ũ' | 'ū' | 'ŭ' | 'ů' | 'ű' | 'ų' => "u",
+        'ŵ' => "w",
+        'ý' | 'ÿ' | 'ŷ' => "y",
+        'ź' | 'ż' | 'ž' => "z",
+        _ => return None,
+    };
+
+    Some(replacement)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accents_are_removed() {
+        let inputs = vec![
+            ("Café au lait", "cafe au lait"),
+            ("Über uns", "uber uns"),
+            ("Straße", "strasse"),
+            ("Œuvre, Ærø", "oeuvre, aero"),
+            // "é" written as "e" followed by a combining acute accent
+            ("Cafe\u{0301}", "cafe"),
+        ];
+
+        for (src, should_be) in inputs {
+            assert_eq!(transliterate(src), should_be, "{}", src);
+        }
+    }
+
+    #[test]
+    fn other_scripts_are_left_alone() {
+        assert_eq!(transliterate("第1章 Intro"), "第1章 intro");
+    }
+
+    #[test]
+    fn the_hash_is_stable() {
+        assert_eq!(stable_hash(""), "811c9dc5");
+        assert_eq!(stable_hash("a"), "e40c292c");
+        assert_ne!(stable_hash("第1章"), stable_hash("第2章"));
+    }
+}
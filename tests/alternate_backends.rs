@@ -9,6 +9,7 @@ use tempdir::TempDir;
 use mdbook::config::Config;
 use mdbook::MDBook;
 use mdbook::renderer::RenderContext;
+use mdbook::test_utils::FixtureBook;
 
 #[test]
 fn passing_alternate_backend() {
@@ -67,17 +68,14 @@ fn backends_receive_render_context_via_stdin() {
 }
 
 fn dummy_book_with_backend(name: &str, command: &str) -> (MDBook, TempDir) {
-    let temp = TempDir::new("mdbook").unwrap();
-
     let mut config = Config::default();
     config
         .set(format!("output.{}.command", name), command)
         .unwrap();
 
-    let md = MDBook::init(temp.path())
+    FixtureBook::new()
         .with_config(config)
+        .generated(1, 0)
         .build()
-        .unwrap();
-
-    (md, temp)
+        .unwrap()
 }
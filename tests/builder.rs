@@ -0,0 +1,132 @@
+//! Integration tests for putting a book together with `MDBookBuilder`.
+
+extern crate mdbook;
+extern crate tempdir;
+
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use tempdir::TempDir;
+use mdbook::{BookItem, MDBookBuilder};
+use mdbook::book::{Book, Chapter};
+use mdbook::errors::Result;
+use mdbook::preprocess::{Preprocessor, PreprocessorContext};
+use mdbook::test_utils::FixtureBook;
+use mdbook::utils::fs::file_to_string;
+
+fn two_chapter_book() -> Book {
+    let mut book = Book::new();
+    book.push_item(Chapter::new("Intro", String::from("# Intro\n\n\"Hello\""), "intro.md"))
+        .push_item(Chapter::new("Usage", String::from("# Usage\n"), "usage.md"));
+    book
+}
+
+#[test]
+fn an_in_memory_book_can_be_rendered() {
+    let temp = TempDir::new("mdbook").unwrap();
+
+    let md = MDBookBuilder::new()
+        .root(temp.path())
+        .book(two_chapter_book())
+        .title("Generated")
+        .author("Someone")
+        .curly_quotes(true)
+        .build_dir("output")
+        .render()
+        .unwrap();
+
+    assert_eq!(md.config.book.title, Some(String::from("Generated")));
+    assert_eq!(md.config.book.authors, vec![String::from("Someone")]);
+    assert!(!temp.path().join("src").exists());
+    assert!(!temp.path().join("book.toml").exists());
+
+    let intro = file_to_string(temp.path().join("output/intro.html")).unwrap();
+    assert!(intro.contains("<title>Intro - Generated</title>"), "{}", intro);
+    assert!(intro.contains("“Hello”"), "{}", intro);
+    assert!(intro.contains(r#"<a href="usage.html">"#), "{}", intro);
+    assert!(temp.path().join("output/usage.html").exists());
+}
+
+#[test]
+fn an_in_memory_book_ignores_book_toml() {
+    let temp = TempDir::new("mdbook").unwrap();
+    File::create(temp.path().join("book.toml"))
+        .unwrap()
+        .write_all(b"[book]\ntitle = \"From Disk\"\n\n[build]\nbuild-dir = \"elsewhere\"\n")
+        .unwrap();
+
+    let md = MDBookBuilder::new()
+        .root(temp.path())
+        .book(two_chapter_book())
+        .render()
+        .unwrap();
+
+    assert_eq!(md.config.book.title, None);
+    assert!(temp.path().join("book/intro.html").exists());
+    assert!(!temp.path().join("elsewhere").exists());
+}
+
+#[test]
+fn book_toml_wins_unless_the_builder_overrides_it() {
+    let temp = TempDir::new("mdbook").unwrap();
+    let mut fixture = FixtureBook::new();
+    fixture.with_title("From Disk")
+           .chapter("Intro", "intro.md", "# Intro\n");
+    fixture.config_mut().book.authors = vec![String::from("Someone")];
+    fixture.write_to(temp.path()).unwrap();
+
+    let md = MDBookBuilder::new()
+        .root(temp.path())
+        .build_dir("output")
+        .build()
+        .unwrap();
+
+    assert_eq!(md.config.book.title, Some(String::from("From Disk")));
+    assert_eq!(md.config.book.authors, vec![String::from("Someone")]);
+    assert_eq!(md.config.build.build_dir, Path::new("output"));
+    assert_eq!(md.iter().count(), 1);
+
+    let md = MDBookBuilder::new()
+        .root(temp.path())
+        .title("Overridden")
+        .build()
+        .unwrap();
+
+    assert_eq!(md.config.book.title, Some(String::from("Overridden")));
+    assert_eq!(md.config.book.authors, vec![String::from("Someone")]);
+}
+
+/// Shouts every chapter.
+struct Shout;
+
+impl Preprocessor for Shout {
+    fn name(&self) -> &str {
+        "shout"
+    }
+
+    fn run(&self, _: &PreprocessorContext, book: &mut Book) -> Result<()> {
+        book.for_each_mut(|item| {
+            if let BookItem::Chapter(ref mut ch) = *item {
+                ch.content = ch.content.to_uppercase();
+            }
+        });
+        Ok(())
+    }
+}
+
+#[test]
+fn the_builders_preprocessors_replace_the_default_ones() {
+    let temp = TempDir::new("mdbook").unwrap();
+    let mut book = Book::new();
+    book.push_item(Chapter::new("Intro", String::from("Some text {{#title Nope}}"), "intro.md"));
+
+    MDBookBuilder::new()
+        .root(temp.path())
+        .book(book)
+        .with_preprocessor(Shout)
+        .render()
+        .unwrap();
+
+    let intro = file_to_string(temp.path().join("book/intro.html")).unwrap();
+    assert!(intro.contains("SOME TEXT {{#TITLE NOPE}}"), "{}", intro);
+}
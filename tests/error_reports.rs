@@ -0,0 +1,84 @@
+//! Integration tests for the machine readable error reports used by
+//! `mdbook --error-format json`.
+
+extern crate mdbook;
+extern crate serde_json;
+extern crate tempdir;
+
+use mdbook::MDBook;
+use mdbook::config::Config;
+use mdbook::errors::ErrorCategory;
+use mdbook::test_utils::FixtureBook;
+use tempdir::TempDir;
+
+use std::fs::File;
+use std::io::Write;
+
+#[test]
+fn a_missing_include_is_a_content_error() {
+    let content = "# Chapter 1\n\nSome text.\n\n{{#include missing.md}}\n";
+    let (md, _temp) = FixtureBook::new()
+        .chapter("Chapter 1", "chapter_1.md", content)
+        .build()
+        .unwrap();
+
+    let report = md.build().unwrap_err().report();
+
+    assert_eq!(report.category, ErrorCategory::Content);
+    assert_eq!(report.category.exit_code(), 3);
+
+    let json = serde_json::to_value(&report).unwrap();
+    assert_eq!(json["kind"], "include-failed");
+    assert_eq!(json["category"], "content");
+    assert_eq!(json["path"], "src/chapter_1.md");
+    assert_eq!(json["line"], 5);
+    assert_eq!(json["column"], serde_json::Value::Null);
+    assert!(json["message"].as_str().unwrap().contains("{{#include missing.md}}"), "{}", json);
+    assert!(!json["causes"].as_array().unwrap().is_empty(), "{}", json);
+}
+
+#[test]
+fn a_broken_config_is_a_config_error() {
+    let report = Config::from_str("[book\ntitle = ").unwrap_err().report();
+
+    assert_eq!(report.kind, "invalid-config");
+    assert_eq!(report.category, ErrorCategory::Config);
+    assert_eq!(report.category.exit_code(), 2);
+    assert_eq!(report.path, None);
+}
+
+#[test]
+fn a_missing_summary_is_an_io_error() {
+    let temp = TempDir::new("mdbook").unwrap();
+
+    let report = match MDBook::load(temp.path()) {
+        Ok(_) => panic!("There's no SUMMARY.md to load"),
+        Err(e) => e.report(),
+    };
+
+    assert_eq!(report.kind, "io");
+    assert_eq!(report.category, ErrorCategory::Io);
+    assert_eq!(report.category.exit_code(), 4);
+}
+
+#[test]
+fn summary_parse_errors_have_a_location() {
+    let temp = TempDir::new("mdbook").unwrap();
+    FixtureBook::new()
+        .chapter("Chapter 1", "chapter_1.md", "# Chapter 1\n")
+        .write_to(temp.path())
+        .unwrap();
+    File::create(temp.path().join("src/SUMMARY.md"))
+        .unwrap()
+        .write_all(b"# Summary\n\n- [Chapter 1](chapter_1.md)\n\n[Suffix](chapter_1.md)\n\n- [Oops](chapter_1.md)\n")
+        .unwrap();
+
+    let report = match MDBook::load(temp.path()) {
+        Ok(_) => panic!("SUMMARY.md is invalid"),
+        Err(e) => e.report(),
+    };
+
+    assert_eq!(report.kind, "summary-parse-error");
+    assert_eq!(report.category, ErrorCategory::Content);
+    assert!(report.line.is_some(), "{:?}", report);
+}
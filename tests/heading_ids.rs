@@ -0,0 +1,49 @@
+extern crate log;
+extern crate mdbook;
+
+use log::Level;
+
+use mdbook::config::Config;
+use mdbook::test_utils::{capture_warnings, FixtureBook};
+
+fn warnings_for(content: &str) -> Vec<(Level, String)> {
+    let cfg = Config::from_str("[output.html]\nwarn-duplicate-ids = true\n").unwrap();
+
+    capture_warnings(|| {
+        FixtureBook::new()
+            .with_config(cfg)
+            .chapter("Chapter", "chapter.md", content)
+            .render()
+            .unwrap();
+    })
+}
+
+#[test]
+fn headings_with_the_same_id_are_reported() {
+    let got = warnings_for("# Setup\n\n## Linux\n\n## Setup\n");
+
+    assert_eq!(got.len(), 1, "{:?}", got);
+    assert_eq!(got[0].0, Level::Warn);
+    assert_eq!(got[0].1,
+               "chapter.html: The heading \"Setup\" has the same id as \"Setup\" (\"setup\"), \
+                so it gets the id \"setup-1\" instead");
+}
+
+#[test]
+fn unique_headings_arent_reported() {
+    let got = warnings_for("# Setup\n\n## Linux\n\n## Windows\n");
+
+    assert!(got.is_empty(), "{:?}", got);
+}
+
+#[test]
+fn duplicates_arent_reported_unless_asked_for() {
+    let got = capture_warnings(|| {
+        FixtureBook::new()
+            .chapter("Chapter", "chapter.md", "# Setup\n\n## Setup\n")
+            .render()
+            .unwrap();
+    });
+
+    assert!(got.is_empty(), "{:?}", got);
+}
@@ -0,0 +1,84 @@
+//! Integration tests for the `[build.hooks]` commands.
+#![cfg(not(windows))]
+
+extern crate mdbook;
+extern crate tempdir;
+
+use std::path::Path;
+use tempdir::TempDir;
+use mdbook::MDBook;
+use mdbook::test_utils::FixtureBook;
+use mdbook::utils::fs::file_to_string;
+
+fn book_with_hooks(pre: &[&str], post: &[&str]) -> (MDBook, TempDir) {
+    let mut fixture = FixtureBook::new();
+    fixture.chapter("Chapter 1", "chapter_1.md", "# Chapter 1\n");
+
+    {
+        let hooks = &mut fixture.config_mut().build.hooks;
+        hooks.pre = pre.iter().map(|s| s.to_string()).collect();
+        hooks.post = post.iter().map(|s| s.to_string()).collect();
+    }
+
+    fixture.build().unwrap()
+}
+
+fn read_marker(root: &Path, name: &str) -> String {
+    file_to_string(root.join(name)).unwrap()
+}
+
+#[test]
+fn pre_hooks_run_before_and_post_hooks_after_rendering() {
+    let (md, temp) = book_with_hooks(
+        &[
+            "test ! -f \"$BUILD_DIR/index.html\" && echo pre-1 >> order.txt",
+            "echo pre-2 >> order.txt",
+        ],
+        &["test -f \"$BUILD_DIR/index.html\" && echo post >> order.txt"],
+    );
+
+    md.build().unwrap();
+
+    assert_eq!(read_marker(temp.path(), "order.txt"), "pre-1\npre-2\npost\n");
+}
+
+#[test]
+fn hooks_are_given_information_about_the_build() {
+    let (md, temp) = book_with_hooks(
+        &["echo \"$BOOK_ROOT|$BUILD_DIR|$RENDERERS\" > env.txt"],
+        &[],
+    );
+
+    md.build().unwrap();
+
+    let should_be = format!(
+        "{}|{}|html\n",
+        temp.path().display(),
+        temp.path().join("book").display()
+    );
+    assert_eq!(read_marker(temp.path(), "env.txt"), should_be);
+}
+
+#[test]
+fn chapters_generated_by_pre_hooks_are_rendered() {
+    let (md, temp) = book_with_hooks(&["echo 'Generated by a hook' >> src/chapter_1.md"], &[]);
+
+    md.build().unwrap();
+
+    let rendered = read_marker(temp.path(), "book/chapter_1.html");
+    assert!(rendered.contains("Generated by a hook"));
+}
+
+#[test]
+fn a_failing_pre_hook_aborts_the_build() {
+    let (md, temp) = book_with_hooks(
+        &["echo 'generation failed' >&2; exit 1"],
+        &["touch post-ran.txt"],
+    );
+
+    let err = md.build().unwrap_err();
+
+    assert!(err.to_string().contains("generation failed"), "{}", err);
+    assert!(!temp.path().join("book").join("index.html").exists());
+    assert!(!temp.path().join("post-ran.txt").exists());
+}
@@ -1,10 +1,12 @@
 extern crate mdbook;
 extern crate tempdir;
 
-use std::path::PathBuf;
-use std::fs;
+use std::path::{Path, PathBuf};
+use std::fs::{self, File};
+use std::io::Write;
 use mdbook::MDBook;
 use mdbook::config::Config;
+use mdbook::utils::fs::file_to_string;
 use tempdir::TempDir;
 
 
@@ -68,3 +70,74 @@ fn book_toml_isnt_required() {
 
     md.build().unwrap();
 }
+
+fn write_file(root: &Path, path: &str, content: &str) {
+    let path = root.join(path);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    File::create(path)
+        .unwrap()
+        .write_all(content.as_bytes())
+        .unwrap();
+}
+
+#[test]
+fn init_creates_a_commented_config_and_gitignore_which_build() {
+    let temp = TempDir::new("mdbook").unwrap();
+
+    let md = MDBook::init(temp.path()).create_gitignore(true).build().unwrap();
+    md.build().unwrap();
+
+    let book_toml = file_to_string(temp.path().join("book.toml")).unwrap();
+    assert!(book_toml.starts_with("# "), "{}", book_toml);
+    assert!(book_toml.contains("build-dir = \"book\""), "{}", book_toml);
+    assert_eq!(file_to_string(temp.path().join(".gitignore")).unwrap(), "book\n");
+    assert!(temp.path().join("book/chapter_1.html").exists());
+}
+
+#[test]
+fn init_only_fills_in_the_missing_pieces_of_a_book() {
+    let temp = TempDir::new("mdbook").unwrap();
+    let book_toml = "[book]\ntitle = \"Mine\"\nsrc = \"text\"\n";
+    write_file(temp.path(), "book.toml", book_toml);
+    write_file(temp.path(), "text/SUMMARY.md", "# Summary\n\n- [Intro](./intro.md)\n");
+    write_file(temp.path(), ".gitignore", "target\n");
+
+    let md = MDBook::init(temp.path()).create_gitignore(true).build().unwrap();
+    md.build().unwrap();
+
+    assert_eq!(file_to_string(temp.path().join("book.toml")).unwrap(), book_toml);
+    assert_eq!(file_to_string(temp.path().join(".gitignore")).unwrap(), "target\n");
+    assert!(temp.path().join("text/intro.md").exists());
+    assert!(!temp.path().join("text/chapter_1.md").exists());
+    assert!(!temp.path().join("src").exists());
+    assert!(temp.path().join("book/intro.html").exists());
+}
+
+#[test]
+fn init_can_copy_the_theme() {
+    let temp = TempDir::new("mdbook").unwrap();
+    write_file(temp.path(), "theme/book.css", "/* mine */");
+
+    let md = MDBook::init(temp.path()).copy_theme(true).build().unwrap();
+    md.build().unwrap();
+
+    for file in &["index.hbs", "header.hbs", "book.js", "highlight.css", "favicon.png"] {
+        assert!(temp.path().join("theme").join(file).exists(), "{} wasn't copied", file);
+    }
+    assert_eq!(file_to_string(temp.path().join("theme/book.css")).unwrap(), "/* mine */");
+    assert!(temp.path().join("book/index.html").exists());
+}
+
+#[test]
+fn init_can_be_forced_to_overwrite_files() {
+    let temp = TempDir::new("mdbook").unwrap();
+    write_file(temp.path(), "src/SUMMARY.md", "# Summary\n\n- [Intro](./intro.md)\n");
+    write_file(temp.path(), "theme/book.css", "/* mine */");
+
+    MDBook::init(temp.path()).copy_theme(true).force(true).build().unwrap();
+
+    let summary = file_to_string(temp.path().join("src/SUMMARY.md")).unwrap();
+    assert!(summary.contains("chapter_1.md"), "{}", summary);
+    assert!(temp.path().join("src/chapter_1.md").exists());
+    assert_ne!(file_to_string(temp.path().join("theme/book.css")).unwrap(), "/* mine */");
+}
@@ -0,0 +1,151 @@
+//! Integration tests for books with a `[language.<code>]` table per language.
+
+extern crate mdbook;
+extern crate tempdir;
+
+use mdbook::MDBook;
+use tempdir::TempDir;
+
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::Path;
+
+const BOOK_TOML: &str = r#"
+[book]
+title = "A Book"
+language = "en"
+
+[language.en]
+name = "English"
+
+[language.ja]
+name = "日本語"
+title = "本"
+"#;
+
+fn write_file(root: &Path, path: &str, content: &str) {
+    let path = root.join(path);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    File::create(path)
+        .unwrap()
+        .write_all(content.as_bytes())
+        .unwrap();
+}
+
+fn read_file(path: &Path) -> String {
+    let mut content = String::new();
+    File::open(path)
+        .unwrap()
+        .read_to_string(&mut content)
+        .unwrap();
+    content
+}
+
+/// A book in English and Japanese, where only the first chapter has been
+/// translated.
+fn multilingual_book() -> TempDir {
+    let temp = TempDir::new("mdbook").unwrap();
+    let root = temp.path();
+
+    write_file(root, "book.toml", BOOK_TOML);
+    write_file(root,
+               "src/en/SUMMARY.md",
+               "# Summary\n\n- [Chapter 1](chapter_1.md)\n- [Chapter 2](chapter_2.md)\n");
+    write_file(root, "src/en/chapter_1.md", "# Chapter 1\n");
+    write_file(root, "src/en/chapter_2.md", "# Chapter 2\n");
+    write_file(root, "src/ja/SUMMARY.md", "# Summary\n\n- [第1章](chapter_1.md)\n");
+    write_file(root, "src/ja/chapter_1.md", "# 第1章\n");
+
+    temp
+}
+
+#[test]
+fn every_language_is_built_into_its_own_directory() {
+    let temp = multilingual_book();
+
+    let books = MDBook::load_all_languages(temp.path()).unwrap();
+    assert_eq!(books.iter().map(|b| b.language()).collect::<Vec<_>>(),
+               vec![Some("en"), Some("ja")]);
+
+    for book in books {
+        book.build().unwrap();
+    }
+
+    let en = read_file(&temp.path().join("book/en/chapter_1.html"));
+    assert!(en.contains(r#"<html lang="en">"#));
+    assert!(en.contains("<title>Chapter 1 - A Book</title>"));
+
+    let ja = read_file(&temp.path().join("book/ja/chapter_1.html"));
+    assert!(ja.contains(r#"<html lang="ja">"#));
+    assert!(ja.contains("<title>第1章 - 本</title>"));
+    assert!(!temp.path().join("book/ja/chapter_2.html").exists());
+}
+
+#[test]
+fn the_language_picker_links_to_the_same_chapter() {
+    let temp = multilingual_book();
+
+    for book in MDBook::load_all_languages(temp.path()).unwrap() {
+        book.build().unwrap();
+    }
+
+    let en = read_file(&temp.path().join("book/en/chapter_1.html"));
+    assert!(en.contains(r#"href="../ja/chapter_1.html""#), "{}", en);
+    assert!(en.contains(r#"href="../en/chapter_1.html" lang="en" hreflang="en" class="current""#));
+
+    let ja = read_file(&temp.path().join("book/ja/chapter_1.html"));
+    assert!(ja.contains(r#"href="../en/chapter_1.html""#));
+}
+
+#[test]
+fn an_untranslated_chapter_links_to_the_other_languages_index() {
+    let temp = multilingual_book();
+
+    for book in MDBook::load_all_languages(temp.path()).unwrap() {
+        book.build().unwrap();
+    }
+
+    let en = read_file(&temp.path().join("book/en/chapter_2.html"));
+    assert!(en.contains(r#"href="../ja/index.html""#), "{}", en);
+    assert!(!en.contains("../ja/chapter_2.html"));
+}
+
+#[test]
+fn one_language_can_be_built_by_itself() {
+    let temp = multilingual_book();
+
+    let book = MDBook::load_language(temp.path(), "ja").unwrap();
+    assert_eq!(book.language(), Some("ja"));
+    assert_eq!(book.config.book.title, Some(String::from("本")));
+    book.build().unwrap();
+
+    assert!(temp.path().join("book/ja/index.html").exists());
+    assert!(!temp.path().join("book/en").exists());
+}
+
+#[test]
+fn loading_the_book_gets_the_default_language() {
+    let temp = multilingual_book();
+
+    let book = MDBook::load(temp.path()).unwrap();
+
+    assert_eq!(book.language(), Some("en"));
+    assert_eq!(book.config.book.src, Path::new("src/en"));
+    assert_eq!(book.config.build.build_dir, Path::new("book/en"));
+}
+
+#[test]
+fn a_book_without_language_tables_has_no_picker() {
+    let temp = TempDir::new("mdbook").unwrap();
+    write_file(temp.path(), "src/SUMMARY.md", "# Summary\n\n- [Chapter 1](chapter_1.md)\n");
+    write_file(temp.path(), "src/chapter_1.md", "# Chapter 1\n");
+
+    let books = MDBook::load_all_languages(temp.path()).unwrap();
+    assert_eq!(books.len(), 1);
+    assert_eq!(books[0].language(), None);
+    books[0].build().unwrap();
+
+    let got = read_file(&temp.path().join("book/chapter_1.html"));
+    assert!(got.contains(r#"<html lang="en">"#));
+    assert!(!got.contains("language-picker"));
+}
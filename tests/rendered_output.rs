@@ -21,6 +21,7 @@ use mdbook::errors::*;
 use mdbook::utils::fs::file_to_string;
 use mdbook::config::Config;
 use mdbook::MDBook;
+use mdbook::test_utils::FixtureBook;
 
 const BOOK_ROOT: &'static str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/dummy_book");
 const TOC_TOP_LEVEL: &[&'static str] = &[
@@ -339,3 +340,552 @@ fn book_with_a_reserved_filename_does_not_build() {
     let got = md.build();
     assert!(got.is_err());
 }
+
+/// Move `second.md` to `renamed.md`, leaving a redirect behind in the config.
+fn book_with_a_renamed_chapter(redirects: &str) -> (MDBook, TempDir) {
+    let temp = DummyBook::new().build().unwrap();
+    let src = temp.path().join("src");
+
+    fs::rename(src.join("second.md"), src.join("renamed.md")).unwrap();
+    let summary = file_to_string(src.join("SUMMARY.md")).unwrap();
+    fs::File::create(src.join("SUMMARY.md"))
+        .unwrap()
+        .write_all(summary.replace("(second.md)", "(renamed.md)").as_bytes())
+        .unwrap();
+
+    let cfg = Config::from_str(&format!("[output.html.redirect]\n{}", redirects)).unwrap();
+    let md = MDBook::load_with_config(temp.path(), cfg).unwrap();
+
+    (md, temp)
+}
+
+#[test]
+fn redirects_are_emitted_for_renamed_chapters() {
+    let (md, temp) = book_with_a_renamed_chapter(
+        r#"
+        "/second.html" = "renamed.html"
+        "/first/old-nested.html" = "nested.html#nested-chapter"
+        "/elsewhere.html" = "https://example.com/"
+        "#,
+    );
+    md.build().unwrap();
+
+    let dest = temp.path().join("book");
+    assert!(dest.join("renamed.html").exists());
+
+    assert_contains_strings(
+        dest.join("second.html"),
+        &[
+            r#"<meta http-equiv="refresh" content="0; URL=renamed.html">"#,
+            r#"<link rel="canonical" href="renamed.html">"#,
+        ],
+    );
+    assert_contains_strings(
+        dest.join("first/old-nested.html"),
+        &[r#"content="0; URL=nested.html#nested-chapter""#],
+    );
+    assert_contains_strings(
+        dest.join("elsewhere.html"),
+        &[r#"<link rel="canonical" href="https://example.com/">"#],
+    );
+}
+
+#[test]
+fn redirecting_over_a_generated_chapter_is_an_error() {
+    let (md, _temp) = book_with_a_renamed_chapter(r#""/intro.html" = "renamed.html""#);
+
+    let err = md.build().unwrap_err();
+
+    let conflict = err.iter()
+        .any(|e| e.to_string().contains("a chapter is rendered to that location"));
+    assert!(conflict, "{}", err);
+}
+
+#[test]
+fn redirecting_to_a_page_which_doesnt_exist_is_an_error() {
+    let (md, _temp) = book_with_a_renamed_chapter(r#""/second.html" = "second.html""#);
+
+    assert!(md.build().is_err());
+}
+
+#[test]
+fn redirects_can_point_at_a_directorys_index_page() {
+    let (md, temp) = book_with_a_renamed_chapter(r#""/old-first.html" = "first/""#);
+
+    md.build().unwrap();
+
+    assert_contains_strings(temp.path().join("book/old-first.html"),
+                            &[r#"content="0; URL=first/""#]);
+}
+
+#[test]
+fn redirects_outside_the_build_directory_are_an_error() {
+    let (md, temp) = book_with_a_renamed_chapter(r#""../escaped.html" = "renamed.html""#);
+
+    assert!(md.build().is_err());
+    assert!(!temp.path().join("escaped.html").exists());
+}
+
+#[test]
+fn chapters_link_to_their_source_for_editing() {
+    let temp = DummyBook::new().build().unwrap();
+    let cfg = Config::from_str(
+        r#"
+        [output.html]
+        git-repository-url = "https://github.com/me/book"
+        edit-url-template = "https://github.com/me/book/edit/master/src/{path}"
+        "#,
+    ).unwrap();
+    let md = MDBook::load_with_config(temp.path(), cfg).unwrap();
+    md.build().unwrap();
+
+    let dest = temp.path().join("book");
+    assert_contains_strings(
+        dest.join("first/nested.html"),
+        &[
+            r#"href="https://github.com/me/book/edit/master/src/first/nested.md""#,
+            r#"href="https://github.com/me/book""#,
+        ],
+    );
+    assert_doesnt_contain_strings(dest.join("print.html"), &["/edit/master/src/"]);
+}
+
+#[test]
+fn part_titles_are_rendered_in_the_sidebar() {
+    let temp = DummyBook::new().build().unwrap();
+    let summary_path = temp.path().join("src").join("SUMMARY.md");
+    let summary = file_to_string(&summary_path).unwrap()
+        .replace("- [First Chapter]", "# Part One\n\n- [First Chapter]")
+        .replace("- [Second Chapter]", "\n# Part Two\n\n- [Second Chapter]");
+    fs::File::create(&summary_path)
+        .unwrap()
+        .write_all(summary.as_bytes())
+        .unwrap();
+
+    let md = MDBook::load(temp.path()).unwrap();
+    md.build().unwrap();
+
+    assert_contains_strings(
+        temp.path().join("book/intro.html"),
+        &[
+            r#"<li class="part-title">Part One</li>"#,
+            r#"<li class="part-title">Part Two</li>"#,
+            r#"<strong aria-hidden="true">2.</strong> Second Chapter"#,
+        ],
+    );
+}
+
+#[test]
+fn chapters_link_to_their_neighbours_in_reading_order() {
+    let temp = DummyBook::new().build().unwrap();
+    let md = MDBook::load(temp.path()).unwrap();
+    md.build().unwrap();
+
+    let dest = temp.path().join("book");
+
+    // the prefix chapter is first, so there's nothing before it
+    assert_contains_strings(dest.join("intro.html"), &[r#"<a rel="next" href="first/index.html""#]);
+    assert_doesnt_contain_strings(dest.join("intro.html"), &[r#"rel="prev""#]);
+
+    assert_contains_strings(
+        dest.join("first/includes.html"),
+        &[
+            r#"<a rel="prev" href="first/nested.html" class="mobile-nav-chapters previous" title="Previous chapter: Nested Chapter""#,
+            r#"<a rel="next" href="second.html" class="mobile-nav-chapters next" title="Next chapter: Second Chapter""#,
+        ],
+    );
+
+    // the suffix chapter comes last, after the separator
+    assert_contains_strings(dest.join("second.html"), &[r#"<a rel="next" href="conclusion.html""#]);
+    assert_contains_strings(dest.join("conclusion.html"), &[r#"<a rel="prev" href="second.html""#]);
+    assert_doesnt_contain_strings(dest.join("conclusion.html"), &[r#"rel="next""#]);
+}
+
+#[test]
+fn the_livereload_script_is_only_added_when_serving() {
+    let temp = DummyBook::new().build().unwrap();
+    let md = MDBook::load(temp.path()).unwrap();
+    md.build().unwrap();
+
+    let intro = temp.path().join("book").join("intro.html");
+    assert_doesnt_contain_strings(&intro, &["new WebSocket("]);
+
+    // `mdbook serve` sets this before building
+    let mut md = MDBook::load(temp.path()).unwrap();
+    md.config.set("output.html.livereload-url", "ws://localhost:3001").unwrap();
+    md.build().unwrap();
+
+    assert_contains_strings(&intro, &[r#"new WebSocket("ws://localhost:3001")"#]);
+}
+
+#[test]
+fn draft_chapters_are_listed_but_not_rendered() {
+    let rendered = FixtureBook::new()
+        .chapter("Intro", "intro.md", "# Intro\n")
+        .draft_chapter("Future")
+        .sub_chapter("Nested", "future/nested.md", "# Nested\n")
+        .chapter("Last", "last.md", "# Last\n")
+        .render()
+        .unwrap();
+
+    let mut pages: Vec<_> = rendered
+        .paths()
+        .into_iter()
+        .filter(|p| p.extension() == Some(OsStr::new("html")))
+        .map(|p| p.to_path_buf())
+        .collect();
+    pages.sort();
+    let should_be: Vec<_> = ["future/nested.html", "index.html", "intro.html", "last.html", "print.html"]
+        .iter()
+        .map(|p| Path::new(p).to_path_buf())
+        .collect();
+    assert_eq!(pages, should_be);
+
+    rendered.assert_contains(
+        "intro.html",
+        &[
+            r#"<span class="draft"><strong aria-hidden="true">2.</strong> Future</span>"#,
+            r#"<a href="future/nested.html"><strong aria-hidden="true">2.1.</strong> Nested</a>"#,
+            r#"<strong aria-hidden="true">3.</strong> Last"#,
+            // navigation skips straight over the draft
+            r#"<a rel="next" href="future/nested.html""#,
+        ],
+    );
+    rendered.assert_contains("future/nested.html", &[r#"<a rel="prev" href="intro.html""#]);
+}
+
+const UNTOUCHED: &str = "left alone by the incremental build";
+
+/// Build the dummy book, then mark some of the output files so we can tell
+/// whether the next build wrote them again.
+fn build_and_mark(root: &Path, pages: &[&str]) {
+    MDBook::load(root).unwrap().build().unwrap();
+
+    for page in pages {
+        fs::File::create(root.join("book").join(page))
+            .unwrap()
+            .write_all(UNTOUCHED.as_bytes())
+            .unwrap();
+    }
+}
+
+fn was_rendered_again(root: &Path, page: &str) -> bool {
+    file_to_string(root.join("book").join(page)).unwrap() != UNTOUCHED
+}
+
+fn append_to(path: &Path, text: &str) {
+    fs::OpenOptions::new()
+        .append(true)
+        .open(path)
+        .unwrap()
+        .write_all(text.as_bytes())
+        .unwrap();
+}
+
+#[test]
+fn only_changed_chapters_are_rendered_again() {
+    let temp = DummyBook::new().build().unwrap();
+    build_and_mark(temp.path(), &["intro.html", "second.html", "first/nested.html", "print.html"]);
+
+    append_to(&temp.path().join("src").join("second.md"), "\nSome new text.\n");
+    MDBook::load(temp.path()).unwrap().build().unwrap();
+
+    assert!(was_rendered_again(temp.path(), "second.html"));
+    assert_contains_strings(temp.path().join("book").join("second.html"), &["Some new text."]);
+    // the print page contains every chapter
+    assert!(was_rendered_again(temp.path(), "print.html"));
+    assert!(!was_rendered_again(temp.path(), "intro.html"));
+    assert!(!was_rendered_again(temp.path(), "first/nested.html"));
+}
+
+#[test]
+fn changing_the_summary_or_config_renders_everything_again() {
+    let temp = DummyBook::new().build().unwrap();
+    let summary = temp.path().join("src").join("SUMMARY.md");
+
+    build_and_mark(temp.path(), &["intro.html", "first/nested.html"]);
+    let content = file_to_string(&summary).unwrap();
+    fs::File::create(&summary)
+        .unwrap()
+        .write_all(content.replace("[Second Chapter]", "[Renamed Chapter]").as_bytes())
+        .unwrap();
+    MDBook::load(temp.path()).unwrap().build().unwrap();
+
+    assert!(was_rendered_again(temp.path(), "intro.html"));
+    assert!(was_rendered_again(temp.path(), "first/nested.html"));
+
+    build_and_mark(temp.path(), &["intro.html", "first/nested.html"]);
+    let mut md = MDBook::load(temp.path()).unwrap();
+    md.config.set("output.html.curly-quotes", true).unwrap();
+    md.build().unwrap();
+
+    assert!(was_rendered_again(temp.path(), "intro.html"));
+    assert!(was_rendered_again(temp.path(), "first/nested.html"));
+}
+
+#[test]
+fn forced_builds_ignore_the_render_cache() {
+    let temp = DummyBook::new().build().unwrap();
+    build_and_mark(temp.path(), &["intro.html", "second.html"]);
+
+    // what `mdbook build --force` does
+    let mut md = MDBook::load(temp.path()).unwrap();
+    md.config.build.incremental = false;
+    md.build().unwrap();
+
+    assert!(was_rendered_again(temp.path(), "intro.html"));
+    assert!(was_rendered_again(temp.path(), "second.html"));
+}
+
+#[test]
+fn creating_a_linked_file_renders_the_chapter_again() {
+    let temp = DummyBook::new().build().unwrap();
+    let src = temp.path().join("src");
+    append_to(&src.join("first").join("nested.md"),
+              "\n![x](../img/x.png) and [new](../new.md)\n");
+    build_and_mark(temp.path(), &["intro.html"]);
+    assert_contains_strings(temp.path().join("book").join("first").join("nested.html"),
+                            &[r#"<a href="../new.md">new</a>"#]);
+
+    write_to(&src.join("img").join("x.png"), "");
+    write_to(&src.join("new.md"), "# New");
+    MDBook::load(temp.path()).unwrap().build().unwrap();
+
+    assert_contains_strings(temp.path().join("book").join("first").join("nested.html"),
+                            &[r#"<img src="img/x.png" alt="x" />"#, r#"<a href="new.html">new</a>"#]);
+    assert!(temp.path().join("book").join("img").join("x.png").exists());
+    assert!(!was_rendered_again(temp.path(), "intro.html"));
+}
+
+#[test]
+fn a_corrupt_render_cache_is_ignored() {
+    let temp = DummyBook::new().build().unwrap();
+    build_and_mark(temp.path(), &["intro.html"]);
+
+    fs::File::create(temp.path().join("book").join(".mdbook-cache.json"))
+        .unwrap()
+        .write_all(b"{ definitely not json")
+        .unwrap();
+    MDBook::load(temp.path()).unwrap().build().unwrap();
+
+    assert!(was_rendered_again(temp.path(), "intro.html"));
+}
+
+fn write_to(path: &Path, content: &str) {
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::File::create(path)
+        .unwrap()
+        .write_all(content.as_bytes())
+        .unwrap();
+}
+
+#[test]
+fn theme_files_are_overridden_one_at_a_time() {
+    let temp = DummyBook::new().build().unwrap();
+    write_to(&temp.path().join("theme").join("book.css"), "body { color: rebeccapurple; }");
+
+    MDBook::load(temp.path()).unwrap().build().unwrap();
+
+    let dest = temp.path().join("book");
+    let css = file_to_string(dest.join("book.css")).unwrap();
+    assert_eq!(css, "body { color: rebeccapurple; }");
+    // everything else still comes from the bundled theme
+    assert_contains_strings(
+        dest.join("intro.html"),
+        &[r#"<link rel="stylesheet" href="book.css">"#, r#"<nav id="sidebar""#],
+    );
+    assert!(dest.join("highlight.css").exists());
+}
+
+#[test]
+fn additional_css_and_js_are_copied_and_linked_after_the_theme() {
+    let temp = DummyBook::new().build().unwrap();
+    write_to(&temp.path().join("custom").join("extra.css"), "h1 { color: red; }");
+    write_to(&temp.path().join("extra.js"), "console.log('hi');");
+
+    let cfg = Config::from_str(
+        r#"
+        [output.html]
+        additional-css = ["custom/extra.css"]
+        additional-js = ["extra.js"]
+        "#,
+    ).unwrap();
+    let md = MDBook::load_with_config(temp.path(), cfg).unwrap();
+    md.build().unwrap();
+
+    let dest = temp.path().join("book");
+    assert!(dest.join("custom").join("extra.css").exists());
+    assert!(dest.join("extra.js").exists());
+
+    let intro = file_to_string(dest.join("intro.html")).unwrap();
+    let book_css = intro.find(r#"href="book.css""#).unwrap();
+    let extra_css = intro.find(r#"<link rel="stylesheet" href="custom/extra.css">"#).unwrap();
+    assert!(book_css < extra_css);
+    let book_js = intro.find(r#"src="book.js""#).unwrap();
+    let extra_js = intro.find(r#"src="extra.js""#).unwrap();
+    assert!(book_js < extra_js);
+}
+
+#[test]
+fn missing_additional_files_fail_the_build() {
+    let temp = DummyBook::new().build().unwrap();
+    let cfg = Config::from_str("[output.html]\nadditional-css = [\"does-not-exist.css\"]").unwrap();
+    let md = MDBook::load_with_config(temp.path(), cfg).unwrap();
+
+    let err = md.build().unwrap_err();
+
+    let mentions_path = err.iter()
+        .any(|e| e.to_string().contains("does-not-exist.css"));
+    assert!(mentions_path, "{}", err);
+}
+
+#[test]
+fn front_matter_sets_the_page_title_and_isnt_rendered() {
+    let rendered = FixtureBook::new()
+        .chapter("Intro", "intro.md", "+++\ntitle = \"Welcome\"\n+++\n# Intro\n")
+        .chapter("Second", "second.md", "Some text\n\n## A Heading\n")
+        .chapter("Last", "last.md", "No headings here\n")
+        .render()
+        .unwrap();
+
+    rendered.assert_contains("intro.html", &["<title>Welcome - </title>"]);
+    rendered.assert_contains("second.html", &["<title>A Heading - </title>"]);
+    rendered.assert_contains("last.html", &["<title>Last - </title>"]);
+
+    let intro = rendered.get_str("intro.html").unwrap();
+    assert!(!intro.contains("+++"));
+}
+
+#[test]
+fn malformed_front_matter_fails_the_build() {
+    let got = FixtureBook::new()
+        .chapter("Intro", "intro.md", "+++\ntitle = \n+++\n# Intro\n")
+        .build();
+
+    let err = match got {
+        Ok(_) => panic!("The front matter isn't valid TOML"),
+        Err(e) => e,
+    };
+    assert!(err.to_string().contains("intro.md: Invalid front matter"), "{}", err);
+    assert_eq!(err.report().kind, "invalid-front-matter");
+}
+
+#[test]
+fn images_shared_by_chapters_are_copied_and_rebased() {
+    let (md, temp) = FixtureBook::new()
+        .chapter("Intro", "intro.md", "![logo](./images/logo.svg)\n")
+        .chapter("Nested", "first/nested.md", "![logo](../images/logo.svg)\n")
+        .build()
+        .unwrap();
+    write_to(&temp.path().join("src/images/logo.svg"), "<svg></svg>");
+
+    md.build().unwrap();
+
+    let build_dir = temp.path().join(&md.config.build.build_dir);
+    assert!(build_dir.join("images/logo.svg").is_file());
+    assert_contains_strings(build_dir.join("intro.html"),
+                            &[r#"<img src="images/logo.svg" alt="logo" />"#]);
+    assert_contains_strings(build_dir.join("first/nested.html"),
+                            &[r#"<img src="images/logo.svg" alt="logo" />"#]);
+}
+
+#[test]
+fn links_to_missing_assets_are_left_alone() {
+    let rendered = FixtureBook::new()
+        .chapter("Nested", "first/nested.md", "[cheatsheet](./assets/cheatsheet.pdf)\n")
+        .render()
+        .unwrap();
+
+    rendered.assert_contains("first/nested.html",
+                             &[r#"<a href="./assets/cheatsheet.pdf">cheatsheet</a>"#]);
+    assert!(!rendered.contains_file("first/assets/cheatsheet.pdf"));
+}
+
+#[test]
+fn variables_are_filled_in_before_rendering() {
+    let config = Config::from_str(
+        "[build]\npreprocess = [\"variables\"]\n\n[book.variables]\nversion = \"1.2\"\n",
+    ).unwrap();
+    let rendered = FixtureBook::new()
+        .with_config(config)
+        .with_title("My Book")
+        .chapter(
+            "Intro",
+            "intro.md",
+            "# Welcome to {{ book.title }} {{ var.version }}\n\n\
+             ```handlebars\n<h1>{{ book.title }}</h1>\n```\n",
+        )
+        .render()
+        .unwrap();
+
+    rendered.assert_contains(
+        "intro.html",
+        &[
+            r#"id="welcome-to-my-book-1-2""#,
+            "Welcome to My Book 1.2</h1>",
+            "&lt;h1&gt;{{ book.title }}&lt;/h1&gt;",
+        ],
+    );
+}
+
+#[test]
+fn the_first_chapter_is_copied_to_the_index() {
+    let rendered = FixtureBook::new()
+        .chapter("Intro", "guide/intro.md", "# Intro\n\nSee [the next chapter](./next.md).\n")
+        .chapter("Next", "guide/next.md", "# Next\n")
+        .render()
+        .unwrap();
+
+    rendered.assert_contains(
+        "index.html",
+        &[
+            r#"<a href="guide/next.html">the next chapter</a>"#,
+            r#"<a href="guide/intro.html" class="active">"#,
+        ],
+    );
+    assert!(!rendered.get_str("index.html").unwrap().contains("<base href="));
+    rendered.assert_contains("guide/intro.html", &[r#"<base href="../">"#]);
+}
+
+#[test]
+fn the_copied_index_has_no_edit_link() {
+    let cfg = Config::from_str(
+        "[output.html]\nedit-url-template = \"https://x/edit/{path}\"\n",
+    ).unwrap();
+    let rendered = FixtureBook::new()
+        .with_config(cfg)
+        .chapter("Intro", "guide/intro.md", "# Intro\n")
+        .render()
+        .unwrap();
+
+    rendered.assert_contains("guide/intro.html", &[r#"href="https://x/edit/guide/intro.md""#]);
+    rendered.assert_contains("index.html", &["<h1>Intro"]);
+    assert!(!rendered.get_str("index.html").unwrap().contains("https://x/edit/"));
+}
+
+#[test]
+fn the_index_can_redirect_to_the_first_chapter() {
+    let cfg = Config::from_str("[output.html]\nindex-strategy = \"redirect\"\n").unwrap();
+    let rendered = FixtureBook::new()
+        .with_config(cfg)
+        .chapter("Intro", "guide/intro.md", "# Intro\n")
+        .render()
+        .unwrap();
+
+    rendered.assert_contains(
+        "index.html",
+        &[r#"<meta http-equiv="refresh" content="0; URL=guide/intro.html">"#],
+    );
+}
+
+#[test]
+fn an_explicit_index_chapter_is_left_alone() {
+    let rendered = FixtureBook::new()
+        .chapter("Intro", "intro.md", "The introduction\n")
+        .chapter("Home", "index.md", "The home page\n")
+        .render()
+        .unwrap();
+
+    rendered.assert_contains("index.html", &["The home page"]);
+    assert!(!rendered.get_str("index.html").unwrap().contains("The introduction"));
+}
@@ -9,6 +9,10 @@ use mdbook::preprocess::{Preprocessor, PreprocessorContext};
 use mdbook::book::Book;
 use mdbook::config::Config;
 use mdbook::errors::*;
+use mdbook::test_utils::FixtureBook;
+
+use std::fs::File;
+use std::io::Write;
 
 use std::sync::{Arc, Mutex};
 
@@ -28,6 +32,84 @@ fn mdbook_detects_book_with_failing_tests() {
     assert!(md.test(vec![]).is_err());
 }
 
+#[test]
+fn code_block_flags_are_respected() {
+    let content = "# Flags\n\n\
+                   ```rust,ignore\nthis isn't rust\n```\n\n\
+                   ```rust,no_run\npanic!(\"never run\");\n```\n\n\
+                   ```rust,should_panic\npanic!(\"expected\");\n```\n\n\
+                   ```rust,compile_fail\nlet x: u32 = \"not a number\";\n```\n";
+    let (mut md, _temp) = FixtureBook::new()
+        .chapter("Flags", "flags.md", content)
+        .build()
+        .unwrap();
+
+    md.test(vec![]).unwrap();
+}
+
+#[test]
+fn code_blocks_which_arent_rust_are_skipped() {
+    let content = "# Other languages\n\n\
+                   ```python\nprint('hello')\n```\n\n\
+                   ```\nneither is this\n```\n\n\
+                   ```rust\nassert_eq!(1 + 1, 2);\n```\n";
+    let (mut md, _temp) = FixtureBook::new()
+        .chapter("Other languages", "other.md", content)
+        .build()
+        .unwrap();
+
+    md.test(vec![]).unwrap();
+}
+
+#[test]
+fn failures_say_where_the_code_block_is() {
+    let content = "# Failing\n\n```rust\nassert!(true);\n```\n\n```rust\nassert!(false);\n```\n";
+    let (mut md, _temp) = FixtureBook::new()
+        .chapter("Passing", "passing.md", "```rust\nfn main() {}\n```\n")
+        .sub_chapter("Failing", "nested/failing.md", content)
+        .build()
+        .unwrap();
+
+    let err = md.test(vec![]).unwrap_err().to_string();
+
+    assert!(err.contains("1 code sample(s) failed"), "{}", err);
+    assert!(err.contains("failing.md:7"), "{}", err);
+    assert!(!err.contains("passing.md"), "{}", err);
+}
+
+#[test]
+fn included_code_is_tested() {
+    let (mut md, temp) = FixtureBook::new()
+        .chapter("Includes", "includes.md", "```rust\n{{#include sample.rs}}\n```\n")
+        .build()
+        .unwrap();
+    File::create(temp.path().join("src/sample.rs"))
+        .unwrap()
+        .write_all(b"assert_eq!(2 + 2, 5);\n")
+        .unwrap();
+
+    let err = md.test(vec![]).unwrap_err().to_string();
+
+    assert!(err.contains("includes.md:1"), "{}", err);
+}
+
+#[test]
+fn failures_below_an_include_say_which_line_of_the_file_they_are_on() {
+    let content = "# Chapter\n\n{{#include intro.md}}\n\n```rust\nassert!(false);\n```\n";
+    let (mut md, temp) = FixtureBook::new()
+        .chapter("Chapter", "chapter.md", content)
+        .build()
+        .unwrap();
+    File::create(temp.path().join("src/intro.md"))
+        .unwrap()
+        .write_all(b"Some\n\nintroductory\n\ntext.\n")
+        .unwrap();
+
+    let err = md.test(vec![]).unwrap_err().to_string();
+
+    assert!(err.contains("chapter.md:5"), "{}", err);
+}
+
 #[test]
 fn mdbook_runs_preprocessors() {
 